@@ -0,0 +1,871 @@
+use std::io::{self, Write};
+
+use super::wasm::*;
+
+trait Encode {
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+
+        for item in self {
+            item.encode(buf);
+        }
+    }
+}
+
+impl Encode for u8 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self);
+    }
+}
+
+fn encode_leb128_u64(mut value: u64, buf: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            buf.push(byte);
+            break;
+        }
+
+        buf.push(byte | 0x80);
+    }
+}
+
+fn encode_leb128_i64(mut value: i64, buf: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        // Stop as soon as the remaining bits are a sign extension of the last byte
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            buf.push(byte);
+            break;
+        }
+
+        buf.push(byte | 0x80);
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_leb128_u64(*self as u64, buf)
+    }
+}
+
+impl Encode for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode(buf)
+    }
+}
+
+impl Encode for i32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_leb128_i64(*self as i64, buf)
+    }
+}
+
+impl Encode for i64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_leb128_i64(*self, buf)
+    }
+}
+
+impl Encode for f32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl Encode for f64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl Encode for Preamble {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.magic);
+        buf.extend_from_slice(&self.version);
+    }
+}
+
+impl Encode for NumType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(match self {
+            NumType::I32 => 0x7f,
+            NumType::I64 => 0x7e,
+            NumType::F32 => 0x7d,
+            NumType::F64 => 0x7c,
+        })
+    }
+}
+
+impl Encode for RefType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(match self {
+            RefType::FuncRef => 0x70,
+            RefType::ExternRef => 0x6F,
+        })
+    }
+}
+
+impl Encode for ValueType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ValueType::NumType(num_type) => num_type.encode(buf),
+            ValueType::RefType(ref_type) => ref_type.encode(buf),
+        }
+    }
+}
+
+impl Encode for FuncType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(0x60);
+        self.parameter_types.encode(buf);
+        self.result_types.encode(buf);
+    }
+}
+
+impl Encode for TypeIdx {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Encode for FuncIdx {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Encode for TableIdx {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Encode for MemIdx {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Encode for GlobalIdx {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Encode for ElemIdx {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Encode for LocalIdx {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Encode for LabelIdx {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Encode for Limits {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self.max {
+            None => {
+                buf.push(0x00);
+                self.min.encode(buf);
+            }
+            Some(max) => {
+                buf.push(0x01);
+                self.min.encode(buf);
+                max.encode(buf);
+            }
+        }
+    }
+}
+
+impl Encode for TableType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.elem_type.encode(buf);
+        self.limits.encode(buf);
+    }
+}
+
+impl Encode for MemType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.limits.encode(buf);
+    }
+}
+
+impl Encode for Mutability {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(match self {
+            Mutability::Constant => 0x00,
+            Mutability::Variable => 0x01,
+        })
+    }
+}
+
+impl Encode for GlobalType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.value_type.encode(buf);
+        self.mutability.encode(buf);
+    }
+}
+
+impl Encode for ImportDescriptor {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ImportDescriptor::Func(type_idx) => {
+                buf.push(0x00);
+                type_idx.encode(buf);
+            }
+            ImportDescriptor::Table(table_type) => {
+                buf.push(0x01);
+                table_type.encode(buf);
+            }
+            ImportDescriptor::Memory(mem_type) => {
+                buf.push(0x02);
+                mem_type.encode(buf);
+            }
+            ImportDescriptor::Global(global_type) => {
+                buf.push(0x03);
+                global_type.encode(buf);
+            }
+        }
+    }
+}
+
+impl Encode for Name {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.0.len() as u32).encode(buf);
+        buf.extend_from_slice(self.0.as_bytes());
+    }
+}
+
+impl Encode for Import {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.module.encode(buf);
+        self.name.encode(buf);
+        self.descriptor.encode(buf);
+    }
+}
+
+impl Encode for BlockType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            BlockType::Empty => buf.push(0x40),
+            BlockType::Value(value_type) => value_type.encode(buf),
+            // Encoded as a (non-negative) signed 33 bit integer
+            BlockType::TypeIdx(TypeIdx(type_idx)) => encode_leb128_i64(*type_idx as i64, buf),
+        }
+    }
+}
+
+impl Encode for MemArg {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.align.encode(buf);
+        self.offset.encode(buf);
+    }
+}
+
+/// Encodes the instructions, without the terminating `end` or `else` opcode
+fn encode_instructions(instructions: &[Instruction], buf: &mut Vec<u8>) {
+    for instruction in instructions {
+        encode_instruction(instruction, buf);
+    }
+}
+
+impl Encode for Vec<Instruction> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_instructions(self, buf);
+        buf.push(0x0B);
+    }
+}
+
+fn encode_instruction(instruction: &Instruction, buf: &mut Vec<u8>) {
+    match instruction {
+        // Control instructions
+        Instruction::Unreachable => buf.push(0x00),
+        Instruction::Nop => buf.push(0x01),
+        Instruction::Block(block_type, code) => {
+            buf.push(0x02);
+            block_type.encode(buf);
+            code.encode(buf);
+        }
+        Instruction::Loop(block_type, code) => {
+            buf.push(0x03);
+            block_type.encode(buf);
+            code.encode(buf);
+        }
+        Instruction::If(block_type, then_code, else_code) => {
+            buf.push(0x04);
+            block_type.encode(buf);
+            encode_instructions(then_code, buf);
+            if !else_code.is_empty() {
+                buf.push(0x05);
+                encode_instructions(else_code, buf);
+            }
+            buf.push(0x0B);
+        }
+        Instruction::Branch(label_idx) => {
+            buf.push(0x0C);
+            label_idx.encode(buf);
+        }
+        Instruction::BranchIf(label_idx) => {
+            buf.push(0x0D);
+            label_idx.encode(buf);
+        }
+        Instruction::BranchTable(label_idxs, label_idx) => {
+            buf.push(0x0E);
+            label_idxs.encode(buf);
+            label_idx.encode(buf);
+        }
+        Instruction::Return => buf.push(0x0F),
+        Instruction::Call(func_idx) => {
+            buf.push(0x10);
+            func_idx.encode(buf);
+        }
+        Instruction::CallIndirect(type_idx, table_idx) => {
+            buf.push(0x11);
+            type_idx.encode(buf);
+            table_idx.encode(buf);
+        }
+
+        // Parametric instructions
+        Instruction::Drop => buf.push(0x1A),
+        Instruction::Select => buf.push(0x1B),
+
+        // Variable instructions
+        Instruction::LocalGet(local_idx) => {
+            buf.push(0x20);
+            local_idx.encode(buf);
+        }
+        Instruction::LocalSet(local_idx) => {
+            buf.push(0x21);
+            local_idx.encode(buf);
+        }
+        Instruction::LocalTee(local_idx) => {
+            buf.push(0x22);
+            local_idx.encode(buf);
+        }
+        Instruction::GlobalGet(global_idx) => {
+            buf.push(0x23);
+            global_idx.encode(buf);
+        }
+        Instruction::GlobalSet(global_idx) => {
+            buf.push(0x24);
+            global_idx.encode(buf);
+        }
+
+        // Table instructions
+        Instruction::TableGet(table_idx) => {
+            buf.push(0x25);
+            table_idx.encode(buf);
+        }
+        Instruction::TableSet(table_idx) => {
+            buf.push(0x26);
+            table_idx.encode(buf);
+        }
+        Instruction::TableInit(table_idx, elem_idx) => {
+            encode_prefixed_opcode(12, buf);
+            elem_idx.encode(buf);
+            table_idx.encode(buf);
+        }
+        Instruction::ElemDrop(elem_idx) => {
+            encode_prefixed_opcode(13, buf);
+            elem_idx.encode(buf);
+        }
+        Instruction::TableCopy(destination, source) => {
+            encode_prefixed_opcode(14, buf);
+            destination.encode(buf);
+            source.encode(buf);
+        }
+        Instruction::TableGrow(table_idx) => {
+            encode_prefixed_opcode(15, buf);
+            table_idx.encode(buf);
+        }
+        Instruction::TableSize(table_idx) => {
+            encode_prefixed_opcode(16, buf);
+            table_idx.encode(buf);
+        }
+        Instruction::TableFill(table_idx) => {
+            encode_prefixed_opcode(17, buf);
+            table_idx.encode(buf);
+        }
+
+        // Memory instructions
+        Instruction::I32Load(memarg) => encode_memory_instruction(0x28, memarg, buf),
+        Instruction::I64Load(memarg) => encode_memory_instruction(0x29, memarg, buf),
+        Instruction::F32Load(memarg) => encode_memory_instruction(0x2A, memarg, buf),
+        Instruction::F64Load(memarg) => encode_memory_instruction(0x2B, memarg, buf),
+        Instruction::I32Load8Signed(memarg) => encode_memory_instruction(0x2C, memarg, buf),
+        Instruction::I32Load8Unsigned(memarg) => encode_memory_instruction(0x2D, memarg, buf),
+        Instruction::I32Load16Signed(memarg) => encode_memory_instruction(0x2E, memarg, buf),
+        Instruction::I32Load16Unsigned(memarg) => encode_memory_instruction(0x2F, memarg, buf),
+        Instruction::I64Load8Signed(memarg) => encode_memory_instruction(0x30, memarg, buf),
+        Instruction::I64Load8Unsigned(memarg) => encode_memory_instruction(0x31, memarg, buf),
+        Instruction::I64Load16Signed(memarg) => encode_memory_instruction(0x32, memarg, buf),
+        Instruction::I64Load16Unsigned(memarg) => encode_memory_instruction(0x33, memarg, buf),
+        Instruction::I64Load32Signed(memarg) => encode_memory_instruction(0x34, memarg, buf),
+        Instruction::I64Load32Unsigned(memarg) => encode_memory_instruction(0x35, memarg, buf),
+        Instruction::I32Store(memarg) => encode_memory_instruction(0x36, memarg, buf),
+        Instruction::I64Store(memarg) => encode_memory_instruction(0x37, memarg, buf),
+        Instruction::F32Store(memarg) => encode_memory_instruction(0x38, memarg, buf),
+        Instruction::F64Store(memarg) => encode_memory_instruction(0x39, memarg, buf),
+        Instruction::I32Store8(memarg) => encode_memory_instruction(0x3A, memarg, buf),
+        Instruction::I32Store16(memarg) => encode_memory_instruction(0x3B, memarg, buf),
+        Instruction::I64Store8(memarg) => encode_memory_instruction(0x3C, memarg, buf),
+        Instruction::I64Store16(memarg) => encode_memory_instruction(0x3D, memarg, buf),
+        Instruction::I64Store32(memarg) => encode_memory_instruction(0x3E, memarg, buf),
+        Instruction::MemorySize => buf.extend_from_slice(&[0x3F, 0x00]),
+        Instruction::MemoryGrow => buf.extend_from_slice(&[0x40, 0x00]),
+
+        // Numeric instructions
+        Instruction::I32Const(value) => {
+            buf.push(0x41);
+            value.encode(buf);
+        }
+        Instruction::I64Const(value) => {
+            buf.push(0x42);
+            value.encode(buf);
+        }
+        Instruction::F32Const(value) => {
+            buf.push(0x43);
+            value.encode(buf);
+        }
+        Instruction::F64Const(value) => {
+            buf.push(0x44);
+            value.encode(buf);
+        }
+
+        Instruction::I32Eqz => buf.push(0x45),
+        Instruction::I32Eq => buf.push(0x46),
+        Instruction::I32Ne => buf.push(0x47),
+        Instruction::I32LtSigned => buf.push(0x48),
+        Instruction::I32LtUnsigned => buf.push(0x49),
+        Instruction::I32GtSigned => buf.push(0x4A),
+        Instruction::I32GtUnsigned => buf.push(0x4B),
+        Instruction::I32LeSigned => buf.push(0x4C),
+        Instruction::I32LeUnsigned => buf.push(0x4D),
+        Instruction::I32GeSigned => buf.push(0x4E),
+        Instruction::I32GeUnsigned => buf.push(0x4F),
+
+        Instruction::I64Eqz => buf.push(0x50),
+        Instruction::I64Eq => buf.push(0x51),
+        Instruction::I64Ne => buf.push(0x52),
+        Instruction::I64LtSigned => buf.push(0x53),
+        Instruction::I64LtUnsigned => buf.push(0x54),
+        Instruction::I64GtSigned => buf.push(0x55),
+        Instruction::I64GtUnsigned => buf.push(0x56),
+        Instruction::I64LeSigned => buf.push(0x57),
+        Instruction::I64LeUnsigned => buf.push(0x58),
+        Instruction::I64GeSigned => buf.push(0x59),
+        Instruction::I64GeUnsigned => buf.push(0x5A),
+
+        Instruction::F32Eq => buf.push(0x5B),
+        Instruction::F32Ne => buf.push(0x5C),
+        Instruction::F32Lt => buf.push(0x5D),
+        Instruction::F32Gt => buf.push(0x5E),
+        Instruction::F32Le => buf.push(0x5F),
+        Instruction::F32Ge => buf.push(0x60),
+
+        Instruction::F64Eq => buf.push(0x61),
+        Instruction::F64Ne => buf.push(0x62),
+        Instruction::F64Lt => buf.push(0x63),
+        Instruction::F64Gt => buf.push(0x64),
+        Instruction::F64Le => buf.push(0x65),
+        Instruction::F64Ge => buf.push(0x66),
+
+        Instruction::I32Clz => buf.push(0x67),
+        Instruction::I32Ctz => buf.push(0x68),
+        Instruction::I32Popcnt => buf.push(0x69),
+        Instruction::I32Add => buf.push(0x6A),
+        Instruction::I32Sub => buf.push(0x6B),
+        Instruction::I32Mul => buf.push(0x6C),
+        Instruction::I32DivSigned => buf.push(0x6D),
+        Instruction::I32DivUnsigned => buf.push(0x6E),
+        Instruction::I32RemSigned => buf.push(0x6F),
+        Instruction::I32RemUnsigned => buf.push(0x70),
+        Instruction::I32And => buf.push(0x71),
+        Instruction::I32Or => buf.push(0x72),
+        Instruction::I32Xor => buf.push(0x73),
+        Instruction::I32Shl => buf.push(0x74),
+        Instruction::I32ShrSigned => buf.push(0x75),
+        Instruction::I32ShrUnsigned => buf.push(0x76),
+        Instruction::I32Rotl => buf.push(0x77),
+        Instruction::I32Rotr => buf.push(0x78),
+
+        Instruction::I64Clz => buf.push(0x79),
+        Instruction::I64Ctz => buf.push(0x7A),
+        Instruction::I64Popcnt => buf.push(0x7B),
+        Instruction::I64Add => buf.push(0x7C),
+        Instruction::I64Sub => buf.push(0x7D),
+        Instruction::I64Mul => buf.push(0x7E),
+        Instruction::I64DivSigned => buf.push(0x7F),
+        Instruction::I64DivUnsigned => buf.push(0x80),
+        Instruction::I64RemSigned => buf.push(0x81),
+        Instruction::I64RemUnsigned => buf.push(0x82),
+        Instruction::I64And => buf.push(0x83),
+        Instruction::I64Or => buf.push(0x84),
+        Instruction::I64Xor => buf.push(0x85),
+        Instruction::I64Shl => buf.push(0x86),
+        Instruction::I64ShrSigned => buf.push(0x87),
+        Instruction::I64ShrUnsigned => buf.push(0x88),
+        Instruction::I64Rotl => buf.push(0x89),
+        Instruction::I64Rotr => buf.push(0x8A),
+
+        Instruction::F32Abs => buf.push(0x8B),
+        Instruction::F32Neg => buf.push(0x8C),
+        Instruction::F32Ceil => buf.push(0x8D),
+        Instruction::F32Floor => buf.push(0x8E),
+        Instruction::F32Trunc => buf.push(0x8F),
+        Instruction::F32Nearest => buf.push(0x90),
+        Instruction::F32Sqrt => buf.push(0x91),
+        Instruction::F32Add => buf.push(0x92),
+        Instruction::F32Sub => buf.push(0x93),
+        Instruction::F32Mul => buf.push(0x94),
+        Instruction::F32Div => buf.push(0x95),
+        Instruction::F32Min => buf.push(0x96),
+        Instruction::F32Max => buf.push(0x97),
+        Instruction::F32Copysign => buf.push(0x98),
+
+        Instruction::F64Abs => buf.push(0x99),
+        Instruction::F64Neg => buf.push(0x9A),
+        Instruction::F64Ceil => buf.push(0x9B),
+        Instruction::F64Floor => buf.push(0x9C),
+        Instruction::F64Trunc => buf.push(0x9D),
+        Instruction::F64Nearest => buf.push(0x9E),
+        Instruction::F64Sqrt => buf.push(0x9F),
+        Instruction::F64Add => buf.push(0xA0),
+        Instruction::F64Sub => buf.push(0xA1),
+        Instruction::F64Mul => buf.push(0xA2),
+        Instruction::F64Div => buf.push(0xA3),
+        Instruction::F64Min => buf.push(0xA4),
+        Instruction::F64Max => buf.push(0xA5),
+        Instruction::F64Copysign => buf.push(0xA6),
+
+        Instruction::I32WrapI64 => buf.push(0xA7),
+        Instruction::I32TruncF32Signed => buf.push(0xA8),
+        Instruction::I32TruncF32Unsigned => buf.push(0xA9),
+        Instruction::I32TruncF64Signed => buf.push(0xAA),
+        Instruction::I32TruncF64Unsigned => buf.push(0xAB),
+        Instruction::I64ExtendI32Signed => buf.push(0xAC),
+        Instruction::I64ExtendI32Unsigned => buf.push(0xAD),
+        Instruction::I64TruncF32Signed => buf.push(0xAE),
+        Instruction::I64TruncF32Unsigned => buf.push(0xAF),
+        Instruction::I64TruncF64Signed => buf.push(0xB0),
+        Instruction::I64TruncF64Unsigned => buf.push(0xB1),
+        Instruction::F32ConvertI32Signed => buf.push(0xB2),
+        Instruction::F32ConvertI32Unsigned => buf.push(0xB3),
+        Instruction::F32ConvertI64Signed => buf.push(0xB4),
+        Instruction::F32ConvertI64Unsigned => buf.push(0xB5),
+        Instruction::F32DemoteF64 => buf.push(0xB6),
+        Instruction::F64ConvertI32Signed => buf.push(0xB7),
+        Instruction::F64ConvertI32Unsigned => buf.push(0xB8),
+        Instruction::F64ConvertI64Signed => buf.push(0xB9),
+        Instruction::F64ConvertI64Unsigned => buf.push(0xBA),
+        Instruction::F64PromoteF32 => buf.push(0xBB),
+        Instruction::I32ReinterpretF32 => buf.push(0xBC),
+        Instruction::I64ReinterpretF64 => buf.push(0xBD),
+        Instruction::F32ReinterpretI32 => buf.push(0xBE),
+        Instruction::F64ReinterpretI64 => buf.push(0xBF),
+
+        // Reference instructions
+        Instruction::RefNull(ref_type) => {
+            buf.push(0xD0);
+            ref_type.encode(buf);
+        }
+        Instruction::RefIsNull => buf.push(0xD1),
+        Instruction::RefFunc(func_idx) => {
+            buf.push(0xD2);
+            func_idx.encode(buf);
+        }
+    }
+}
+
+/// Encodes the opcode of an instruction with the `0xFC` prefix
+fn encode_prefixed_opcode(opcode: u32, buf: &mut Vec<u8>) {
+    buf.push(0xFC);
+    opcode.encode(buf);
+}
+
+fn encode_memory_instruction(opcode: u8, memarg: &MemArg, buf: &mut Vec<u8>) {
+    buf.push(opcode);
+    memarg.encode(buf);
+}
+
+impl Encode for Global {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.global_type.encode(buf);
+        self.expression.encode(buf);
+    }
+}
+
+impl Encode for ExportDescriptor {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ExportDescriptor::Func(func_idx) => {
+                buf.push(0x00);
+                func_idx.encode(buf);
+            }
+            ExportDescriptor::Table(table_idx) => {
+                buf.push(0x01);
+                table_idx.encode(buf);
+            }
+            ExportDescriptor::Memory(mem_idx) => {
+                buf.push(0x02);
+                mem_idx.encode(buf);
+            }
+            ExportDescriptor::Global(global_idx) => {
+                buf.push(0x03);
+                global_idx.encode(buf);
+            }
+        }
+    }
+}
+
+impl Encode for Export {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.name.encode(buf);
+        self.descriptor.encode(buf);
+    }
+}
+
+impl Encode for Start {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.func.encode(buf);
+    }
+}
+
+impl Encode for Elem {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        // Use the compact encodings (vectors of function indices) whenever possible
+        let funcs: Option<Vec<&FuncIdx>> = match self.elem_type {
+            RefType::FuncRef => self
+                .init
+                .iter()
+                .map(|expression| match expression.as_slice() {
+                    [Instruction::RefFunc(func_idx)] => Some(func_idx),
+                    _ => None,
+                })
+                .collect(),
+            RefType::ExternRef => None,
+        };
+
+        let flags: u32 = match self.mode {
+            ElemMode::Passive => 1,
+            ElemMode::Active {
+                table: TableIdx(0), ..
+            } if self.elem_type == RefType::FuncRef => 0,
+            ElemMode::Active { .. } => 2,
+            ElemMode::Declarative => 3,
+        };
+
+        let flags = match funcs {
+            Some(_) => flags,
+            None => flags | 0x04,
+        };
+
+        flags.encode(buf);
+
+        if let ElemMode::Active { table, offset } = &self.mode {
+            if flags & 0x02 != 0 {
+                table.encode(buf);
+            }
+            offset.encode(buf);
+        }
+
+        match funcs {
+            Some(funcs) => {
+                if flags & 0x03 != 0 {
+                    buf.push(0x00); // elemkind funcref
+                }
+
+                (funcs.len() as u32).encode(buf);
+                for func_idx in funcs {
+                    func_idx.encode(buf);
+                }
+            }
+            None => {
+                if flags & 0x03 != 0 {
+                    self.elem_type.encode(buf);
+                }
+
+                self.init.encode(buf);
+            }
+        }
+    }
+}
+
+impl Encode for Data {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match &self.mode {
+            DataMode::Active {
+                memory: MemIdx(0),
+                offset,
+            } => {
+                buf.push(0x00);
+                offset.encode(buf);
+            }
+            DataMode::Passive => buf.push(0x01),
+            DataMode::Active { memory, offset } => {
+                buf.push(0x02);
+                memory.encode(buf);
+                offset.encode(buf);
+            }
+        }
+
+        (self.init.len() as u32).encode(buf);
+        buf.extend_from_slice(&self.init);
+    }
+}
+
+impl Encode for Code {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut code = vec![];
+
+        // Compress the locals into runs of equal types
+        let mut locals: Vec<Locals> = vec![];
+        for &t in &self.locals {
+            match locals.last_mut() {
+                Some(last) if last.t == t => last.n += 1,
+                _ => locals.push(Locals { n: 1, t }),
+            }
+        }
+
+        (locals.len() as u32).encode(&mut code);
+        for local in &locals {
+            local.n.encode(&mut code);
+            local.t.encode(&mut code);
+        }
+
+        self.body.encode(&mut code);
+
+        (code.len() as u32).encode(buf);
+        buf.extend_from_slice(&code);
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+}
+
+fn encode_subsection<T: Encode>(id: u8, contents: &T, buf: &mut Vec<u8>) {
+    let mut subsection = vec![];
+    contents.encode(&mut subsection);
+
+    buf.push(id);
+    (subsection.len() as u32).encode(buf);
+    buf.extend_from_slice(&subsection);
+}
+
+/// Encodes the contents of the custom name section
+impl Encode for Names {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        Name("name".to_owned()).encode(buf);
+
+        if let Some(module) = &self.module {
+            encode_subsection(0, module, buf);
+        }
+        if !self.functions.is_empty() {
+            encode_subsection(1, &self.functions, buf);
+        }
+        if !self.locals.is_empty() {
+            encode_subsection(2, &self.locals, buf);
+        }
+    }
+}
+
+fn encode_section<T: Encode>(id: SectionId, contents: &T, buf: &mut Vec<u8>) {
+    let mut section = vec![];
+    contents.encode(&mut section);
+
+    buf.push(match id {
+        SectionId::Custom => 0,
+        SectionId::Type => 1,
+        SectionId::Import => 2,
+        SectionId::Function => 3,
+        SectionId::Table => 4,
+        SectionId::Memory => 5,
+        SectionId::Global => 6,
+        SectionId::Export => 7,
+        SectionId::Start => 8,
+        SectionId::Element => 9,
+        SectionId::Code => 10,
+        SectionId::Data => 11,
+        SectionId::DataCount => 12,
+    });
+
+    (section.len() as u32).encode(buf);
+    buf.extend_from_slice(&section);
+}
+
+fn encode_vec_section<T: Encode>(id: SectionId, contents: &Vec<T>, buf: &mut Vec<u8>) {
+    // Empty sections are left out entirely
+    if !contents.is_empty() {
+        encode_section(id, contents, buf);
+    }
+}
+
+impl Module {
+    /// Serialises the module into the binary format.
+    ///
+    /// Apart from the name section, custom sections are not retained by the parser,
+    /// and hence are not written either.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![];
+
+        self.preamble.encode(&mut buf);
+
+        encode_vec_section(SectionId::Type, &self.types, &mut buf);
+        encode_vec_section(SectionId::Import, &self.imports, &mut buf);
+        encode_vec_section(SectionId::Function, &self.functions, &mut buf);
+        encode_vec_section(SectionId::Table, &self.tables, &mut buf);
+        encode_vec_section(SectionId::Memory, &self.memories, &mut buf);
+        encode_vec_section(SectionId::Global, &self.globals, &mut buf);
+        encode_vec_section(SectionId::Export, &self.exports, &mut buf);
+        if let Some(start) = &self.start {
+            encode_section(SectionId::Start, start, &mut buf);
+        }
+        encode_vec_section(SectionId::Element, &self.elems, &mut buf);
+
+        // The data count section is only needed for passive data segments,
+        // so leave it out otherwise to stay compatible with MVP consumers
+        if self
+            .datas
+            .iter()
+            .any(|data| matches!(data.mode, DataMode::Passive))
+        {
+            encode_section(SectionId::DataCount, &(self.datas.len() as u32), &mut buf);
+        }
+
+        encode_vec_section(SectionId::Code, &self.codes, &mut buf);
+        encode_vec_section(SectionId::Data, &self.datas, &mut buf);
+
+        if !self.names.is_empty() {
+            encode_section(SectionId::Custom, &self.names, &mut buf);
+        }
+
+        buf
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.encode())
+    }
+}
@@ -1,3 +1,7 @@
+pub mod encoder;
 pub mod parser;
+pub mod printer;
+pub mod validate;
 pub mod vm;
 pub mod wasm;
+pub mod wat;
@@ -1,98 +1,60 @@
-use wario::vm::{ExternFunction, Machine};
-use wario::wasm::{
-    BlockType, Code, Func, FuncIdx, FuncType, Instruction, LabelIdx, LocalIdx, MemArg, NumType,
-    ValueType,
-};
+use wario::vm::{Extern, Func, Linker, Store};
+use wario::wat;
+
+const GAME: &str = r#"
+(module
+  (import "env" "display_player" (func $display_player (param i32)))
+  (memory 1)
+
+  ;; fn move_player(position: i32) -> i32 {
+  ;;   if position == 80 {
+  ;;     0
+  ;;   }
+  ;;   else {
+  ;;     position + 1
+  ;;   }
+  ;; }
+  (func $move_player (param i32) (result i32)
+    (block $moved (result i32)
+      (block $wrap
+        (br_if $wrap (i32.eq (local.get 0) (i32.const 80)))
+        (br $moved (i32.add (local.get 0) (i32.const 1))))
+      (i32.const 0)))
+
+  ;; int position = 0;
+  ;; while (true) {
+  ;;   display_player(position);
+  ;;   position = move_player(position);
+  ;; }
+  (func $main (export "main")
+    (i32.store (i32.const 0) (i32.const 0))
+    (loop $forever
+      (call $display_player (i32.load (i32.const 0)))
+      (i32.store (i32.const 0) (call $move_player (i32.load (i32.const 0))))
+      (br $forever))))
+"#;
 
 fn main() {
-    // int i = 0;
-    // while (true) {
-    //   push(i)
-    //   call(print)
-    //   i++;
-    // }
-
-    let code = vec![
-        Instruction::I32Const(0),
-        Instruction::I32Store(MemArg {
-            align: 0,
-            offset: 0,
-        }),
-        Instruction::Loop(
-            BlockType::Empty,
-            vec![
-                Instruction::I32Load(MemArg {
-                    align: 0,
-                    offset: 0,
-                }),
-                Instruction::Call(FuncIdx(1)),
-                Instruction::I32Load(MemArg {
-                    align: 0,
-                    offset: 0,
-                }),
-                Instruction::Call(FuncIdx(0)),
-                Instruction::I32Store(MemArg {
-                    align: 0,
-                    offset: 0,
-                }),
-            ],
-        ),
-    ];
-
-    // fn increment(value: i32) {
-    //   if value == 80 {
-    //     0
-    //   }
-    //   else {
-    //     value + 1
-    //   }
-    // }
-
-    let move_player = Func {
-        ftype: FuncType {
-            parameter_types: vec![ValueType::NumType(NumType::I32)],
-            result_types: vec![ValueType::NumType(NumType::I32)],
-        },
-        code: Code {
-            locals: vec![],
-            body: vec![
-                Instruction::LocalGet(LocalIdx(0)),
-                Instruction::I32Const(80),
-                Instruction::I32Eq,
-                Instruction::Block(
-                    BlockType::Empty,
-                    vec![
-                        Instruction::Block(
-                            BlockType::Empty,
-                            vec![
-                                Instruction::BranchIf(LabelIdx(0)),
-                                Instruction::LocalGet(LocalIdx(0)),
-                                Instruction::I32Const(1),
-                                Instruction::I32Add,
-                                Instruction::Branch(LabelIdx(1)),
-                            ],
-                        ),
-                        Instruction::I32Const(0),
-                    ],
-                ),
-            ],
-        },
-    };
-
-    let display_player = ExternFunction {
-        param_count: 1,
-        fun: Box::new(|args: &[i32]| {
-            println!("{} B-)", " ".repeat(args[0] as usize));
-            None
-        }),
-    };
-
-    let module_functions = vec![move_player];
-    let mut extern_functions = vec![display_player];
-    let mut locals = vec![];
-
-    let mut machine = Machine::new();
-    machine.debugging = false;
-
-    machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+    let module = wat::parse(GAME).unwrap();
+
+    let mut store = Store::new();
+    let display_player = Func::wrap(&mut store, |position: i32| {
+        println!("{} B-)", " ".repeat(position as usize));
+    });
+
+    let mut linker = Linker::new();
+    linker
+        .define(
+            "env",
+            "display_player",
+            Extern::Func(display_player.address()),
+        )
+        .unwrap();
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+
+    let main = instance.get_typed_func::<(), ()>("main").unwrap();
+
+    if let Err(trap) = main.call(&mut store, ()) {
+        eprintln!("{}", trap);
+    }
 }
@@ -1,228 +1,692 @@
-use std::convert::TryInto;
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
+use std::fmt;
+use std::io::{self, Read};
 
 use super::wasm::*;
 
-#[derive(Debug)]
-enum ParseErr {
-    Err(String),
-    Eof,
+/// Describes why a module could not be decoded.
+///
+/// Every variant carries the byte offset (relative to the start of the module) at which
+/// the problem was detected, together with the section that was being decoded at that time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Io {
+        kind: io::ErrorKind,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    UnexpectedEof {
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidMagic {
+        magic: [u8; 4],
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidVersion {
+        version: [u8; 4],
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    UnknownSection {
+        id: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    /// A non-custom section that is duplicated or that is not in the order of the spec
+    SectionOutOfOrder {
+        id: SectionId,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    SectionSizeMismatch {
+        expected: u32,
+        actual: u64,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    CodeSizeMismatch {
+        expected: u32,
+        actual: u64,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    UnknownOpcode {
+        opcode: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    /// An unknown opcode following a prefix byte, such as `0xFC`
+    UnknownPrefixedOpcode {
+        prefix: u8,
+        opcode: u32,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    UnexpectedElse {
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    ZeroByteExpected {
+        byte: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidBlockType {
+        block_type: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidValueType {
+        value_type: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidRefType {
+        value_type: ValueType,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidFuncTypeMarker {
+        marker: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidMutability {
+        mutability: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidLimitsFlag {
+        flag: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidImportDescriptor {
+        id: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidExportDescriptor {
+        id: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidElemKind {
+        elem_kind: u8,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidElemFlags {
+        flags: u32,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidDataFlags {
+        flags: u32,
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    InvalidUtf8 {
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    LebOverflow {
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    LebOutOfRange {
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    /// A function body declares more than `MAX_LOCALS` locals
+    TooManyLocals {
+        offset: u64,
+        section: Option<SectionId>,
+    },
+    /// Blocks are nested more than `MAX_NESTING_DEPTH` levels deep
+    NestingTooDeep {
+        offset: u64,
+        section: Option<SectionId>,
+    },
 }
 
-type ParseResult<T> = std::result::Result<T, ParseErr>;
-pub type Result<T> = std::result::Result<T, String>;
+impl ParseError {
+    /// Byte offset at which the error was detected
+    pub fn offset(&self) -> u64 {
+        match *self {
+            Self::Io { offset, .. }
+            | Self::UnexpectedEof { offset, .. }
+            | Self::InvalidMagic { offset, .. }
+            | Self::InvalidVersion { offset, .. }
+            | Self::UnknownSection { offset, .. }
+            | Self::SectionOutOfOrder { offset, .. }
+            | Self::SectionSizeMismatch { offset, .. }
+            | Self::CodeSizeMismatch { offset, .. }
+            | Self::UnknownOpcode { offset, .. }
+            | Self::UnknownPrefixedOpcode { offset, .. }
+            | Self::UnexpectedElse { offset, .. }
+            | Self::ZeroByteExpected { offset, .. }
+            | Self::InvalidBlockType { offset, .. }
+            | Self::InvalidValueType { offset, .. }
+            | Self::InvalidRefType { offset, .. }
+            | Self::InvalidFuncTypeMarker { offset, .. }
+            | Self::InvalidMutability { offset, .. }
+            | Self::InvalidLimitsFlag { offset, .. }
+            | Self::InvalidImportDescriptor { offset, .. }
+            | Self::InvalidExportDescriptor { offset, .. }
+            | Self::InvalidElemKind { offset, .. }
+            | Self::InvalidElemFlags { offset, .. }
+            | Self::InvalidDataFlags { offset, .. }
+            | Self::InvalidUtf8 { offset, .. }
+            | Self::LebOverflow { offset, .. }
+            | Self::LebOutOfRange { offset, .. }
+            | Self::TooManyLocals { offset, .. }
+            | Self::NestingTooDeep { offset, .. } => offset,
+        }
+    }
+
+    /// Section that was being decoded when the error was detected, if any
+    pub fn section(&self) -> Option<SectionId> {
+        match *self {
+            Self::Io { section, .. }
+            | Self::UnexpectedEof { section, .. }
+            | Self::InvalidMagic { section, .. }
+            | Self::InvalidVersion { section, .. }
+            | Self::UnknownSection { section, .. }
+            | Self::SectionOutOfOrder { section, .. }
+            | Self::SectionSizeMismatch { section, .. }
+            | Self::CodeSizeMismatch { section, .. }
+            | Self::UnknownOpcode { section, .. }
+            | Self::UnknownPrefixedOpcode { section, .. }
+            | Self::UnexpectedElse { section, .. }
+            | Self::ZeroByteExpected { section, .. }
+            | Self::InvalidBlockType { section, .. }
+            | Self::InvalidValueType { section, .. }
+            | Self::InvalidRefType { section, .. }
+            | Self::InvalidFuncTypeMarker { section, .. }
+            | Self::InvalidMutability { section, .. }
+            | Self::InvalidLimitsFlag { section, .. }
+            | Self::InvalidImportDescriptor { section, .. }
+            | Self::InvalidExportDescriptor { section, .. }
+            | Self::InvalidElemKind { section, .. }
+            | Self::InvalidElemFlags { section, .. }
+            | Self::InvalidDataFlags { section, .. }
+            | Self::InvalidUtf8 { section, .. }
+            | Self::LebOverflow { section, .. }
+            | Self::LebOutOfRange { section, .. }
+            | Self::TooManyLocals { section, .. }
+            | Self::NestingTooDeep { section, .. } => section,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io { kind, .. } => write!(f, "Unable to read data: {:?}", kind)?,
+            Self::UnexpectedEof { .. } => write!(f, "Unexpected end of file detected")?,
+            Self::InvalidMagic { magic, .. } => write!(f, "Invalid magic value: {:?}", magic)?,
+            Self::InvalidVersion { version, .. } => write!(f, "Invalid version: {:?}", version)?,
+            Self::UnknownSection { id, .. } => write!(f, "Found unknown section id: {}", id)?,
+            Self::SectionOutOfOrder { id, .. } => {
+                write!(f, "Section is duplicated or out of order: {:?}", id)?
+            }
+            Self::SectionSizeMismatch {
+                expected, actual, ..
+            } => write!(
+                f,
+                "Section size mismatch: expected size: {} actual size: {}",
+                expected, actual
+            )?,
+            Self::CodeSizeMismatch {
+                expected, actual, ..
+            } => write!(
+                f,
+                "Code size mismatch: expected size: {} actual size: {}",
+                expected, actual
+            )?,
+            Self::UnknownOpcode { opcode, .. } => {
+                write!(f, "Unsupported opcode found: {:#04X}", opcode)?
+            }
+            Self::UnknownPrefixedOpcode { prefix, opcode, .. } => {
+                write!(f, "Unsupported opcode found: {:#04X} {}", prefix, opcode)?
+            }
+            Self::UnexpectedElse { .. } => {
+                write!(f, "Unexpected else found outside of an if block")?
+            }
+            Self::ZeroByteExpected { byte, .. } => {
+                write!(f, "Zero byte expected, found: {:#04X}", byte)?
+            }
+            Self::InvalidBlockType { block_type, .. } => {
+                write!(f, "Unsupported blocktype: {:#04X}", block_type)?
+            }
+            Self::InvalidValueType { value_type, .. } => {
+                write!(f, "Invalid value type encountered: {:#04X}", value_type)?
+            }
+            Self::InvalidRefType { value_type, .. } => {
+                write!(f, "Invalid RefType: {:?}", value_type)?
+            }
+            Self::InvalidFuncTypeMarker { marker, .. } => {
+                write!(f, "Invalid marker found for FuncType: {:#04X}", marker)?
+            }
+            Self::InvalidMutability { mutability, .. } => {
+                write!(f, "Invalid mutability: {}", mutability)?
+            }
+            Self::InvalidLimitsFlag { flag, .. } => {
+                write!(f, "Invalid limits flag: {:#04X}", flag)?
+            }
+            Self::InvalidImportDescriptor { id, .. } => {
+                write!(f, "Invalid import descriptor type: {}", id)?
+            }
+            Self::InvalidExportDescriptor { id, .. } => {
+                write!(f, "Invalid export descriptor type: {}", id)?
+            }
+            Self::InvalidElemKind { elem_kind, .. } => {
+                write!(f, "Invalid element kind: {:#04X}", elem_kind)?
+            }
+            Self::InvalidElemFlags { flags, .. } => {
+                write!(f, "Invalid element segment flags: {}", flags)?
+            }
+            Self::InvalidDataFlags { flags, .. } => {
+                write!(f, "Invalid data segment flags: {}", flags)?
+            }
+            Self::InvalidUtf8 { .. } => write!(f, "Invalid UTF8 string")?,
+            Self::LebOverflow { .. } => write!(f, "LEB128 encoded integer is too long")?,
+            Self::LebOutOfRange { .. } => write!(f, "LEB128 encoded integer is too large")?,
+            Self::TooManyLocals { .. } => write!(f, "Too many locals declared")?,
+            Self::NestingTooDeep { .. } => write!(f, "Blocks are nested too deeply")?,
+        }
+
+        write!(f, " (stream pos = {0} ({0:#04X})", self.offset())?;
+        if let Some(section) = self.section() {
+            write!(f, ", section = {:?}", section)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub type Result<T> = std::result::Result<T, ParseError>;
+
+/// Maximum number of locals a single function body may declare
+pub const MAX_LOCALS: u32 = 50_000;
+
+/// Wraps any `Read` source and keeps track of the number of bytes consumed,
+/// such that section and code sizes can be verified without requiring `Seek`.
+/// It also remembers the section being decoded, such that errors can report it.
+struct Reader<R> {
+    inner: R,
+    position: u64,
+    section: Option<SectionId>,
+    /// The last non-custom section, which the next one has to follow
+    last_section: Option<SectionId>,
+}
+
+impl<R: Read> Reader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            position: 0,
+            section: None,
+            last_section: None,
+        }
+    }
+
+    fn skip(&mut self, size: u64) -> Result<()> {
+        let start = self.position;
+        let skipped = match io::copy(&mut self.by_ref().take(size), &mut io::sink()) {
+            Ok(skipped) => skipped,
+            Err(err) => {
+                return Err(ParseError::Io {
+                    kind: err.kind(),
+                    offset: self.position,
+                    section: self.section,
+                })
+            }
+        };
+
+        if skipped != size {
+            return Err(ParseError::UnexpectedEof {
+                offset: start + skipped,
+                section: self.section,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.position += size as u64;
+        Ok(size)
+    }
+}
 
 trait Parse: Sized {
-    fn parse(file: &mut File) -> ParseResult<Self>;
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self>;
 }
 
 impl<T: Parse> Parse for Vec<T> {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let n = u32::parse(file)?;
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let n = u32::parse(reader)?;
 
         let mut result_type = vec![];
         for _ in 0..n {
-            result_type.push(Parse::parse(file)?);
+            result_type.push(Parse::parse(reader)?);
         }
 
         Ok(result_type)
     }
 }
 
+impl<A: Parse, B: Parse> Parse for (A, B) {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok((Parse::parse(reader)?, Parse::parse(reader)?))
+    }
+}
+
 impl<const SIZE: usize> Parse for [u8; SIZE] {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         let mut buf = [0; SIZE];
 
-        match file.read(&mut buf) {
-            Err(err) => Err(ParseErr::Err(format!("Unable to read data: {}", err))),
-            Ok(s) if s == SIZE => Ok(buf),
-            Ok(0) => Err(ParseErr::Eof),
-            Ok(s) => Err(ParseErr::Err(format!(
-                "Unable to read data: expected size to be read: {} actual size read: {}",
-                SIZE, s
-            ))),
+        // A single read() is allowed to return less than requested,
+        // so keep reading until the buffer is full or the source is exhausted
+        let mut s = 0;
+        while s < SIZE {
+            match reader.read(&mut buf[s..]) {
+                Ok(0) => break,
+                Ok(n) => s += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    return Err(ParseError::Io {
+                        kind: err.kind(),
+                        offset: reader.position,
+                        section: reader.section,
+                    })
+                }
+            }
+        }
+
+        if s != SIZE {
+            return Err(ParseError::UnexpectedEof {
+                offset: reader.position,
+                section: reader.section,
+            });
         }
+
+        Ok(buf)
     }
 }
 
 impl Parse for u8 {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(<[u8; 1]>::parse(file)?[0])
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(<[u8; 1]>::parse(reader)?[0])
     }
 }
 
-fn parse_leb128_u32(file: &mut File) -> ParseResult<u32> {
-    let mut result = 0u32;
-
+/// Decodes a LEB128 encoded integer of at most `bits` bits, of which the first byte has
+/// already been read. The result is returned zero extended to 64 bits for unsigned integers
+/// and sign extended to 64 bits for signed integers.
+///
+/// As required by the spec, the encoding may not take more than ceil(bits / 7) bytes, and the
+/// bits of the last byte that exceed `bits` have to be zero (unsigned) or a proper sign extension
+/// (signed). Note that trailing padding bytes within this maximum length are allowed.
+fn parse_leb128<R: Read>(
+    reader: &mut Reader<R>,
+    first: u8,
+    bits: u32,
+    signed: bool,
+) -> Result<u64> {
+    let offset = reader.position - 1;
+    let mut result = 0u64;
+
+    let mut value = first;
     let mut shift = 0;
     loop {
-        let value = u8::parse(file)?;
+        let remaining = bits - shift;
+        if remaining <= 7 {
+            // This is the last byte that is allowed in the encoding
+            if value & 0x80 != 0 {
+                return Err(ParseError::LebOverflow {
+                    offset,
+                    section: reader.section,
+                });
+            }
 
-        result |= (value as u32 & 0x7f) << shift;
+            let in_range = if signed {
+                // The sign bit and all unused bits have to be equal
+                let unused = 0x7f & !((1u8 << (remaining - 1)) - 1);
+                value & unused == 0 || value & unused == unused
+            } else {
+                let unused = 0x7f & !((1u16 << remaining) - 1) as u8;
+                value & unused == 0
+            };
 
-        if value & 0x80 == 0 {
-            break;
+            if !in_range {
+                return Err(ParseError::LebOutOfRange {
+                    offset,
+                    section: reader.section,
+                });
+            }
         }
 
+        result |= (value as u64 & 0x7f) << shift;
         shift += 7;
-    }
-
-    Ok(result)
-}
-
-fn parse_leb128_i32(file: &mut File) -> ParseResult<i32> {
-    let mut result = 0i32;
-
-    let mut value;
-    let mut shift = 0;
-    loop {
-        value = u8::parse(file)?;
-
-        result |= (value as i32 & 0x7f) << shift;
 
         if value & 0x80 == 0 {
             break;
         }
 
-        shift += 7;
+        value = u8::parse(reader)?;
     }
 
-    if value & 0x40 != 0 {
+    if signed && shift < 64 && value & 0x40 != 0 {
         result |= !0 << shift;
     }
 
     Ok(result)
 }
 
+fn parse_leb128_u32<R: Read>(reader: &mut Reader<R>) -> Result<u32> {
+    let first = u8::parse(reader)?;
+    Ok(parse_leb128(reader, first, 32, false)? as u32)
+}
+
+fn parse_leb128_u64<R: Read>(reader: &mut Reader<R>) -> Result<u64> {
+    let first = u8::parse(reader)?;
+    parse_leb128(reader, first, 64, false)
+}
+
+fn parse_leb128_i32<R: Read>(reader: &mut Reader<R>) -> Result<i32> {
+    let first = u8::parse(reader)?;
+    Ok(parse_leb128(reader, first, 32, true)? as i32)
+}
+
+fn parse_leb128_i64<R: Read>(reader: &mut Reader<R>) -> Result<i64> {
+    let first = u8::parse(reader)?;
+    Ok(parse_leb128(reader, first, 64, true)? as i64)
+}
+
+/// Decodes a signed 33 bit integer, of which the first byte has already been read
+fn parse_leb128_s33<R: Read>(reader: &mut Reader<R>, first: u8) -> Result<i64> {
+    Ok(parse_leb128(reader, first, 33, true)? as i64)
+}
+
 impl Parse for u32 {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        parse_leb128_u32(file)
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        parse_leb128_u32(reader)
+    }
+}
+
+impl Parse for u64 {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        parse_leb128_u64(reader)
     }
 }
 
 impl Parse for usize {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(u32::parse(file)? as usize)
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(u32::parse(reader)? as usize)
     }
 }
 
 impl Parse for i32 {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        parse_leb128_i32(file)
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        parse_leb128_i32(reader)
+    }
+}
+
+impl Parse for i64 {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        parse_leb128_i64(reader)
+    }
+}
+
+impl Parse for f32 {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(f32::from_le_bytes(
+            <[u8; std::mem::size_of::<f32>()]>::parse(reader)?,
+        ))
     }
 }
 
 impl Parse for f64 {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         Ok(f64::from_le_bytes(
-            <[u8; std::mem::size_of::<f64>()]>::parse(file)?,
+            <[u8; std::mem::size_of::<f64>()]>::parse(reader)?,
         ))
     }
 }
 
 impl Parse for Preamble {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let magic = <[u8; 4]>::parse(file)?;
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let magic = <[u8; 4]>::parse(reader)?;
         if &magic != b"\0asm" {
-            return Err(ParseErr::Err("Invalid magic value".to_owned()));
+            return Err(ParseError::InvalidMagic {
+                magic,
+                offset: reader.position - 4,
+                section: reader.section,
+            });
         }
 
-        let version = <[u8; 4]>::parse(file)?;
+        let version = <[u8; 4]>::parse(reader)?;
         if version != [1, 0, 0, 0] {
-            return Err(ParseErr::Err("Invalid version".to_owned()));
+            return Err(ParseError::InvalidVersion {
+                version,
+                offset: reader.position - 4,
+                section: reader.section,
+            });
         };
 
-        Ok(Preamble {
-            magic: magic.try_into().unwrap(),
-            version: version.try_into().unwrap(),
-        })
+        Ok(Preamble { magic, version })
+    }
+}
+
+fn value_type_from_u8(value_type: u8) -> Option<ValueType> {
+    match value_type {
+        0x7f => Some(ValueType::NumType(NumType::I32)),
+        0x7e => Some(ValueType::NumType(NumType::I64)),
+        0x7d => Some(ValueType::NumType(NumType::F32)),
+        0x7c => Some(ValueType::NumType(NumType::F64)),
+        0x70 => Some(ValueType::RefType(RefType::FuncRef)),
+        0x6F => Some(ValueType::RefType(RefType::ExternRef)),
+        _ => None,
     }
 }
 
 impl Parse for ValueType {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let value_type = u8::parse(file)?;
-
-        match value_type {
-            0x7f => Ok(Self::NumType(NumType::I32)),
-            0x7e => Ok(Self::NumType(NumType::I64)),
-            0x7d => Ok(Self::NumType(NumType::F32)),
-            0x7c => Ok(Self::NumType(NumType::F64)),
-            0x70 => Ok(Self::RefType(RefType::FuncRef)),
-            0x6F => Ok(Self::RefType(RefType::ExternRef)),
-            _ => Err(ParseErr::Err(format!(
-                "Invalid value type encountered: {}",
-                value_type
-            ))),
-        }
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let value_type = u8::parse(reader)?;
+
+        value_type_from_u8(value_type).ok_or(ParseError::InvalidValueType {
+            value_type,
+            offset: reader.position - 1,
+            section: reader.section,
+        })
     }
 }
 
 impl Parse for FuncType {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let marker = u8::parse(file)?;
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let marker = u8::parse(reader)?;
         if marker != 0x60 {
-            return Err(ParseErr::Err(format!(
-                "Invalid marker found for FuncType: {}",
-                marker
-            )));
+            return Err(ParseError::InvalidFuncTypeMarker {
+                marker,
+                offset: reader.position - 1,
+                section: reader.section,
+            });
         }
 
         Ok(FuncType {
-            parameter_types: Parse::parse(file)?,
-            result_types: Parse::parse(file)?,
+            parameter_types: Parse::parse(reader)?,
+            result_types: Parse::parse(reader)?,
         })
     }
 }
 
 impl Parse for TypeIdx {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(Self(Parse::parse(file)?))
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self(Parse::parse(reader)?))
     }
 }
 
 impl Parse for FuncIdx {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(Self(Parse::parse(file)?))
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self(Parse::parse(reader)?))
     }
 }
 
 impl Parse for TableIdx {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(Self(Parse::parse(file)?))
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self(Parse::parse(reader)?))
     }
 }
 
 impl Parse for MemIdx {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(Self(Parse::parse(file)?))
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self(Parse::parse(reader)?))
     }
 }
 
 impl Parse for GlobalIdx {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(Self(Parse::parse(file)?))
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self(Parse::parse(reader)?))
+    }
+}
+
+impl Parse for ElemIdx {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self(Parse::parse(reader)?))
     }
 }
 
 impl Parse for LocalIdx {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(Self(Parse::parse(file)?))
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self(Parse::parse(reader)?))
     }
 }
 
 impl Parse for LabelIdx {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(Self(Parse::parse(file)?))
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self(Parse::parse(reader)?))
     }
 }
 
 impl Parse for RefType {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let result = match ValueType::parse(file)? {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let result = match ValueType::parse(reader)? {
             ValueType::RefType(value) => value,
-            elem_type => return Err(ParseErr::Err(format!("Invalid RefType: {:?}", elem_type))),
+            value_type => {
+                return Err(ParseError::InvalidRefType {
+                    value_type,
+                    offset: reader.position - 1,
+                    section: reader.section,
+                })
+            }
         };
 
         Ok(result)
@@ -230,13 +694,23 @@ impl Parse for RefType {
 }
 
 impl Parse for Limits {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let has_max = u8::parse(file)? == 1;
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let has_max = match u8::parse(reader)? {
+            0x00 => false,
+            0x01 => true,
+            flag => {
+                return Err(ParseError::InvalidLimitsFlag {
+                    flag,
+                    offset: reader.position - 1,
+                    section: reader.section,
+                })
+            }
+        };
 
         let result = Self {
-            min: u32::parse(file)?,
+            min: u32::parse(reader)?,
             max: if has_max {
-                Some(u32::parse(file)?)
+                Some(u32::parse(reader)?)
             } else {
                 None
             },
@@ -247,10 +721,10 @@ impl Parse for Limits {
 }
 
 impl Parse for TableType {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         let result = Self {
-            elem_type: Parse::parse(file)?,
-            limits: Parse::parse(file)?,
+            elem_type: Parse::parse(reader)?,
+            limits: Parse::parse(reader)?,
         };
 
         Ok(result)
@@ -258,56 +732,69 @@ impl Parse for TableType {
 }
 
 impl Parse for MemType {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         Ok(Self {
-            limits: Parse::parse(file)?,
+            limits: Parse::parse(reader)?,
         })
     }
 }
 
 impl Parse for Mutability {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(match u8::parse(file)? {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(match u8::parse(reader)? {
             0x00 => Self::Constant,
             0x01 => Self::Variable,
-            mutability => return Err(ParseErr::Err(format!("Invalid mutability: {}", mutability))),
+            mutability => {
+                return Err(ParseError::InvalidMutability {
+                    mutability,
+                    offset: reader.position - 1,
+                    section: reader.section,
+                })
+            }
         })
     }
 }
 
 impl Parse for GlobalType {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         Ok(Self {
-            value_type: Parse::parse(file)?,
-            mutability: Parse::parse(file)?,
+            value_type: Parse::parse(reader)?,
+            mutability: Parse::parse(reader)?,
         })
     }
 }
 
 impl Parse for ImportDescriptor {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(match u8::parse(file)? {
-            0x00 => Self::Func(Parse::parse(file)?),
-            0x01 => Self::Table(Parse::parse(file)?),
-            0x02 => Self::Memory(Parse::parse(file)?),
-            0x03 => Self::Global(Parse::parse(file)?),
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(match u8::parse(reader)? {
+            0x00 => Self::Func(Parse::parse(reader)?),
+            0x01 => Self::Table(Parse::parse(reader)?),
+            0x02 => Self::Memory(Parse::parse(reader)?),
+            0x03 => Self::Global(Parse::parse(reader)?),
             id => {
-                return Err(ParseErr::Err(format!(
-                    "Invalid import descriptor type: {}",
-                    id
-                )))
+                return Err(ParseError::InvalidImportDescriptor {
+                    id,
+                    offset: reader.position - 1,
+                    section: reader.section,
+                })
             }
         })
     }
 }
 
 impl Parse for Name {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let result = Parse::parse(file)?;
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let offset = reader.position;
+        let result = Parse::parse(reader)?;
 
         let result = match String::from_utf8(result) {
             Ok(result) => result,
-            Err(err) => return Err(ParseErr::Err(format!("Invalid UTF8 string: {}", err))),
+            Err(_) => {
+                return Err(ParseError::InvalidUtf8 {
+                    offset,
+                    section: reader.section,
+                })
+            }
         };
 
         Ok(Name(result))
@@ -315,218 +802,789 @@ impl Parse for Name {
 }
 
 impl Parse for Import {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         Ok(Self {
-            module: Parse::parse(file)?,
-            name: Parse::parse(file)?,
-            descriptor: Parse::parse(file)?,
+            module: Parse::parse(reader)?,
+            name: Parse::parse(reader)?,
+            descriptor: Parse::parse(reader)?,
         })
     }
 }
 
 impl Parse for BlockType {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let id = u8::parse(file)?;
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        // The block type is encoded as a signed 33 bit integer, where the empty type and
+        // the value types occupy the (single byte) negative numbers, and type indices the
+        // non-negative numbers
+        let offset = reader.position;
+        let id = u8::parse(reader)?;
+
+        let invalid_block_type = ParseError::InvalidBlockType {
+            block_type: id,
+            offset,
+            section: reader.section,
+        };
 
-        Ok(match id {
-            0x40 => BlockType::Empty,
-            _ => panic!("Unsupported blocktype: {}", id),
-        })
+        if id == 0x40 {
+            return Ok(BlockType::Empty);
+        }
+
+        if id & 0xC0 == 0x40 {
+            return match value_type_from_u8(id) {
+                Some(value_type) => Ok(BlockType::Value(value_type)),
+                None => Err(invalid_block_type),
+            };
+        }
+
+        match parse_leb128_s33(reader, id)? {
+            type_idx if type_idx >= 0 => Ok(BlockType::TypeIdx(TypeIdx(type_idx as usize))),
+            _ => Err(invalid_block_type),
+        }
+    }
+}
+
+fn parse_zero_byte<R: Read>(reader: &mut Reader<R>) -> Result<()> {
+    match u8::parse(reader)? {
+        0x00 => Ok(()),
+        byte => Err(ParseError::ZeroByteExpected {
+            byte,
+            offset: reader.position - 1,
+            section: reader.section,
+        }),
     }
 }
 
 impl Parse for MemArg {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         Ok(Self {
-            align: Parse::parse(file)?,
-            offset: Parse::parse(file)?,
+            align: Parse::parse(reader)?,
+            offset: Parse::parse(reader)?,
         })
     }
 }
 
-impl Parse for Vec<Instruction> {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let mut result = vec![];
-
-        loop {
-            let opcode = u8::parse(file)?;
-
-            let instruction = match opcode {
-                0x05 => break, // else
-                0x0B => break, // end
-
-                // Control instructions
-                0x00 => Instruction::Unreachable,
-                0x02 => Instruction::Block(Parse::parse(file)?, Parse::parse(file)?),
-                0x03 => Instruction::Loop(Parse::parse(file)?, Parse::parse(file)?),
-                0x04 => Instruction::If(
-                    Parse::parse(file)?,
-                    Parse::parse(file)?,
-                    Parse::parse(file)?,
-                ),
-                0x0C => Instruction::Branch(Parse::parse(file)?),
-                0x0D => Instruction::BranchIf(Parse::parse(file)?),
-                0x0F => Instruction::Return,
-                0x10 => Instruction::Call(Parse::parse(file)?),
-
-                // Variable instructions
-                0x20 => Instruction::LocalGet(Parse::parse(file)?),
-                0x21 => Instruction::LocalSet(Parse::parse(file)?),
-                0x23 => Instruction::GlobalGet(Parse::parse(file)?),
-                0x24 => Instruction::GlobalSet(Parse::parse(file)?),
-
-                // Memory instructions
-                0x28 => Instruction::I32Load(Parse::parse(file)?),
-                0x36 => Instruction::I32Store(Parse::parse(file)?),
-
-                // Numeric instructions
-                0x41 => Instruction::I32Const(Parse::parse(file)?),
-                0x44 => Instruction::F64Const(Parse::parse(file)?),
-                0x46 => Instruction::I32Eq,
-                0x4A => Instruction::I32GtSigned,
-                0x63 => Instruction::F64Lt,
-                0x64 => Instruction::F64Gt,
-                0x66 => Instruction::F64Ge,
-                0x6A => Instruction::I32Add,
-                0x6B => Instruction::I32Sub,
-                0x6C => Instruction::I32Mul,
-                0xA0 => Instruction::F64Add,
-                0xA1 => Instruction::F64Sub,
-                0xA2 => Instruction::F64Mul,
-                0xA3 => Instruction::F64Div,
-
-                _ => panic!(
-                    "
-                    Unsupported opcode found: {0:#04X} (stream pos = {1} ({1:#04X})).
-                    Decoded instructions so far: {2:?}
-                    ",
-                    opcode,
-                    file.stream_position().unwrap() - 1,
-                    result,
-                ),
-            };
+/// Decodes an instruction with the `0xFC` prefix, which has been read
+fn parse_prefixed_instruction<R: Read>(reader: &mut Reader<R>) -> Result<Instruction> {
+    let offset = reader.position - 1;
 
-            result.push(instruction);
+    Ok(match u32::parse(reader)? {
+        12 => {
+            let elem = Parse::parse(reader)?;
+            Instruction::TableInit(Parse::parse(reader)?, elem)
         }
+        13 => Instruction::ElemDrop(Parse::parse(reader)?),
+        14 => Instruction::TableCopy(Parse::parse(reader)?, Parse::parse(reader)?),
+        15 => Instruction::TableGrow(Parse::parse(reader)?),
+        16 => Instruction::TableSize(Parse::parse(reader)?),
+        17 => Instruction::TableFill(Parse::parse(reader)?),
+        opcode => {
+            return Err(ParseError::UnknownPrefixedOpcode {
+                prefix: 0xFC,
+                opcode,
+                offset,
+                section: reader.section,
+            })
+        }
+    })
+}
 
-        Ok(result)
+/// A block, loop or if of which the instructions are being decoded
+struct OpenBlock {
+    opcode: u8,
+    block_type: BlockType,
+    /// The instructions of the then branch, once the `else` opcode has been found
+    then_code: Option<Vec<Instruction>>,
+    /// The instructions of the enclosing code that precede the block
+    outer: Vec<Instruction>,
+}
+
+/// Decodes instructions up to and including the `end` opcode that terminates an expression.
+///
+/// Nested blocks are kept on a stack of their own rather than decoded recursively, such that
+/// deeply nested input is rejected instead of exhausting the call stack.
+fn parse_instructions<R: Read>(reader: &mut Reader<R>) -> Result<Vec<Instruction>> {
+    let mut blocks: Vec<OpenBlock> = vec![];
+    let mut result = vec![];
+
+    loop {
+        let opcode = u8::parse(reader)?;
+
+        let instruction = match opcode {
+            // block, loop and if
+            0x02..=0x04 => {
+                if blocks.len() == MAX_NESTING_DEPTH {
+                    return Err(ParseError::NestingTooDeep {
+                        offset: reader.position - 1,
+                        section: reader.section,
+                    });
+                }
+
+                blocks.push(OpenBlock {
+                    opcode,
+                    block_type: Parse::parse(reader)?,
+                    then_code: None,
+                    outer: std::mem::take(&mut result),
+                });
+                continue;
+            }
+
+            // else
+            0x05 => match blocks.last_mut() {
+                Some(block) if block.opcode == 0x04 && block.then_code.is_none() => {
+                    block.then_code = Some(std::mem::take(&mut result));
+                    continue;
+                }
+                _ => {
+                    return Err(ParseError::UnexpectedElse {
+                        offset: reader.position - 1,
+                        section: reader.section,
+                    })
+                }
+            },
+
+            // end
+            0x0B => {
+                let block = match blocks.pop() {
+                    Some(block) => block,
+                    None => return Ok(result),
+                };
+
+                let code = std::mem::replace(&mut result, block.outer);
+                match (block.opcode, block.then_code) {
+                    (0x02, _) => Instruction::Block(block.block_type, code),
+                    (0x03, _) => Instruction::Loop(block.block_type, code),
+                    (_, Some(then_code)) => Instruction::If(block.block_type, then_code, code),
+                    (_, None) => Instruction::If(block.block_type, code, vec![]),
+                }
+            }
+
+            // Control instructions
+            0x00 => Instruction::Unreachable,
+            0x01 => Instruction::Nop,
+            0x0C => Instruction::Branch(Parse::parse(reader)?),
+            0x0D => Instruction::BranchIf(Parse::parse(reader)?),
+            0x0E => Instruction::BranchTable(Parse::parse(reader)?, Parse::parse(reader)?),
+            0x0F => Instruction::Return,
+            0x10 => Instruction::Call(Parse::parse(reader)?),
+            0x11 => Instruction::CallIndirect(Parse::parse(reader)?, Parse::parse(reader)?),
+
+            // Parametric instructions
+            0x1A => Instruction::Drop,
+            0x1B => Instruction::Select,
+
+            // Variable instructions
+            0x20 => Instruction::LocalGet(Parse::parse(reader)?),
+            0x21 => Instruction::LocalSet(Parse::parse(reader)?),
+            0x22 => Instruction::LocalTee(Parse::parse(reader)?),
+            0x23 => Instruction::GlobalGet(Parse::parse(reader)?),
+            0x24 => Instruction::GlobalSet(Parse::parse(reader)?),
+
+            // Table instructions
+            0x25 => Instruction::TableGet(Parse::parse(reader)?),
+            0x26 => Instruction::TableSet(Parse::parse(reader)?),
+
+            // Memory instructions
+            0x28 => Instruction::I32Load(Parse::parse(reader)?),
+            0x29 => Instruction::I64Load(Parse::parse(reader)?),
+            0x2A => Instruction::F32Load(Parse::parse(reader)?),
+            0x2B => Instruction::F64Load(Parse::parse(reader)?),
+            0x2C => Instruction::I32Load8Signed(Parse::parse(reader)?),
+            0x2D => Instruction::I32Load8Unsigned(Parse::parse(reader)?),
+            0x2E => Instruction::I32Load16Signed(Parse::parse(reader)?),
+            0x2F => Instruction::I32Load16Unsigned(Parse::parse(reader)?),
+            0x30 => Instruction::I64Load8Signed(Parse::parse(reader)?),
+            0x31 => Instruction::I64Load8Unsigned(Parse::parse(reader)?),
+            0x32 => Instruction::I64Load16Signed(Parse::parse(reader)?),
+            0x33 => Instruction::I64Load16Unsigned(Parse::parse(reader)?),
+            0x34 => Instruction::I64Load32Signed(Parse::parse(reader)?),
+            0x35 => Instruction::I64Load32Unsigned(Parse::parse(reader)?),
+            0x36 => Instruction::I32Store(Parse::parse(reader)?),
+            0x37 => Instruction::I64Store(Parse::parse(reader)?),
+            0x38 => Instruction::F32Store(Parse::parse(reader)?),
+            0x39 => Instruction::F64Store(Parse::parse(reader)?),
+            0x3A => Instruction::I32Store8(Parse::parse(reader)?),
+            0x3B => Instruction::I32Store16(Parse::parse(reader)?),
+            0x3C => Instruction::I64Store8(Parse::parse(reader)?),
+            0x3D => Instruction::I64Store16(Parse::parse(reader)?),
+            0x3E => Instruction::I64Store32(Parse::parse(reader)?),
+            0x3F => {
+                parse_zero_byte(reader)?;
+                Instruction::MemorySize
+            }
+            0x40 => {
+                parse_zero_byte(reader)?;
+                Instruction::MemoryGrow
+            }
+
+            // Numeric instructions
+            0x41 => Instruction::I32Const(Parse::parse(reader)?),
+            0x42 => Instruction::I64Const(Parse::parse(reader)?),
+            0x43 => Instruction::F32Const(Parse::parse(reader)?),
+            0x44 => Instruction::F64Const(Parse::parse(reader)?),
+            0x45 => Instruction::I32Eqz,
+            0x46 => Instruction::I32Eq,
+            0x47 => Instruction::I32Ne,
+            0x48 => Instruction::I32LtSigned,
+            0x49 => Instruction::I32LtUnsigned,
+            0x4A => Instruction::I32GtSigned,
+            0x4B => Instruction::I32GtUnsigned,
+            0x4C => Instruction::I32LeSigned,
+            0x4D => Instruction::I32LeUnsigned,
+            0x4E => Instruction::I32GeSigned,
+            0x4F => Instruction::I32GeUnsigned,
+
+            0x50 => Instruction::I64Eqz,
+            0x51 => Instruction::I64Eq,
+            0x52 => Instruction::I64Ne,
+            0x53 => Instruction::I64LtSigned,
+            0x54 => Instruction::I64LtUnsigned,
+            0x55 => Instruction::I64GtSigned,
+            0x56 => Instruction::I64GtUnsigned,
+            0x57 => Instruction::I64LeSigned,
+            0x58 => Instruction::I64LeUnsigned,
+            0x59 => Instruction::I64GeSigned,
+            0x5A => Instruction::I64GeUnsigned,
+
+            0x5B => Instruction::F32Eq,
+            0x5C => Instruction::F32Ne,
+            0x5D => Instruction::F32Lt,
+            0x5E => Instruction::F32Gt,
+            0x5F => Instruction::F32Le,
+            0x60 => Instruction::F32Ge,
+
+            0x61 => Instruction::F64Eq,
+            0x62 => Instruction::F64Ne,
+            0x63 => Instruction::F64Lt,
+            0x64 => Instruction::F64Gt,
+            0x65 => Instruction::F64Le,
+            0x66 => Instruction::F64Ge,
+
+            0x67 => Instruction::I32Clz,
+            0x68 => Instruction::I32Ctz,
+            0x69 => Instruction::I32Popcnt,
+            0x6A => Instruction::I32Add,
+            0x6B => Instruction::I32Sub,
+            0x6C => Instruction::I32Mul,
+            0x6D => Instruction::I32DivSigned,
+            0x6E => Instruction::I32DivUnsigned,
+            0x6F => Instruction::I32RemSigned,
+            0x70 => Instruction::I32RemUnsigned,
+            0x71 => Instruction::I32And,
+            0x72 => Instruction::I32Or,
+            0x73 => Instruction::I32Xor,
+            0x74 => Instruction::I32Shl,
+            0x75 => Instruction::I32ShrSigned,
+            0x76 => Instruction::I32ShrUnsigned,
+            0x77 => Instruction::I32Rotl,
+            0x78 => Instruction::I32Rotr,
+
+            0x79 => Instruction::I64Clz,
+            0x7A => Instruction::I64Ctz,
+            0x7B => Instruction::I64Popcnt,
+            0x7C => Instruction::I64Add,
+            0x7D => Instruction::I64Sub,
+            0x7E => Instruction::I64Mul,
+            0x7F => Instruction::I64DivSigned,
+            0x80 => Instruction::I64DivUnsigned,
+            0x81 => Instruction::I64RemSigned,
+            0x82 => Instruction::I64RemUnsigned,
+            0x83 => Instruction::I64And,
+            0x84 => Instruction::I64Or,
+            0x85 => Instruction::I64Xor,
+            0x86 => Instruction::I64Shl,
+            0x87 => Instruction::I64ShrSigned,
+            0x88 => Instruction::I64ShrUnsigned,
+            0x89 => Instruction::I64Rotl,
+            0x8A => Instruction::I64Rotr,
+
+            0x8B => Instruction::F32Abs,
+            0x8C => Instruction::F32Neg,
+            0x8D => Instruction::F32Ceil,
+            0x8E => Instruction::F32Floor,
+            0x8F => Instruction::F32Trunc,
+            0x90 => Instruction::F32Nearest,
+            0x91 => Instruction::F32Sqrt,
+            0x92 => Instruction::F32Add,
+            0x93 => Instruction::F32Sub,
+            0x94 => Instruction::F32Mul,
+            0x95 => Instruction::F32Div,
+            0x96 => Instruction::F32Min,
+            0x97 => Instruction::F32Max,
+            0x98 => Instruction::F32Copysign,
+
+            0x99 => Instruction::F64Abs,
+            0x9A => Instruction::F64Neg,
+            0x9B => Instruction::F64Ceil,
+            0x9C => Instruction::F64Floor,
+            0x9D => Instruction::F64Trunc,
+            0x9E => Instruction::F64Nearest,
+            0x9F => Instruction::F64Sqrt,
+            0xA0 => Instruction::F64Add,
+            0xA1 => Instruction::F64Sub,
+            0xA2 => Instruction::F64Mul,
+            0xA3 => Instruction::F64Div,
+            0xA4 => Instruction::F64Min,
+            0xA5 => Instruction::F64Max,
+            0xA6 => Instruction::F64Copysign,
+
+            0xA7 => Instruction::I32WrapI64,
+            0xA8 => Instruction::I32TruncF32Signed,
+            0xA9 => Instruction::I32TruncF32Unsigned,
+            0xAA => Instruction::I32TruncF64Signed,
+            0xAB => Instruction::I32TruncF64Unsigned,
+            0xAC => Instruction::I64ExtendI32Signed,
+            0xAD => Instruction::I64ExtendI32Unsigned,
+            0xAE => Instruction::I64TruncF32Signed,
+            0xAF => Instruction::I64TruncF32Unsigned,
+            0xB0 => Instruction::I64TruncF64Signed,
+            0xB1 => Instruction::I64TruncF64Unsigned,
+            0xB2 => Instruction::F32ConvertI32Signed,
+            0xB3 => Instruction::F32ConvertI32Unsigned,
+            0xB4 => Instruction::F32ConvertI64Signed,
+            0xB5 => Instruction::F32ConvertI64Unsigned,
+            0xB6 => Instruction::F32DemoteF64,
+            0xB7 => Instruction::F64ConvertI32Signed,
+            0xB8 => Instruction::F64ConvertI32Unsigned,
+            0xB9 => Instruction::F64ConvertI64Signed,
+            0xBA => Instruction::F64ConvertI64Unsigned,
+            0xBB => Instruction::F64PromoteF32,
+            0xBC => Instruction::I32ReinterpretF32,
+            0xBD => Instruction::I64ReinterpretF64,
+            0xBE => Instruction::F32ReinterpretI32,
+            0xBF => Instruction::F64ReinterpretI64,
+
+            // Reference instructions
+            0xD0 => Instruction::RefNull(Parse::parse(reader)?),
+            0xD1 => Instruction::RefIsNull,
+            0xD2 => Instruction::RefFunc(Parse::parse(reader)?),
+
+            0xFC => parse_prefixed_instruction(reader)?,
+
+            opcode => {
+                return Err(ParseError::UnknownOpcode {
+                    opcode,
+                    offset: reader.position - 1,
+                    section: reader.section,
+                })
+            }
+        };
+
+        result.push(instruction);
+    }
+}
+
+impl Parse for Vec<Instruction> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        parse_instructions(reader)
     }
 }
 
 impl Parse for Global {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         Ok(Self {
-            global_type: Parse::parse(file)?,
-            expression: Parse::parse(file)?,
+            global_type: Parse::parse(reader)?,
+            expression: Parse::parse(reader)?,
         })
     }
 }
 
 impl Parse for ExportDescriptor {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        Ok(match u8::parse(file)? {
-            0x00 => Self::Func(Parse::parse(file)?),
-            0x01 => Self::Table(Parse::parse(file)?),
-            0x02 => Self::Memory(Parse::parse(file)?),
-            0x03 => Self::Global(Parse::parse(file)?),
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(match u8::parse(reader)? {
+            0x00 => Self::Func(Parse::parse(reader)?),
+            0x01 => Self::Table(Parse::parse(reader)?),
+            0x02 => Self::Memory(Parse::parse(reader)?),
+            0x03 => Self::Global(Parse::parse(reader)?),
             id => {
-                return Err(ParseErr::Err(format!(
-                    "Invalid export descriptor type: {}",
-                    id
-                )))
+                return Err(ParseError::InvalidExportDescriptor {
+                    id,
+                    offset: reader.position - 1,
+                    section: reader.section,
+                })
             }
         })
     }
 }
 
 impl Parse for Export {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(Self {
+            name: Parse::parse(reader)?,
+            descriptor: Parse::parse(reader)?,
+        })
+    }
+}
+
+impl Parse for Start {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         Ok(Self {
-            name: Parse::parse(file)?,
-            descriptor: Parse::parse(file)?,
+            func: Parse::parse(reader)?,
+        })
+    }
+}
+
+/// Parses the elemkind of the legacy element segment encodings, which only allows funcref
+fn parse_elem_kind<R: Read>(reader: &mut Reader<R>) -> Result<RefType> {
+    match u8::parse(reader)? {
+        0x00 => Ok(RefType::FuncRef),
+        elem_kind => Err(ParseError::InvalidElemKind {
+            elem_kind,
+            offset: reader.position - 1,
+            section: reader.section,
+        }),
+    }
+}
+
+/// Parses a vector of function indices, expressed as the equivalent `ref.func` expressions
+fn parse_elem_funcs<R: Read>(reader: &mut Reader<R>) -> Result<Vec<Vec<Instruction>>> {
+    Ok(Vec::<FuncIdx>::parse(reader)?
+        .into_iter()
+        .map(|func| vec![Instruction::RefFunc(func)])
+        .collect())
+}
+
+impl Parse for Elem {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let offset = reader.position;
+        let flags = u32::parse(reader)?;
+
+        let result = match flags {
+            0 => {
+                let offset = Parse::parse(reader)?;
+                Self {
+                    elem_type: RefType::FuncRef,
+                    init: parse_elem_funcs(reader)?,
+                    mode: ElemMode::Active {
+                        table: TableIdx(0),
+                        offset,
+                    },
+                }
+            }
+            1 => Self {
+                elem_type: parse_elem_kind(reader)?,
+                init: parse_elem_funcs(reader)?,
+                mode: ElemMode::Passive,
+            },
+            2 => {
+                let table = Parse::parse(reader)?;
+                let offset = Parse::parse(reader)?;
+                Self {
+                    elem_type: parse_elem_kind(reader)?,
+                    init: parse_elem_funcs(reader)?,
+                    mode: ElemMode::Active { table, offset },
+                }
+            }
+            3 => Self {
+                elem_type: parse_elem_kind(reader)?,
+                init: parse_elem_funcs(reader)?,
+                mode: ElemMode::Declarative,
+            },
+            4 => {
+                let offset = Parse::parse(reader)?;
+                Self {
+                    elem_type: RefType::FuncRef,
+                    init: Parse::parse(reader)?,
+                    mode: ElemMode::Active {
+                        table: TableIdx(0),
+                        offset,
+                    },
+                }
+            }
+            5 => Self {
+                elem_type: Parse::parse(reader)?,
+                init: Parse::parse(reader)?,
+                mode: ElemMode::Passive,
+            },
+            6 => {
+                let table = Parse::parse(reader)?;
+                let offset = Parse::parse(reader)?;
+                Self {
+                    elem_type: Parse::parse(reader)?,
+                    init: Parse::parse(reader)?,
+                    mode: ElemMode::Active { table, offset },
+                }
+            }
+            7 => Self {
+                elem_type: Parse::parse(reader)?,
+                init: Parse::parse(reader)?,
+                mode: ElemMode::Declarative,
+            },
+            flags => {
+                return Err(ParseError::InvalidElemFlags {
+                    flags,
+                    offset,
+                    section: reader.section,
+                })
+            }
+        };
+
+        Ok(result)
+    }
+}
+
+impl Parse for Data {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let offset = reader.position;
+        let flags = u32::parse(reader)?;
+
+        let mode = match flags {
+            0 => DataMode::Active {
+                memory: MemIdx(0),
+                offset: Parse::parse(reader)?,
+            },
+            1 => DataMode::Passive,
+            2 => DataMode::Active {
+                memory: Parse::parse(reader)?,
+                offset: Parse::parse(reader)?,
+            },
+            flags => {
+                return Err(ParseError::InvalidDataFlags {
+                    flags,
+                    offset,
+                    section: reader.section,
+                })
+            }
+        };
+
+        Ok(Self {
+            init: Parse::parse(reader)?,
+            mode,
         })
     }
 }
 
 impl Parse for Locals {
-    fn parse(file: &mut File) -> ParseResult<Self> {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
         Ok(Self {
-            n: Parse::parse(file)?,
-            t: Parse::parse(file)?,
+            n: Parse::parse(reader)?,
+            t: Parse::parse(reader)?,
         })
     }
 }
 
 impl Parse for Code {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let _size = u32::parse(file)?;
-        let start = file.stream_position().unwrap();
-
-        let locals = Vec::<Locals>::parse(file)?
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let size = u32::parse(reader)?;
+        let start = reader.position;
+
+        // The declared counts are only expanded after their total has been checked,
+        // such that a tiny module cannot request an arbitrarily large allocation
+        let declared = Vec::<Locals>::parse(reader)?;
+        let count = declared
             .iter()
-            .flat_map(|local| vec![local.t; local.n as usize])
-            .collect();
+            .try_fold(0u32, |count, local| count.checked_add(local.n))
+            .filter(|&count| count <= MAX_LOCALS)
+            .ok_or(ParseError::TooManyLocals {
+                offset: start,
+                section: reader.section,
+            })?;
+
+        let mut locals = Vec::with_capacity(count as usize);
+        for local in declared {
+            locals.extend(std::iter::repeat_n(local.t, local.n as usize));
+        }
 
-        let body = Parse::parse(file)?;
+        let body = Parse::parse(reader)?;
 
-        let stop = file.stream_position().unwrap();
-        assert_eq!(_size, (stop - start) as u32);
+        let stop = reader.position;
+        if size as u64 != stop - start {
+            return Err(ParseError::CodeSizeMismatch {
+                expected: size,
+                actual: stop - start,
+                offset: start,
+                section: reader.section,
+            });
+        }
 
         Ok(Self { locals, body })
     }
 }
 
+impl Parse for SectionId {
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        Ok(match u8::parse(reader)? {
+            0 => SectionId::Custom,
+            1 => SectionId::Type,
+            2 => SectionId::Import,
+            3 => SectionId::Function,
+            4 => SectionId::Table,
+            5 => SectionId::Memory,
+            6 => SectionId::Global,
+            7 => SectionId::Export,
+            8 => SectionId::Start,
+            9 => SectionId::Element,
+            10 => SectionId::Code,
+            11 => SectionId::Data,
+            12 => SectionId::DataCount,
+            id => {
+                return Err(ParseError::UnknownSection {
+                    id,
+                    offset: reader.position - 1,
+                    section: reader.section,
+                })
+            }
+        })
+    }
+}
+
+/// Parses the subsections of the name section
+fn parse_names(bytes: &[u8]) -> Result<Names> {
+    let reader = &mut Reader::new(bytes);
+    let mut names = Names::default();
+
+    while reader.position < bytes.len() as u64 {
+        let id = u8::parse(reader)?;
+        let size = u32::parse(reader)?;
+        let start = reader.position;
+
+        match id {
+            0 => names.module = Some(Parse::parse(reader)?),
+            1 => names.functions = Parse::parse(reader)?,
+            2 => names.locals = Parse::parse(reader)?,
+            _ => reader.skip(size as u64)?,
+        }
+
+        let stop = reader.position;
+        if size as u64 != stop - start {
+            return Err(ParseError::SectionSizeMismatch {
+                expected: size,
+                actual: stop - start,
+                offset: start,
+                section: reader.section,
+            });
+        }
+    }
+
+    Ok(names)
+}
+
+/// Parses a custom section, of which only the name section is interpreted
+fn parse_custom_section<R: Read>(reader: &mut Reader<R>, size: u32) -> Result<Section> {
+    let start = reader.position;
+    let name = Name::parse(reader)?;
+
+    // A name that exceeds the section is reported as size mismatch by the caller
+    let remaining = match (size as u64).checked_sub(reader.position - start) {
+        Some(remaining) => remaining,
+        None => return Ok(Section::Custom),
+    };
+
+    if name.0 != "name" {
+        reader.skip(remaining)?;
+        return Ok(Section::Custom);
+    }
+
+    let mut contents = vec![];
+    let offset = reader.position;
+    if let Err(err) = reader.by_ref().take(remaining).read_to_end(&mut contents) {
+        return Err(ParseError::Io {
+            kind: err.kind(),
+            offset: reader.position,
+            section: reader.section,
+        });
+    }
+
+    if (contents.len() as u64) < remaining {
+        return Err(ParseError::UnexpectedEof {
+            offset: offset + contents.len() as u64,
+            section: reader.section,
+        });
+    }
+
+    // A malformed name section does not invalidate the module, it is just ignored
+    Ok(match parse_names(&contents) {
+        Ok(names) => Section::Name(names),
+        Err(_) => Section::Custom,
+    })
+}
+
+/// The position of a non-custom section in a module, which differs from its id
+/// for the data count section
+fn section_order(id: SectionId) -> u8 {
+    match id {
+        SectionId::Custom => 0,
+        SectionId::Type => 1,
+        SectionId::Import => 2,
+        SectionId::Function => 3,
+        SectionId::Table => 4,
+        SectionId::Memory => 5,
+        SectionId::Global => 6,
+        SectionId::Export => 7,
+        SectionId::Start => 8,
+        SectionId::Element => 9,
+        SectionId::DataCount => 10,
+        SectionId::Code => 11,
+        SectionId::Data => 12,
+    }
+}
+
 impl Parse for Section {
-    fn parse(file: &mut File) -> ParseResult<Self> {
-        let id = u8::parse(file)?;
-        let size = u32::parse(file)?;
-        let start = file.stream_position().unwrap();
+    fn parse<R: Read>(reader: &mut Reader<R>) -> Result<Self> {
+        let id = SectionId::parse(reader)?;
+
+        // Custom sections may appear anywhere, all others at most once and in order
+        if id != SectionId::Custom {
+            if let Some(last) = reader.last_section {
+                if section_order(id) <= section_order(last) {
+                    return Err(ParseError::SectionOutOfOrder {
+                        id,
+                        offset: reader.position - 1,
+                        section: reader.section,
+                    });
+                }
+            }
+            reader.last_section = Some(id);
+        }
+
+        reader.section = Some(id);
+
+        let size = u32::parse(reader)?;
+        let start = reader.position;
 
         let section = match id {
-            00 => Section::Custom,
-            01 => Section::Type(Parse::parse(file)?),
-            02 => Section::Import(Parse::parse(file)?),
-            03 => Section::Function(Parse::parse(file)?),
-            04 => Section::Table,
-            05 => Section::Memory(Parse::parse(file)?),
-            06 => Section::Global(Parse::parse(file)?),
-            07 => Section::Export(Parse::parse(file)?),
-            08 => Section::Start,
-            09 => Section::Element,
-            10 => Section::Code(Parse::parse(file)?),
-            11 => Section::Data,
-            _ => return Err(ParseErr::Err(format!("Found unknown section id: {}", id))),
+            SectionId::Custom => parse_custom_section(reader, size)?,
+            SectionId::Type => Section::Type(Parse::parse(reader)?),
+            SectionId::Import => Section::Import(Parse::parse(reader)?),
+            SectionId::Function => Section::Function(Parse::parse(reader)?),
+            SectionId::Table => Section::Table(Parse::parse(reader)?),
+            SectionId::Memory => Section::Memory(Parse::parse(reader)?),
+            SectionId::Global => Section::Global(Parse::parse(reader)?),
+            SectionId::Export => Section::Export(Parse::parse(reader)?),
+            SectionId::Start => Section::Start(Parse::parse(reader)?),
+            SectionId::Element => Section::Element(Parse::parse(reader)?),
+            SectionId::Code => Section::Code(Parse::parse(reader)?),
+            SectionId::Data => Section::Data(Parse::parse(reader)?),
+            SectionId::DataCount => Section::DataCount(Parse::parse(reader)?),
         };
 
-        match section {
-            Section::Type(_) => {}
-            Section::Import(_) => {}
-            Section::Function(_) => {}
-            Section::Memory(_) => {}
-            Section::Global(_) => {}
-            Section::Export(_) => {}
-            Section::Code(_) => {}
-            _ => {
-                file.seek(SeekFrom::Current(size as i64)).unwrap();
-            }
+        let stop = reader.position;
+        if size as u64 != stop - start {
+            return Err(ParseError::SectionSizeMismatch {
+                expected: size,
+                actual: stop - start,
+                offset: start,
+                section: reader.section,
+            });
         }
 
-        let stop = file.stream_position().unwrap();
-
-        assert_eq!(size, (stop - start) as u32);
+        reader.section = None;
 
         Ok(section)
     }
 }
 
-fn parse_sections(file: &mut File) -> Result<Vec<Section>> {
+fn parse_sections<R: Read>(reader: &mut Reader<R>) -> Result<Vec<Section>> {
     let mut sections = Vec::new();
 
     loop {
-        match Section::parse(file) {
+        let start = reader.position;
+
+        match Section::parse(reader) {
             Ok(section) => sections.push(section),
-            Err(ParseErr::Eof) => break,
-            Err(ParseErr::Err(err)) => return Err(err),
+
+            // Running out of data is only fine in between sections
+            Err(ParseError::UnexpectedEof { offset, .. }) if offset == start => break,
+            Err(err) => return Err(err),
         }
     }
 
@@ -534,37 +1592,39 @@ fn parse_sections(file: &mut File) -> Result<Vec<Section>> {
 }
 
 impl Module {
-    pub fn parse(file: &mut File) -> Result<Module> {
-        let preamble = match Parse::parse(file) {
-            Ok(x) => x,
-            Err(ParseErr::Err(err)) => return Err(err),
-            Err(ParseErr::Eof) => return Err("Unexpected end of file detected".to_owned()),
-        };
+    pub fn parse<R: Read>(reader: R) -> Result<Module> {
+        let mut reader = Reader::new(reader);
+        let reader = &mut reader;
+
+        let preamble = Parse::parse(reader)?;
 
         let mut module = Module {
             preamble,
-            types: vec![],
-            imports: vec![],
-            functions: vec![],
-            memories: vec![],
-            globals: vec![],
-            exports: vec![],
-            codes: vec![],
+            ..Default::default()
         };
 
-        for section in parse_sections(file)? {
+        for section in parse_sections(reader)? {
             match section {
                 Section::Type(types) => module.types = types,
                 Section::Import(imports) => module.imports = imports,
                 Section::Function(functions) => module.functions = functions,
+                Section::Table(tables) => module.tables = tables,
                 Section::Memory(memories) => module.memories = memories,
                 Section::Global(globals) => module.globals = globals,
                 Section::Export(exports) => module.exports = exports,
+                Section::Start(start) => module.start = Some(start),
+                Section::Element(elems) => module.elems = elems,
                 Section::Code(codes) => module.codes = codes,
-                section => println!("Section {:?} not implemented yet, skipping", section),
+                Section::Data(datas) => module.datas = datas,
+                Section::Name(names) => module.names = names,
+                Section::Custom | Section::DataCount(_) => {}
             }
         }
 
         Ok(module)
     }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Module> {
+        Module::parse(bytes)
+    }
 }
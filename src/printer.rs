@@ -0,0 +1,859 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+
+use super::wasm::*;
+
+const INDENT: &str = "  ";
+
+fn is_idchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c)
+}
+
+/// Turns the debug names into symbolic identifiers, skipping names that are not valid
+/// identifiers or that are not unique
+fn identifiers<'a, I: Iterator<Item = (usize, &'a Name)>>(names: I) -> HashMap<usize, String> {
+    let mut seen = HashSet::new();
+
+    names
+        .filter(|(_, name)| !name.0.is_empty() && name.0.chars().all(is_idchar))
+        .filter(|(_, name)| seen.insert(&name.0))
+        .map(|(index, name)| (index, format!("${}", name.0)))
+        .collect()
+}
+
+fn value_type(value_type: &ValueType) -> &'static str {
+    match value_type {
+        ValueType::NumType(NumType::I32) => "i32",
+        ValueType::NumType(NumType::I64) => "i64",
+        ValueType::NumType(NumType::F32) => "f32",
+        ValueType::NumType(NumType::F64) => "f64",
+        ValueType::RefType(RefType::FuncRef) => "funcref",
+        ValueType::RefType(RefType::ExternRef) => "externref",
+    }
+}
+
+fn ref_type(ref_type: &RefType) -> &'static str {
+    value_type(&ValueType::RefType(*ref_type))
+}
+
+/// Instructions without any immediates
+fn plain_instruction(instruction: &Instruction) -> Option<&'static str> {
+    Some(match instruction {
+        Instruction::Unreachable => "unreachable",
+        Instruction::Nop => "nop",
+        Instruction::Return => "return",
+        Instruction::Drop => "drop",
+        Instruction::Select => "select",
+        Instruction::MemorySize => "memory.size",
+        Instruction::MemoryGrow => "memory.grow",
+        Instruction::RefIsNull => "ref.is_null",
+
+        Instruction::I32Eqz => "i32.eqz",
+        Instruction::I32Eq => "i32.eq",
+        Instruction::I32Ne => "i32.ne",
+        Instruction::I32LtSigned => "i32.lt_s",
+        Instruction::I32LtUnsigned => "i32.lt_u",
+        Instruction::I32GtSigned => "i32.gt_s",
+        Instruction::I32GtUnsigned => "i32.gt_u",
+        Instruction::I32LeSigned => "i32.le_s",
+        Instruction::I32LeUnsigned => "i32.le_u",
+        Instruction::I32GeSigned => "i32.ge_s",
+        Instruction::I32GeUnsigned => "i32.ge_u",
+
+        Instruction::I64Eqz => "i64.eqz",
+        Instruction::I64Eq => "i64.eq",
+        Instruction::I64Ne => "i64.ne",
+        Instruction::I64LtSigned => "i64.lt_s",
+        Instruction::I64LtUnsigned => "i64.lt_u",
+        Instruction::I64GtSigned => "i64.gt_s",
+        Instruction::I64GtUnsigned => "i64.gt_u",
+        Instruction::I64LeSigned => "i64.le_s",
+        Instruction::I64LeUnsigned => "i64.le_u",
+        Instruction::I64GeSigned => "i64.ge_s",
+        Instruction::I64GeUnsigned => "i64.ge_u",
+
+        Instruction::F32Eq => "f32.eq",
+        Instruction::F32Ne => "f32.ne",
+        Instruction::F32Lt => "f32.lt",
+        Instruction::F32Gt => "f32.gt",
+        Instruction::F32Le => "f32.le",
+        Instruction::F32Ge => "f32.ge",
+
+        Instruction::F64Eq => "f64.eq",
+        Instruction::F64Ne => "f64.ne",
+        Instruction::F64Lt => "f64.lt",
+        Instruction::F64Gt => "f64.gt",
+        Instruction::F64Le => "f64.le",
+        Instruction::F64Ge => "f64.ge",
+
+        Instruction::I32Clz => "i32.clz",
+        Instruction::I32Ctz => "i32.ctz",
+        Instruction::I32Popcnt => "i32.popcnt",
+        Instruction::I32Add => "i32.add",
+        Instruction::I32Sub => "i32.sub",
+        Instruction::I32Mul => "i32.mul",
+        Instruction::I32DivSigned => "i32.div_s",
+        Instruction::I32DivUnsigned => "i32.div_u",
+        Instruction::I32RemSigned => "i32.rem_s",
+        Instruction::I32RemUnsigned => "i32.rem_u",
+        Instruction::I32And => "i32.and",
+        Instruction::I32Or => "i32.or",
+        Instruction::I32Xor => "i32.xor",
+        Instruction::I32Shl => "i32.shl",
+        Instruction::I32ShrSigned => "i32.shr_s",
+        Instruction::I32ShrUnsigned => "i32.shr_u",
+        Instruction::I32Rotl => "i32.rotl",
+        Instruction::I32Rotr => "i32.rotr",
+
+        Instruction::I64Clz => "i64.clz",
+        Instruction::I64Ctz => "i64.ctz",
+        Instruction::I64Popcnt => "i64.popcnt",
+        Instruction::I64Add => "i64.add",
+        Instruction::I64Sub => "i64.sub",
+        Instruction::I64Mul => "i64.mul",
+        Instruction::I64DivSigned => "i64.div_s",
+        Instruction::I64DivUnsigned => "i64.div_u",
+        Instruction::I64RemSigned => "i64.rem_s",
+        Instruction::I64RemUnsigned => "i64.rem_u",
+        Instruction::I64And => "i64.and",
+        Instruction::I64Or => "i64.or",
+        Instruction::I64Xor => "i64.xor",
+        Instruction::I64Shl => "i64.shl",
+        Instruction::I64ShrSigned => "i64.shr_s",
+        Instruction::I64ShrUnsigned => "i64.shr_u",
+        Instruction::I64Rotl => "i64.rotl",
+        Instruction::I64Rotr => "i64.rotr",
+
+        Instruction::F32Abs => "f32.abs",
+        Instruction::F32Neg => "f32.neg",
+        Instruction::F32Ceil => "f32.ceil",
+        Instruction::F32Floor => "f32.floor",
+        Instruction::F32Trunc => "f32.trunc",
+        Instruction::F32Nearest => "f32.nearest",
+        Instruction::F32Sqrt => "f32.sqrt",
+        Instruction::F32Add => "f32.add",
+        Instruction::F32Sub => "f32.sub",
+        Instruction::F32Mul => "f32.mul",
+        Instruction::F32Div => "f32.div",
+        Instruction::F32Min => "f32.min",
+        Instruction::F32Max => "f32.max",
+        Instruction::F32Copysign => "f32.copysign",
+
+        Instruction::F64Abs => "f64.abs",
+        Instruction::F64Neg => "f64.neg",
+        Instruction::F64Ceil => "f64.ceil",
+        Instruction::F64Floor => "f64.floor",
+        Instruction::F64Trunc => "f64.trunc",
+        Instruction::F64Nearest => "f64.nearest",
+        Instruction::F64Sqrt => "f64.sqrt",
+        Instruction::F64Add => "f64.add",
+        Instruction::F64Sub => "f64.sub",
+        Instruction::F64Mul => "f64.mul",
+        Instruction::F64Div => "f64.div",
+        Instruction::F64Min => "f64.min",
+        Instruction::F64Max => "f64.max",
+        Instruction::F64Copysign => "f64.copysign",
+
+        Instruction::I32WrapI64 => "i32.wrap_i64",
+        Instruction::I32TruncF32Signed => "i32.trunc_f32_s",
+        Instruction::I32TruncF32Unsigned => "i32.trunc_f32_u",
+        Instruction::I32TruncF64Signed => "i32.trunc_f64_s",
+        Instruction::I32TruncF64Unsigned => "i32.trunc_f64_u",
+        Instruction::I64ExtendI32Signed => "i64.extend_i32_s",
+        Instruction::I64ExtendI32Unsigned => "i64.extend_i32_u",
+        Instruction::I64TruncF32Signed => "i64.trunc_f32_s",
+        Instruction::I64TruncF32Unsigned => "i64.trunc_f32_u",
+        Instruction::I64TruncF64Signed => "i64.trunc_f64_s",
+        Instruction::I64TruncF64Unsigned => "i64.trunc_f64_u",
+        Instruction::F32ConvertI32Signed => "f32.convert_i32_s",
+        Instruction::F32ConvertI32Unsigned => "f32.convert_i32_u",
+        Instruction::F32ConvertI64Signed => "f32.convert_i64_s",
+        Instruction::F32ConvertI64Unsigned => "f32.convert_i64_u",
+        Instruction::F32DemoteF64 => "f32.demote_f64",
+        Instruction::F64ConvertI32Signed => "f64.convert_i32_s",
+        Instruction::F64ConvertI32Unsigned => "f64.convert_i32_u",
+        Instruction::F64ConvertI64Signed => "f64.convert_i64_s",
+        Instruction::F64ConvertI64Unsigned => "f64.convert_i64_u",
+        Instruction::F64PromoteF32 => "f64.promote_f32",
+        Instruction::I32ReinterpretF32 => "i32.reinterpret_f32",
+        Instruction::I64ReinterpretF64 => "i64.reinterpret_f64",
+        Instruction::F32ReinterpretI32 => "f32.reinterpret_i32",
+        Instruction::F64ReinterpretI64 => "f64.reinterpret_i64",
+
+        _ => return None,
+    })
+}
+
+/// Memory instructions, together with their memarg and natural alignment (as exponent of 2)
+fn memory_instruction(instruction: &Instruction) -> Option<(&'static str, &MemArg, usize)> {
+    Some(match instruction {
+        Instruction::I32Load(memarg) => ("i32.load", memarg, 2),
+        Instruction::I64Load(memarg) => ("i64.load", memarg, 3),
+        Instruction::F32Load(memarg) => ("f32.load", memarg, 2),
+        Instruction::F64Load(memarg) => ("f64.load", memarg, 3),
+        Instruction::I32Load8Signed(memarg) => ("i32.load8_s", memarg, 0),
+        Instruction::I32Load8Unsigned(memarg) => ("i32.load8_u", memarg, 0),
+        Instruction::I32Load16Signed(memarg) => ("i32.load16_s", memarg, 1),
+        Instruction::I32Load16Unsigned(memarg) => ("i32.load16_u", memarg, 1),
+        Instruction::I64Load8Signed(memarg) => ("i64.load8_s", memarg, 0),
+        Instruction::I64Load8Unsigned(memarg) => ("i64.load8_u", memarg, 0),
+        Instruction::I64Load16Signed(memarg) => ("i64.load16_s", memarg, 1),
+        Instruction::I64Load16Unsigned(memarg) => ("i64.load16_u", memarg, 1),
+        Instruction::I64Load32Signed(memarg) => ("i64.load32_s", memarg, 2),
+        Instruction::I64Load32Unsigned(memarg) => ("i64.load32_u", memarg, 2),
+        Instruction::I32Store(memarg) => ("i32.store", memarg, 2),
+        Instruction::I64Store(memarg) => ("i64.store", memarg, 3),
+        Instruction::F32Store(memarg) => ("f32.store", memarg, 2),
+        Instruction::F64Store(memarg) => ("f64.store", memarg, 3),
+        Instruction::I32Store8(memarg) => ("i32.store8", memarg, 0),
+        Instruction::I32Store16(memarg) => ("i32.store16", memarg, 1),
+        Instruction::I64Store8(memarg) => ("i64.store8", memarg, 0),
+        Instruction::I64Store16(memarg) => ("i64.store16", memarg, 1),
+        Instruction::I64Store32(memarg) => ("i64.store32", memarg, 2),
+        _ => return None,
+    })
+}
+
+/// Writes the special float values (NaN and infinity), which don't have a decimal notation
+fn write_special_float<W: Write>(
+    w: &mut W,
+    negative: bool,
+    mantissa: u64,
+    mantissa_bits: u32,
+) -> fmt::Result {
+    let sign = if negative { "-" } else { "" };
+
+    match mantissa {
+        0 => write!(w, "{}inf", sign),
+        m if m == 1 << (mantissa_bits - 1) => write!(w, "{}nan", sign),
+        m => write!(w, "{}nan:0x{:x}", sign, m),
+    }
+}
+
+/// Writes the float such that it parses back into the exact same bits
+fn write_f32<W: Write>(w: &mut W, value: f32) -> fmt::Result {
+    if value.is_finite() {
+        return write!(w, "{:?}", value);
+    }
+
+    let mantissa = value.to_bits() & 0x7F_FFFF;
+    write_special_float(w, value.is_sign_negative(), mantissa as u64, 23)
+}
+
+/// Writes the float such that it parses back into the exact same bits
+fn write_f64<W: Write>(w: &mut W, value: f64) -> fmt::Result {
+    if value.is_finite() {
+        return write!(w, "{:?}", value);
+    }
+
+    let mantissa = value.to_bits() & 0xF_FFFF_FFFF_FFFF;
+    write_special_float(w, value.is_sign_negative(), mantissa, 52)
+}
+
+fn write_string<W: Write>(w: &mut W, bytes: &[u8]) -> fmt::Result {
+    w.write_char('"')?;
+
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(w, "\\{}", byte as char)?,
+            0x20..=0x7E => w.write_char(byte as char)?,
+            _ => write!(w, "\\{:02x}", byte)?,
+        }
+    }
+
+    w.write_char('"')
+}
+
+fn write_limits<W: Write>(w: &mut W, limits: &Limits) -> fmt::Result {
+    write!(w, "{}", limits.min)?;
+
+    match limits.max {
+        Some(max) => write!(w, " {}", max),
+        None => Ok(()),
+    }
+}
+
+fn write_global_type<W: Write>(w: &mut W, global_type: &GlobalType) -> fmt::Result {
+    match global_type.mutability {
+        Mutability::Constant => write!(w, "{}", value_type(&global_type.value_type)),
+        Mutability::Variable => write!(w, "(mut {})", value_type(&global_type.value_type)),
+    }
+}
+
+fn write_func_type<W: Write>(w: &mut W, ftype: &FuncType) -> fmt::Result {
+    if !ftype.parameter_types.is_empty() {
+        write!(w, " (param")?;
+        for t in &ftype.parameter_types {
+            write!(w, " {}", value_type(t))?;
+        }
+        write!(w, ")")?;
+    }
+
+    if !ftype.result_types.is_empty() {
+        write!(w, " (result")?;
+        for t in &ftype.result_types {
+            write!(w, " {}", value_type(t))?;
+        }
+        write!(w, ")")?;
+    }
+
+    Ok(())
+}
+
+struct Printer<'a, W> {
+    w: W,
+    module: &'a Module,
+    funcs: HashMap<usize, String>,
+    locals: HashMap<usize, HashMap<usize, String>>,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    fn new(w: W, module: &'a Module) -> Self {
+        let names = &module.names;
+
+        let funcs = identifiers(names.functions.iter().map(|(idx, name)| (idx.0, name)));
+        let locals = names
+            .locals
+            .iter()
+            .map(|(idx, locals)| {
+                let locals = identifiers(locals.iter().map(|(idx, name)| (idx.0, name)));
+                (idx.0, locals)
+            })
+            .collect();
+
+        Self {
+            w,
+            module,
+            funcs,
+            locals,
+        }
+    }
+
+    fn newline(&mut self, depth: usize) -> fmt::Result {
+        writeln!(self.w)?;
+        for _ in 0..depth {
+            self.w.write_str(INDENT)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_func_idx(&mut self, func: &FuncIdx) -> fmt::Result {
+        match self.funcs.get(&func.0) {
+            Some(id) => write!(self.w, "{}", id),
+            None => write!(self.w, "{}", func.0),
+        }
+    }
+
+    /// Writes the symbolic identifier of the function (if any), followed by its index
+    fn write_func_id(&mut self, func: usize) -> fmt::Result {
+        if let Some(id) = self.funcs.get(&func) {
+            write!(self.w, " {}", id)?;
+        }
+
+        write!(self.w, " (;{};)", func)
+    }
+
+    fn write_block_type(&mut self, block_type: &BlockType) -> fmt::Result {
+        match block_type {
+            BlockType::Empty => Ok(()),
+            BlockType::Value(t) => write!(self.w, " (result {})", value_type(t)),
+            BlockType::TypeIdx(type_idx) => write!(self.w, " (type {})", type_idx.0),
+        }
+    }
+
+    /// Writes a non-block instruction with its immediates, where `func` is the function
+    /// the instruction belongs to (used for naming its locals)
+    fn write_instruction(&mut self, instruction: &Instruction, func: Option<usize>) -> fmt::Result {
+        if let Some(keyword) = plain_instruction(instruction) {
+            return self.w.write_str(keyword);
+        }
+
+        if let Some((keyword, memarg, natural_alignment)) = memory_instruction(instruction) {
+            self.w.write_str(keyword)?;
+            if memarg.offset != 0 {
+                write!(self.w, " offset={}", memarg.offset)?;
+            }
+            if memarg.align != natural_alignment {
+                write!(self.w, " align={}", 1u64 << memarg.align)?;
+            }
+            return Ok(());
+        }
+
+        match instruction {
+            Instruction::Branch(label) => write!(self.w, "br {}", label.0),
+            Instruction::BranchIf(label) => write!(self.w, "br_if {}", label.0),
+            Instruction::BranchTable(labels, default) => {
+                write!(self.w, "br_table")?;
+                for label in labels {
+                    write!(self.w, " {}", label.0)?;
+                }
+                write!(self.w, " {}", default.0)
+            }
+            Instruction::Call(func) => {
+                write!(self.w, "call ")?;
+                self.write_func_idx(func)
+            }
+            Instruction::CallIndirect(type_idx, table_idx) => {
+                write!(self.w, "call_indirect")?;
+                if table_idx.0 != 0 {
+                    write!(self.w, " {}", table_idx.0)?;
+                }
+                write!(self.w, " (type {})", type_idx.0)
+            }
+            Instruction::LocalGet(local)
+            | Instruction::LocalSet(local)
+            | Instruction::LocalTee(local) => {
+                let keyword = match instruction {
+                    Instruction::LocalGet(_) => "local.get",
+                    Instruction::LocalSet(_) => "local.set",
+                    _ => "local.tee",
+                };
+
+                let id = func
+                    .and_then(|func| self.locals.get(&func))
+                    .and_then(|locals| locals.get(&local.0))
+                    .cloned();
+
+                match id {
+                    Some(id) => write!(self.w, "{} {}", keyword, id),
+                    None => write!(self.w, "{} {}", keyword, local.0),
+                }
+            }
+            Instruction::GlobalGet(global) => write!(self.w, "global.get {}", global.0),
+            Instruction::GlobalSet(global) => write!(self.w, "global.set {}", global.0),
+            Instruction::TableGet(table) => write!(self.w, "table.get {}", table.0),
+            Instruction::TableSet(table) => write!(self.w, "table.set {}", table.0),
+            Instruction::TableSize(table) => write!(self.w, "table.size {}", table.0),
+            Instruction::TableGrow(table) => write!(self.w, "table.grow {}", table.0),
+            Instruction::TableFill(table) => write!(self.w, "table.fill {}", table.0),
+            Instruction::TableCopy(destination, source) => {
+                write!(self.w, "table.copy {} {}", destination.0, source.0)
+            }
+            Instruction::TableInit(table, elem) => {
+                write!(self.w, "table.init {} {}", table.0, elem.0)
+            }
+            Instruction::ElemDrop(elem) => write!(self.w, "elem.drop {}", elem.0),
+            Instruction::I32Const(value) => write!(self.w, "i32.const {}", value),
+            Instruction::I64Const(value) => write!(self.w, "i64.const {}", value),
+            Instruction::F32Const(value) => {
+                write!(self.w, "f32.const ")?;
+                write_f32(&mut self.w, *value)
+            }
+            Instruction::F64Const(value) => {
+                write!(self.w, "f64.const ")?;
+                write_f64(&mut self.w, *value)
+            }
+            Instruction::RefNull(RefType::FuncRef) => write!(self.w, "ref.null func"),
+            Instruction::RefNull(RefType::ExternRef) => write!(self.w, "ref.null extern"),
+            Instruction::RefFunc(func) => {
+                write!(self.w, "ref.func ")?;
+                self.write_func_idx(func)
+            }
+            _ => unreachable!("Block instructions are written by write_code"),
+        }
+    }
+
+    /// Writes the instructions in the flat format, one per line
+    fn write_code(&mut self, code: &[Instruction], depth: usize, func: usize) -> fmt::Result {
+        for instruction in code {
+            self.newline(depth)?;
+
+            match instruction {
+                Instruction::Block(block_type, code) | Instruction::Loop(block_type, code) => {
+                    match instruction {
+                        Instruction::Block(..) => self.w.write_str("block")?,
+                        _ => self.w.write_str("loop")?,
+                    }
+                    self.write_block_type(block_type)?;
+                    self.write_code(code, depth + 1, func)?;
+                    self.newline(depth)?;
+                    self.w.write_str("end")?;
+                }
+                Instruction::If(block_type, then_code, else_code) => {
+                    self.w.write_str("if")?;
+                    self.write_block_type(block_type)?;
+                    self.write_code(then_code, depth + 1, func)?;
+                    if !else_code.is_empty() {
+                        self.newline(depth)?;
+                        self.w.write_str("else")?;
+                        self.write_code(else_code, depth + 1, func)?;
+                    }
+                    self.newline(depth)?;
+                    self.w.write_str("end")?;
+                }
+                _ => self.write_instruction(instruction, Some(func))?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a constant expression, folding every instruction on its own
+    fn write_expression(&mut self, expression: &[Instruction]) -> fmt::Result {
+        for (i, instruction) in expression.iter().enumerate() {
+            if i > 0 {
+                self.w.write_char(' ')?;
+            }
+
+            self.w.write_char('(')?;
+            self.write_instruction(instruction, None)?;
+            self.w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_offset(&mut self, offset: &[Instruction]) -> fmt::Result {
+        if offset.len() == 1 {
+            return self.write_expression(offset);
+        }
+
+        self.w.write_str("(offset ")?;
+        self.write_expression(offset)?;
+        self.w.write_char(')')
+    }
+
+    /// Writes parameters or locals, grouping the ones without a symbolic identifier
+    fn write_locals(
+        &mut self,
+        keyword: &str,
+        types: &[ValueType],
+        first: usize,
+        func: usize,
+    ) -> fmt::Result {
+        let ids = self.locals.get(&func);
+        let mut group_open = false;
+
+        for (i, t) in types.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+
+            match ids.and_then(|ids| ids.get(&(first + i))) {
+                Some(id) => {
+                    if group_open {
+                        self.w.write_char(')')?;
+                        group_open = false;
+                    }
+                    write!(
+                        self.w,
+                        "{}({} {} {})",
+                        separator,
+                        keyword,
+                        id,
+                        value_type(t)
+                    )?;
+                }
+                None if group_open => write!(self.w, " {}", value_type(t))?,
+                None => {
+                    write!(self.w, "{}({} {}", separator, keyword, value_type(t))?;
+                    group_open = true;
+                }
+            }
+        }
+
+        if group_open {
+            self.w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_types(&mut self) -> fmt::Result {
+        for (i, ftype) in self.module.types.iter().enumerate() {
+            self.newline(1)?;
+            write!(self.w, "(type (;{};) (func", i)?;
+            write_func_type(&mut self.w, ftype)?;
+            self.w.write_str("))")?;
+        }
+
+        Ok(())
+    }
+
+    fn write_imports(&mut self) -> fmt::Result {
+        let mut funcs = 0;
+        let mut tables = 0;
+        let mut memories = 0;
+        let mut globals = 0;
+
+        for import in &self.module.imports {
+            self.newline(1)?;
+            self.w.write_str("(import ")?;
+            write_string(&mut self.w, import.module.0.as_bytes())?;
+            self.w.write_char(' ')?;
+            write_string(&mut self.w, import.name.0.as_bytes())?;
+
+            match &import.descriptor {
+                ImportDescriptor::Func(type_idx) => {
+                    self.w.write_str(" (func")?;
+                    self.write_func_id(funcs)?;
+                    write!(self.w, " (type {})", type_idx.0)?;
+                    funcs += 1;
+                }
+                ImportDescriptor::Table(table_type) => {
+                    write!(self.w, " (table (;{};) ", tables)?;
+                    write_limits(&mut self.w, &table_type.limits)?;
+                    write!(self.w, " {}", ref_type(&table_type.elem_type))?;
+                    tables += 1;
+                }
+                ImportDescriptor::Memory(mem_type) => {
+                    write!(self.w, " (memory (;{};) ", memories)?;
+                    write_limits(&mut self.w, &mem_type.limits)?;
+                    memories += 1;
+                }
+                ImportDescriptor::Global(global_type) => {
+                    write!(self.w, " (global (;{};) ", globals)?;
+                    write_global_type(&mut self.w, global_type)?;
+                    globals += 1;
+                }
+            }
+
+            self.w.write_str("))")?;
+        }
+
+        Ok(())
+    }
+
+    fn write_funcs(&mut self) -> fmt::Result {
+        let module = self.module;
+        let imported = module
+            .imports
+            .iter()
+            .filter(|import| matches!(import.descriptor, ImportDescriptor::Func(_)))
+            .count();
+
+        for (i, (type_idx, code)) in module.functions.iter().zip(&module.codes).enumerate() {
+            let func = imported + i;
+
+            self.newline(1)?;
+            self.w.write_str("(func")?;
+            self.write_func_id(func)?;
+            write!(self.w, " (type {})", type_idx.0)?;
+
+            // Repeat the type inline, which allows naming the parameters
+            let parameter_count = match module.types.get(type_idx.0) {
+                Some(ftype) => {
+                    if !ftype.parameter_types.is_empty() {
+                        self.w.write_char(' ')?;
+                        self.write_locals("param", &ftype.parameter_types, 0, func)?;
+                    }
+                    write_func_type(
+                        &mut self.w,
+                        &FuncType {
+                            parameter_types: vec![],
+                            result_types: ftype.result_types.clone(),
+                        },
+                    )?;
+                    ftype.parameter_types.len()
+                }
+                None => 0,
+            };
+
+            if !code.locals.is_empty() {
+                self.newline(2)?;
+                self.write_locals("local", &code.locals, parameter_count, func)?;
+            }
+
+            self.write_code(&code.body, 2, func)?;
+            self.w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_tables(&mut self) -> fmt::Result {
+        let module = self.module;
+        let imported = module
+            .imports
+            .iter()
+            .filter(|import| matches!(import.descriptor, ImportDescriptor::Table(_)))
+            .count();
+
+        for (i, table_type) in module.tables.iter().enumerate() {
+            self.newline(1)?;
+            write!(self.w, "(table (;{};) ", imported + i)?;
+            write_limits(&mut self.w, &table_type.limits)?;
+            write!(self.w, " {})", ref_type(&table_type.elem_type))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_memories(&mut self) -> fmt::Result {
+        let module = self.module;
+        let imported = module
+            .imports
+            .iter()
+            .filter(|import| matches!(import.descriptor, ImportDescriptor::Memory(_)))
+            .count();
+
+        for (i, limits) in module.memories.iter().enumerate() {
+            self.newline(1)?;
+            write!(self.w, "(memory (;{};) ", imported + i)?;
+            write_limits(&mut self.w, limits)?;
+            self.w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_globals(&mut self) -> fmt::Result {
+        let module = self.module;
+        let imported = module
+            .imports
+            .iter()
+            .filter(|import| matches!(import.descriptor, ImportDescriptor::Global(_)))
+            .count();
+
+        for (i, global) in module.globals.iter().enumerate() {
+            self.newline(1)?;
+            write!(self.w, "(global (;{};) ", imported + i)?;
+            write_global_type(&mut self.w, &global.global_type)?;
+            self.w.write_char(' ')?;
+            self.write_expression(&global.expression)?;
+            self.w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_exports(&mut self) -> fmt::Result {
+        for export in &self.module.exports {
+            self.newline(1)?;
+            self.w.write_str("(export ")?;
+            write_string(&mut self.w, export.name.0.as_bytes())?;
+
+            match &export.descriptor {
+                ExportDescriptor::Func(func) => {
+                    self.w.write_str(" (func ")?;
+                    self.write_func_idx(func)?;
+                }
+                ExportDescriptor::Table(table) => write!(self.w, " (table {}", table.0)?,
+                ExportDescriptor::Memory(memory) => write!(self.w, " (memory {}", memory.0)?,
+                ExportDescriptor::Global(global) => write!(self.w, " (global {}", global.0)?,
+            }
+
+            self.w.write_str("))")?;
+        }
+
+        Ok(())
+    }
+
+    fn write_start(&mut self) -> fmt::Result {
+        if let Some(start) = &self.module.start {
+            self.newline(1)?;
+            self.w.write_str("(start ")?;
+            self.write_func_idx(&start.func)?;
+            self.w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_elems(&mut self) -> fmt::Result {
+        for (i, elem) in self.module.elems.iter().enumerate() {
+            self.newline(1)?;
+            write!(self.w, "(elem (;{};)", i)?;
+
+            match &elem.mode {
+                ElemMode::Passive => {}
+                ElemMode::Active { table, offset } => {
+                    if table.0 != 0 {
+                        write!(self.w, " (table {})", table.0)?;
+                    }
+                    self.w.write_char(' ')?;
+                    self.write_offset(offset)?;
+                }
+                ElemMode::Declarative => self.w.write_str(" declare")?,
+            }
+
+            // Use the compact notation when all initializers are plain function references
+            let funcs: Option<Vec<_>> = elem
+                .init
+                .iter()
+                .map(|expression| match expression[..] {
+                    [Instruction::RefFunc(func)] => Some(func),
+                    _ => None,
+                })
+                .collect();
+
+            match funcs {
+                Some(funcs) if elem.elem_type == RefType::FuncRef => {
+                    self.w.write_str(" func")?;
+                    for func in &funcs {
+                        self.w.write_char(' ')?;
+                        self.write_func_idx(func)?;
+                    }
+                }
+                _ => {
+                    write!(self.w, " {}", ref_type(&elem.elem_type))?;
+                    for expression in &elem.init {
+                        self.w.write_char(' ')?;
+                        if expression.len() == 1 {
+                            self.write_expression(expression)?;
+                        } else {
+                            self.w.write_str("(item ")?;
+                            self.write_expression(expression)?;
+                            self.w.write_char(')')?;
+                        }
+                    }
+                }
+            }
+
+            self.w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_datas(&mut self) -> fmt::Result {
+        for (i, data) in self.module.datas.iter().enumerate() {
+            self.newline(1)?;
+            write!(self.w, "(data (;{};)", i)?;
+
+            if let DataMode::Active { memory, offset } = &data.mode {
+                if memory.0 != 0 {
+                    write!(self.w, " (memory {})", memory.0)?;
+                }
+                self.w.write_char(' ')?;
+                self.write_offset(offset)?;
+            }
+
+            self.w.write_char(' ')?;
+            write_string(&mut self.w, &data.init)?;
+            self.w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_module(&mut self) -> fmt::Result {
+        self.w.write_str("(module")?;
+
+        let name = self.module.names.module.as_ref();
+        if let Some(name) = name.filter(|name| !name.0.is_empty() && name.0.chars().all(is_idchar))
+        {
+            write!(self.w, " ${}", name.0)?;
+        }
+
+        self.write_types()?;
+        self.write_imports()?;
+        self.write_funcs()?;
+        self.write_tables()?;
+        self.write_memories()?;
+        self.write_globals()?;
+        self.write_exports()?;
+        self.write_start()?;
+        self.write_elems()?;
+        self.write_datas()?;
+
+        self.w.write_char(')')
+    }
+}
+
+/// Prints the module in the WebAssembly text format, using the flat format for function bodies.
+/// Symbolic identifiers are taken from the name section, when available.
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f, self).write_module()
+    }
+}
@@ -0,0 +1,1041 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::wasm::*;
+
+/// The part of the module in which a validation error was detected.
+///
+/// Instructions are numbered (from 0) in the order in which they appear in the function body,
+/// where nested instructions follow the `block`, `loop` or `if` instruction they belong to.
+/// Problems at the end of a block are reported at the last instruction of the block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Location {
+    Module,
+    Import(usize),
+    Func { func: FuncIdx, instruction: usize },
+    Table(TableIdx),
+    Memory(MemIdx),
+    Global(GlobalIdx),
+    Export(usize),
+    Start,
+    Elem(usize),
+    Data(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Module => write!(f, "module"),
+            Self::Import(index) => write!(f, "import {}", index),
+            Self::Func { func, instruction } => {
+                write!(f, "function {}, instruction {}", func.0, instruction)
+            }
+            Self::Table(table) => write!(f, "table {}", table.0),
+            Self::Memory(memory) => write!(f, "memory {}", memory.0),
+            Self::Global(global) => write!(f, "global {}", global.0),
+            Self::Export(index) => write!(f, "export {}", index),
+            Self::Start => write!(f, "start function"),
+            Self::Elem(index) => write!(f, "element segment {}", index),
+            Self::Data(index) => write!(f, "data segment {}", index),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationErrorKind {
+    /// An operand of the given type was expected, where None stands for any type
+    TypeMismatch {
+        expected: Option<ValueType>,
+        actual: Option<ValueType>,
+    },
+    OperandStackUnderflow,
+    /// Values are left on the operand stack at the end of a block
+    OperandStackHeightMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    UnknownType(TypeIdx),
+    UnknownFunc(FuncIdx),
+    UnknownTable(TableIdx),
+    UnknownMemory(MemIdx),
+    UnknownGlobal(GlobalIdx),
+    UnknownElem(ElemIdx),
+    UnknownLocal(LocalIdx),
+    UnknownLabel(LabelIdx),
+    BranchTableArityMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    InvalidAlignment {
+        align: usize,
+        natural: usize,
+    },
+    ImmutableGlobal(GlobalIdx),
+    UndeclaredFuncRef(FuncIdx),
+    TableTypeMismatch {
+        expected: RefType,
+        actual: RefType,
+    },
+    ConstantExpressionRequired,
+    InvalidLimits {
+        min: u32,
+        max: Option<u32>,
+    },
+    MultipleMemories,
+    FunctionCodeMismatch {
+        functions: usize,
+        codes: usize,
+    },
+    DuplicateExport(Name),
+    InvalidStartFunction,
+    /// Blocks are nested more than `MAX_NESTING_DEPTH` levels deep
+    NestingTooDeep,
+    /// An instruction that the validator does not know the typing rules of
+    UnsupportedInstruction(String),
+}
+
+impl fmt::Display for ValidationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TypeMismatch { expected, actual } => {
+                let describe = |t: &Option<ValueType>| match t {
+                    Some(t) => format!("{:?}", t),
+                    None => "any".to_owned(),
+                };
+                write!(
+                    f,
+                    "Type mismatch: expected: {} actual: {}",
+                    describe(expected),
+                    describe(actual)
+                )
+            }
+            Self::OperandStackUnderflow => write!(f, "Operand stack underflow"),
+            Self::OperandStackHeightMismatch { expected, actual } => write!(
+                f,
+                "Operand stack height mismatch at end of block: expected: {} actual: {}",
+                expected, actual
+            ),
+            Self::UnknownType(type_idx) => write!(f, "Unknown type: {}", type_idx.0),
+            Self::UnknownFunc(func) => write!(f, "Unknown function: {}", func.0),
+            Self::UnknownTable(table) => write!(f, "Unknown table: {}", table.0),
+            Self::UnknownMemory(memory) => write!(f, "Unknown memory: {}", memory.0),
+            Self::UnknownGlobal(global) => write!(f, "Unknown global: {}", global.0),
+            Self::UnknownElem(elem) => write!(f, "Unknown element segment: {}", elem.0),
+            Self::UnknownLocal(local) => write!(f, "Unknown local: {}", local.0),
+            Self::UnknownLabel(label) => write!(f, "Unknown label: {}", label.0),
+            Self::BranchTableArityMismatch { expected, actual } => write!(
+                f,
+                "Branch table targets have different arities: expected: {} actual: {}",
+                expected, actual
+            ),
+            Self::InvalidAlignment { align, natural } => write!(
+                f,
+                "Alignment 2^{} exceeds the natural alignment 2^{}",
+                align, natural
+            ),
+            Self::ImmutableGlobal(global) => {
+                write!(f, "Global {} is immutable and can't be set", global.0)
+            }
+            Self::UndeclaredFuncRef(func) => write!(
+                f,
+                "Function {} is referenced, but not declared in an element segment, export or global",
+                func.0
+            ),
+            Self::TableTypeMismatch { expected, actual } => write!(
+                f,
+                "Table type mismatch: expected: {:?} actual: {:?}",
+                expected, actual
+            ),
+            Self::ConstantExpressionRequired => write!(f, "Constant expression required"),
+            Self::InvalidLimits { min, max } => {
+                write!(f, "Invalid limits: min: {} max: {:?}", min, max)
+            }
+            Self::MultipleMemories => write!(f, "At most one memory is allowed"),
+            Self::FunctionCodeMismatch { functions, codes } => write!(
+                f,
+                "Number of functions ({}) and code entries ({}) differ",
+                functions, codes
+            ),
+            Self::DuplicateExport(name) => write!(f, "Duplicate export name: {}", name.0),
+            Self::InvalidStartFunction => {
+                write!(f, "The start function must not have parameters or results")
+            }
+            Self::NestingTooDeep => write!(f, "Blocks are nested too deeply"),
+            Self::UnsupportedInstruction(instruction) => {
+                write!(f, "Unsupported instruction: {}", instruction)
+            }
+        }
+    }
+}
+
+/// Describes why a module is not valid, and where the problem was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub kind: ValidationErrorKind,
+    pub location: Location,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.kind, self.location)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+pub type Result<T> = std::result::Result<T, ValidationError>;
+
+type Check<T> = std::result::Result<T, ValidationErrorKind>;
+
+const I32: ValueType = ValueType::NumType(NumType::I32);
+const I64: ValueType = ValueType::NumType(NumType::I64);
+const F32: ValueType = ValueType::NumType(NumType::F32);
+const F64: ValueType = ValueType::NumType(NumType::F64);
+
+/// The largest number of pages a memory can have (4GiB in total)
+const MAX_PAGES: u32 = 0x10000;
+
+/// The operand types of instructions that just pop and push values,
+/// without any further requirements
+fn operator_type(
+    instruction: &Instruction,
+) -> Option<(&'static [ValueType], &'static [ValueType])> {
+    use Instruction::*;
+
+    Some(match instruction {
+        I32Eqz => (&[I32], &[I32]),
+        I64Eqz => (&[I64], &[I32]),
+
+        I32Eq | I32Ne | I32LtSigned | I32LtUnsigned | I32GtSigned | I32GtUnsigned | I32LeSigned
+        | I32LeUnsigned | I32GeSigned | I32GeUnsigned => (&[I32, I32], &[I32]),
+        I64Eq | I64Ne | I64LtSigned | I64LtUnsigned | I64GtSigned | I64GtUnsigned | I64LeSigned
+        | I64LeUnsigned | I64GeSigned | I64GeUnsigned => (&[I64, I64], &[I32]),
+        F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge => (&[F32, F32], &[I32]),
+        F64Eq | F64Ne | F64Lt | F64Gt | F64Le | F64Ge => (&[F64, F64], &[I32]),
+
+        I32Clz | I32Ctz | I32Popcnt => (&[I32], &[I32]),
+        I32Add | I32Sub | I32Mul | I32DivSigned | I32DivUnsigned | I32RemSigned
+        | I32RemUnsigned | I32And | I32Or | I32Xor | I32Shl | I32ShrSigned | I32ShrUnsigned
+        | I32Rotl | I32Rotr => (&[I32, I32], &[I32]),
+
+        I64Clz | I64Ctz | I64Popcnt => (&[I64], &[I64]),
+        I64Add | I64Sub | I64Mul | I64DivSigned | I64DivUnsigned | I64RemSigned
+        | I64RemUnsigned | I64And | I64Or | I64Xor | I64Shl | I64ShrSigned | I64ShrUnsigned
+        | I64Rotl | I64Rotr => (&[I64, I64], &[I64]),
+
+        F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt => (&[F32], &[F32]),
+        F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign => (&[F32, F32], &[F32]),
+
+        F64Abs | F64Neg | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Sqrt => (&[F64], &[F64]),
+        F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => (&[F64, F64], &[F64]),
+
+        I32WrapI64 => (&[I64], &[I32]),
+        I32TruncF32Signed | I32TruncF32Unsigned | I32ReinterpretF32 => (&[F32], &[I32]),
+        I32TruncF64Signed | I32TruncF64Unsigned => (&[F64], &[I32]),
+        I64ExtendI32Signed | I64ExtendI32Unsigned => (&[I32], &[I64]),
+        I64TruncF32Signed | I64TruncF32Unsigned => (&[F32], &[I64]),
+        I64TruncF64Signed | I64TruncF64Unsigned | I64ReinterpretF64 => (&[F64], &[I64]),
+        F32ConvertI32Signed | F32ConvertI32Unsigned | F32ReinterpretI32 => (&[I32], &[F32]),
+        F32ConvertI64Signed | F32ConvertI64Unsigned => (&[I64], &[F32]),
+        F32DemoteF64 => (&[F64], &[F32]),
+        F64ConvertI32Signed | F64ConvertI32Unsigned => (&[I32], &[F64]),
+        F64ConvertI64Signed | F64ConvertI64Unsigned | F64ReinterpretI64 => (&[I64], &[F64]),
+        F64PromoteF32 => (&[F32], &[F64]),
+
+        I32Const(_) => (&[], &[I32]),
+        I64Const(_) => (&[], &[I64]),
+        F32Const(_) => (&[], &[F32]),
+        F64Const(_) => (&[], &[F64]),
+
+        _ => return None,
+    })
+}
+
+/// The operand types of memory instructions, together with their memarg and
+/// natural alignment (as exponent of 2)
+fn memory_type(
+    instruction: &Instruction,
+) -> Option<(&'static [ValueType], &'static [ValueType], &MemArg, usize)> {
+    use Instruction::*;
+
+    Some(match instruction {
+        I32Load(memarg) => (&[I32], &[I32], memarg, 2),
+        I64Load(memarg) => (&[I32], &[I64], memarg, 3),
+        F32Load(memarg) => (&[I32], &[F32], memarg, 2),
+        F64Load(memarg) => (&[I32], &[F64], memarg, 3),
+        I32Load8Signed(memarg) | I32Load8Unsigned(memarg) => (&[I32], &[I32], memarg, 0),
+        I32Load16Signed(memarg) | I32Load16Unsigned(memarg) => (&[I32], &[I32], memarg, 1),
+        I64Load8Signed(memarg) | I64Load8Unsigned(memarg) => (&[I32], &[I64], memarg, 0),
+        I64Load16Signed(memarg) | I64Load16Unsigned(memarg) => (&[I32], &[I64], memarg, 1),
+        I64Load32Signed(memarg) | I64Load32Unsigned(memarg) => (&[I32], &[I64], memarg, 2),
+        I32Store(memarg) => (&[I32, I32], &[], memarg, 2),
+        I64Store(memarg) => (&[I32, I64], &[], memarg, 3),
+        F32Store(memarg) => (&[I32, F32], &[], memarg, 2),
+        F64Store(memarg) => (&[I32, F64], &[], memarg, 3),
+        I32Store8(memarg) => (&[I32, I32], &[], memarg, 0),
+        I32Store16(memarg) => (&[I32, I32], &[], memarg, 1),
+        I64Store8(memarg) => (&[I32, I64], &[], memarg, 0),
+        I64Store16(memarg) => (&[I32, I64], &[], memarg, 1),
+        I64Store32(memarg) => (&[I32, I64], &[], memarg, 2),
+        _ => return None,
+    })
+}
+
+/// Everything that is defined by the module, including the imports (section 3.1.1 from spec)
+struct Context<'a> {
+    types: &'a [FuncType],
+    funcs: Vec<TypeIdx>,
+    tables: Vec<TableType>,
+    memories: Vec<Limits>,
+    globals: Vec<GlobalType>,
+    /// The types of the element segments
+    elems: Vec<RefType>,
+    /// The number of imported globals, which are the only ones allowed in constant expressions
+    imported_globals: usize,
+    /// The functions that may be referenced by `ref.func` within function bodies
+    refs: HashSet<usize>,
+}
+
+impl<'a> Context<'a> {
+    fn ftype(&self, type_idx: TypeIdx) -> Check<&'a FuncType> {
+        let types = self.types;
+        types
+            .get(type_idx.0)
+            .ok_or(ValidationErrorKind::UnknownType(type_idx))
+    }
+
+    fn func_type(&self, func: FuncIdx) -> Check<&'a FuncType> {
+        match self.funcs.get(func.0) {
+            Some(&type_idx) => self.ftype(type_idx),
+            None => Err(ValidationErrorKind::UnknownFunc(func)),
+        }
+    }
+
+    fn table(&self, table: TableIdx) -> Check<&TableType> {
+        self.tables
+            .get(table.0)
+            .ok_or(ValidationErrorKind::UnknownTable(table))
+    }
+
+    fn memory(&self, memory: MemIdx) -> Check<&Limits> {
+        self.memories
+            .get(memory.0)
+            .ok_or(ValidationErrorKind::UnknownMemory(memory))
+    }
+
+    fn global(&self, global: GlobalIdx) -> Check<&GlobalType> {
+        self.globals
+            .get(global.0)
+            .ok_or(ValidationErrorKind::UnknownGlobal(global))
+    }
+
+    fn elem(&self, elem: ElemIdx) -> Check<RefType> {
+        self.elems
+            .get(elem.0)
+            .copied()
+            .ok_or(ValidationErrorKind::UnknownElem(elem))
+    }
+
+    /// The parameter and result types of a block
+    fn block_type(&self, block_type: &BlockType) -> Check<(Vec<ValueType>, Vec<ValueType>)> {
+        Ok(match block_type {
+            BlockType::Empty => (vec![], vec![]),
+            BlockType::Value(t) => (vec![], vec![*t]),
+            BlockType::TypeIdx(type_idx) => {
+                let ftype = self.ftype(*type_idx)?;
+                (ftype.parameter_types.clone(), ftype.result_types.clone())
+            }
+        })
+    }
+}
+
+/// The instructions of a block that remain to be checked, and the else branch that follows
+/// them in case of an if
+type Nested<'c> = (std::slice::Iter<'c, Instruction>, Option<&'c [Instruction]>);
+
+/// An entry on the control stack (appendix 7.3 from spec)
+struct ControlFrame {
+    is_loop: bool,
+    start_types: Vec<ValueType>,
+    end_types: Vec<ValueType>,
+    height: usize,
+    unreachable: bool,
+}
+
+impl ControlFrame {
+    /// The types of the values that a branch to this frame takes
+    fn label_types(&self) -> &[ValueType] {
+        if self.is_loop {
+            &self.start_types
+        } else {
+            &self.end_types
+        }
+    }
+}
+
+/// Type checks instruction sequences with the algorithm of appendix 7.3 from spec,
+/// where an operand of type None is of unknown type (after unconditional branches)
+struct Validator<'a> {
+    context: &'a Context<'a>,
+    locals: Vec<ValueType>,
+    operands: Vec<Option<ValueType>>,
+    controls: Vec<ControlFrame>,
+    /// The number of instructions that have been checked so far
+    instruction: usize,
+}
+
+impl<'a> Validator<'a> {
+    fn new(context: &'a Context<'a>, locals: Vec<ValueType>) -> Self {
+        Self {
+            context,
+            locals,
+            operands: vec![],
+            controls: vec![],
+            instruction: 0,
+        }
+    }
+
+    fn push_operand(&mut self, t: Option<ValueType>) {
+        self.operands.push(t);
+    }
+
+    fn pop_operand(&mut self) -> Check<Option<ValueType>> {
+        let frame = self.controls.last().unwrap();
+
+        if self.operands.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return Err(ValidationErrorKind::OperandStackUnderflow);
+        }
+
+        Ok(self.operands.pop().unwrap())
+    }
+
+    fn pop_expected(&mut self, expected: ValueType) -> Check<Option<ValueType>> {
+        match self.pop_operand()? {
+            Some(actual) if actual != expected => Err(ValidationErrorKind::TypeMismatch {
+                expected: Some(expected),
+                actual: Some(actual),
+            }),
+            actual => Ok(actual),
+        }
+    }
+
+    fn push_operands(&mut self, types: &[ValueType]) {
+        for &t in types {
+            self.push_operand(Some(t));
+        }
+    }
+
+    fn pop_operands(&mut self, types: &[ValueType]) -> Check<Vec<Option<ValueType>>> {
+        let mut operands = vec![];
+        for &t in types.iter().rev() {
+            operands.push(self.pop_expected(t)?);
+        }
+
+        operands.reverse();
+        Ok(operands)
+    }
+
+    fn push_control(
+        &mut self,
+        is_loop: bool,
+        start_types: Vec<ValueType>,
+        end_types: Vec<ValueType>,
+    ) -> Check<()> {
+        // The function body itself does not count as a block
+        if self.controls.len() > MAX_NESTING_DEPTH {
+            return Err(ValidationErrorKind::NestingTooDeep);
+        }
+
+        self.controls.push(ControlFrame {
+            is_loop,
+            start_types: start_types.clone(),
+            end_types,
+            height: self.operands.len(),
+            unreachable: false,
+        });
+
+        self.push_operands(&start_types);
+        Ok(())
+    }
+
+    fn pop_control(&mut self) -> Check<ControlFrame> {
+        let end_types = self.controls.last().unwrap().end_types.clone();
+        self.pop_operands(&end_types)?;
+
+        let frame = self.controls.pop().unwrap();
+        if self.operands.len() != frame.height {
+            return Err(ValidationErrorKind::OperandStackHeightMismatch {
+                expected: frame.height,
+                actual: self.operands.len(),
+            });
+        }
+
+        Ok(frame)
+    }
+
+    fn label(&self, label: LabelIdx) -> Check<&ControlFrame> {
+        if label.0 >= self.controls.len() {
+            return Err(ValidationErrorKind::UnknownLabel(label));
+        }
+
+        Ok(&self.controls[self.controls.len() - 1 - label.0])
+    }
+
+    fn label_types(&self, label: LabelIdx) -> Check<Vec<ValueType>> {
+        Ok(self.label(label)?.label_types().to_vec())
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self.controls.last_mut().unwrap();
+        self.operands.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn local(&self, local: LocalIdx) -> Check<ValueType> {
+        self.locals
+            .get(local.0)
+            .copied()
+            .ok_or(ValidationErrorKind::UnknownLocal(local))
+    }
+
+    fn check_memory(&self, memarg: &MemArg, natural: usize) -> Check<()> {
+        self.context.memory(MemIdx(0))?;
+
+        if memarg.align > natural {
+            return Err(ValidationErrorKind::InvalidAlignment {
+                align: memarg.align,
+                natural,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks the body of a block, after which the block's results are pushed.
+    ///
+    /// Nested blocks are checked without recursion: the instructions that remain to be
+    /// checked in each of the entered blocks are kept on a stack, innermost last, along
+    /// with the else branch of an if that still has to be checked.
+    fn check_block(&mut self, code: &[Instruction]) -> Check<()> {
+        let mut blocks = vec![(code.iter(), None)];
+
+        while let Some((instructions, else_code)) = blocks.last_mut() {
+            match instructions.next() {
+                Some(instruction) => {
+                    if let Some(nested) = self.check_instruction(instruction)? {
+                        blocks.push(nested);
+                    }
+                }
+                None => {
+                    let frame = self.pop_control()?;
+
+                    match else_code.take() {
+                        // A missing else branch has to pass the parameters on as results
+                        Some(code) => {
+                            self.push_control(false, frame.start_types, frame.end_types)?;
+                            *instructions = code.iter();
+                        }
+                        None => {
+                            self.push_operands(&frame.end_types);
+                            blocks.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks an instruction. For a block, loop or if, its control frame is pushed and
+    /// the code that remains to be checked is returned.
+    fn check_instruction<'c>(&mut self, instruction: &'c Instruction) -> Check<Option<Nested<'c>>> {
+        self.instruction += 1;
+
+        if let Some((inputs, outputs)) = operator_type(instruction) {
+            self.pop_operands(inputs)?;
+            self.push_operands(outputs);
+            return Ok(None);
+        }
+
+        if let Some((inputs, outputs, memarg, natural)) = memory_type(instruction) {
+            self.check_memory(memarg, natural)?;
+            self.pop_operands(inputs)?;
+            self.push_operands(outputs);
+            return Ok(None);
+        }
+
+        let context = self.context;
+
+        match instruction {
+            Instruction::Unreachable => self.set_unreachable(),
+            Instruction::Nop => {}
+            Instruction::Block(block_type, code) | Instruction::Loop(block_type, code) => {
+                let (start_types, end_types) = context.block_type(block_type)?;
+                self.pop_operands(&start_types)?;
+
+                let is_loop = matches!(instruction, Instruction::Loop(..));
+                self.push_control(is_loop, start_types, end_types)?;
+                return Ok(Some((code.iter(), None)));
+            }
+            Instruction::If(block_type, then_code, else_code) => {
+                let (start_types, end_types) = context.block_type(block_type)?;
+                self.pop_expected(I32)?;
+                self.pop_operands(&start_types)?;
+
+                self.push_control(false, start_types, end_types)?;
+                return Ok(Some((then_code.iter(), Some(else_code))));
+            }
+            Instruction::Branch(label) => {
+                let types = self.label_types(*label)?;
+                self.pop_operands(&types)?;
+                self.set_unreachable();
+            }
+            Instruction::BranchIf(label) => {
+                self.pop_expected(I32)?;
+                let types = self.label_types(*label)?;
+                self.pop_operands(&types)?;
+                self.push_operands(&types);
+            }
+            Instruction::BranchTable(labels, default) => {
+                self.pop_expected(I32)?;
+
+                let arity = self.label(*default)?.label_types().len();
+                for label in labels {
+                    let types = self.label_types(*label)?;
+                    if types.len() != arity {
+                        return Err(ValidationErrorKind::BranchTableArityMismatch {
+                            expected: arity,
+                            actual: types.len(),
+                        });
+                    }
+
+                    // The operands have to match every target, but stay unknown where they are
+                    for t in self.pop_operands(&types)? {
+                        self.push_operand(t);
+                    }
+                }
+
+                let types = self.label_types(*default)?;
+                self.pop_operands(&types)?;
+                self.set_unreachable();
+            }
+            Instruction::Return => {
+                let types = self.controls[0].end_types.clone();
+                self.pop_operands(&types)?;
+                self.set_unreachable();
+            }
+            Instruction::Call(func) => {
+                let ftype = context.func_type(*func)?;
+                self.pop_operands(&ftype.parameter_types)?;
+                self.push_operands(&ftype.result_types);
+            }
+            Instruction::CallIndirect(type_idx, table) => {
+                let table_type = context.table(*table)?;
+                if table_type.elem_type != RefType::FuncRef {
+                    return Err(ValidationErrorKind::TableTypeMismatch {
+                        expected: RefType::FuncRef,
+                        actual: table_type.elem_type,
+                    });
+                }
+
+                let ftype = context.ftype(*type_idx)?;
+                self.pop_expected(I32)?;
+                self.pop_operands(&ftype.parameter_types)?;
+                self.push_operands(&ftype.result_types);
+            }
+            Instruction::Drop => {
+                self.pop_operand()?;
+            }
+            Instruction::Select => {
+                self.pop_expected(I32)?;
+                let t1 = self.pop_operand()?;
+                let t2 = self.pop_operand()?;
+
+                // Without a type annotation, only numeric operands are allowed
+                for t in [t1, t2].iter().flatten() {
+                    if let ValueType::RefType(_) = t {
+                        return Err(ValidationErrorKind::TypeMismatch {
+                            expected: None,
+                            actual: Some(*t),
+                        });
+                    }
+                }
+
+                match (t1, t2) {
+                    (Some(t1), Some(t2)) if t1 != t2 => {
+                        return Err(ValidationErrorKind::TypeMismatch {
+                            expected: Some(t1),
+                            actual: Some(t2),
+                        })
+                    }
+                    (None, t) | (t, None) => self.push_operand(t),
+                    (t, _) => self.push_operand(t),
+                }
+            }
+            Instruction::LocalGet(local) => {
+                let t = self.local(*local)?;
+                self.push_operand(Some(t));
+            }
+            Instruction::LocalSet(local) => {
+                let t = self.local(*local)?;
+                self.pop_expected(t)?;
+            }
+            Instruction::LocalTee(local) => {
+                let t = self.local(*local)?;
+                self.pop_expected(t)?;
+                self.push_operand(Some(t));
+            }
+            Instruction::GlobalGet(global) => {
+                let global_type = context.global(*global)?;
+                self.push_operand(Some(global_type.value_type));
+            }
+            Instruction::GlobalSet(global) => {
+                let global_type = context.global(*global)?;
+                if global_type.mutability != Mutability::Variable {
+                    return Err(ValidationErrorKind::ImmutableGlobal(*global));
+                }
+                self.pop_expected(global_type.value_type)?;
+            }
+            Instruction::TableGet(table) => {
+                let t = ValueType::RefType(context.table(*table)?.elem_type);
+                self.pop_expected(I32)?;
+                self.push_operand(Some(t));
+            }
+            Instruction::TableSet(table) => {
+                let t = ValueType::RefType(context.table(*table)?.elem_type);
+                self.pop_expected(t)?;
+                self.pop_expected(I32)?;
+            }
+            Instruction::TableSize(table) => {
+                context.table(*table)?;
+                self.push_operand(Some(I32));
+            }
+            Instruction::TableGrow(table) => {
+                let t = ValueType::RefType(context.table(*table)?.elem_type);
+                self.pop_operands(&[t, I32])?;
+                self.push_operand(Some(I32));
+            }
+            Instruction::TableFill(table) => {
+                let t = ValueType::RefType(context.table(*table)?.elem_type);
+                self.pop_operands(&[I32, t, I32])?;
+            }
+            Instruction::TableCopy(destination, source) => {
+                let expected = context.table(*destination)?.elem_type;
+                let actual = context.table(*source)?.elem_type;
+                if actual != expected {
+                    return Err(ValidationErrorKind::TableTypeMismatch { expected, actual });
+                }
+                self.pop_operands(&[I32, I32, I32])?;
+            }
+            Instruction::TableInit(table, elem) => {
+                let expected = context.table(*table)?.elem_type;
+                let actual = context.elem(*elem)?;
+                if actual != expected {
+                    return Err(ValidationErrorKind::TableTypeMismatch { expected, actual });
+                }
+                self.pop_operands(&[I32, I32, I32])?;
+            }
+            Instruction::ElemDrop(elem) => {
+                context.elem(*elem)?;
+            }
+            Instruction::MemorySize => {
+                context.memory(MemIdx(0))?;
+                self.push_operand(Some(I32));
+            }
+            Instruction::MemoryGrow => {
+                context.memory(MemIdx(0))?;
+                self.pop_expected(I32)?;
+                self.push_operand(Some(I32));
+            }
+            Instruction::RefNull(ref_type) => {
+                self.push_operand(Some(ValueType::RefType(*ref_type)));
+            }
+            Instruction::RefIsNull => {
+                if let Some(ValueType::NumType(t)) = self.pop_operand()? {
+                    return Err(ValidationErrorKind::TypeMismatch {
+                        expected: None,
+                        actual: Some(ValueType::NumType(t)),
+                    });
+                }
+                self.push_operand(Some(I32));
+            }
+            Instruction::RefFunc(func) => {
+                context.func_type(*func)?;
+                if !context.refs.contains(&func.0) {
+                    return Err(ValidationErrorKind::UndeclaredFuncRef(*func));
+                }
+                self.push_operand(Some(ValueType::RefType(RefType::FuncRef)));
+            }
+            _ => {
+                let instruction = format!("{:?}", instruction);
+                return Err(ValidationErrorKind::UnsupportedInstruction(instruction));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn check_limits(limits: &Limits, bound: Option<u32>) -> Check<()> {
+    let exceeds = |value: u32| bound.is_some_and(|bound| value > bound);
+
+    let invalid = match limits.max {
+        Some(max) => max < limits.min || exceeds(max),
+        None => false,
+    };
+
+    if invalid || exceeds(limits.min) {
+        return Err(ValidationErrorKind::InvalidLimits {
+            min: limits.min,
+            max: limits.max,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the expression is constant and of the given type (section 3.3.10 from spec)
+fn check_constant(context: &Context, expression: &[Instruction], t: ValueType) -> Check<()> {
+    for instruction in expression {
+        match instruction {
+            Instruction::I32Const(_)
+            | Instruction::I64Const(_)
+            | Instruction::F32Const(_)
+            | Instruction::F64Const(_)
+            | Instruction::RefNull(_)
+            | Instruction::RefFunc(_) => {}
+            Instruction::GlobalGet(global) => {
+                let global_type = context.global(*global)?;
+                if global.0 >= context.imported_globals
+                    || global_type.mutability != Mutability::Constant
+                {
+                    return Err(ValidationErrorKind::ConstantExpressionRequired);
+                }
+            }
+            _ => return Err(ValidationErrorKind::ConstantExpressionRequired),
+        }
+    }
+
+    let mut validator = Validator::new(context, vec![]);
+    validator.push_control(false, vec![], vec![t])?;
+    validator.check_block(expression)
+}
+
+fn check_func(context: &Context, ftype: &FuncType, code: &Code) -> (usize, Check<()>) {
+    let mut locals = ftype.parameter_types.clone();
+    locals.extend_from_slice(&code.locals);
+
+    let mut validator = Validator::new(context, locals);
+    let result = validator
+        .push_control(false, vec![], ftype.result_types.clone())
+        .and_then(|_| validator.check_block(&code.body));
+    (validator.instruction.saturating_sub(1), result)
+}
+
+/// Builds the context from the module, and collects the function references that are
+/// declared outside of function bodies
+fn context(module: &Module) -> Context<'_> {
+    let mut context = Context {
+        types: &module.types,
+        funcs: vec![],
+        tables: vec![],
+        memories: vec![],
+        globals: vec![],
+        elems: module.elems.iter().map(|elem| elem.elem_type).collect(),
+        imported_globals: 0,
+        refs: HashSet::new(),
+    };
+
+    for import in &module.imports {
+        match &import.descriptor {
+            ImportDescriptor::Func(type_idx) => context.funcs.push(*type_idx),
+            ImportDescriptor::Table(table_type) => context.tables.push(*table_type),
+            ImportDescriptor::Memory(mem_type) => context.memories.push(mem_type.limits),
+            ImportDescriptor::Global(global_type) => context.globals.push(*global_type),
+        }
+    }
+    context.imported_globals = context.globals.len();
+
+    context.funcs.extend(&module.functions);
+    context.tables.extend(&module.tables);
+    context.memories.extend(&module.memories);
+    context
+        .globals
+        .extend(module.globals.iter().map(|global| global.global_type));
+
+    let expressions = module
+        .globals
+        .iter()
+        .map(|global| &global.expression)
+        .chain(module.elems.iter().flat_map(|elem| &elem.init));
+    for instruction in expressions.flatten() {
+        if let Instruction::RefFunc(func) = instruction {
+            context.refs.insert(func.0);
+        }
+    }
+
+    for export in &module.exports {
+        if let ExportDescriptor::Func(func) = export.descriptor {
+            context.refs.insert(func.0);
+        }
+    }
+
+    context
+}
+
+/// Checks that the module is valid (section 3 from spec), such that it can be instantiated
+/// and executed without any further type checks.
+pub fn validate(module: &Module) -> Result<()> {
+    let error = |kind, location| Err(ValidationError { kind, location });
+    let context = context(module);
+
+    for (i, import) in module.imports.iter().enumerate() {
+        let result = match &import.descriptor {
+            ImportDescriptor::Func(type_idx) => context.ftype(*type_idx).map(|_| ()),
+            ImportDescriptor::Table(table_type) => check_limits(&table_type.limits, None),
+            ImportDescriptor::Memory(mem_type) => check_limits(&mem_type.limits, Some(MAX_PAGES)),
+            ImportDescriptor::Global(_) => Ok(()),
+        };
+
+        if let Err(kind) = result {
+            return error(kind, Location::Import(i));
+        }
+    }
+
+    if module.functions.len() != module.codes.len() {
+        let kind = ValidationErrorKind::FunctionCodeMismatch {
+            functions: module.functions.len(),
+            codes: module.codes.len(),
+        };
+        return error(kind, Location::Module);
+    }
+
+    let imported_tables = context.tables.len() - module.tables.len();
+    for (i, table_type) in module.tables.iter().enumerate() {
+        if let Err(kind) = check_limits(&table_type.limits, None) {
+            return error(kind, Location::Table(TableIdx(imported_tables + i)));
+        }
+    }
+
+    let imported_memories = context.memories.len() - module.memories.len();
+    for (i, limits) in module.memories.iter().enumerate() {
+        if let Err(kind) = check_limits(limits, Some(MAX_PAGES)) {
+            return error(kind, Location::Memory(MemIdx(imported_memories + i)));
+        }
+    }
+
+    if context.memories.len() > 1 {
+        return error(ValidationErrorKind::MultipleMemories, Location::Module);
+    }
+
+    for (i, global) in module.globals.iter().enumerate() {
+        let t = global.global_type.value_type;
+        if let Err(kind) = check_constant(&context, &global.expression, t) {
+            return error(
+                kind,
+                Location::Global(GlobalIdx(context.imported_globals + i)),
+            );
+        }
+    }
+
+    let mut names = HashSet::new();
+    for (i, export) in module.exports.iter().enumerate() {
+        let result = match export.descriptor {
+            ExportDescriptor::Func(func) => context.func_type(func).map(|_| ()),
+            ExportDescriptor::Table(table) => context.table(table).map(|_| ()),
+            ExportDescriptor::Memory(memory) => context.memory(memory).map(|_| ()),
+            ExportDescriptor::Global(global) => context.global(global).map(|_| ()),
+        };
+
+        let result = result.and_then(|_| match names.insert(&export.name.0) {
+            true => Ok(()),
+            false => Err(ValidationErrorKind::DuplicateExport(Name(
+                export.name.0.clone(),
+            ))),
+        });
+
+        if let Err(kind) = result {
+            return error(kind, Location::Export(i));
+        }
+    }
+
+    if let Some(start) = &module.start {
+        let result = context.func_type(start.func).and_then(|ftype| {
+            match ftype.parameter_types.is_empty() && ftype.result_types.is_empty() {
+                true => Ok(()),
+                false => Err(ValidationErrorKind::InvalidStartFunction),
+            }
+        });
+
+        if let Err(kind) = result {
+            return error(kind, Location::Start);
+        }
+    }
+
+    for (i, elem) in module.elems.iter().enumerate() {
+        let check_elem = || {
+            for expression in &elem.init {
+                check_constant(&context, expression, ValueType::RefType(elem.elem_type))?;
+            }
+
+            if let ElemMode::Active { table, offset } = &elem.mode {
+                let table_type = context.table(*table)?;
+                if table_type.elem_type != elem.elem_type {
+                    return Err(ValidationErrorKind::TableTypeMismatch {
+                        expected: table_type.elem_type,
+                        actual: elem.elem_type,
+                    });
+                }
+
+                check_constant(&context, offset, I32)?;
+            }
+
+            Ok(())
+        };
+
+        if let Err(kind) = check_elem() {
+            return error(kind, Location::Elem(i));
+        }
+    }
+
+    for (i, data) in module.datas.iter().enumerate() {
+        let check_data = || {
+            if let DataMode::Active { memory, offset } = &data.mode {
+                context.memory(*memory)?;
+                check_constant(&context, offset, I32)?;
+            }
+
+            Ok(())
+        };
+
+        if let Err(kind) = check_data() {
+            return error(kind, Location::Data(i));
+        }
+    }
+
+    let imported_funcs = context.funcs.len() - module.functions.len();
+    for (i, (type_idx, code)) in module.functions.iter().zip(&module.codes).enumerate() {
+        let func = FuncIdx(imported_funcs + i);
+
+        let (instruction, result) = match context.ftype(*type_idx) {
+            Ok(ftype) => check_func(&context, ftype, code),
+            Err(kind) => (0, Err(kind)),
+        };
+
+        if let Err(kind) = result {
+            return error(kind, Location::Func { func, instruction });
+        }
+    }
+
+    Ok(())
+}
+
+impl Module {
+    pub fn validate(&self) -> Result<()> {
+        validate(self)
+    }
+}
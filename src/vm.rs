@@ -1,4 +1,36 @@
-use super::wasm::{Func, FuncIdx, Instruction, LabelIdx, LocalIdx};
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use super::wasm::{
+    BlockType, Code, ElemIdx, FuncIdx, GlobalIdx, Instruction, LabelIdx, LocalIdx, MemArg, NumType,
+    RefType, TableIdx, TypeIdx, ValueType,
+};
+
+mod float;
+mod func;
+mod instance;
+mod linker;
+mod memory;
+mod store;
+mod table;
+mod trap;
+
+pub use func::{CallError, Caller, Func, IntoFunc, TypedFunc, WasmType, WasmTypeList};
+pub use instance::{Instance, InstantiationError};
+pub use linker::{LinkError, Linker};
+pub use memory::{Memory, MemoryAccessError, PAGE_SIZE};
+pub use store::{Extern, FuncAddr, GlobalAddr, GlobalError, MemAddr, Store, TableAddr};
+pub use table::{Table, TableAccessError};
+pub use trap::{FrameInfo, Result, Trap, TrapKind};
+
+use float::Float;
+use store::{ElemAddr, FuncInst};
+
+/// The maximum number of nested calls before execution traps with `TrapKind::StackOverflow`.
+///
+/// Calls and blocks are kept on the heap rather than the native stack, so the limit only
+/// bounds the memory used by the frames.
+pub const MAX_CALL_DEPTH: usize = 10_000;
 
 #[derive(Debug)]
 pub enum ControlFlow {
@@ -6,262 +38,1370 @@ pub enum ControlFlow {
     Branch(usize),
 }
 
-// TODO: add all four datatypes: i32, i64, f32, f64
-// TODO: load/store should have offset
-// TODO: memory.size
-// TODO: memory.grow
-// TODO: local.tee
-// TODO: local.drop
-// TODO: select
-// TODO: br_table
-// TODO: wasm parser (into Module)
-// TODO: memory initialization
-// TODO: obtain exported functions to find entry point(s)
-// TODO: what about local memory, like the call frame
-//       For example:
-//
-//       auto foo() {
-//         int a[20];
-//         for (auto &e : a)
-//           e = 0.0;
-//       }
-//
-//       Will this set the size of the local memory to 20?
-
-impl Func {
-    fn call(
-        &self,
-        machine: &mut Machine,
-        module_functions: &Vec<Func>,
-        extern_functions: &mut Vec<ExternFunction>,
-    ) {
-        // pop param_count parameters off the stack
-        let mut args = machine
-            .stack
-            .split_off(machine.stack.len() - self.ftype.parameter_types.len());
-
-        machine.invoke(
-            &self.code.body,
-            module_functions,
-            extern_functions,
-            &mut args,
-        );
+/// The state of a function call, or of code run by `Machine::invoke`
+#[derive(Debug)]
+struct Frame {
+    /// The parameters, followed by the declared locals
+    locals: Vec<Value>,
+    /// The instance whose code is executed
+    instance: Instance,
+    /// The function that is called, or `None` for invoked code
+    function: Option<Function>,
+    /// The code of the frame, followed by the blocks, ifs and loops entered within it
+    blocks: Vec<Block>,
+}
+
+/// A function that is executed in a frame
+#[derive(Debug)]
+struct Function {
+    address: FuncAddr,
+    /// The index of the function in the module that defines it
+    index: FuncIdx,
+    code: Rc<Code>,
+}
+
+/// A block, `if` or loop that is being executed
+#[derive(Debug)]
+struct Block {
+    /// The position of the block instruction in the enclosing code
+    start: usize,
+    /// Whether the else branch of an `if` is executed
+    is_else: bool,
+    is_loop: bool,
+    /// The position of the next instruction to execute
+    position: usize,
+    label: Label,
+}
+
+impl Block {
+    /// Returns the block for the code of a frame, whose label is that of the function
+    fn body(label: Label) -> Self {
+        Block {
+            start: 0,
+            is_else: false,
+            is_loop: false,
+            position: 0,
+            label,
+        }
     }
 }
 
-pub struct ExternFunction<'a> {
-    // TODO: replace param_count with a FuncType
-    pub param_count: usize,
-    pub fun: Box<dyn FnMut(&[i32]) -> Option<i32> + 'a>,
+/// What ends a run of instructions that do not affect control flow
+#[derive(Debug)]
+enum Step {
+    /// The end of the code was reached
+    End,
+    Enter(Block),
+    Call(FuncAddr),
+    Jump(ControlFlow),
+}
+
+/// The target of a branch: the height of the operand stack below the block,
+/// and the number of values a branch to it carries over
+#[derive(Debug, Copy, Clone)]
+struct Label {
+    height: usize,
+    arity: usize,
+}
+
+/// A runtime value, as found on the operand stack, in locals and in globals
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    /// A reference to a function, or `None` for a null reference
+    FuncRef(Option<FuncAddr>),
+    /// A reference to a host value, or `None` for a null reference
+    ExternRef(Option<usize>),
 }
 
-impl<'a> ExternFunction<'a> {
-    fn call(&mut self, machine: &mut Machine) {
-        let args = machine
-            .stack
-            .split_off(machine.stack.len() - self.param_count);
+impl Value {
+    /// Returns the default (zero or null) value of a type, used to initialize locals
+    pub fn default(value_type: ValueType) -> Self {
+        match value_type {
+            ValueType::NumType(NumType::I32) => Value::I32(0),
+            ValueType::NumType(NumType::I64) => Value::I64(0),
+            ValueType::NumType(NumType::F32) => Value::F32(0.0),
+            ValueType::NumType(NumType::F64) => Value::F64(0.0),
+            ValueType::RefType(RefType::FuncRef) => Value::FuncRef(None),
+            ValueType::RefType(RefType::ExternRef) => Value::ExternRef(None),
+        }
+    }
 
-        if let Some(result) = (self.fun)(&args) {
-            machine.stack.push(result)
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::I32(_) => ValueType::NumType(NumType::I32),
+            Value::I64(_) => ValueType::NumType(NumType::I64),
+            Value::F32(_) => ValueType::NumType(NumType::F32),
+            Value::F64(_) => ValueType::NumType(NumType::F64),
+            Value::FuncRef(_) => ValueType::RefType(RefType::FuncRef),
+            Value::ExternRef(_) => ValueType::RefType(RefType::ExternRef),
         }
     }
 }
 
+macro_rules! value_conversions {
+    ($($type:ty => $variant:ident),*) => {
+        $(
+            impl From<$type> for Value {
+                fn from(value: $type) -> Self {
+                    Value::$variant(value)
+                }
+            }
+
+            impl TryFrom<Value> for $type {
+                /// The value is handed back if it is of a different type
+                type Error = Value;
+
+                fn try_from(value: Value) -> std::result::Result<Self, Self::Error> {
+                    match value {
+                        Value::$variant(value) => Ok(value),
+                        _ => Err(value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+value_conversions!(i32 => I32, i64 => I64, f32 => F32, f64 => F64);
+
+/// Executes code against the functions, tables, memories and globals of a `Store`
 pub struct Machine {
-    pub stack: Vec<i32>,
-    pub memory: Vec<i32>,
+    pub stack: Vec<Value>,
     pub debugging: bool,
+    /// The frames of the active calls, innermost last
+    frames: Vec<Frame>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Machine {
     pub fn new() -> Self {
         Machine {
             stack: Vec::new(),
-            memory: vec![0; 10],
             debugging: true,
+            frames: Vec::new(),
         }
     }
 
-    pub fn invoke(
-        self: &mut Self,
-        code: &Vec<Instruction>,
-        module_functions: &Vec<Func>,
-        extern_functions: &mut Vec<ExternFunction>,
-        locals: &mut Vec<i32>,
-    ) -> Option<ControlFlow> {
-        for instruction in code {
-            if self.debugging {
-                println!("> {:?}", instruction);
-                println!("  locals: {:?}", locals);
+    /// Returns the number of parameters and results of a block
+    fn block_arity(instance: &Instance, block_type: &BlockType) -> Result<(usize, usize)> {
+        match block_type {
+            BlockType::Empty => Ok((0, 0)),
+            BlockType::Value(_) => Ok((0, 1)),
+            BlockType::TypeIdx(TypeIdx(type_index)) => {
+                let ftype = instance
+                    .0
+                    .types
+                    .get(*type_index)
+                    .ok_or(TrapKind::UnknownIndex)?;
+                Ok((ftype.parameter_types.len(), ftype.result_types.len()))
             }
+        }
+    }
 
-            match instruction {
-                Instruction::I32Const(value) => self.stack.push(*value),
+    /// Looks up the address of an entity in one of the index spaces of an instance
+    fn resolve<A: Copy>(addresses: &[A], index: usize) -> Result<A> {
+        addresses
+            .get(index)
+            .copied()
+            .ok_or_else(|| TrapKind::UnknownIndex.into())
+    }
 
-                // TODO: Load/Store indirect (maybe to support arrays? first implement loops and conditionals?)
-                Instruction::I32Load(memarg) => self.stack.push(self.memory[memarg.offset]),
-                Instruction::I32Store(memarg) => {
-                    self.memory[memarg.offset] = self.stack.pop().unwrap()
-                }
+    fn pop(&mut self) -> Result<Value> {
+        self.stack
+            .pop()
+            .ok_or_else(|| TrapKind::OperandStackUnderflow.into())
+    }
 
-                Instruction::I32Add => {
-                    let right = self.stack.pop().unwrap();
-                    let left = self.stack.pop().unwrap();
-                    self.stack.push(left + right);
-                }
+    /// Pops an operand of the given type
+    fn pop_as<T: TryFrom<Value, Error = Value>>(&mut self) -> Result<T> {
+        T::try_from(self.pop()?).map_err(|_| TrapKind::OperandTypeMismatch.into())
+    }
 
-                Instruction::I32Sub => {
-                    let right = self.stack.pop().unwrap();
-                    let left = self.stack.pop().unwrap();
-                    self.stack.push(left - right);
-                }
+    /// Pops the top `count` operands, keeping their order
+    fn pop_values(&mut self, count: usize) -> Result<Vec<Value>> {
+        let height = self.height_below(count)?;
+        Ok(self.stack.split_off(height))
+    }
 
-                Instruction::I32Mul => {
-                    let right = self.stack.pop().unwrap();
-                    let left = self.stack.pop().unwrap();
-                    self.stack.push(left * right);
-                }
+    /// Returns the height of the stack without its top `count` operands
+    fn height_below(&self, count: usize) -> Result<usize> {
+        self.stack
+            .len()
+            .checked_sub(count)
+            .ok_or_else(|| TrapKind::OperandStackUnderflow.into())
+    }
+
+    /// Pops two operands of type `T` and pushes the result of `op` applied to them
+    fn binary<T, R>(&mut self, op: impl FnOnce(T, T) -> R) -> Result<()>
+    where
+        T: TryFrom<Value, Error = Value>,
+        R: Into<Value>,
+    {
+        let right = self.pop_as::<T>()?;
+        let left = self.pop_as::<T>()?;
+        self.stack.push(op(left, right).into());
+        Ok(())
+    }
+
+    /// Pops an operand of type `T` and pushes the result of `op` applied to it
+    fn unary<T, R>(&mut self, op: impl FnOnce(T) -> R) -> Result<()>
+    where
+        T: TryFrom<Value, Error = Value>,
+        R: Into<Value>,
+    {
+        let value = self.pop_as::<T>()?;
+        self.stack.push(op(value).into());
+        Ok(())
+    }
+
+    /// Pops two integer operands and pushes the result of dividing them with `op`,
+    /// which returns `None` if the result overflows. Traps if the divisor is zero.
+    fn divide<T, R>(&mut self, op: impl FnOnce(T, T) -> Option<R>) -> Result<()>
+    where
+        T: TryFrom<Value, Error = Value> + Default + PartialEq,
+        R: Into<Value>,
+    {
+        let right = self.pop_as::<T>()?;
+        let left = self.pop_as::<T>()?;
+
+        if right == T::default() {
+            return Err(TrapKind::IntegerDivideByZero.into());
+        }
+
+        let result = op(left, right).ok_or(TrapKind::IntegerOverflow)?;
+        self.stack.push(result.into());
+        Ok(())
+    }
+
+    /// Pops a float operand and pushes the result of converting it to an integer with
+    /// `convert`. Traps if the operand is NaN, or if its truncation towards zero lies
+    /// outside of `[min, max)`, the range of the integer type.
+    fn truncate<F, R>(&mut self, min: F, max: F, convert: impl FnOnce(F) -> R) -> Result<()>
+    where
+        F: TryFrom<Value, Error = Value> + Float,
+        R: Into<Value>,
+    {
+        let value = self.pop_as::<F>()?;
+
+        if value.is_nan() {
+            return Err(TrapKind::InvalidConversionToInteger.into());
+        }
+
+        let truncated = value.trunc();
+        if truncated < min || truncated >= max {
+            return Err(TrapKind::IntegerOverflow.into());
+        }
+
+        self.stack.push(convert(value).into());
+        Ok(())
+    }
+
+    /// Pops an operand that is used as an unsigned index or length
+    fn pop_index(&mut self) -> Result<usize> {
+        Ok(self.pop_as::<i32>()? as u32 as usize)
+    }
+
+    /// Pops the base address operand and adds the static offset to it
+    fn effective_address(&mut self, memarg: &MemArg) -> Result<usize> {
+        let base = self.pop_index()?;
+        Ok(base.saturating_add(memarg.offset))
+    }
+
+    /// Returns the memory of an instance
+    fn memory<'s, T>(store: &'s mut Store<T>, instance: &Instance) -> Result<&'s mut Memory> {
+        Ok(store.memory_mut(Self::resolve(&instance.0.memories, 0)?))
+    }
+
+    fn table<'s, T>(
+        store: &'s mut Store<T>,
+        instance: &Instance,
+        TableIdx(index): TableIdx,
+    ) -> Result<&'s mut Table> {
+        Ok(store.table_mut(Self::resolve(&instance.0.tables, index)?))
+    }
+
+    /// Pops the index of a table element and returns the function it refers to,
+    /// checking its type against the one expected by `call_indirect`
+    fn indirect_callee<T>(
+        &mut self,
+        store: &mut Store<T>,
+        instance: &Instance,
+        TypeIdx(type_index): TypeIdx,
+        table: TableIdx,
+    ) -> Result<FuncAddr> {
+        let index = self.pop_index()?;
+
+        let address = match Self::table(store, instance, table)?.get(index)? {
+            Value::FuncRef(Some(address)) => address,
+            Value::FuncRef(None) => return Err(TrapKind::UninitializedElement.into()),
+            _ => return Err(TrapKind::OperandTypeMismatch.into()),
+        };
+
+        let FuncAddr(func) = address;
+        let expected = instance
+            .0
+            .types
+            .get(type_index)
+            .ok_or(TrapKind::UnknownIndex)?;
+        if store.funcs[func].func_type() != expected {
+            return Err(TrapKind::IndirectCallTypeMismatch.into());
+        }
+
+        Ok(address)
+    }
+
+    /// Loads `N` bytes from memory and pushes them as a value
+    fn load<const N: usize, V: Into<Value>, T>(
+        &mut self,
+        (store, instance): (&mut Store<T>, &Instance),
+        memarg: &MemArg,
+        convert: impl FnOnce([u8; N]) -> V,
+    ) -> Result<()> {
+        let address = self.effective_address(memarg)?;
+        let mut bytes = [0; N];
+        Self::memory(store, instance)?.read(address, &mut bytes)?;
+        self.stack.push(convert(bytes).into());
+        Ok(())
+    }
+
+    /// Pops a value and stores it in memory as `N` bytes
+    fn store<V: TryFrom<Value, Error = Value>, const N: usize, T>(
+        &mut self,
+        (store, instance): (&mut Store<T>, &Instance),
+        memarg: &MemArg,
+        convert: impl FnOnce(V) -> [u8; N],
+    ) -> Result<()> {
+        let value = self.pop_as::<V>()?;
+        let address = self.effective_address(memarg)?;
+        Self::memory(store, instance)?.write(address, &convert(value))?;
+        Ok(())
+    }
+
+    /// Returns the frame of the innermost call
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("Code is executed in a frame")
+    }
+
+    /// Returns a local of the innermost call
+    fn local(&mut self, LocalIdx(index): LocalIdx) -> Result<&mut Value> {
+        self.frame()
+            .locals
+            .get_mut(index)
+            .ok_or_else(|| TrapKind::UnknownIndex.into())
+    }
+
+    /// Checks the types of the results that a function leaves on the stack
+    fn check_results(&self, result_types: &[ValueType]) -> Result<()> {
+        let results = &self.stack[self.height_below(result_types.len())?..];
+
+        if results
+            .iter()
+            .zip(result_types)
+            .all(|(result, result_type)| result.value_type() == *result_type)
+        {
+            Ok(())
+        } else {
+            Err(TrapKind::OperandTypeMismatch.into())
+        }
+    }
+
+    /// Drops all operands pushed since the label was entered, except for the
+    /// top values that are carried over by a branch to it
+    fn unwind(&mut self, label: Label) -> Result<()> {
+        let values = self.pop_values(label.arity)?;
+        self.stack.truncate(label.height);
+        self.stack.extend(values);
+        Ok(())
+    }
+
+    /// Calls the function at the given address, taking its parameters from the stack
+    /// and leaving its results there
+    pub(crate) fn call<T>(&mut self, store: &mut Store<T>, address: FuncAddr) -> Result<()> {
+        let base = self.frames.len();
+        self.enter(store, address)?;
+
+        if self.frames.len() > base {
+            self.run(store, base, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Pushes a frame for a call to the function at the given address, taking its
+    /// parameters from the stack.
+    ///
+    /// Host functions are called right away instead, and are given the instance of the
+    /// calling frame, if any.
+    fn enter<T>(&mut self, store: &mut Store<T>, FuncAddr(address): FuncAddr) -> Result<()> {
+        if self.frames.len() == MAX_CALL_DEPTH {
+            return Err(TrapKind::StackOverflow.into());
+        }
+
+        match &store.funcs[address] {
+            FuncInst::Wasm {
+                ftype,
+                instance,
+                index,
+                code,
+            } => {
+                let parameter_count = ftype.parameter_types.len();
+                let label = Label {
+                    height: self.height_below(parameter_count).map_err(|trap| {
+                        trap.unwind(instance.frame_info(FuncAddr(address), *index))
+                    })?,
+                    arity: ftype.result_types.len(),
+                };
+
+                let mut locals = self.pop_values(parameter_count)?;
+                locals.extend(code.locals.iter().map(|local| Value::default(*local)));
+                self.frames.push(Frame {
+                    locals,
+                    instance: instance.clone(),
+                    function: Some(Function {
+                        address: FuncAddr(address),
+                        index: *index,
+                        code: Rc::clone(code),
+                    }),
+                    blocks: vec![Block::body(label)],
+                });
+                Ok(())
+            }
+            FuncInst::Host(function) => {
+                let function = Rc::clone(function);
+                let caller = self.frames.last().map(|frame| frame.instance.clone());
+                function.call(self, store, caller.as_ref())
+            }
+        }
+    }
+
+    /// Executes an instruction that does not affect control flow
+    fn execute<T>(
+        &mut self,
+        store: &mut Store<T>,
+        instance: &Instance,
+        instruction: &Instruction,
+    ) -> Result<()> {
+        match instruction {
+            Instruction::I32Const(value) => self.stack.push(Value::I32(*value)),
+            Instruction::I64Const(value) => self.stack.push(Value::I64(*value)),
+            Instruction::F32Const(value) => self.stack.push(Value::F32(*value)),
+            Instruction::F64Const(value) => self.stack.push(Value::F64(*value)),
+
+            Instruction::I32Load(memarg) => {
+                self.load((store, instance), memarg, i32::from_le_bytes)?
+            }
+            Instruction::I64Load(memarg) => {
+                self.load((store, instance), memarg, i64::from_le_bytes)?
+            }
+            Instruction::F32Load(memarg) => {
+                self.load((store, instance), memarg, f32::from_le_bytes)?
+            }
+            Instruction::F64Load(memarg) => {
+                self.load((store, instance), memarg, f64::from_le_bytes)?
+            }
+            Instruction::I32Load8Signed(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    i8::from_le_bytes(bytes) as i32
+                })?
+            }
+            Instruction::I32Load8Unsigned(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    u8::from_le_bytes(bytes) as i32
+                })?
+            }
+            Instruction::I32Load16Signed(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    i16::from_le_bytes(bytes) as i32
+                })?
+            }
+            Instruction::I32Load16Unsigned(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    u16::from_le_bytes(bytes) as i32
+                })?
+            }
+            Instruction::I64Load8Signed(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    i8::from_le_bytes(bytes) as i64
+                })?
+            }
+            Instruction::I64Load8Unsigned(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    u8::from_le_bytes(bytes) as i64
+                })?
+            }
+            Instruction::I64Load16Signed(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    i16::from_le_bytes(bytes) as i64
+                })?
+            }
+            Instruction::I64Load16Unsigned(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    u16::from_le_bytes(bytes) as i64
+                })?
+            }
+            Instruction::I64Load32Signed(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    i32::from_le_bytes(bytes) as i64
+                })?
+            }
+            Instruction::I64Load32Unsigned(memarg) => {
+                self.load((store, instance), memarg, |bytes| {
+                    u32::from_le_bytes(bytes) as i64
+                })?
+            }
+            Instruction::I32Store(memarg) => {
+                self.store((store, instance), memarg, i32::to_le_bytes)?
+            }
+            Instruction::I64Store(memarg) => {
+                self.store((store, instance), memarg, i64::to_le_bytes)?
+            }
+            Instruction::F32Store(memarg) => {
+                self.store((store, instance), memarg, f32::to_le_bytes)?
+            }
+            Instruction::F64Store(memarg) => {
+                self.store((store, instance), memarg, f64::to_le_bytes)?
+            }
+            Instruction::I32Store8(memarg) => {
+                self.store((store, instance), memarg, |value: i32| {
+                    (value as u8).to_le_bytes()
+                })?
+            }
+            Instruction::I32Store16(memarg) => {
+                self.store((store, instance), memarg, |value: i32| {
+                    (value as u16).to_le_bytes()
+                })?
+            }
+            Instruction::I64Store8(memarg) => {
+                self.store((store, instance), memarg, |value: i64| {
+                    (value as u8).to_le_bytes()
+                })?
+            }
+            Instruction::I64Store16(memarg) => {
+                self.store((store, instance), memarg, |value: i64| {
+                    (value as u16).to_le_bytes()
+                })?
+            }
+            Instruction::I64Store32(memarg) => {
+                self.store((store, instance), memarg, |value: i64| {
+                    (value as u32).to_le_bytes()
+                })?
+            }
+            Instruction::MemorySize => {
+                let size = Self::memory(store, instance)?.size();
+                self.stack.push(Value::I32(size as i32));
+            }
+            Instruction::MemoryGrow => {
+                let delta = self.pop_as::<i32>()? as u32;
+                let result = Self::memory(store, instance)?
+                    .grow(delta)
+                    .map_or(-1, |size| size as i32);
+                self.stack.push(Value::I32(result));
+            }
+
+            Instruction::I32Eqz => self.unary(|value: i32| (value == 0) as i32)?,
+            Instruction::I32Eq => self.binary(|left: i32, right| (left == right) as i32)?,
+            Instruction::I32Ne => self.binary(|left: i32, right| (left != right) as i32)?,
+            Instruction::I32LtSigned => self.binary(|left: i32, right| (left < right) as i32)?,
+            Instruction::I32LtUnsigned => {
+                self.binary(|left: i32, right| ((left as u32) < (right as u32)) as i32)?
+            }
+            Instruction::I32GtSigned => self.binary(|left: i32, right| (left > right) as i32)?,
+            Instruction::I32GtUnsigned => {
+                self.binary(|left: i32, right| (left as u32 > right as u32) as i32)?
+            }
+            Instruction::I32LeSigned => self.binary(|left: i32, right| (left <= right) as i32)?,
+            Instruction::I32LeUnsigned => {
+                self.binary(|left: i32, right| (left as u32 <= right as u32) as i32)?
+            }
+            Instruction::I32GeSigned => self.binary(|left: i32, right| (left >= right) as i32)?,
+            Instruction::I32GeUnsigned => {
+                self.binary(|left: i32, right| (left as u32 >= right as u32) as i32)?
+            }
+
+            Instruction::I32Clz => self.unary(|value: i32| value.leading_zeros() as i32)?,
+            Instruction::I32Ctz => self.unary(|value: i32| value.trailing_zeros() as i32)?,
+            Instruction::I32Popcnt => self.unary(|value: i32| value.count_ones() as i32)?,
+            Instruction::I32Add => self.binary(i32::wrapping_add)?,
+            Instruction::I32Sub => self.binary(i32::wrapping_sub)?,
+            Instruction::I32Mul => self.binary(i32::wrapping_mul)?,
+            Instruction::I32DivSigned => self.divide(i32::checked_div)?,
+            Instruction::I32DivUnsigned => {
+                self.divide(|left: i32, right| Some((left as u32 / right as u32) as i32))?
+            }
+            Instruction::I32RemSigned => {
+                self.divide(|left: i32, right| Some(left.wrapping_rem(right)))?
+            }
+            Instruction::I32RemUnsigned => {
+                self.divide(|left: i32, right| Some((left as u32 % right as u32) as i32))?
+            }
+            Instruction::I32And => self.binary(|left: i32, right| left & right)?,
+            Instruction::I32Or => self.binary(|left: i32, right| left | right)?,
+            Instruction::I32Xor => self.binary(|left: i32, right| left ^ right)?,
+            // The shift and rotate counts are taken modulo the bit width
+            Instruction::I32Shl => {
+                self.binary(|left: i32, right| left.wrapping_shl(right as u32))?
+            }
+            Instruction::I32ShrSigned => {
+                self.binary(|left: i32, right| left.wrapping_shr(right as u32))?
+            }
+            Instruction::I32ShrUnsigned => {
+                self.binary(|left: i32, right| (left as u32).wrapping_shr(right as u32) as i32)?
+            }
+            Instruction::I32Rotl => {
+                self.binary(|left: i32, right| left.rotate_left(right as u32))?
+            }
+            Instruction::I32Rotr => {
+                self.binary(|left: i32, right| left.rotate_right(right as u32))?
+            }
+
+            Instruction::I64Eqz => self.unary(|value: i64| (value == 0) as i32)?,
+            Instruction::I64Eq => self.binary(|left: i64, right| (left == right) as i32)?,
+            Instruction::I64Ne => self.binary(|left: i64, right| (left != right) as i32)?,
+            Instruction::I64LtSigned => self.binary(|left: i64, right| (left < right) as i32)?,
+            Instruction::I64LtUnsigned => {
+                self.binary(|left: i64, right| ((left as u64) < (right as u64)) as i32)?
+            }
+            Instruction::I64GtSigned => self.binary(|left: i64, right| (left > right) as i32)?,
+            Instruction::I64GtUnsigned => {
+                self.binary(|left: i64, right| (left as u64 > right as u64) as i32)?
+            }
+            Instruction::I64LeSigned => self.binary(|left: i64, right| (left <= right) as i32)?,
+            Instruction::I64LeUnsigned => {
+                self.binary(|left: i64, right| (left as u64 <= right as u64) as i32)?
+            }
+            Instruction::I64GeSigned => self.binary(|left: i64, right| (left >= right) as i32)?,
+            Instruction::I64GeUnsigned => {
+                self.binary(|left: i64, right| (left as u64 >= right as u64) as i32)?
+            }
+
+            Instruction::I64Clz => self.unary(|value: i64| value.leading_zeros() as i64)?,
+            Instruction::I64Ctz => self.unary(|value: i64| value.trailing_zeros() as i64)?,
+            Instruction::I64Popcnt => self.unary(|value: i64| value.count_ones() as i64)?,
+            Instruction::I64Add => self.binary(i64::wrapping_add)?,
+            Instruction::I64Sub => self.binary(i64::wrapping_sub)?,
+            Instruction::I64Mul => self.binary(i64::wrapping_mul)?,
+            Instruction::I64DivSigned => self.divide(i64::checked_div)?,
+            Instruction::I64DivUnsigned => {
+                self.divide(|left: i64, right| Some((left as u64 / right as u64) as i64))?
+            }
+            Instruction::I64RemSigned => {
+                self.divide(|left: i64, right| Some(left.wrapping_rem(right)))?
+            }
+            Instruction::I64RemUnsigned => {
+                self.divide(|left: i64, right| Some((left as u64 % right as u64) as i64))?
+            }
+            Instruction::I64And => self.binary(|left: i64, right| left & right)?,
+            Instruction::I64Or => self.binary(|left: i64, right| left | right)?,
+            Instruction::I64Xor => self.binary(|left: i64, right| left ^ right)?,
+            Instruction::I64Shl => {
+                self.binary(|left: i64, right| left.wrapping_shl(right as u32))?
+            }
+            Instruction::I64ShrSigned => {
+                self.binary(|left: i64, right| left.wrapping_shr(right as u32))?
+            }
+            Instruction::I64ShrUnsigned => {
+                self.binary(|left: i64, right| (left as u64).wrapping_shr(right as u32) as i64)?
+            }
+            Instruction::I64Rotl => {
+                self.binary(|left: i64, right| left.rotate_left(right as u32))?
+            }
+            Instruction::I64Rotr => {
+                self.binary(|left: i64, right| left.rotate_right(right as u32))?
+            }
+
+            Instruction::I32WrapI64 => self.unary(|value: i64| value as i32)?,
+            Instruction::I64ExtendI32Signed => self.unary(|value: i32| value as i64)?,
+            Instruction::I64ExtendI32Unsigned => self.unary(|value: i32| value as u32 as i64)?,
+
+            Instruction::F32Eq => self.binary(|left: f32, right| (left == right) as i32)?,
+            Instruction::F32Ne => self.binary(|left: f32, right| (left != right) as i32)?,
+            Instruction::F32Lt => self.binary(|left: f32, right| (left < right) as i32)?,
+            Instruction::F32Gt => self.binary(|left: f32, right| (left > right) as i32)?,
+            Instruction::F32Le => self.binary(|left: f32, right| (left <= right) as i32)?,
+            Instruction::F32Ge => self.binary(|left: f32, right| (left >= right) as i32)?,
+
+            // Unlike arithmetic, these only change the sign bit and keep NaN payloads
+            Instruction::F32Abs => self.unary(f32::abs)?,
+            Instruction::F32Neg => self.unary(|value: f32| -value)?,
+            Instruction::F32Copysign => self.binary(f32::copysign)?,
+            Instruction::F32Ceil => self.unary(|value: f32| float::canonical(value.ceil()))?,
+            Instruction::F32Floor => self.unary(|value: f32| float::canonical(value.floor()))?,
+            Instruction::F32Trunc => self.unary(|value: f32| float::canonical(value.trunc()))?,
+            Instruction::F32Nearest => {
+                self.unary(|value: f32| float::canonical(value.round_ties_even()))?
+            }
+            Instruction::F32Sqrt => self.unary(|value: f32| float::canonical(value.sqrt()))?,
+            Instruction::F32Add => {
+                self.binary(|left: f32, right| float::canonical(left + right))?
+            }
+            Instruction::F32Sub => {
+                self.binary(|left: f32, right| float::canonical(left - right))?
+            }
+            Instruction::F32Mul => {
+                self.binary(|left: f32, right| float::canonical(left * right))?
+            }
+            Instruction::F32Div => {
+                self.binary(|left: f32, right| float::canonical(left / right))?
+            }
+            Instruction::F32Min => self.binary(float::min::<f32>)?,
+            Instruction::F32Max => self.binary(float::max::<f32>)?,
+
+            Instruction::F64Eq => self.binary(|left: f64, right| (left == right) as i32)?,
+            Instruction::F64Ne => self.binary(|left: f64, right| (left != right) as i32)?,
+            Instruction::F64Lt => self.binary(|left: f64, right| (left < right) as i32)?,
+            Instruction::F64Gt => self.binary(|left: f64, right| (left > right) as i32)?,
+            Instruction::F64Le => self.binary(|left: f64, right| (left <= right) as i32)?,
+            Instruction::F64Ge => self.binary(|left: f64, right| (left >= right) as i32)?,
+
+            Instruction::F64Abs => self.unary(f64::abs)?,
+            Instruction::F64Neg => self.unary(|value: f64| -value)?,
+            Instruction::F64Copysign => self.binary(f64::copysign)?,
+            Instruction::F64Ceil => self.unary(|value: f64| float::canonical(value.ceil()))?,
+            Instruction::F64Floor => self.unary(|value: f64| float::canonical(value.floor()))?,
+            Instruction::F64Trunc => self.unary(|value: f64| float::canonical(value.trunc()))?,
+            Instruction::F64Nearest => {
+                self.unary(|value: f64| float::canonical(value.round_ties_even()))?
+            }
+            Instruction::F64Sqrt => self.unary(|value: f64| float::canonical(value.sqrt()))?,
+            Instruction::F64Add => {
+                self.binary(|left: f64, right| float::canonical(left + right))?
+            }
+            Instruction::F64Sub => {
+                self.binary(|left: f64, right| float::canonical(left - right))?
+            }
+            Instruction::F64Mul => {
+                self.binary(|left: f64, right| float::canonical(left * right))?
+            }
+            Instruction::F64Div => {
+                self.binary(|left: f64, right| float::canonical(left / right))?
+            }
+            Instruction::F64Min => self.binary(float::min::<f64>)?,
+            Instruction::F64Max => self.binary(float::max::<f64>)?,
+
+            // The bounds are powers of two, which both float types represent exactly
+            Instruction::I32TruncF32Signed => {
+                self.truncate(-2147483648.0, 2147483648.0, |value: f32| value as i32)?
+            }
+            Instruction::I32TruncF32Unsigned => {
+                self.truncate(0.0, 4294967296.0, |value: f32| value as u32 as i32)?
+            }
+            Instruction::I32TruncF64Signed => {
+                self.truncate(-2147483648.0, 2147483648.0, |value: f64| value as i32)?
+            }
+            Instruction::I32TruncF64Unsigned => {
+                self.truncate(0.0, 4294967296.0, |value: f64| value as u32 as i32)?
+            }
+            Instruction::I64TruncF32Signed => self.truncate(
+                -9223372036854775808.0,
+                9223372036854775808.0,
+                |value: f32| value as i64,
+            )?,
+            Instruction::I64TruncF32Unsigned => {
+                self.truncate(0.0, 18446744073709551616.0, |value: f32| {
+                    value as u64 as i64
+                })?
+            }
+            Instruction::I64TruncF64Signed => self.truncate(
+                -9223372036854775808.0,
+                9223372036854775808.0,
+                |value: f64| value as i64,
+            )?,
+            Instruction::I64TruncF64Unsigned => {
+                self.truncate(0.0, 18446744073709551616.0, |value: f64| {
+                    value as u64 as i64
+                })?
+            }
+
+            // Integers are rounded to the nearest float, ties to even
+            Instruction::F32ConvertI32Signed => self.unary(|value: i32| value as f32)?,
+            Instruction::F32ConvertI32Unsigned => self.unary(|value: i32| value as u32 as f32)?,
+            Instruction::F32ConvertI64Signed => self.unary(|value: i64| value as f32)?,
+            Instruction::F32ConvertI64Unsigned => self.unary(|value: i64| value as u64 as f32)?,
+            Instruction::F64ConvertI32Signed => self.unary(|value: i32| value as f64)?,
+            Instruction::F64ConvertI32Unsigned => self.unary(|value: i32| value as u32 as f64)?,
+            Instruction::F64ConvertI64Signed => self.unary(|value: i64| value as f64)?,
+            Instruction::F64ConvertI64Unsigned => self.unary(|value: i64| value as u64 as f64)?,
+            Instruction::F32DemoteF64 => self.unary(|value: f64| float::canonical(value as f32))?,
+            Instruction::F64PromoteF32 => {
+                self.unary(|value: f32| float::canonical(value as f64))?
+            }
+
+            // Reinterpretations keep all bits, including NaN payloads
+            Instruction::I32ReinterpretF32 => self.unary(|value: f32| value.to_bits() as i32)?,
+            Instruction::I64ReinterpretF64 => self.unary(|value: f64| value.to_bits() as i64)?,
+            Instruction::F32ReinterpretI32 => {
+                self.unary(|value: i32| f32::from_bits(value as u32))?
+            }
+            Instruction::F64ReinterpretI64 => {
+                self.unary(|value: i64| f64::from_bits(value as u64))?
+            }
+
+            Instruction::RefNull(ref_type) => self
+                .stack
+                .push(Value::default(ValueType::RefType(*ref_type))),
+            Instruction::RefIsNull => {
+                let is_null = matches!(self.pop()?, Value::FuncRef(None) | Value::ExternRef(None));
+                self.stack.push(Value::I32(is_null as i32));
+            }
+            Instruction::RefFunc(FuncIdx(index)) => {
+                let address = Self::resolve(&instance.0.funcs, *index)?;
+                self.stack.push(Value::FuncRef(Some(address)));
+            }
+
+            Instruction::Drop => {
+                self.pop()?;
+            }
+            Instruction::Select => {
+                let condition = self.pop_as::<i32>()?;
+                let second = self.pop()?;
+                let first = self.pop()?;
+                self.stack.push(if condition != 0 { first } else { second });
+            }
+
+            Instruction::LocalGet(local) => {
+                let value = *self.local(*local)?;
+                self.stack.push(value);
+            }
+            Instruction::LocalSet(local) => {
+                let value = self.pop()?;
+                *self.local(*local)? = value;
+            }
+            Instruction::LocalTee(local) => {
+                let value = self.pop()?;
+                self.stack.push(value);
+                *self.local(*local)? = value;
+            }
+
+            Instruction::GlobalGet(GlobalIdx(index)) => {
+                let address = Self::resolve(&instance.0.globals, *index)?;
+                self.stack.push(store.global(address))
+            }
+            Instruction::GlobalSet(GlobalIdx(index)) => {
+                let address = Self::resolve(&instance.0.globals, *index)?;
+                let value = self.pop()?;
+                store.set_global(address, value)?;
+            }
+
+            Instruction::TableGet(table) => {
+                let index = self.pop_index()?;
+                let value = Self::table(store, instance, *table)?.get(index)?;
+                self.stack.push(value);
+            }
+            Instruction::TableSet(table) => {
+                let value = self.pop()?;
+                let index = self.pop_index()?;
+                Self::table(store, instance, *table)?.set(index, value)?;
+            }
+            Instruction::TableSize(table) => {
+                let size = Self::table(store, instance, *table)?.size();
+                self.stack.push(Value::I32(size as i32));
+            }
+            Instruction::TableGrow(table) => {
+                let delta = self.pop_as::<i32>()? as u32;
+                let init = self.pop()?;
+                let result = Self::table(store, instance, *table)?.grow(delta, init);
+                self.stack
+                    .push(Value::I32(result.map_or(-1, |size| size as i32)));
+            }
+            Instruction::TableFill(table) => {
+                let length = self.pop_index()?;
+                let value = self.pop()?;
+                let index = self.pop_index()?;
+                Self::table(store, instance, *table)?.fill(index, value, length)?;
+            }
+            Instruction::TableCopy(destination, source) => {
+                let length = self.pop_index()?;
+                let source_index = self.pop_index()?;
+                let destination_index = self.pop_index()?;
+
+                let values = Self::table(store, instance, *source)?
+                    .read(source_index, length)?
+                    .to_vec();
+                Self::table(store, instance, *destination)?.write(destination_index, &values)?;
+            }
+            Instruction::TableInit(table, ElemIdx(elem)) => {
+                let length = self.pop_index()?;
+                let source_index = self.pop_index()?;
+                let destination_index = self.pop_index()?;
+
+                let ElemAddr(address) = Self::resolve(&instance.0.elems, *elem)?;
+                let elements = &store.elems[address].elements;
+                let values = match source_index.checked_add(length) {
+                    Some(end) if end <= elements.len() => elements[source_index..end].to_vec(),
+                    _ => return Err(TrapKind::TableOutOfBounds.into()),
+                };
+                Self::table(store, instance, *table)?.write(destination_index, &values)?;
+            }
+            Instruction::ElemDrop(ElemIdx(elem)) => {
+                let ElemAddr(address) = Self::resolve(&instance.0.elems, *elem)?;
+                store.elems[address].elements = Vec::new();
+            }
+
+            _ => {
+                let instruction = format!("{:?}", instruction);
+                return Err(TrapKind::UnsupportedInstruction(instruction).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pops the condition of an `if`, and returns the block that an instruction enters
+    fn enter_block(
+        &mut self,
+        instance: &Instance,
+        start: usize,
+        instruction: &Instruction,
+    ) -> Result<Block> {
+        let (block_type, is_else, is_loop) = match instruction {
+            Instruction::Block(block_type, _) => (block_type, false, false),
+            Instruction::If(block_type, ..) => (block_type, self.pop_as::<i32>()? == 0, false),
+            Instruction::Loop(block_type, _) => (block_type, false, true),
+            _ => unreachable!("Not a block: {:?}", instruction),
+        };
+
+        let (param_count, result_count) = Self::block_arity(instance, block_type)?;
+        Ok(Block {
+            start,
+            is_else,
+            is_loop,
+            position: 0,
+            label: Label {
+                height: self.height_below(param_count)?,
+                // Branching to a loop restarts it, carrying over its parameters
+                arity: if is_loop { param_count } else { result_count },
+            },
+        })
+    }
 
-                Instruction::I32Eq => {
-                    let right = self.stack.pop().unwrap();
-                    let left = self.stack.pop().unwrap();
-                    self.stack.push((left == right) as i32);
+    /// Returns the code of the innermost block, found by following the blocks
+    /// from the code of the frame
+    fn block_code<'c>(code: &'c [Instruction], blocks: &[Block]) -> &'c [Instruction] {
+        blocks[1..]
+            .iter()
+            .fold(code, |code, block| match &code[block.start] {
+                Instruction::Block(_, code) | Instruction::Loop(_, code) => code,
+                Instruction::If(_, _, else_code) if block.is_else => else_code,
+                Instruction::If(_, then_code, _) => then_code,
+                instruction => unreachable!("Not a block: {:?}", instruction),
+            })
+    }
+
+    #[inline(never)]
+    fn trace(instruction: &Instruction, locals: &[Value]) {
+        println!("> {:?}", instruction);
+        println!("  locals: {:?}", locals);
+    }
+
+    #[inline(never)]
+    fn trace_stack(stack: &[Value]) {
+        println!("  stack: {:?}", stack);
+    }
+
+    /// Executes code in the context of an instance, whose functions, tables,
+    /// memories and globals are found in the given store. The code runs in a
+    /// frame of its own, with the given locals.
+    pub fn invoke<T>(
+        &mut self,
+        store: &mut Store<T>,
+        instance: &Instance,
+        code: &[Instruction],
+        locals: &mut Vec<Value>,
+    ) -> Result<Option<ControlFlow>> {
+        let base = self.frames.len();
+        let label = Label {
+            height: self.stack.len(),
+            arity: 0,
+        };
+        self.frames.push(Frame {
+            locals: std::mem::take(locals),
+            instance: instance.clone(),
+            function: None,
+            blocks: vec![Block::body(label)],
+        });
+
+        let result = self.run(store, base, code);
+        *locals = self.frames.pop().expect("Frame pushed above").locals;
+        result
+    }
+
+    /// Executes the frame at `base` and the calls made from it, until the code of that
+    /// frame ends. `invoked` is the code of a frame without a function.
+    ///
+    /// The frames of the calls that a trap unwinds are dropped.
+    fn run<T>(
+        &mut self,
+        store: &mut Store<T>,
+        base: usize,
+        invoked: &[Instruction],
+    ) -> Result<Option<ControlFlow>> {
+        self.run_frames(store, base, invoked).map_err(|mut trap| {
+            while let Some(frame) = self.frames[base..].last() {
+                let function = match &frame.function {
+                    Some(function) => function,
+                    None => break,
+                };
+
+                trap = trap.unwind(frame.instance.frame_info(function.address, function.index));
+                self.frames.pop();
+            }
+            trap
+        })
+    }
+
+    fn run_frames<T>(
+        &mut self,
+        store: &mut Store<T>,
+        base: usize,
+        invoked: &[Instruction],
+    ) -> Result<Option<ControlFlow>> {
+        loop {
+            let frame = self.frames.last().expect("Code is executed in a frame");
+            let instance = frame.instance.clone();
+            let function_code = frame
+                .function
+                .as_ref()
+                .map(|function| function.code.clone());
+            let code = function_code.as_ref().map_or(invoked, |code| &code.body);
+            let code = Self::block_code(code, &frame.blocks);
+            let position = frame.blocks.last().expect("Frame has a body").position;
+
+            let (position, step) = self.run_block(store, &instance, code, position)?;
+            let blocks = &mut self.frame().blocks;
+            blocks.last_mut().expect("Frame has a body").position = position;
+            let depth = blocks.len() - 1;
+
+            let flow = match step {
+                Step::Enter(block) => {
+                    blocks.push(block);
+                    continue;
+                }
+                Step::Call(address) => {
+                    self.enter(store, address)?;
+                    continue;
                 }
 
-                // TODO: Indirect addressing to support arrays?
-                // TODO: LocalSet?
-                Instruction::LocalGet(LocalIdx(address)) => self.stack.push(locals[*address]),
+                // Reaching the end of a block or loop leaves it
+                Step::End if depth > 0 => {
+                    blocks.pop();
+                    continue;
+                }
+                Step::End => None,
 
-                Instruction::Call(FuncIdx(function_index)) => {
-                    let function_index = *function_index;
+                Step::Jump(ControlFlow::Branch(level)) if level < depth => {
+                    let target = depth - level;
+                    let label = blocks[target].label;
 
-                    if function_index < module_functions.len() {
-                        module_functions[function_index].call(
-                            self,
-                            module_functions,
-                            extern_functions,
-                        )
+                    // Branching to a loop restarts it, while branching to a block
+                    // continues after its end
+                    if blocks[target].is_loop {
+                        blocks.truncate(target + 1);
+                        blocks[target].position = 0;
                     } else {
-                        let function_index = function_index - module_functions.len();
-                        extern_functions[function_index].call(self)
+                        blocks.truncate(target);
                     }
+
+                    self.unwind(label)?;
+                    continue;
                 }
+                Step::Jump(ControlFlow::Branch(level)) => Some(ControlFlow::Branch(level - depth)),
+                Step::Jump(ControlFlow::Return) => Some(ControlFlow::Return),
+            };
 
-                Instruction::Return => return Some(ControlFlow::Return),
-                Instruction::Branch(LabelIdx(level)) => return Some(ControlFlow::Branch(*level)),
-                Instruction::BranchIf(LabelIdx(level)) => {
-                    let condition = self.stack.pop().unwrap();
+            // The code of the frame ended
+            let frame = self.frames.last().expect("Code is executed in a frame");
+            let FuncAddr(address) = match &frame.function {
+                Some(function) => function.address,
+                None => return Ok(flow),
+            };
 
-                    if condition != 0 {
-                        return Some(ControlFlow::Branch(*level));
-                    }
+            // Returning, falling off the end or branching to the label of the
+            // function body all leave just the results
+            self.unwind(frame.blocks[0].label)?;
+            self.check_results(&store.funcs[address].func_type().result_types)?;
+            self.frames.pop();
+
+            if self.frames.len() == base {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Executes code from the given position up to the first instruction that affects
+    /// control flow, and returns the position after it along with its effect
+    fn run_block<T>(
+        &mut self,
+        store: &mut Store<T>,
+        instance: &Instance,
+        code: &[Instruction],
+        mut position: usize,
+    ) -> Result<(usize, Step)> {
+        while let Some(instruction) = code.get(position) {
+            position += 1;
+
+            if self.debugging {
+                Self::trace(instruction, &self.frame().locals);
+            }
+
+            let step = match instruction {
+                Instruction::Unreachable => return Err(TrapKind::Unreachable.into()),
+                Instruction::Nop => None,
+
+                Instruction::Call(FuncIdx(index)) => {
+                    Some(Step::Call(Self::resolve(&instance.0.funcs, *index)?))
+                }
+                Instruction::CallIndirect(type_idx, table) => {
+                    let address = self.indirect_callee(store, instance, *type_idx, *table)?;
+                    Some(Step::Call(address))
                 }
 
-                Instruction::Block(_, block_code) => {
-                    match self.invoke(block_code, module_functions, extern_functions, locals) {
-                        None => {}
+                Instruction::Return => Some(Step::Jump(ControlFlow::Return)),
+                Instruction::Branch(LabelIdx(level)) => {
+                    Some(Step::Jump(ControlFlow::Branch(*level)))
+                }
+                Instruction::BranchIf(LabelIdx(level)) => {
+                    let condition = self.pop_as::<i32>()?;
+                    (condition != 0).then_some(Step::Jump(ControlFlow::Branch(*level)))
+                }
 
-                        Some(ControlFlow::Return) => return Some(ControlFlow::Return),
-                        Some(ControlFlow::Branch(level)) => {
-                            if level > 0 {
-                                return Some(ControlFlow::Branch(level - 1));
-                            }
-                        }
-                    }
+                Instruction::BranchTable(labels, LabelIdx(default)) => {
+                    let index = self.pop_index()?;
+                    let LabelIdx(level) = labels.get(index).copied().unwrap_or(LabelIdx(*default));
+                    Some(Step::Jump(ControlFlow::Branch(level)))
                 }
 
-                Instruction::Loop(_, loop_code) => loop {
-                    match self.invoke(loop_code, module_functions, extern_functions, locals) {
-                        None => {}
+                Instruction::Block(..) | Instruction::If(..) | Instruction::Loop(..) => {
+                    let block = self.enter_block(instance, position - 1, instruction)?;
+                    Some(Step::Enter(block))
+                }
 
-                        Some(ControlFlow::Return) => return Some(ControlFlow::Return),
-                        Some(ControlFlow::Branch(level)) => {
-                            if level > 0 {
-                                return Some(ControlFlow::Branch(level - 1));
-                            }
-                        }
-                    }
-                },
+                _ => {
+                    self.execute(store, instance, instruction)?;
+                    None
+                }
+            };
 
-                _ => panic!("Unsupported instruction encountered: {:?}", instruction),
+            if let Some(step) = step {
+                return Ok((position, step));
             }
 
             if self.debugging {
-                println!("  stack: {:?}", self.stack);
-                println!("  memory: {:?}", self.memory);
+                Self::trace_stack(&self.stack);
             }
         }
 
-        None
+        Ok((position, Step::End))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::vm::{ExternFunction, Machine};
+    use crate::vm::{
+        Caller, Extern, FrameInfo, Func, FuncAddr, Instance, Machine, MemAddr, Store, Trap,
+        TrapKind, Value, MAX_CALL_DEPTH, PAGE_SIZE,
+    };
     use crate::wasm::{
-        BlockType, Code, Func, FuncIdx, FuncType, Instruction, LabelIdx, LocalIdx, MemArg, NumType,
-        ValueType,
+        BlockType, ElemIdx, FuncIdx, GlobalIdx, Instruction, LabelIdx, LocalIdx, MemArg, Name,
+        NumType, RefType, TypeIdx, ValueType,
     };
+    use crate::wat;
+
+    fn instantiate<T>(store: &mut Store<T>, text: &str, imports: &[Extern]) -> Instance {
+        Instance::new(store, &wat::parse(text).unwrap(), imports).unwrap()
+    }
 
     #[test]
     fn constant() {
         let code = vec![Instruction::I32Const(42)];
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
         let mut locals = vec![];
 
         let mut machine = Machine::new();
         assert_eq!(machine.stack, vec![]);
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
 
-        assert_eq!(machine.stack, vec![42]);
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
     }
 
     #[test]
     fn load() {
-        let code = vec![Instruction::I32Load(MemArg {
-            align: 0,
-            offset: 0,
-        })];
+        let code = vec![
+            Instruction::I32Const(4),
+            Instruction::I32Load(MemArg {
+                align: 2,
+                offset: 4,
+            }),
+        ];
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, r#"(memory 1) (data (i32.const 8) "\2a")"#, &[]);
         let mut locals = vec![];
 
         let mut machine = Machine::new();
         assert_eq!(machine.stack, vec![]);
 
-        machine.memory[0] = 42;
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
 
-        assert_eq!(machine.stack, vec![42]);
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
     }
 
     #[test]
     fn store() {
         let code = vec![Instruction::I32Store(MemArg {
-            align: 0,
-            offset: 0,
+            align: 2,
+            offset: 4,
         })];
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
         let mut locals = vec![];
 
         let mut machine = Machine::new();
 
-        machine.stack = vec![42];
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        machine.stack = vec![Value::I32(4), Value::I32(42)];
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
 
         assert_eq!(machine.stack, vec![]);
-        assert_eq!(machine.memory[0], 42);
+        assert_eq!(store.memory(MemAddr(0)).data()[8..12], 42i32.to_le_bytes());
     }
 
     #[test]
-    fn add() {
+    fn load_and_store_widths() {
+        let memarg = |offset| MemArg { align: 0, offset };
+
+        let code = vec![
+            Instruction::I32Const(0),
+            Instruction::I64Const(-2),
+            Instruction::I64Store(memarg(0)),
+            Instruction::I32Const(0),
+            Instruction::I32Load8Signed(memarg(0)),
+            Instruction::I32Const(0),
+            Instruction::I32Load16Unsigned(memarg(0)),
+            Instruction::I32Const(0),
+            Instruction::I64Load32Unsigned(memarg(4)),
+            Instruction::I32Const(8),
+            Instruction::I32Const(0x1234),
+            Instruction::I32Store8(memarg(0)),
+            Instruction::I32Const(8),
+            Instruction::I64Load(memarg(0)),
+            Instruction::I32Const(16),
+            Instruction::F64Const(-1.5),
+            Instruction::F64Store(memarg(0)),
+            Instruction::I32Const(16),
+            Instruction::F64Load(memarg(0)),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(
+            machine.stack,
+            vec![
+                Value::I32(-2),
+                Value::I32(0xfffe),
+                Value::I64(0xffff_ffff),
+                Value::I64(0x34),
+                Value::F64(-1.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_out_of_bounds() {
+        let code = vec![
+            Instruction::I32Const(PAGE_SIZE as i32 - 2),
+            Instruction::I32Load(MemArg {
+                align: 2,
+                offset: 0,
+            }),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        let result = machine.invoke(&mut store, &instance, &code, &mut locals);
+
+        assert_eq!(result.unwrap_err().kind, TrapKind::MemoryOutOfBounds);
+    }
+
+    #[test]
+    fn store_out_of_bounds() {
+        // The offset is added to the unsigned base address
+        let code = vec![
+            Instruction::I32Const(-1),
+            Instruction::I32Const(0),
+            Instruction::I32Store8(MemArg {
+                align: 0,
+                offset: 1,
+            }),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        let result = machine.invoke(&mut store, &instance, &code, &mut locals);
+
+        assert_eq!(result.unwrap_err().kind, TrapKind::MemoryOutOfBounds);
+    }
+
+    #[test]
+    fn memory_size_and_grow() {
+        let code = vec![
+            Instruction::I32Const(1),
+            Instruction::MemoryGrow,
+            Instruction::I32Const(1),
+            Instruction::MemoryGrow,
+            Instruction::MemorySize,
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1 2)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(
+            machine.stack,
+            vec![Value::I32(1), Value::I32(-1), Value::I32(2)]
+        );
+    }
+
+    #[test]
+    fn add() {
         let a = 1;
         let b = 2;
 
@@ -271,390 +1411,1637 @@ mod tests {
             Instruction::I32Add,
         ];
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(a + b)]);
+    }
+
+    #[test]
+    fn sub() {
+        let a = 1;
+        let b = 2;
+
+        let code = vec![
+            Instruction::I32Const(a),
+            Instruction::I32Const(b),
+            Instruction::I32Sub,
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(a - b)]);
+    }
+
+    #[test]
+    fn mul() {
+        let a = 2;
+        let b = 3;
+
+        let code = vec![
+            Instruction::I32Const(a),
+            Instruction::I32Const(b),
+            Instruction::I32Mul,
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(a * b)]);
+    }
+
+    #[test]
+    fn eq() {
+        let a = 2;
+        let b = 3;
+        let c = 3;
+
+        let code = vec![
+            Instruction::I32Const(a),
+            Instruction::I32Const(b),
+            Instruction::I32Eq,
+            Instruction::I32Const(b),
+            Instruction::I32Const(c),
+            Instruction::I32Eq,
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(
+            machine.stack,
+            vec![Value::I32((a == b) as i32), Value::I32((b == c) as i32)]
+        );
+    }
+
+    #[test]
+    fn localget() {
+        let code = vec![Instruction::LocalGet(LocalIdx(0))];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![Value::I32(42)];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
+    }
+
+    #[test]
+    fn call_module_function() {
+        let code = vec![Instruction::Call(FuncIdx(0))];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(func (result i32) i32.const 42)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
+    }
+
+    #[test]
+    fn call_module_function_with_args() {
+        let a = 5;
+        let b = 3;
+
+        let code = vec![
+            Instruction::I32Const(a),
+            Instruction::I32Const(b),
+            Instruction::Call(FuncIdx(0)),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(
+            &mut store,
+            "(func (param i32 i32) (result i32) local.get 0 local.get 1 i32.sub)",
+            &[],
+        );
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(a - b)]);
+    }
+
+    #[test]
+    fn call_extern_function() {
+        let code = vec![Instruction::Call(FuncIdx(0))];
+
+        let mut store = Store::with_data(false);
+        let function = Func::wrap(&mut store, |mut caller: Caller<bool>| {
+            *caller.data_mut() = true;
+        });
+        let instance = instantiate(
+            &mut store,
+            r#"(import "env" "f" (func))"#,
+            &[Extern::Func(function.address())],
+        );
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert!(store.data());
+    }
+
+    #[test]
+    fn call_extern_function_with_args() {
+        let a = 5;
+        let b = 3;
+
+        let code = vec![
+            Instruction::I32Const(a),
+            Instruction::I32Const(b),
+            Instruction::Call(FuncIdx(0)),
+        ];
+
+        let mut store = Store::new();
+        let function = Func::wrap(&mut store, |a: i32, b: i32| a - b);
+        let instance = instantiate(
+            &mut store,
+            r#"(import "env" "f" (func (param i32 i32) (result i32)))"#,
+            &[Extern::Func(function.address())],
+        );
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(a - b)]);
+    }
+
+    #[test]
+    fn return_statement() {
+        let code = vec![
+            Instruction::I32Const(42),
+            Instruction::Block(
+                BlockType::Empty,
+                vec![
+                    Instruction::Return,
+                    Instruction::I32Const(43),
+                    Instruction::I32Const(44),
+                ],
+            ),
+            Instruction::I32Const(45),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
+    }
+
+    #[test]
+    fn return_keeps_only_results() {
+        let code = vec![
+            Instruction::I32Const(7),
+            Instruction::Call(FuncIdx(0)),
+            Instruction::Call(FuncIdx(1)),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(
+            &mut store,
+            r#"
+            (func (result i32)
+              i32.const 1 i32.const 2 (block i32.const 3 return) unreachable)
+            (func (result i32 i64)
+              i32.const 4 i32.const 5 i64.const 6 br 0)
+            "#,
+            &[],
+        );
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(
+            machine.stack,
+            vec![Value::I32(7), Value::I32(3), Value::I32(5), Value::I64(6)]
+        );
+    }
+
+    #[test]
+    fn declared_locals() {
+        let code = vec![
+            Instruction::I32Const(5),
+            Instruction::Call(FuncIdx(0)),
+            Instruction::Call(FuncIdx(1)),
+            Instruction::Call(FuncIdx(1)),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(
+            &mut store,
+            r#"
+            (func (param i32) (result i32 i64 f32) (local i64 f32)
+              local.get 0 local.get 1 local.get 2)
+            (func (result i32) (local i32)
+              (local.set 0 (i32.add (local.get 0) (i32.const 1)))
+              local.get 0)
+            "#,
+            &[],
+        );
         let mut locals = vec![];
 
         let mut machine = Machine::new();
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        // Every call starts with zeroed locals
+        assert_eq!(
+            machine.stack,
+            vec![
+                Value::I32(5),
+                Value::I64(0),
+                Value::F32(0.0),
+                Value::I32(1),
+                Value::I32(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn result_types_are_checked() {
+        let mut machine = Machine::new();
+        machine.stack = vec![Value::I32(1), Value::F32(2.0)];
+
+        let i32 = ValueType::NumType(NumType::I32);
+        let f32 = ValueType::NumType(NumType::F32);
+
+        assert!(machine.check_results(&[f32]).is_ok());
+        assert!(machine.check_results(&[i32, f32]).is_ok());
+        assert_eq!(
+            machine.check_results(&[f32, f32]),
+            Err(Trap::new(TrapKind::OperandTypeMismatch))
+        );
+        assert_eq!(
+            machine.check_results(&[i32, i32, f32]),
+            Err(Trap::new(TrapKind::OperandStackUnderflow))
+        );
+    }
+
+    #[test]
+    fn simple_break() {
+        let code = vec![
+            Instruction::I32Const(42),
+            Instruction::Branch(LabelIdx(0)),
+            Instruction::I32Const(43),
+            Instruction::I32Const(44),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
+    }
+
+    #[test]
+    fn nested_break_single() {
+        let code = vec![
+            Instruction::I32Const(42),
+            Instruction::Block(
+                BlockType::Empty,
+                vec![
+                    Instruction::Branch(LabelIdx(0)),
+                    Instruction::I32Const(43),
+                    Instruction::I32Const(44),
+                ],
+            ),
+            Instruction::I32Const(45),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42), Value::I32(45)]);
+    }
+
+    #[test]
+    fn nested_break_double() {
+        let code = vec![
+            Instruction::I32Const(42),
+            Instruction::Block(
+                BlockType::Empty,
+                vec![
+                    Instruction::Branch(LabelIdx(1)),
+                    Instruction::I32Const(43),
+                    Instruction::I32Const(44),
+                ],
+            ),
+            Instruction::I32Const(45),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
+    }
+
+    #[test]
+    fn break_with_result() {
+        let code = vec![
+            Instruction::I32Const(42),
+            Instruction::Block(
+                BlockType::Value(ValueType::NumType(NumType::I32)),
+                vec![
+                    Instruction::I32Const(43),
+                    Instruction::I32Const(44),
+                    Instruction::Branch(LabelIdx(0)),
+                    Instruction::I32Const(45),
+                ],
+            ),
+            Instruction::I32Const(46),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(
+            machine.stack,
+            vec![Value::I32(42), Value::I32(44), Value::I32(46)]
+        );
+    }
+
+    #[test]
+    fn break_with_params_and_result() {
+        let code = vec![
+            Instruction::I32Const(42),
+            Instruction::I32Const(1),
+            Instruction::Block(
+                BlockType::TypeIdx(TypeIdx(0)),
+                vec![
+                    Instruction::I32Const(2),
+                    Instruction::I32Add,
+                    Instruction::I32Const(43),
+                    Instruction::I32Const(44),
+                    Instruction::Branch(LabelIdx(0)),
+                ],
+            ),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(type (func (param i32) (result i32)))", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42), Value::I32(44)]);
+    }
+
+    #[test]
+    fn simple_break_if() {
+        let code = vec![
+            Instruction::I32Const(0),
+            Instruction::BranchIf(LabelIdx(0)),
+            Instruction::I32Const(42),
+            Instruction::I32Const(1),
+            Instruction::BranchIf(LabelIdx(0)),
+            Instruction::I32Const(45),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
+    }
+
+    #[test]
+    fn loop_statement() {
+        // int i = 0;
+        // while (true) {
+        //   if (i == 4) break;
+        //   sum += 42;
+        //   i++;
+        //   continue;
+        // }
+
+        let code = vec![
+            Instruction::I32Const(0),
+            Instruction::I32Const(0),
+            Instruction::I32Store(MemArg {
+                align: 0,
+                offset: 0,
+            }),
+            Instruction::Loop(
+                BlockType::Empty,
+                vec![
+                    Instruction::I32Const(0),
+                    Instruction::I32Load(MemArg {
+                        align: 0,
+                        offset: 0,
+                    }),
+                    Instruction::I32Const(4),
+                    Instruction::I32Eq,
+                    Instruction::BranchIf(LabelIdx(1)),
+                    Instruction::LocalGet(LocalIdx(0)),
+                    Instruction::I32Const(42),
+                    Instruction::I32Add,
+                    Instruction::LocalSet(LocalIdx(0)),
+                    Instruction::I32Const(0),
+                    Instruction::I32Const(0),
+                    Instruction::I32Load(MemArg {
+                        align: 0,
+                        offset: 0,
+                    }),
+                    Instruction::I32Const(1),
+                    Instruction::I32Add,
+                    Instruction::I32Store(MemArg {
+                        align: 0,
+                        offset: 0,
+                    }),
+                    Instruction::Branch(LabelIdx(0)),
+                ],
+            ),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![Value::I32(0)];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![]);
+        assert_eq!(locals, vec![Value::I32(4 * 42)]);
+    }
+
+    #[test]
+    fn loop_fallthrough() {
+        let code = vec![
+            Instruction::Loop(BlockType::Empty, vec![Instruction::I32Const(42)]),
+            Instruction::I32Const(43),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42), Value::I32(43)]);
+    }
+
+    #[test]
+    fn if_else() {
+        let code = |condition| {
+            vec![
+                Instruction::I32Const(condition),
+                Instruction::If(
+                    BlockType::Value(ValueType::NumType(NumType::I32)),
+                    vec![Instruction::I32Const(1)],
+                    vec![
+                        Instruction::I32Const(2),
+                        Instruction::I32Const(3),
+                        Instruction::Branch(LabelIdx(0)),
+                    ],
+                ),
+            ]
+        };
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code(-1), &mut locals)
+            .unwrap();
+        machine
+            .invoke(&mut store, &instance, &code(0), &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(1), Value::I32(3)]);
+    }
+
+    #[test]
+    fn if_without_else() {
+        let code = vec![
+            Instruction::I32Const(42),
+            Instruction::I32Const(0),
+            Instruction::If(BlockType::Empty, vec![Instruction::Unreachable], vec![]),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
+    }
+
+    #[test]
+    fn branch_table() {
+        // Each target sets local 0 to its own number, the default target leaves it unchanged
+        let code = |index| {
+            vec![Instruction::Block(
+                BlockType::Empty,
+                vec![
+                    Instruction::Block(
+                        BlockType::Empty,
+                        vec![
+                            Instruction::Block(
+                                BlockType::Empty,
+                                vec![
+                                    Instruction::I32Const(index),
+                                    Instruction::BranchTable(
+                                        vec![LabelIdx(0), LabelIdx(1)],
+                                        LabelIdx(2),
+                                    ),
+                                ],
+                            ),
+                            Instruction::I32Const(0),
+                            Instruction::LocalSet(LocalIdx(0)),
+                            Instruction::Branch(LabelIdx(1)),
+                        ],
+                    ),
+                    Instruction::I32Const(1),
+                    Instruction::LocalSet(LocalIdx(0)),
+                ],
+            )]
+        };
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+
+        let mut machine = Machine::new();
+
+        for (index, target) in [(0, 0), (1, 1), (2, -1), (7, -1), (-1, -1)] {
+            let mut locals = vec![Value::I32(-1)];
+
+            machine
+                .invoke(&mut store, &instance, &code(index), &mut locals)
+                .unwrap();
+
+            assert_eq!(locals, vec![Value::I32(target)]);
+        }
+
+        assert_eq!(machine.stack, vec![]);
+    }
+
+    #[test]
+    fn drop_and_select() {
+        let code = vec![
+            Instruction::I32Const(1),
+            Instruction::I32Const(2),
+            Instruction::Drop,
+            Instruction::F32Const(1.0),
+            Instruction::F32Const(2.0),
+            Instruction::I32Const(0),
+            Instruction::Select,
+            Instruction::I64Const(1),
+            Instruction::I64Const(2),
+            Instruction::I32Const(7),
+            Instruction::Select,
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(
+            machine.stack,
+            vec![Value::I32(1), Value::F32(2.0), Value::I64(1)]
+        );
+    }
+
+    #[test]
+    fn localset_and_localtee() {
+        let code = vec![
+            Instruction::I32Const(1),
+            Instruction::LocalSet(LocalIdx(0)),
+            Instruction::I64Const(2),
+            Instruction::LocalTee(LocalIdx(1)),
+        ];
+
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![Value::I32(0), Value::I64(0)];
+
+        let mut machine = Machine::new();
+
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
+
+        assert_eq!(machine.stack, vec![Value::I64(2)]);
+        assert_eq!(locals, vec![Value::I32(1), Value::I64(2)]);
+    }
+
+    /// Executes code that needs no module, and returns its single result
+    fn evaluate(code: &[Instruction]) -> Result<Value, TrapKind> {
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "", &[]);
+
+        let mut machine = Machine::new();
+        machine.debugging = false;
+
+        machine
+            .invoke(&mut store, &instance, code, &mut vec![])
+            .map_err(|trap| trap.kind)?;
+
+        assert_eq!(machine.stack.len(), 1);
+        Ok(machine.stack[0])
+    }
+
+    fn i32_unary(instruction: Instruction, value: i32) -> Result<Value, TrapKind> {
+        evaluate(&[Instruction::I32Const(value), instruction])
+    }
+
+    fn i32_binary(instruction: Instruction, left: i32, right: i32) -> Result<Value, TrapKind> {
+        evaluate(&[
+            Instruction::I32Const(left),
+            Instruction::I32Const(right),
+            instruction,
+        ])
+    }
+
+    fn i64_unary(instruction: Instruction, value: i64) -> Result<Value, TrapKind> {
+        evaluate(&[Instruction::I64Const(value), instruction])
+    }
+
+    fn i64_binary(instruction: Instruction, left: i64, right: i64) -> Result<Value, TrapKind> {
+        evaluate(&[
+            Instruction::I64Const(left),
+            Instruction::I64Const(right),
+            instruction,
+        ])
+    }
+
+    #[test]
+    fn integer_arithmetic_wraps() {
+        use Instruction::*;
+
+        assert_eq!(i32_binary(I32Add, i32::MAX, 1), Ok(Value::I32(i32::MIN)));
+        assert_eq!(i32_binary(I32Sub, i32::MIN, 1), Ok(Value::I32(i32::MAX)));
+        assert_eq!(i32_binary(I32Mul, 0x10000, 0x10000), Ok(Value::I32(0)));
+        assert_eq!(i32_binary(I32Mul, i32::MIN, -1), Ok(Value::I32(i32::MIN)));
+
+        assert_eq!(i64_binary(I64Add, i64::MAX, 1), Ok(Value::I64(i64::MIN)));
+        assert_eq!(i64_binary(I64Sub, i64::MIN, 1), Ok(Value::I64(i64::MAX)));
+        assert_eq!(
+            i64_binary(I64Mul, 0x1_0000_0000, 0x1_0000_0000),
+            Ok(Value::I64(0))
+        );
+    }
+
+    #[test]
+    fn integer_division() {
+        use Instruction::*;
+
+        // Signed division truncates towards zero
+        assert_eq!(i32_binary(I32DivSigned, 7, -2), Ok(Value::I32(-3)));
+        assert_eq!(i32_binary(I32DivSigned, -7, 2), Ok(Value::I32(-3)));
+        assert_eq!(i32_binary(I32RemSigned, -7, 2), Ok(Value::I32(-1)));
+        assert_eq!(i32_binary(I32RemSigned, 7, -2), Ok(Value::I32(1)));
+        assert_eq!(i32_binary(I32DivUnsigned, -1, 2), Ok(Value::I32(i32::MAX)));
+        assert_eq!(i32_binary(I32RemUnsigned, -1, 10), Ok(Value::I32(5)));
+
+        assert_eq!(
+            i32_binary(I32DivSigned, i32::MIN, -1),
+            Err(TrapKind::IntegerOverflow)
+        );
+        assert_eq!(i32_binary(I32RemSigned, i32::MIN, -1), Ok(Value::I32(0)));
+
+        for instruction in [I32DivSigned, I32DivUnsigned, I32RemSigned, I32RemUnsigned] {
+            assert_eq!(
+                i32_binary(instruction, 1, 0),
+                Err(TrapKind::IntegerDivideByZero)
+            );
+        }
+
+        assert_eq!(i64_binary(I64DivSigned, -7, 2), Ok(Value::I64(-3)));
+        assert_eq!(i64_binary(I64RemSigned, -7, 2), Ok(Value::I64(-1)));
+        assert_eq!(i64_binary(I64DivUnsigned, -1, 2), Ok(Value::I64(i64::MAX)));
+        assert_eq!(i64_binary(I64RemUnsigned, -1, 10), Ok(Value::I64(5)));
+
+        assert_eq!(
+            i64_binary(I64DivSigned, i64::MIN, -1),
+            Err(TrapKind::IntegerOverflow)
+        );
+        assert_eq!(i64_binary(I64RemSigned, i64::MIN, -1), Ok(Value::I64(0)));
+
+        for instruction in [I64DivSigned, I64DivUnsigned, I64RemSigned, I64RemUnsigned] {
+            assert_eq!(
+                i64_binary(instruction, 1, 0),
+                Err(TrapKind::IntegerDivideByZero)
+            );
+        }
+    }
+
+    #[test]
+    fn integer_bitwise_operations() {
+        use Instruction::*;
+
+        assert_eq!(i32_binary(I32And, 0b1100, 0b1010), Ok(Value::I32(0b1000)));
+        assert_eq!(i32_binary(I32Or, 0b1100, 0b1010), Ok(Value::I32(0b1110)));
+        assert_eq!(i32_binary(I32Xor, 0b1100, 0b1010), Ok(Value::I32(0b0110)));
+
+        // Shift and rotate counts are taken modulo the bit width
+        assert_eq!(i32_binary(I32Shl, 1, 33), Ok(Value::I32(2)));
+        assert_eq!(i32_binary(I32ShrSigned, -8, 1), Ok(Value::I32(-4)));
+        assert_eq!(
+            i32_binary(I32ShrUnsigned, -8, 1),
+            Ok(Value::I32(0x7FFF_FFFC))
+        );
+        assert_eq!(i32_binary(I32ShrSigned, i32::MIN, -1), Ok(Value::I32(-1)));
+        assert_eq!(
+            i32_binary(I32Rotl, 0x8000_0001u32 as i32, 1),
+            Ok(Value::I32(3))
+        );
+        assert_eq!(i32_binary(I32Rotr, 1, 33), Ok(Value::I32(i32::MIN)));
+
+        assert_eq!(i32_unary(I32Clz, 0), Ok(Value::I32(32)));
+        assert_eq!(i32_unary(I32Clz, 1), Ok(Value::I32(31)));
+        assert_eq!(i32_unary(I32Ctz, 0), Ok(Value::I32(32)));
+        assert_eq!(i32_unary(I32Ctz, 8), Ok(Value::I32(3)));
+        assert_eq!(i32_unary(I32Popcnt, -1), Ok(Value::I32(32)));
+
+        assert_eq!(i64_binary(I64And, -1, 0xFF), Ok(Value::I64(0xFF)));
+        assert_eq!(i64_binary(I64Shl, 1, 65), Ok(Value::I64(2)));
+        assert_eq!(i64_binary(I64ShrSigned, -8, 1), Ok(Value::I64(-4)));
+        assert_eq!(i64_binary(I64ShrUnsigned, -1, 60), Ok(Value::I64(0xF)));
+        assert_eq!(i64_binary(I64Rotl, i64::MIN, 1), Ok(Value::I64(1)));
+        assert_eq!(i64_binary(I64Rotr, 1, 65), Ok(Value::I64(i64::MIN)));
+
+        // The results of bit counting have the type of the operand
+        assert_eq!(i64_unary(I64Clz, 1), Ok(Value::I64(63)));
+        assert_eq!(i64_unary(I64Ctz, 0), Ok(Value::I64(64)));
+        assert_eq!(i64_unary(I64Popcnt, -1), Ok(Value::I64(64)));
+    }
+
+    #[test]
+    fn integer_comparison() {
+        use Instruction::*;
+
+        assert_eq!(i32_unary(I32Eqz, 0), Ok(Value::I32(1)));
+        assert_eq!(i32_unary(I32Eqz, -1), Ok(Value::I32(0)));
+        assert_eq!(i32_binary(I32Ne, 1, 2), Ok(Value::I32(1)));
+        assert_eq!(i32_binary(I32LtSigned, -1, 1), Ok(Value::I32(1)));
+        assert_eq!(i32_binary(I32LtUnsigned, -1, 1), Ok(Value::I32(0)));
+        assert_eq!(i32_binary(I32GtSigned, -1, 1), Ok(Value::I32(0)));
+        assert_eq!(i32_binary(I32GtUnsigned, -1, 1), Ok(Value::I32(1)));
+        assert_eq!(i32_binary(I32LeSigned, 2, 2), Ok(Value::I32(1)));
+        assert_eq!(i32_binary(I32LeUnsigned, -1, 2), Ok(Value::I32(0)));
+        assert_eq!(i32_binary(I32GeSigned, 0, -1), Ok(Value::I32(1)));
+        assert_eq!(i32_binary(I32GeUnsigned, 0, -1), Ok(Value::I32(0)));
+
+        // Comparisons of 64-bit integers result in an i32
+        assert_eq!(i64_unary(I64Eqz, 0), Ok(Value::I32(1)));
+        assert_eq!(i64_binary(I64Ne, 1, 1), Ok(Value::I32(0)));
+        assert_eq!(i64_binary(I64LtSigned, -1, 1), Ok(Value::I32(1)));
+        assert_eq!(i64_binary(I64LtUnsigned, -1, 1), Ok(Value::I32(0)));
+        assert_eq!(i64_binary(I64GtUnsigned, -1, 1), Ok(Value::I32(1)));
+        assert_eq!(
+            i64_binary(I64LeSigned, i64::MIN, i64::MAX),
+            Ok(Value::I32(1))
+        );
+        assert_eq!(
+            i64_binary(I64GeUnsigned, i64::MIN, i64::MAX),
+            Ok(Value::I32(1))
+        );
+    }
+
+    #[test]
+    fn integer_conversions() {
+        use Instruction::*;
+
+        // Wrapping keeps the low 32 bits
+        assert_eq!(
+            i64_unary(I32WrapI64, 0x1_2345_6789),
+            Ok(Value::I32(0x2345_6789))
+        );
+        assert_eq!(i64_unary(I32WrapI64, 0xFFFF_FFFF), Ok(Value::I32(-1)));
+        assert_eq!(i64_unary(I32WrapI64, i64::MIN), Ok(Value::I32(0)));
+
+        assert_eq!(i32_unary(I64ExtendI32Signed, -1), Ok(Value::I64(-1)));
+        assert_eq!(
+            i32_unary(I64ExtendI32Signed, i32::MAX),
+            Ok(Value::I64(0x7FFF_FFFF))
+        );
+        assert_eq!(
+            i32_unary(I64ExtendI32Unsigned, -1),
+            Ok(Value::I64(0xFFFF_FFFF))
+        );
+        assert_eq!(
+            i32_unary(I64ExtendI32Unsigned, i32::MIN),
+            Ok(Value::I64(0x8000_0000))
+        );
+    }
+
+    fn f32_unary(instruction: Instruction, value: f32) -> u32 {
+        match evaluate(&[Instruction::F32Const(value), instruction]) {
+            Ok(Value::F32(result)) => result.to_bits(),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    fn f32_binary(instruction: Instruction, left: f32, right: f32) -> u32 {
+        let code = [
+            Instruction::F32Const(left),
+            Instruction::F32Const(right),
+            instruction,
+        ];
+        match evaluate(&code) {
+            Ok(Value::F32(result)) => result.to_bits(),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    fn f64_unary(instruction: Instruction, value: f64) -> u64 {
+        match evaluate(&[Instruction::F64Const(value), instruction]) {
+            Ok(Value::F64(result)) => result.to_bits(),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    fn f64_binary(instruction: Instruction, left: f64, right: f64) -> u64 {
+        let code = [
+            Instruction::F64Const(left),
+            Instruction::F64Const(right),
+            instruction,
+        ];
+        match evaluate(&code) {
+            Ok(Value::F64(result)) => result.to_bits(),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    const F32_CANONICAL_NAN: u32 = 0x7FC0_0000;
+    const F64_CANONICAL_NAN: u64 = 0x7FF8_0000_0000_0000;
 
-        assert_eq!(machine.stack, vec![a + b]);
+    #[test]
+    fn float_nan_results_are_canonical() {
+        use Instruction::*;
+
+        let signaling_nan = f32::from_bits(0x7FA0_0001);
+        assert_eq!(f32_binary(F32Add, signaling_nan, 1.0), F32_CANONICAL_NAN);
+        assert_eq!(
+            f32_binary(F32Sub, f32::INFINITY, f32::INFINITY),
+            F32_CANONICAL_NAN
+        );
+        assert_eq!(f32_binary(F32Div, 0.0, 0.0), F32_CANONICAL_NAN);
+        assert_eq!(f32_unary(F32Sqrt, -1.0), F32_CANONICAL_NAN);
+        assert_eq!(f32_unary(F32Floor, -signaling_nan), F32_CANONICAL_NAN);
+
+        let negative_nan = f64::from_bits(0xFFF0_0000_0000_0001);
+        assert_eq!(f64_binary(F64Mul, f64::INFINITY, 0.0), F64_CANONICAL_NAN);
+        assert_eq!(f64_binary(F64Sub, 1.0, negative_nan), F64_CANONICAL_NAN);
+        assert_eq!(f64_unary(F64Sqrt, -1.0), F64_CANONICAL_NAN);
+        assert_eq!(f64_unary(F64Nearest, negative_nan), F64_CANONICAL_NAN);
+
+        // Comparisons with NaN are false, except for inequality
+        let nan = f64::NAN;
+        assert_eq!(
+            evaluate(&[F64Const(nan), F64Const(nan), F64Eq]),
+            Ok(Value::I32(0))
+        );
+        assert_eq!(
+            evaluate(&[F64Const(nan), F64Const(nan), F64Ne]),
+            Ok(Value::I32(1))
+        );
+        assert_eq!(
+            evaluate(&[F32Const(1.0), F32Const(f32::NAN), F32Ge]),
+            Ok(Value::I32(0))
+        );
     }
 
     #[test]
-    fn sub() {
-        let a = 1;
-        let b = 2;
-
-        let code = vec![
-            Instruction::I32Const(a),
-            Instruction::I32Const(b),
-            Instruction::I32Sub,
-        ];
+    fn float_sign_operations() {
+        use Instruction::*;
+
+        assert_eq!(f32_unary(F32Abs, -0.0), 0.0f32.to_bits());
+        assert_eq!(f32_unary(F32Neg, 0.0), (-0.0f32).to_bits());
+        assert_eq!(f32_binary(F32Copysign, 2.0, -0.0), (-2.0f32).to_bits());
+        assert_eq!(f64_unary(F64Neg, -1.5), 1.5f64.to_bits());
+        assert_eq!(f64_binary(F64Copysign, -3.0, 1.0), 3.0f64.to_bits());
+
+        // NaN payloads are kept
+        assert_eq!(f32_unary(F32Abs, f32::from_bits(0xFFA0_0001)), 0x7FA0_0001);
+        assert_eq!(f32_unary(F32Neg, f32::from_bits(0x7FA0_0001)), 0xFFA0_0001);
+        assert_eq!(
+            f64_binary(F64Copysign, f64::from_bits(0x7FF0_0000_0000_0001), -1.0),
+            0xFFF0_0000_0000_0001
+        );
+    }
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
-        let mut locals = vec![];
+    #[test]
+    fn float_rounding() {
+        use Instruction::*;
+
+        // Ties are rounded to even
+        assert_eq!(f32_unary(F32Nearest, 0.5), 0.0f32.to_bits());
+        assert_eq!(f32_unary(F32Nearest, 1.5), 2.0f32.to_bits());
+        assert_eq!(f32_unary(F32Nearest, 2.5), 2.0f32.to_bits());
+        assert_eq!(f32_unary(F32Nearest, -0.5), (-0.0f32).to_bits());
+        assert_eq!(f64_unary(F64Nearest, -2.5), (-2.0f64).to_bits());
+        assert_eq!(f64_unary(F64Nearest, 3.5), 4.0f64.to_bits());
+        assert_eq!(
+            f64_unary(F64Nearest, 4503599627370497.0),
+            4503599627370497.0f64.to_bits()
+        );
 
-        let mut machine = Machine::new();
+        assert_eq!(f32_unary(F32Ceil, -0.5), (-0.0f32).to_bits());
+        assert_eq!(f32_unary(F32Floor, -0.5), (-1.0f32).to_bits());
+        assert_eq!(f32_unary(F32Trunc, -1.7), (-1.0f32).to_bits());
+        assert_eq!(f64_unary(F64Ceil, 1.2), 2.0f64.to_bits());
+        assert_eq!(f64_unary(F64Floor, 1.8), 1.0f64.to_bits());
+        assert_eq!(f64_unary(F64Trunc, -0.7), (-0.0f64).to_bits());
+        assert_eq!(f64_unary(F64Sqrt, 2.25), 1.5f64.to_bits());
+        assert_eq!(
+            f64_unary(F64Floor, f64::NEG_INFINITY),
+            f64::NEG_INFINITY.to_bits()
+        );
+    }
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+    #[test]
+    fn float_min_and_max() {
+        use Instruction::*;
+
+        // Negative zero is smaller than positive zero
+        assert_eq!(f32_binary(F32Min, 0.0, -0.0), (-0.0f32).to_bits());
+        assert_eq!(f32_binary(F32Min, -0.0, 0.0), (-0.0f32).to_bits());
+        assert_eq!(f32_binary(F32Max, -0.0, 0.0), 0.0f32.to_bits());
+        assert_eq!(f32_binary(F32Max, 0.0, -0.0), 0.0f32.to_bits());
+        assert_eq!(f64_binary(F64Min, 0.0, -0.0), (-0.0f64).to_bits());
+        assert_eq!(f64_binary(F64Max, -0.0, 0.0), 0.0f64.to_bits());
+
+        assert_eq!(f32_binary(F32Min, -1.0, 2.0), (-1.0f32).to_bits());
+        assert_eq!(f32_binary(F32Max, -1.0, 2.0), 2.0f32.to_bits());
+        assert_eq!(
+            f64_binary(F64Min, f64::NEG_INFINITY, 1.0),
+            f64::NEG_INFINITY.to_bits()
+        );
 
-        assert_eq!(machine.stack, vec![a - b]);
+        // Unlike Rust's `min` and `max`, a NaN operand results in NaN
+        assert_eq!(f32_binary(F32Min, 1.0, f32::NAN), F32_CANONICAL_NAN);
+        assert_eq!(f32_binary(F32Max, f32::NAN, 1.0), F32_CANONICAL_NAN);
+        assert_eq!(f64_binary(F64Min, f64::NAN, 1.0), F64_CANONICAL_NAN);
+        assert_eq!(
+            f64_binary(F64Max, 1.0, f64::from_bits(0x7FF0_0000_0000_0001)),
+            F64_CANONICAL_NAN
+        );
     }
 
     #[test]
-    fn mul() {
-        let a = 2;
-        let b = 3;
-
+    fn float_arithmetic() {
         let code = vec![
-            Instruction::I32Const(a),
-            Instruction::I32Const(b),
-            Instruction::I32Mul,
+            Instruction::F64Const(1.5),
+            Instruction::F64Const(2.0),
+            Instruction::F64Mul,
+            Instruction::F64Const(0.5),
+            Instruction::F64Sub,
+            Instruction::F32Const(1.0),
+            Instruction::F32Const(4.0),
+            Instruction::F32Div,
         ];
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
         let mut locals = vec![];
 
         let mut machine = Machine::new();
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
 
-        assert_eq!(machine.stack, vec![a * b]);
+        assert_eq!(machine.stack, vec![Value::F64(2.5), Value::F32(0.25)]);
     }
 
-    #[test]
-    fn eq() {
-        let a = 2;
-        let b = 3;
-        let c = 3;
+    fn f32_convert(instruction: Instruction, value: f32) -> Result<Value, TrapKind> {
+        evaluate(&[Instruction::F32Const(value), instruction])
+    }
 
-        let code = vec![
-            Instruction::I32Const(a),
-            Instruction::I32Const(b),
-            Instruction::I32Eq,
-            Instruction::I32Const(b),
-            Instruction::I32Const(c),
-            Instruction::I32Eq,
-        ];
+    fn f64_convert(instruction: Instruction, value: f64) -> Result<Value, TrapKind> {
+        evaluate(&[Instruction::F64Const(value), instruction])
+    }
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
-        let mut locals = vec![];
+    #[test]
+    fn float_to_integer_truncation() {
+        use Instruction::*;
 
-        let mut machine = Machine::new();
+        assert_eq!(f32_convert(I32TruncF32Signed, -1.9), Ok(Value::I32(-1)));
+        assert_eq!(
+            f32_convert(I32TruncF32Signed, -2147483648.0),
+            Ok(Value::I32(i32::MIN))
+        );
+        assert_eq!(
+            f32_convert(I32TruncF32Signed, 2147483520.0),
+            Ok(Value::I32(2147483520))
+        );
+        assert_eq!(
+            f32_convert(I32TruncF32Signed, 2147483648.0),
+            Err(TrapKind::IntegerOverflow)
+        );
+        assert_eq!(
+            f64_convert(I32TruncF64Signed, 2147483647.9),
+            Ok(Value::I32(i32::MAX))
+        );
+        assert_eq!(
+            f64_convert(I32TruncF64Signed, -2147483648.9),
+            Ok(Value::I32(i32::MIN))
+        );
+        assert_eq!(
+            f64_convert(I32TruncF64Signed, -2147483649.0),
+            Err(TrapKind::IntegerOverflow)
+        );
+
+        // Values that truncate to zero are in range of the unsigned types
+        assert_eq!(f32_convert(I32TruncF32Unsigned, -0.9), Ok(Value::I32(0)));
+        assert_eq!(
+            f32_convert(I32TruncF32Unsigned, -1.0),
+            Err(TrapKind::IntegerOverflow)
+        );
+        assert_eq!(
+            f64_convert(I32TruncF64Unsigned, 4294967295.5),
+            Ok(Value::I32(-1))
+        );
+        assert_eq!(
+            f64_convert(I32TruncF64Unsigned, 4294967296.0),
+            Err(TrapKind::IntegerOverflow)
+        );
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        assert_eq!(
+            f64_convert(I64TruncF64Signed, -9223372036854775808.0),
+            Ok(Value::I64(i64::MIN))
+        );
+        assert_eq!(
+            f64_convert(I64TruncF64Signed, 9223372036854775808.0),
+            Err(TrapKind::IntegerOverflow)
+        );
+        assert_eq!(
+            f32_convert(I64TruncF32Signed, -1e10),
+            Ok(Value::I64(-10_000_000_000))
+        );
+        assert_eq!(
+            f64_convert(I64TruncF64Unsigned, 18446744073709549568.0),
+            Ok(Value::I64(-2048))
+        );
+        assert_eq!(
+            f32_convert(I64TruncF32Unsigned, 18446744073709551616.0),
+            Err(TrapKind::IntegerOverflow)
+        );
 
-        assert_eq!(machine.stack, vec![(a == b) as i32, (b == c) as i32]);
+        for instruction in [I32TruncF32Signed, I32TruncF32Unsigned, I64TruncF32Signed] {
+            assert_eq!(
+                f32_convert(instruction.clone(), f32::NAN),
+                Err(TrapKind::InvalidConversionToInteger)
+            );
+            assert_eq!(
+                f32_convert(instruction, f32::INFINITY),
+                Err(TrapKind::IntegerOverflow)
+            );
+        }
+        for instruction in [I32TruncF64Unsigned, I64TruncF64Signed, I64TruncF64Unsigned] {
+            assert_eq!(
+                f64_convert(instruction.clone(), -f64::NAN),
+                Err(TrapKind::InvalidConversionToInteger)
+            );
+            assert_eq!(
+                f64_convert(instruction, f64::NEG_INFINITY),
+                Err(TrapKind::IntegerOverflow)
+            );
+        }
     }
 
     #[test]
-    fn localget() {
-        let code = vec![Instruction::LocalGet(LocalIdx(0))];
-
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
-        let mut locals = vec![42];
-
-        let mut machine = Machine::new();
+    fn integer_to_float_conversion() {
+        use Instruction::*;
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        assert_eq!(i32_unary(F32ConvertI32Signed, -1), Ok(Value::F32(-1.0)));
+        assert_eq!(
+            i32_unary(F32ConvertI32Unsigned, -1),
+            Ok(Value::F32(4294967296.0))
+        );
+        assert_eq!(
+            i32_unary(F64ConvertI32Unsigned, -1),
+            Ok(Value::F64(4294967295.0))
+        );
+        assert_eq!(
+            i32_unary(F64ConvertI32Signed, i32::MIN),
+            Ok(Value::F64(-2147483648.0))
+        );
 
-        assert_eq!(machine.stack, vec![42]);
+        // Rounds to nearest, ties to even
+        assert_eq!(
+            i32_unary(F32ConvertI32Signed, 16777217),
+            Ok(Value::F32(16777216.0))
+        );
+        assert_eq!(
+            i32_unary(F32ConvertI32Signed, 16777219),
+            Ok(Value::F32(16777220.0))
+        );
+        assert_eq!(
+            i64_unary(F32ConvertI64Signed, i64::MAX),
+            Ok(Value::F32(9223372036854775808.0))
+        );
+        assert_eq!(
+            i64_unary(F32ConvertI64Unsigned, -1),
+            Ok(Value::F32(18446744073709551616.0))
+        );
+        assert_eq!(
+            i64_unary(F64ConvertI64Signed, 0x20_0000_0000_0003),
+            Ok(Value::F64(9007199254740996.0))
+        );
+        assert_eq!(
+            i64_unary(F64ConvertI64Unsigned, i64::MIN),
+            Ok(Value::F64(9223372036854775808.0))
+        );
     }
 
     #[test]
-    fn call_module_function() {
-        let code = vec![Instruction::Call(FuncIdx(0))];
+    fn float_width_conversion() {
+        use Instruction::*;
 
-        let function = Func {
-            ftype: FuncType {
-                parameter_types: vec![],
-                result_types: vec![ValueType::NumType(NumType::I32)],
-            },
-            code: Code {
-                locals: vec![],
-                body: vec![Instruction::I32Const(42)],
-            },
-        };
+        assert_eq!(f64_convert(F32DemoteF64, 0.1), Ok(Value::F32(0.1)));
+        assert_eq!(
+            f64_convert(F32DemoteF64, 1e300),
+            Ok(Value::F32(f32::INFINITY))
+        );
+        assert_eq!(f64_convert(F32DemoteF64, 1e-300), Ok(Value::F32(0.0)));
+        assert_eq!(
+            f32_convert(F64PromoteF32, 0.1),
+            Ok(Value::F64(0.1f32 as f64))
+        );
 
-        let module_functions = vec![function];
-        let mut extern_functions = vec![];
-        let mut locals = vec![];
+        let bits = |result| match result {
+            Ok(Value::F32(value)) => value.to_bits() as u64,
+            Ok(Value::F64(value)) => value.to_bits(),
+            result => panic!("Unexpected result: {:?}", result),
+        };
+        assert_eq!(bits(f32_convert(F64PromoteF32, -0.0)), (-0.0f64).to_bits());
+
+        // NaN results are canonical
+        assert_eq!(
+            bits(f64_convert(
+                F32DemoteF64,
+                f64::from_bits(0xFFF0_0000_0000_0001)
+            )),
+            0x7FC0_0000
+        );
+        assert_eq!(
+            bits(f32_convert(F64PromoteF32, f32::from_bits(0x7FA0_0001))),
+            0x7FF8_0000_0000_0000
+        );
+    }
 
-        let mut machine = Machine::new();
+    #[test]
+    fn reinterpretation() {
+        use Instruction::*;
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        assert_eq!(
+            f32_convert(I32ReinterpretF32, -0.0),
+            Ok(Value::I32(i32::MIN))
+        );
+        assert_eq!(
+            f64_convert(I64ReinterpretF64, 1.0),
+            Ok(Value::I64(0x3FF0_0000_0000_0000))
+        );
 
-        assert_eq!(machine.stack, vec![42]);
+        // NaN payloads are kept
+        assert_eq!(
+            f32_convert(I32ReinterpretF32, f32::from_bits(0x7FA0_0001)),
+            Ok(Value::I32(0x7FA0_0001))
+        );
+        match i32_unary(F32ReinterpretI32, 0xFFA0_0001u32 as i32) {
+            Ok(Value::F32(value)) => assert_eq!(value.to_bits(), 0xFFA0_0001),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match i64_unary(F64ReinterpretI64, 0x7FF0_0000_0000_0001) {
+            Ok(Value::F64(value)) => assert_eq!(value.to_bits(), 0x7FF0_0000_0000_0001),
+            result => panic!("Unexpected result: {:?}", result),
+        }
     }
 
     #[test]
-    fn call_module_function_with_args() {
-        let a = 5;
-        let b = 3;
-
+    fn float_comparison() {
         let code = vec![
-            Instruction::I32Const(a),
-            Instruction::I32Const(b),
-            Instruction::Call(FuncIdx(0)),
+            Instruction::F64Const(1.0),
+            Instruction::F64Const(2.0),
+            Instruction::F64Lt,
+            Instruction::F64Const(f64::NAN),
+            Instruction::F64Const(f64::NAN),
+            Instruction::F64Eq,
+            Instruction::F32Const(2.0),
+            Instruction::F32Const(2.0),
+            Instruction::F32Ge,
         ];
 
-        let function = Func {
-            ftype: FuncType {
-                // TODO: simplify ValueType::NumType(NumType::I32) -> NumType::I32
-                parameter_types: vec![
-                    ValueType::NumType(NumType::I32),
-                    ValueType::NumType(NumType::I32),
-                ],
-                result_types: vec![ValueType::NumType(NumType::I32)],
-            },
-            code: Code {
-                locals: vec![],
-                body: vec![
-                    Instruction::LocalGet(LocalIdx(0)),
-                    Instruction::LocalGet(LocalIdx(1)),
-                    Instruction::I32Sub,
-                ],
-            },
-        };
-
-        let module_functions = vec![function];
-        let mut extern_functions = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
         let mut locals = vec![];
 
         let mut machine = Machine::new();
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
 
-        assert_eq!(machine.stack, vec![a - b]);
+        assert_eq!(
+            machine.stack,
+            vec![Value::I32(1), Value::I32(0), Value::I32(1)]
+        );
     }
 
     #[test]
-    fn call_extern_function() {
-        let code = vec![Instruction::Call(FuncIdx(0))];
-
-        let mut function_was_called = false;
-        {
-            let function = ExternFunction {
-                param_count: 0,
-                fun: Box::new(|_: &[i32]| {
-                    function_was_called = true;
-                    None
-                }),
-            };
+    fn i64_arithmetic() {
+        let code = vec![
+            Instruction::I64Const(i64::MAX),
+            Instruction::I64Const(1),
+            Instruction::I64Add,
+            Instruction::I64Const(i64::MIN),
+            Instruction::I64Eq,
+        ];
 
-            let module_functions = vec![];
-            let mut extern_functions = vec![function];
-            let mut locals = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
+        let mut locals = vec![];
 
-            let mut machine = Machine::new();
+        let mut machine = Machine::new();
 
-            machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
-        }
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
 
-        assert_eq!(function_was_called, true);
+        assert_eq!(machine.stack, vec![Value::I32(1)]);
     }
 
     #[test]
-    fn call_extern_function_with_args() {
-        let a = 5;
-        let b = 3;
-
+    fn call_extern_function_with_float_args() {
         let code = vec![
-            Instruction::I32Const(a),
-            Instruction::I32Const(b),
+            Instruction::F64Const(1.5),
+            Instruction::I64Const(2),
             Instruction::Call(FuncIdx(0)),
         ];
 
-        let function = ExternFunction {
-            param_count: 2,
-            fun: Box::new(|args: &[i32]| Some(args[0] - args[1])),
-        };
-
-        let module_functions = vec![];
-        let mut extern_functions = vec![function];
+        let mut store = Store::new();
+        let function = Func::wrap(&mut store, |a: f64, b: i64| a * b as f64);
+        let instance = instantiate(
+            &mut store,
+            r#"(import "env" "f" (func (param f64 i64) (result f64)))"#,
+            &[Extern::Func(function.address())],
+        );
         let mut locals = vec![];
 
         let mut machine = Machine::new();
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
 
-        assert_eq!(machine.stack, vec![a - b]);
+        assert_eq!(machine.stack, vec![Value::F64(3.0)]);
     }
 
     #[test]
-    fn return_statement() {
+    fn default_values() {
+        assert_eq!(
+            Value::default(ValueType::NumType(NumType::F64)),
+            Value::F64(0.0)
+        );
+        assert_eq!(
+            Value::default(ValueType::RefType(RefType::FuncRef)),
+            Value::FuncRef(None)
+        );
+        assert_eq!(Value::I64(7).value_type(), ValueType::NumType(NumType::I64));
+    }
+
+    #[test]
+    fn global_get_and_set() {
         let code = vec![
             Instruction::I32Const(42),
-            Instruction::Block(
-                BlockType::Empty,
-                vec![
-                    Instruction::Return,
-                    Instruction::I32Const(43),
-                    Instruction::I32Const(44),
-                ],
-            ),
-            Instruction::I32Const(45),
+            Instruction::GlobalSet(GlobalIdx(0)),
+            Instruction::GlobalGet(GlobalIdx(0)),
+            Instruction::GlobalGet(GlobalIdx(1)),
         ];
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(
+            &mut store,
+            "(global (mut i32) (i32.const 1)) (global i64 (i64.const 7))",
+            &[],
+        );
         let mut locals = vec![];
 
         let mut machine = Machine::new();
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        machine
+            .invoke(&mut store, &instance, &code, &mut locals)
+            .unwrap();
 
-        assert_eq!(machine.stack, vec![42]);
+        assert_eq!(machine.stack, vec![Value::I32(42), Value::I64(7)]);
     }
 
     #[test]
-    fn simple_break() {
-        let code = vec![
-            Instruction::I32Const(42),
-            Instruction::Branch(LabelIdx(0)),
-            Instruction::I32Const(43),
-            Instruction::I32Const(44),
+    fn global_set_checks_mutability_and_type() {
+        let mut store = Store::new();
+        let instance = instantiate(
+            &mut store,
+            "(global (mut i32) (i32.const 1)) (global i64 (i64.const 7))",
+            &[],
+        );
+
+        let mut machine = Machine::new();
+
+        let codes = [
+            vec![
+                Instruction::I64Const(2),
+                Instruction::GlobalSet(GlobalIdx(0)),
+            ],
+            vec![
+                Instruction::I64Const(2),
+                Instruction::GlobalSet(GlobalIdx(1)),
+            ],
         ];
+        for code in codes {
+            let result = machine.invoke(&mut store, &instance, &code, &mut vec![]);
+            assert_eq!(result.unwrap_err().kind, TrapKind::OperandTypeMismatch);
+        }
+
+        assert_eq!(store.global(instance.0.globals[0]), Value::I32(1));
+        assert_eq!(store.global(instance.0.globals[1]), Value::I64(7));
+    }
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+    #[test]
+    fn unreachable() {
+        let code = vec![Instruction::I32Const(42), Instruction::Call(FuncIdx(0))];
+
+        // The trap unwinds through both functions
+        let mut store = Store::new();
+        let instance = instantiate(
+            &mut store,
+            "(func (param i32) nop call 1) (func unreachable i32.const 43 drop)",
+            &[],
+        );
         let mut locals = vec![];
 
         let mut machine = Machine::new();
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        let result = machine.invoke(&mut store, &instance, &code, &mut locals);
 
-        assert_eq!(machine.stack, vec![42]);
+        let trap = result.unwrap_err();
+        assert_eq!(trap.kind, TrapKind::Unreachable);
+        assert_eq!(
+            trap.backtrace
+                .iter()
+                .map(|frame| (frame.address, frame.index))
+                .collect::<Vec<_>>(),
+            vec![(FuncAddr(1), FuncIdx(1)), (FuncAddr(0), FuncIdx(0))]
+        );
     }
 
     #[test]
-    fn nested_break_single() {
-        let code = vec![
-            Instruction::I32Const(42),
-            Instruction::Block(
-                BlockType::Empty,
-                vec![
-                    Instruction::Branch(LabelIdx(0)),
-                    Instruction::I32Const(43),
-                    Instruction::I32Const(44),
-                ],
-            ),
-            Instruction::I32Const(45),
-        ];
-
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+    fn stack_overflow() {
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(func (block (call 0)))", &[]);
         let mut locals = vec![];
 
         let mut machine = Machine::new();
-
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
-
-        assert_eq!(machine.stack, vec![42, 45]);
+        machine.debugging = false;
+
+        let result = machine.call(&mut store, instance.0.funcs[0]);
+
+        let trap = result.unwrap_err();
+        assert_eq!(trap.kind, TrapKind::StackOverflow);
+        assert_eq!(trap.backtrace.len(), MAX_CALL_DEPTH);
+
+        // The machine can be used again after a trap
+        assert!(machine.frames.is_empty());
+        machine.stack.clear();
+        machine
+            .invoke(
+                &mut store,
+                &instance,
+                &[Instruction::I32Const(42)],
+                &mut locals,
+            )
+            .unwrap();
+        assert_eq!(machine.stack, vec![Value::I32(42)]);
     }
 
     #[test]
-    fn nested_break_double() {
-        let code = vec![
-            Instruction::I32Const(42),
-            Instruction::Block(
-                BlockType::Empty,
-                vec![
-                    Instruction::Branch(LabelIdx(1)),
-                    Instruction::I32Const(43),
-                    Instruction::I32Const(44),
-                ],
-            ),
-            Instruction::I32Const(45),
-        ];
+    fn deep_recursion_in_nested_blocks() {
+        // Counts down to zero, making each call from within 100 nested blocks
+        let text = format!(
+            r#"
+            (func (param i32) (result i32)
+              (if (result i32) (i32.eqz (local.get 0))
+                (then (i32.const 0))
+                (else
+                  {}
+                  (i32.add (call 0 (i32.sub (local.get 0) (i32.const 1))) (i32.const 1))
+                  {})))
+            "#,
+            "block (result i32) ".repeat(100),
+            "end ".repeat(100)
+        );
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
-        let mut locals = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, &text, &[]);
 
         let mut machine = Machine::new();
+        machine.debugging = false;
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        let depth = MAX_CALL_DEPTH as i32 - 1;
+        machine.stack.push(Value::I32(depth));
+        machine.call(&mut store, instance.0.funcs[0]).unwrap();
+        assert_eq!(machine.stack, vec![Value::I32(depth)]);
 
-        assert_eq!(machine.stack, vec![42]);
+        machine.stack = vec![Value::I32(depth + 1)];
+        let trap = machine.call(&mut store, instance.0.funcs[0]).unwrap_err();
+        assert_eq!(trap.kind, TrapKind::StackOverflow);
+        assert!(machine.frames.is_empty());
     }
 
     #[test]
-    fn simple_break_if() {
-        let code = vec![
-            Instruction::I32Const(0),
-            Instruction::BranchIf(LabelIdx(0)),
-            Instruction::I32Const(42),
-            Instruction::I32Const(1),
-            Instruction::BranchIf(LabelIdx(0)),
-            Instruction::I32Const(45),
-        ];
+    fn operand_stack_underflow() {
+        let code = vec![Instruction::I32Const(1), Instruction::I32Add];
 
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(memory 1)", &[]);
         let mut locals = vec![];
 
         let mut machine = Machine::new();
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        let result = machine.invoke(&mut store, &instance, &code, &mut locals);
 
-        assert_eq!(machine.stack, vec![42]);
+        assert_eq!(result.unwrap_err().kind, TrapKind::OperandStackUnderflow);
     }
 
     #[test]
-    fn loop_statement() {
-        // int i = 0;
-        // while (true) {
-        //   if (i == 4) break;
-        //   "push 42"
-        //   i++;
-        // }
-
-        let code = vec![
-            Instruction::I32Const(0),
-            Instruction::I32Store(MemArg {
-                align: 0,
-                offset: 0,
-            }),
-            Instruction::Loop(
-                BlockType::Empty,
-                vec![
-                    Instruction::I32Load(MemArg {
-                        align: 0,
-                        offset: 0,
-                    }),
-                    Instruction::I32Const(4),
-                    Instruction::I32Eq,
-                    Instruction::BranchIf(LabelIdx(1)),
-                    Instruction::I32Const(42),
-                    Instruction::I32Load(MemArg {
-                        align: 0,
-                        offset: 0,
-                    }),
-                    Instruction::I32Const(1),
-                    Instruction::I32Add,
-                    Instruction::I32Store(MemArg {
-                        align: 0,
-                        offset: 0,
-                    }),
-                ],
-            ),
-        ];
-
-        let module_functions = vec![];
-        let mut extern_functions = vec![];
-        let mut locals = vec![];
+    fn unknown_indices() {
+        let mut store = Store::new();
+        let instance = instantiate(&mut store, "(func)", &[]);
 
         let mut machine = Machine::new();
 
-        machine.invoke(&code, &module_functions, &mut extern_functions, &mut locals);
+        let codes = [
+            vec![Instruction::LocalGet(LocalIdx(5))],
+            vec![Instruction::I32Const(1), Instruction::LocalSet(LocalIdx(1))],
+            vec![Instruction::I32Const(1), Instruction::LocalTee(LocalIdx(1))],
+            vec![Instruction::Call(FuncIdx(1))],
+            vec![Instruction::RefFunc(FuncIdx(1))],
+            vec![Instruction::GlobalGet(GlobalIdx(0))],
+            vec![Instruction::MemorySize],
+            vec![Instruction::ElemDrop(ElemIdx(0))],
+        ];
+        for code in codes {
+            let mut locals = vec![Value::I32(0)];
+            let result = machine.invoke(&mut store, &instance, &code, &mut locals);
+
+            assert_eq!(
+                result.unwrap_err().kind,
+                TrapKind::UnknownIndex,
+                "{:?}",
+                code
+            );
+            assert_eq!(locals, vec![Value::I32(0)]);
+        }
+    }
+
+    #[test]
+    fn trap_message() {
+        let trap = Trap {
+            kind: TrapKind::IntegerDivideByZero,
+            backtrace: vec![
+                FrameInfo {
+                    address: FuncAddr(5),
+                    index: FuncIdx(3),
+                    module_name: Some(Name("math".to_owned())),
+                    name: Some(Name("divide".to_owned())),
+                },
+                FrameInfo {
+                    address: FuncAddr(2),
+                    index: FuncIdx(0),
+                    module_name: None,
+                    name: None,
+                },
+            ],
+        };
 
-        assert_eq!(machine.stack, vec![42, 42, 42, 42]);
+        assert_eq!(
+            trap.to_string(),
+            "wasm trap: integer divide by zero\nwasm backtrace:\n  \
+             0: divide (function 3, address 5) in module math\n  \
+             1: function 0 (address 2)"
+        );
     }
 } // mod tests
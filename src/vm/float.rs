@@ -0,0 +1,81 @@
+/// The properties of `f32` and `f64` that the WebAssembly float operations depend on
+pub(crate) trait Float: Copy + PartialOrd {
+    /// The canonical NaN: positive, quiet and without any other payload bits set
+    const CANONICAL_NAN: Self;
+
+    fn is_nan(self) -> bool;
+    fn is_sign_negative(self) -> bool;
+    fn trunc(self) -> Self;
+}
+
+macro_rules! floats {
+    ($($type:ident => $canonical_nan:expr),*) => {
+        $(
+            impl Float for $type {
+                const CANONICAL_NAN: Self = $type::from_bits($canonical_nan);
+
+                fn is_nan(self) -> bool {
+                    $type::is_nan(self)
+                }
+
+                fn is_sign_negative(self) -> bool {
+                    $type::is_sign_negative(self)
+                }
+
+                fn trunc(self) -> Self {
+                    $type::trunc(self)
+                }
+            }
+        )*
+    };
+}
+
+floats!(f32 => 0x7FC0_0000, f64 => 0x7FF8_0000_0000_0000);
+
+/// Replaces a NaN by the canonical NaN, so that the result of an arithmetic
+/// operation does not depend on the NaN payloads chosen by the hardware
+pub(crate) fn canonical<F: Float>(value: F) -> F {
+    if value.is_nan() {
+        F::CANONICAL_NAN
+    } else {
+        value
+    }
+}
+
+/// Returns the smaller operand, or NaN if either of them is NaN. Negative zero
+/// is smaller than positive zero.
+pub(crate) fn min<F: Float>(left: F, right: F) -> F {
+    if left.is_nan() || right.is_nan() {
+        F::CANONICAL_NAN
+    } else if left == right {
+        // Only differs for zeros of opposite signs
+        if left.is_sign_negative() {
+            left
+        } else {
+            right
+        }
+    } else if left < right {
+        left
+    } else {
+        right
+    }
+}
+
+/// Returns the larger operand, or NaN if either of them is NaN. Positive zero
+/// is larger than negative zero.
+pub(crate) fn max<F: Float>(left: F, right: F) -> F {
+    if left.is_nan() || right.is_nan() {
+        F::CANONICAL_NAN
+    } else if left == right {
+        // Only differs for zeros of opposite signs
+        if left.is_sign_negative() {
+            right
+        } else {
+            left
+        }
+    } else if left > right {
+        left
+    } else {
+        right
+    }
+}
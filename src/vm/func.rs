@@ -0,0 +1,366 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+
+use std::rc::Rc;
+
+use super::store::FuncInst;
+use super::{
+    Extern, FuncAddr, GlobalError, Instance, Machine, Memory, Store, Trap, TrapKind, Value,
+};
+use crate::wasm::{FuncType, NumType, ValueType};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallError {
+    /// The instance does not export a function of the given name
+    UnknownFunction(String),
+    /// The arguments, or the parameters and results of a typed function,
+    /// do not match the type of the function
+    SignatureMismatch {
+        expected: FuncType,
+        actual: FuncType,
+    },
+    Trap(Trap),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownFunction(name) => write!(f, "Unknown function: {}", name),
+            Self::SignatureMismatch { expected, actual } => write!(
+                f,
+                "Signature mismatch: expected: {:?} actual: {:?}",
+                expected, actual
+            ),
+            Self::Trap(trap) => write!(f, "{}", trap),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+impl From<Trap> for CallError {
+    fn from(trap: Trap) -> Self {
+        Self::Trap(trap)
+    }
+}
+
+/// A function in a `Store` that can be called by the host
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    address: FuncAddr,
+    ftype: FuncType,
+}
+
+impl Func {
+    pub(crate) fn new(address: FuncAddr, ftype: FuncType) -> Self {
+        Func { address, ftype }
+    }
+
+    /// Allocates a host function in the store. Its type is derived from the
+    /// parameter and result types of the closure, which may take a `Caller`
+    /// as its first argument:
+    ///
+    /// ```
+    /// # use wario::vm::{Caller, Func, Store};
+    /// let mut store = Store::with_data(0);
+    /// let scale = Func::wrap(&mut store, |a: i32, b: f64| -> i64 { (a as f64 * b) as i64 });
+    /// let divide = Func::wrap(&mut store, |a: i32, b: i32| (a / b, a % b));
+    /// let count = Func::wrap(&mut store, |mut caller: Caller<i32>| *caller.data_mut() += 1);
+    /// ```
+    pub fn wrap<T, P, R>(store: &mut Store<T>, function: impl IntoFunc<T, P, R>) -> Self {
+        let function = function.into_host_func();
+        let ftype = function.ftype.clone();
+
+        store.funcs.push(FuncInst::Host(Rc::new(function)));
+        Func::new(FuncAddr(store.funcs.len() - 1), ftype)
+    }
+
+    pub fn address(&self) -> FuncAddr {
+        self.address
+    }
+
+    pub fn func_type(&self) -> &FuncType {
+        &self.ftype
+    }
+
+    /// Calls the function with arguments matching its parameter types
+    pub fn call<T>(&self, store: &mut Store<T>, args: &[Value]) -> Result<Vec<Value>, CallError> {
+        let argument_types: Vec<_> = args.iter().map(Value::value_type).collect();
+
+        if argument_types != self.ftype.parameter_types {
+            return Err(CallError::SignatureMismatch {
+                expected: self.ftype.clone(),
+                actual: FuncType {
+                    parameter_types: argument_types,
+                    result_types: self.ftype.result_types.clone(),
+                },
+            });
+        }
+
+        Ok(self.call_unchecked(store, args)?)
+    }
+
+    fn call_unchecked<T>(&self, store: &mut Store<T>, args: &[Value]) -> Result<Vec<Value>, Trap> {
+        let mut machine = Machine::new();
+        machine.debugging = false;
+        machine.stack.extend_from_slice(args);
+
+        machine.call(store, self.address)?;
+        machine.pop_values(self.ftype.result_types.len())
+    }
+
+    /// Returns a view of the function with the given Rust parameter and result types
+    pub fn typed<P: WasmTypeList, R: WasmTypeList>(&self) -> Result<TypedFunc<P, R>, CallError> {
+        let ftype = FuncType {
+            parameter_types: P::value_types(),
+            result_types: R::value_types(),
+        };
+
+        if ftype != self.ftype {
+            return Err(CallError::SignatureMismatch {
+                expected: self.ftype.clone(),
+                actual: ftype,
+            });
+        }
+
+        Ok(TypedFunc {
+            func: self.clone(),
+            signature: PhantomData,
+        })
+    }
+}
+
+/// A function whose signature has been checked against the Rust types `P` and `R`
+#[derive(Debug, Clone)]
+pub struct TypedFunc<P, R> {
+    func: Func,
+    signature: PhantomData<fn(P) -> R>,
+}
+
+impl<P: WasmTypeList, R: WasmTypeList> TypedFunc<P, R> {
+    pub fn func(&self) -> &Func {
+        &self.func
+    }
+
+    pub fn call<T>(&self, store: &mut Store<T>, params: P) -> Result<R, Trap> {
+        let results = self.func.call_unchecked(store, &params.into_values())?;
+        Ok(R::from_values(&results).expect("Results of a type-checked function"))
+    }
+}
+
+/// The context in which a host function is called: the store, and the instance
+/// whose code called the function
+pub struct Caller<'s, T> {
+    store: &'s mut Store<T>,
+    instance: Option<&'s Instance>,
+}
+
+impl<'s, T> Caller<'s, T> {
+    pub fn data(&self) -> &T {
+        self.store.data()
+    }
+
+    pub fn data_mut(&mut self) -> &mut T {
+        self.store.data_mut()
+    }
+
+    /// Returns an export of the calling instance, or `None` if the function
+    /// was called by the host
+    pub fn get_export(&self, name: &str) -> Option<Extern> {
+        self.instance?.get_export(name)
+    }
+
+    /// Returns an exported memory of the calling instance
+    pub fn get_memory(&mut self, name: &str) -> Option<&mut Memory> {
+        match self.get_export(name)? {
+            Extern::Memory(address) => Some(self.store.memory_mut(address)),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an exported global of the calling instance
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.instance?.get_global(self.store, name)
+    }
+
+    /// Changes the value of an exported, mutable global of the calling instance
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), GlobalError> {
+        match self.instance {
+            Some(instance) => instance.set_global(self.store, name, value),
+            None => Err(GlobalError::UnknownGlobal(name.to_owned())),
+        }
+    }
+}
+
+type HostFn<T> = Box<dyn Fn(Caller<T>, &[Value]) -> Option<Vec<Value>>>;
+
+/// A function defined by the host
+pub struct HostFunc<T> {
+    pub(crate) ftype: FuncType,
+    /// Returns `None` if the arguments do not match the parameter types
+    fun: HostFn<T>,
+}
+
+impl<T> HostFunc<T> {
+    pub(crate) fn call(
+        &self,
+        machine: &mut Machine,
+        store: &mut Store<T>,
+        instance: Option<&Instance>,
+    ) -> Result<(), Trap> {
+        let args = machine.pop_values(self.ftype.parameter_types.len())?;
+        let caller = Caller { store, instance };
+        let results = (self.fun)(caller, &args).ok_or(TrapKind::OperandTypeMismatch)?;
+
+        machine.stack.extend(results);
+        Ok(())
+    }
+}
+
+/// A Rust closure that can be used as a host function, with parameters `P`
+/// (a tuple of `WasmType`s, optionally preceded by a `Caller`) and results `R`
+pub trait IntoFunc<T, P, R> {
+    fn into_host_func(self) -> HostFunc<T>;
+}
+
+macro_rules! into_funcs {
+    ($(($($param:ident),*)),*) => {
+        $(
+            #[allow(non_snake_case)]
+            impl<T, Fun, $($param: WasmType,)* R: WasmTypeList> IntoFunc<T, ($($param,)*), R> for Fun
+            where
+                Fun: Fn($($param),*) -> R + 'static,
+            {
+                fn into_host_func(self) -> HostFunc<T> {
+                    HostFunc {
+                        ftype: FuncType {
+                            parameter_types: <($($param,)*)>::value_types(),
+                            result_types: R::value_types(),
+                        },
+                        fun: Box::new(move |_, args| {
+                            let ($($param,)*) = <($($param,)*)>::from_values(args)?;
+                            Some(self($($param),*).into_values())
+                        }),
+                    }
+                }
+            }
+
+            #[allow(non_snake_case)]
+            impl<T, Fun, $($param: WasmType,)* R: WasmTypeList>
+                IntoFunc<T, (Caller<'_, T>, $($param,)*), R> for Fun
+            where
+                Fun: Fn(Caller<T>, $($param),*) -> R + 'static,
+            {
+                fn into_host_func(self) -> HostFunc<T> {
+                    HostFunc {
+                        ftype: FuncType {
+                            parameter_types: <($($param,)*)>::value_types(),
+                            result_types: R::value_types(),
+                        },
+                        fun: Box::new(move |caller, args| {
+                            let ($($param,)*) = <($($param,)*)>::from_values(args)?;
+                            Some(self(caller, $($param),*).into_values())
+                        }),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+into_funcs!(
+    (),
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+    (A, B, C, D, E, F, G),
+    (A, B, C, D, E, F, G, H)
+);
+
+/// A Rust type that represents a WebAssembly value type
+pub trait WasmType: Into<Value> + TryFrom<Value, Error = Value> {
+    fn value_type() -> ValueType;
+}
+
+macro_rules! wasm_types {
+    ($($type:ty => $num_type:ident),*) => {
+        $(
+            impl WasmType for $type {
+                fn value_type() -> ValueType {
+                    ValueType::NumType(NumType::$num_type)
+                }
+            }
+        )*
+    };
+}
+
+wasm_types!(i32 => I32, i64 => I64, f32 => F32, f64 => F64);
+
+/// A Rust type that represents a sequence of WebAssembly values: `()`, a single
+/// `WasmType`, or a tuple of them
+pub trait WasmTypeList: Sized {
+    fn value_types() -> Vec<ValueType>;
+    fn into_values(self) -> Vec<Value>;
+    /// Returns `None` if the values are not of the expected number and types
+    fn from_values(values: &[Value]) -> Option<Self>;
+}
+
+impl<T: WasmType> WasmTypeList for T {
+    fn value_types() -> Vec<ValueType> {
+        vec![T::value_type()]
+    }
+
+    fn into_values(self) -> Vec<Value> {
+        vec![self.into()]
+    }
+
+    fn from_values(values: &[Value]) -> Option<Self> {
+        match values {
+            [value] => T::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! wasm_type_lists {
+    ($(($($name:ident),*)),*) => {
+        $(
+            #[allow(non_snake_case, unused_mut)]
+            impl<$($name: WasmType),*> WasmTypeList for ($($name,)*) {
+                fn value_types() -> Vec<ValueType> {
+                    vec![$($name::value_type()),*]
+                }
+
+                fn into_values(self) -> Vec<Value> {
+                    let ($($name,)*) = self;
+                    vec![$($name.into()),*]
+                }
+
+                fn from_values(values: &[Value]) -> Option<Self> {
+                    let mut values = values.iter();
+                    let list = ($($name::try_from(*values.next()?).ok()?,)*);
+                    match values.next() {
+                        None => Some(list),
+                        Some(_) => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+wasm_type_lists!(
+    (),
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+    (A, B, C, D, E, F, G),
+    (A, B, C, D, E, F, G, H)
+);
@@ -0,0 +1,388 @@
+use std::fmt;
+use std::rc::Rc;
+
+use super::store::{ElemAddr, FuncInst};
+use super::{
+    CallError, Extern, FrameInfo, Func, FuncAddr, GlobalAddr, GlobalError, Machine, MemAddr,
+    Memory, Store, Table, TableAddr, Trap, TypedFunc, Value, WasmTypeList,
+};
+use crate::validate::ValidationError;
+use crate::wasm::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstantiationError {
+    Validation(ValidationError),
+    ImportCountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The entity provided for the import at the given index is not of the expected type
+    IncompatibleImport {
+        index: usize,
+        module: Name,
+        name: Name,
+    },
+    /// The table with the given index is too large to be allocated
+    TableTooLarge(TableIdx),
+    /// The memory with the given index is too large to be allocated
+    MemoryTooLarge(MemIdx),
+    /// Initializing the tables or memories, or running the start function trapped
+    Trap(Trap),
+}
+
+impl fmt::Display for InstantiationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Validation(error) => write!(f, "Invalid module: {}", error),
+            Self::ImportCountMismatch { expected, actual } => {
+                write!(f, "Expected {} imports, got {}", expected, actual)
+            }
+            Self::IncompatibleImport {
+                index,
+                module,
+                name,
+            } => write!(
+                f,
+                "Incompatible import {:?}.{:?} (import {})",
+                module, name, index
+            ),
+            Self::TableTooLarge(TableIdx(index)) => {
+                write!(f, "Table {} is too large to be allocated", index)
+            }
+            Self::MemoryTooLarge(MemIdx(index)) => {
+                write!(f, "Memory {} is too large to be allocated", index)
+            }
+            Self::Trap(trap) => write!(f, "Instantiation failed: {}", trap),
+        }
+    }
+}
+
+impl std::error::Error for InstantiationError {}
+
+impl From<ValidationError> for InstantiationError {
+    fn from(error: ValidationError) -> Self {
+        Self::Validation(error)
+    }
+}
+
+impl From<Trap> for InstantiationError {
+    fn from(trap: Trap) -> Self {
+        Self::Trap(trap)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, InstantiationError>;
+
+/// The runtime representation of a module: its types, and the addresses of
+/// its entities in the store (section 4.2.5 from spec)
+#[derive(Debug, Default)]
+pub(crate) struct ModuleInst {
+    pub(crate) types: Vec<FuncType>,
+    pub(crate) funcs: Vec<FuncAddr>,
+    /// The type of each function, as declared by the module
+    pub(crate) func_types: Vec<TypeIdx>,
+    pub(crate) tables: Vec<TableAddr>,
+    pub(crate) memories: Vec<MemAddr>,
+    pub(crate) globals: Vec<GlobalAddr>,
+    pub(crate) elems: Vec<ElemAddr>,
+    pub(crate) exports: Vec<(String, Extern)>,
+    /// The debug names of the module, used to describe its functions in backtraces
+    pub(crate) names: Names,
+}
+
+/// An instantiated module. Its functions, tables, memories and globals live in a `Store`.
+#[derive(Debug, Clone)]
+pub struct Instance(pub(crate) Rc<ModuleInst>);
+
+impl Instance {
+    /// Validates and instantiates a module (section 4.5.4 from spec).
+    ///
+    /// `imports` provides an entity for each import of the module, in order. The imported
+    /// functions come first in the function index space, followed by those of the module.
+    pub fn new<T>(store: &mut Store<T>, module: &Module, imports: &[Extern]) -> Result<Self> {
+        module.validate()?;
+
+        if imports.len() != module.imports.len() {
+            return Err(InstantiationError::ImportCountMismatch {
+                expected: module.imports.len(),
+                actual: imports.len(),
+            });
+        }
+
+        let mut inst = ModuleInst {
+            types: module.types.clone(),
+            names: module.names.clone(),
+            ..ModuleInst::default()
+        };
+
+        for (index, (import, external)) in module.imports.iter().zip(imports).enumerate() {
+            if !matches(store, *external, &import.descriptor, &module.types) {
+                return Err(InstantiationError::IncompatibleImport {
+                    index,
+                    module: import.module.clone(),
+                    name: import.name.clone(),
+                });
+            }
+
+            match *external {
+                Extern::Func(address) => {
+                    if let ImportDescriptor::Func(type_index) = import.descriptor {
+                        inst.func_types.push(type_index);
+                    }
+                    inst.funcs.push(address)
+                }
+                Extern::Table(address) => inst.tables.push(address),
+                Extern::Memory(address) => inst.memories.push(address),
+                Extern::Global(address) => inst.globals.push(address),
+            }
+        }
+
+        // The functions are allocated after the instance is complete,
+        // but their addresses are needed by `ref.func` and the exports
+        let imported_funcs = inst.funcs.len();
+        let first_address = store.funcs.len();
+        inst.funcs
+            .extend((0..module.functions.len()).map(|offset| FuncAddr(first_address + offset)));
+        inst.func_types.extend_from_slice(&module.functions);
+
+        for global in &module.globals {
+            let value = evaluate(store, &inst, &global.expression);
+            let address = store
+                .add_global(global.global_type, value)
+                .expect("Validated global initializer");
+            inst.globals.push(address);
+        }
+
+        for elem in &module.elems {
+            let elements = elem
+                .init
+                .iter()
+                .map(|expression| evaluate(store, &inst, expression))
+                .collect();
+            inst.elems.push(store.add_elem(elements));
+        }
+
+        for table_type in &module.tables {
+            let table = Table::new(*table_type).ok_or(InstantiationError::TableTooLarge(
+                TableIdx(inst.tables.len()),
+            ))?;
+            inst.tables.push(store.add_table(table));
+        }
+
+        for limits in &module.memories {
+            let memory = Memory::new(*limits).ok_or(InstantiationError::MemoryTooLarge(MemIdx(
+                inst.memories.len(),
+            )))?;
+            inst.memories.push(store.add_memory(memory));
+        }
+
+        for export in &module.exports {
+            let external = match export.descriptor {
+                ExportDescriptor::Func(FuncIdx(index)) => Extern::Func(inst.funcs[index]),
+                ExportDescriptor::Table(TableIdx(index)) => Extern::Table(inst.tables[index]),
+                ExportDescriptor::Memory(MemIdx(index)) => Extern::Memory(inst.memories[index]),
+                ExportDescriptor::Global(GlobalIdx(index)) => Extern::Global(inst.globals[index]),
+            };
+
+            inst.exports.push((export.name.0.clone(), external));
+        }
+
+        let instance = Instance(Rc::new(inst));
+
+        for (offset, (TypeIdx(type_index), code)) in
+            module.functions.iter().zip(&module.codes).enumerate()
+        {
+            store.funcs.push(FuncInst::Wasm {
+                ftype: module.types[*type_index].clone(),
+                instance: instance.clone(),
+                index: FuncIdx(imported_funcs + offset),
+                code: Rc::new(code.clone()),
+            });
+        }
+
+        // Active segments are copied into their table, after which they are dropped
+        // like declarative ones
+        for (elem, ElemAddr(address)) in module.elems.iter().zip(&instance.0.elems) {
+            match &elem.mode {
+                ElemMode::Passive => {}
+                ElemMode::Active {
+                    table: TableIdx(table),
+                    offset,
+                } => {
+                    let offset = evaluate_offset(store, &instance.0, offset);
+                    let elements = std::mem::take(&mut store.elems[*address].elements);
+
+                    store
+                        .table_mut(instance.0.tables[*table])
+                        .write(offset, &elements)
+                        .map_err(Trap::from)?;
+                }
+                ElemMode::Declarative => store.elems[*address].elements = Vec::new(),
+            }
+        }
+
+        for data in &module.datas {
+            if let DataMode::Active {
+                memory: MemIdx(memory),
+                offset,
+            } = &data.mode
+            {
+                let offset = evaluate_offset(store, &instance.0, offset);
+
+                store
+                    .memory_mut(instance.0.memories[*memory])
+                    .write(offset, &data.init)
+                    .map_err(Trap::from)?;
+            }
+        }
+
+        if let Some(Start {
+            func: FuncIdx(start),
+        }) = module.start
+        {
+            let mut machine = Machine::new();
+            machine.debugging = false;
+            machine.call(store, instance.0.funcs[start])?;
+        }
+
+        Ok(instance)
+    }
+
+    /// Returns the exports of the instance, in the order of the export section
+    pub fn exports(&self) -> impl Iterator<Item = (&str, Extern)> {
+        self.0
+            .exports
+            .iter()
+            .map(|(name, external)| (name.as_str(), *external))
+    }
+
+    pub fn get_export(&self, name: &str) -> Option<Extern> {
+        self.exports()
+            .find(|(export_name, _)| *export_name == name)
+            .map(|(_, external)| external)
+    }
+
+    /// Returns the value of the exported global of the given name
+    pub fn get_global<T>(&self, store: &Store<T>, name: &str) -> Option<Value> {
+        match self.get_export(name)? {
+            Extern::Global(address) => Some(store.global(address)),
+            _ => None,
+        }
+    }
+
+    /// Changes the value of the exported, mutable global of the given name
+    pub fn set_global<T>(
+        &self,
+        store: &mut Store<T>,
+        name: &str,
+        value: Value,
+    ) -> std::result::Result<(), GlobalError> {
+        match self.get_export(name) {
+            Some(Extern::Global(address)) => store.set_global(address, value),
+            _ => Err(GlobalError::UnknownGlobal(name.to_owned())),
+        }
+    }
+
+    /// Returns the exported function of the given name
+    pub fn get_func(&self, name: &str) -> Option<Func> {
+        let address = match self.get_export(name)? {
+            Extern::Func(address) => address,
+            _ => return None,
+        };
+        let index = self.0.funcs.iter().position(|func| *func == address)?;
+        let TypeIdx(type_index) = self.0.func_types[index];
+
+        Some(Func::new(address, self.0.types[type_index].clone()))
+    }
+
+    /// Returns the exported function of the given name, if its type matches the Rust
+    /// parameter and result types `P` and `R`
+    pub fn get_typed_func<P: WasmTypeList, R: WasmTypeList>(
+        &self,
+        name: &str,
+    ) -> std::result::Result<TypedFunc<P, R>, CallError> {
+        self.get_func(name)
+            .ok_or_else(|| CallError::UnknownFunction(name.to_owned()))?
+            .typed()
+    }
+
+    /// Describes a function of this instance for a backtrace
+    pub(crate) fn frame_info(&self, address: FuncAddr, index: FuncIdx) -> FrameInfo {
+        let names = &self.0.names;
+        let name = names
+            .functions
+            .iter()
+            .find(|(function, _)| *function == index)
+            .map(|(_, name)| name.clone());
+
+        FrameInfo {
+            address,
+            index,
+            module_name: names.module.clone(),
+            name,
+        }
+    }
+}
+
+/// Whether an external entity can be used for an import (section 4.5.2 from spec).
+///
+/// Entities that do not exist in the store, such as those of another store, never match.
+fn matches<T>(
+    store: &Store<T>,
+    external: Extern,
+    descriptor: &ImportDescriptor,
+    types: &[FuncType],
+) -> bool {
+    match (external, descriptor) {
+        (Extern::Func(FuncAddr(address)), ImportDescriptor::Func(TypeIdx(type_index))) => store
+            .funcs
+            .get(address)
+            .is_some_and(|func| func.func_type() == &types[*type_index]),
+        (Extern::Table(TableAddr(address)), ImportDescriptor::Table(table_type)) => {
+            store.tables.get(address).is_some_and(|table| {
+                table.elem_type() == table_type.elem_type
+                    && limits_match(table.limits(), table_type.limits)
+            })
+        }
+        (Extern::Memory(MemAddr(address)), ImportDescriptor::Memory(mem_type)) => store
+            .memories
+            .get(address)
+            .is_some_and(|memory| limits_match(memory.limits(), mem_type.limits)),
+        (Extern::Global(GlobalAddr(address)), ImportDescriptor::Global(global_type)) => store
+            .globals
+            .get(address)
+            .is_some_and(|global| global.global_type == *global_type),
+        _ => false,
+    }
+}
+
+fn limits_match(actual: Limits, expected: Limits) -> bool {
+    actual.min >= expected.min
+        && match (actual.max, expected.max) {
+            (_, None) => true,
+            (Some(actual), Some(expected)) => actual <= expected,
+            (None, Some(_)) => false,
+        }
+}
+
+/// Evaluates the validated offset of an active element or data segment
+fn evaluate_offset<T>(store: &Store<T>, inst: &ModuleInst, expression: &[Instruction]) -> usize {
+    match evaluate(store, inst, expression) {
+        Value::I32(offset) => offset as u32 as usize,
+        value => unreachable!("Validated segment offset of type {:?}", value),
+    }
+}
+
+/// Evaluates a validated constant expression
+fn evaluate<T>(store: &Store<T>, inst: &ModuleInst, expression: &[Instruction]) -> Value {
+    match expression {
+        [Instruction::I32Const(value)] => Value::I32(*value),
+        [Instruction::I64Const(value)] => Value::I64(*value),
+        [Instruction::F32Const(value)] => Value::F32(*value),
+        [Instruction::F64Const(value)] => Value::F64(*value),
+        [Instruction::RefNull(ref_type)] => Value::default(ValueType::RefType(*ref_type)),
+        [Instruction::RefFunc(FuncIdx(index))] => Value::FuncRef(Some(inst.funcs[*index])),
+        [Instruction::GlobalGet(GlobalIdx(index))] => store.global(inst.globals[*index]),
+        _ => unreachable!("Invalid constant expression: {:?}", expression),
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{Extern, Instance, InstantiationError, Store};
+use crate::wasm::{Module, Name};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkError {
+    /// Nothing has been defined under the module and name of an import
+    MissingImport {
+        module: Name,
+        name: Name,
+    },
+    /// Something has already been defined under the given module and name
+    DuplicateDefinition {
+        module: String,
+        name: String,
+    },
+    Instantiation(InstantiationError),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingImport { module, name } => {
+                write!(f, "Missing import {:?}.{:?}", module, name)
+            }
+            Self::DuplicateDefinition { module, name } => {
+                write!(f, "{}.{} is already defined", module, name)
+            }
+            Self::Instantiation(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+impl From<InstantiationError> for LinkError {
+    fn from(error: InstantiationError) -> Self {
+        Self::Instantiation(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, LinkError>;
+
+/// Resolves the imports of modules by their module and field name
+#[derive(Debug, Default)]
+pub struct Linker {
+    definitions: HashMap<String, HashMap<String, Extern>>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines `external` as the entity to use for imports of `module.name`
+    pub fn define(&mut self, module: &str, name: &str, external: Extern) -> Result<&mut Self> {
+        let definitions = self.definitions.entry(module.to_owned()).or_default();
+
+        if definitions.contains_key(name) {
+            return Err(LinkError::DuplicateDefinition {
+                module: module.to_owned(),
+                name: name.to_owned(),
+            });
+        }
+
+        definitions.insert(name.to_owned(), external);
+        Ok(self)
+    }
+
+    /// Defines all exports of an instance under the given module name
+    pub fn define_instance(&mut self, module: &str, instance: &Instance) -> Result<&mut Self> {
+        for (name, external) in instance.exports() {
+            self.define(module, name, external)?;
+        }
+
+        Ok(self)
+    }
+
+    pub fn get(&self, module: &str, name: &str) -> Option<Extern> {
+        self.definitions.get(module)?.get(name).copied()
+    }
+
+    /// Instantiates a module, looking up each of its imports by module and field name
+    pub fn instantiate<T>(&self, store: &mut Store<T>, module: &Module) -> Result<Instance> {
+        let imports = module
+            .imports
+            .iter()
+            .map(|import| {
+                self.get(&import.module.0, &import.name.0)
+                    .ok_or_else(|| LinkError::MissingImport {
+                        module: import.module.clone(),
+                        name: import.name.clone(),
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Instance::new(store, module, &imports)?)
+    }
+}
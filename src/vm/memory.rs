@@ -0,0 +1,175 @@
+use std::fmt;
+
+use crate::wasm::Limits;
+
+/// The size of a memory page in bytes
+pub const PAGE_SIZE: usize = 0x10000;
+
+/// The number of pages addressable with 32-bit addresses
+const MAX_PAGES: u32 = 0x10000;
+
+/// An access that does not lie entirely within the bounds of a memory
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryAccessError {
+    pub address: usize,
+    pub length: usize,
+}
+
+impl fmt::Display for MemoryAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Out of bounds memory access: {} bytes at address {:#x}",
+            self.length, self.address
+        )
+    }
+}
+
+impl std::error::Error for MemoryAccessError {}
+
+/// A linear memory: a little-endian byte array that is sized in pages of 64 KiB
+#[derive(Debug, Clone, PartialEq)]
+pub struct Memory {
+    data: Vec<u8>,
+    max: Option<u32>,
+}
+
+impl Memory {
+    /// Creates a zeroed memory of `limits.min` pages that can grow up to `limits.max` pages.
+    ///
+    /// Returns `None` if the memory can not be allocated.
+    pub fn new(limits: Limits) -> Option<Self> {
+        let size = limits.min as usize * PAGE_SIZE;
+
+        let mut data = Vec::new();
+        data.try_reserve_exact(size).ok()?;
+        data.resize(size, 0);
+
+        Some(Memory {
+            data,
+            max: limits.max,
+        })
+    }
+
+    /// Returns the current size and the maximum size in pages
+    pub fn limits(&self) -> Limits {
+        Limits {
+            min: self.size(),
+            max: self.max,
+        }
+    }
+
+    /// Returns the current size in pages
+    pub fn size(&self) -> u32 {
+        (self.data.len() / PAGE_SIZE) as u32
+    }
+
+    /// Grows the memory by `delta` pages and returns its previous size in pages.
+    ///
+    /// Returns `None` and leaves the memory untouched if the new size would exceed
+    /// the maximum, or if the memory can not be allocated.
+    pub fn grow(&mut self, delta: u32) -> Option<u32> {
+        let size = self.size();
+        let new_size = size.checked_add(delta)?;
+
+        if new_size > self.max.unwrap_or(MAX_PAGES).min(MAX_PAGES) {
+            return None;
+        }
+
+        let additional = delta as usize * PAGE_SIZE;
+        self.data.try_reserve_exact(additional).ok()?;
+        self.data.resize(self.data.len() + additional, 0);
+
+        Some(size)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Fills `buffer` with the bytes starting at `address`
+    pub fn read(&self, address: usize, buffer: &mut [u8]) -> Result<(), MemoryAccessError> {
+        let range = self.range(address, buffer.len())?;
+        buffer.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    /// Copies `bytes` into the memory starting at `address`
+    pub fn write(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryAccessError> {
+        let range = self.range(address, bytes.len())?;
+        self.data[range].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn range(
+        &self,
+        address: usize,
+        length: usize,
+    ) -> Result<std::ops::Range<usize>, MemoryAccessError> {
+        match address.checked_add(length) {
+            Some(end) if end <= self.data.len() => Ok(address..end),
+            _ => Err(MemoryAccessError { address, length }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Memory, MemoryAccessError, PAGE_SIZE};
+    use crate::wasm::Limits;
+
+    #[test]
+    fn size_and_grow() {
+        let mut memory = Memory::new(Limits {
+            min: 1,
+            max: Some(3),
+        })
+        .unwrap();
+        assert_eq!(memory.size(), 1);
+        assert_eq!(memory.data().len(), PAGE_SIZE);
+
+        assert_eq!(memory.grow(2), Some(1));
+        assert_eq!(memory.size(), 3);
+
+        assert_eq!(memory.grow(1), None);
+        assert_eq!(memory.grow(0), Some(3));
+        assert_eq!(memory.size(), 3);
+    }
+
+    #[test]
+    fn grow_without_maximum() {
+        let mut memory = Memory::new(Limits { min: 0, max: None }).unwrap();
+        assert_eq!(memory.grow(1), Some(0));
+        assert_eq!(memory.grow(0x10000), None);
+        assert_eq!(memory.size(), 1);
+    }
+
+    #[test]
+    fn read_and_write() {
+        let mut memory = Memory::new(Limits { min: 1, max: None }).unwrap();
+
+        memory.write(PAGE_SIZE - 2, &[1, 2]).unwrap();
+        let mut buffer = [0; 2];
+        memory.read(PAGE_SIZE - 2, &mut buffer).unwrap();
+        assert_eq!(buffer, [1, 2]);
+
+        assert_eq!(
+            memory.write(PAGE_SIZE - 1, &[1, 2]),
+            Err(MemoryAccessError {
+                address: PAGE_SIZE - 1,
+                length: 2,
+            })
+        );
+        assert_eq!(
+            memory.read(usize::MAX, &mut buffer),
+            Err(MemoryAccessError {
+                address: usize::MAX,
+                length: 2,
+            })
+        );
+    }
+}
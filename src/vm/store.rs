@@ -0,0 +1,209 @@
+use std::fmt;
+use std::rc::Rc;
+
+use super::func::HostFunc;
+use super::{Instance, Memory, Table, Value};
+use crate::wasm::{Code, FuncIdx, FuncType, GlobalType, Mutability, ValueType};
+
+/// The address of a function in a `Store`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FuncAddr(pub usize);
+/// The address of a table in a `Store`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TableAddr(pub usize);
+/// The address of a memory in a `Store`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemAddr(pub usize);
+/// The address of a global in a `Store`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GlobalAddr(pub usize);
+
+/// The address of an element segment in a `Store`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct ElemAddr(pub(crate) usize);
+
+/// An entity that can be imported or exported
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Extern {
+    Func(FuncAddr),
+    Table(TableAddr),
+    Memory(MemAddr),
+    Global(GlobalAddr),
+}
+
+pub(crate) enum FuncInst<T> {
+    Wasm {
+        ftype: FuncType,
+        instance: Instance,
+        /// The index of the function in the module that defines it
+        index: FuncIdx,
+        code: Rc<Code>,
+    },
+    Host(Rc<HostFunc<T>>),
+}
+
+impl<T> FuncInst<T> {
+    pub(crate) fn func_type(&self) -> &FuncType {
+        match self {
+            FuncInst::Wasm { ftype, .. } => ftype,
+            FuncInst::Host(function) => &function.ftype,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlobalError {
+    /// No global is exported under the given name
+    UnknownGlobal(String),
+    /// The global is constant
+    Immutable,
+    /// The value does not have the type of the global
+    TypeMismatch {
+        expected: ValueType,
+        actual: ValueType,
+    },
+}
+
+impl fmt::Display for GlobalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownGlobal(name) => write!(f, "Unknown global: {}", name),
+            Self::Immutable => write!(f, "Global is immutable"),
+            Self::TypeMismatch { expected, actual } => write!(
+                f,
+                "Type mismatch: expected: {:?} actual: {:?}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GlobalError {}
+
+pub(crate) struct GlobalInst {
+    pub(crate) global_type: GlobalType,
+    pub(crate) value: Value,
+}
+
+/// The references of an element segment, which `table.init` copies until the
+/// segment is dropped
+pub(crate) struct ElemInst {
+    pub(crate) elements: Vec<Value>,
+}
+
+/// All functions, tables, memories and globals that have been allocated, either
+/// by the host or by instantiating modules (section 4.2.3 from spec).
+///
+/// The store also owns the host's data `T`, which host functions can access
+/// through their `Caller`.
+#[derive(Default)]
+pub struct Store<T = ()> {
+    pub(crate) funcs: Vec<FuncInst<T>>,
+    pub(crate) tables: Vec<Table>,
+    pub(crate) memories: Vec<Memory>,
+    pub(crate) globals: Vec<GlobalInst>,
+    pub(crate) elems: Vec<ElemInst>,
+    data: T,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T> Store<T> {
+    pub fn with_data(data: T) -> Self {
+        Store {
+            funcs: Vec::new(),
+            tables: Vec::new(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            elems: Vec::new(),
+            data,
+        }
+    }
+
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    pub fn add_table(&mut self, table: Table) -> TableAddr {
+        self.tables.push(table);
+        TableAddr(self.tables.len() - 1)
+    }
+
+    pub fn add_memory(&mut self, memory: Memory) -> MemAddr {
+        self.memories.push(memory);
+        MemAddr(self.memories.len() - 1)
+    }
+
+    /// Allocates a global, whose value has to be of the given type
+    pub fn add_global(
+        &mut self,
+        global_type: GlobalType,
+        value: Value,
+    ) -> Result<GlobalAddr, GlobalError> {
+        if value.value_type() != global_type.value_type {
+            return Err(GlobalError::TypeMismatch {
+                expected: global_type.value_type,
+                actual: value.value_type(),
+            });
+        }
+
+        self.globals.push(GlobalInst { global_type, value });
+        Ok(GlobalAddr(self.globals.len() - 1))
+    }
+
+    pub(crate) fn add_elem(&mut self, elements: Vec<Value>) -> ElemAddr {
+        self.elems.push(ElemInst { elements });
+        ElemAddr(self.elems.len() - 1)
+    }
+
+    pub fn global(&self, GlobalAddr(address): GlobalAddr) -> Value {
+        self.globals[address].value
+    }
+
+    /// Changes the value of a mutable global
+    pub fn set_global(
+        &mut self,
+        GlobalAddr(address): GlobalAddr,
+        value: Value,
+    ) -> Result<(), GlobalError> {
+        let global = &mut self.globals[address];
+
+        if global.global_type.mutability == Mutability::Constant {
+            return Err(GlobalError::Immutable);
+        }
+
+        if value.value_type() != global.global_type.value_type {
+            return Err(GlobalError::TypeMismatch {
+                expected: global.global_type.value_type,
+                actual: value.value_type(),
+            });
+        }
+
+        global.value = value;
+        Ok(())
+    }
+
+    pub fn table(&self, TableAddr(address): TableAddr) -> &Table {
+        &self.tables[address]
+    }
+
+    pub fn table_mut(&mut self, TableAddr(address): TableAddr) -> &mut Table {
+        &mut self.tables[address]
+    }
+
+    pub fn memory(&self, MemAddr(address): MemAddr) -> &Memory {
+        &self.memories[address]
+    }
+
+    pub fn memory_mut(&mut self, MemAddr(address): MemAddr) -> &mut Memory {
+        &mut self.memories[address]
+    }
+}
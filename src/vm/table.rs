@@ -0,0 +1,266 @@
+use std::fmt;
+
+use super::Value;
+use crate::wasm::{Limits, RefType, TableType, ValueType};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TableAccessError {
+    /// The access does not lie entirely within the bounds of the table
+    OutOfBounds { index: usize, length: usize },
+    /// The value is not of the element type of the table
+    TypeMismatch {
+        expected: RefType,
+        actual: ValueType,
+    },
+}
+
+impl fmt::Display for TableAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfBounds { index, length } => write!(
+                f,
+                "Out of bounds table access: {} elements at index {}",
+                length, index
+            ),
+            Self::TypeMismatch { expected, actual } => write!(
+                f,
+                "Type mismatch: expected: {:?} actual: {:?}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TableAccessError {}
+
+/// A vector of references, such as the function pointers used by `call_indirect`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    elements: Vec<Value>,
+    table_type: TableType,
+}
+
+impl Table {
+    /// Creates a table of `limits.min` null references.
+    ///
+    /// Returns `None` if the table can not be allocated.
+    pub fn new(table_type: TableType) -> Option<Self> {
+        let null = Value::default(ValueType::RefType(table_type.elem_type));
+        let size = table_type.limits.min as usize;
+
+        let mut elements = Vec::new();
+        elements.try_reserve_exact(size).ok()?;
+        elements.resize(size, null);
+
+        Some(Table {
+            elements,
+            table_type,
+        })
+    }
+
+    pub fn elem_type(&self) -> RefType {
+        self.table_type.elem_type
+    }
+
+    /// Returns the current size and the maximum size
+    pub fn limits(&self) -> Limits {
+        Limits {
+            min: self.size(),
+            max: self.table_type.limits.max,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.elements.len() as u32
+    }
+
+    /// Grows the table by `delta` elements set to `init`, and returns its previous size.
+    ///
+    /// Returns `None` and leaves the table untouched if the new size would exceed
+    /// the maximum, if the table can not be allocated, or if `init` is not of the
+    /// element type.
+    pub fn grow(&mut self, delta: u32, init: Value) -> Option<u32> {
+        self.check_type(init).ok()?;
+
+        let size = self.size();
+        let new_size = size.checked_add(delta)?;
+
+        if new_size > self.table_type.limits.max.unwrap_or(u32::MAX) {
+            return None;
+        }
+
+        self.elements.try_reserve_exact(delta as usize).ok()?;
+        self.elements.resize(new_size as usize, init);
+
+        Some(size)
+    }
+
+    pub fn get(&self, index: usize) -> Result<Value, TableAccessError> {
+        Ok(self.read(index, 1)?[0])
+    }
+
+    pub fn set(&mut self, index: usize, value: Value) -> Result<(), TableAccessError> {
+        self.write(index, &[value])
+    }
+
+    /// Returns the `length` elements starting at `index`
+    pub fn read(&self, index: usize, length: usize) -> Result<&[Value], TableAccessError> {
+        let range = self.range(index, length)?;
+        Ok(&self.elements[range])
+    }
+
+    /// Copies `values` into the table starting at `index`
+    pub fn write(&mut self, index: usize, values: &[Value]) -> Result<(), TableAccessError> {
+        values
+            .iter()
+            .try_for_each(|value| self.check_type(*value))?;
+
+        let range = self.range(index, values.len())?;
+        self.elements[range].copy_from_slice(values);
+        Ok(())
+    }
+
+    /// Sets the `length` elements starting at `index` to `value`
+    pub fn fill(
+        &mut self,
+        index: usize,
+        value: Value,
+        length: usize,
+    ) -> Result<(), TableAccessError> {
+        self.check_type(value)?;
+
+        let range = self.range(index, length)?;
+        self.elements[range].fill(value);
+        Ok(())
+    }
+
+    fn check_type(&self, value: Value) -> Result<(), TableAccessError> {
+        if value.value_type() == ValueType::RefType(self.elem_type()) {
+            Ok(())
+        } else {
+            Err(TableAccessError::TypeMismatch {
+                expected: self.elem_type(),
+                actual: value.value_type(),
+            })
+        }
+    }
+
+    fn range(
+        &self,
+        index: usize,
+        length: usize,
+    ) -> Result<std::ops::Range<usize>, TableAccessError> {
+        match index.checked_add(length) {
+            Some(end) if end <= self.elements.len() => Ok(index..end),
+            _ => Err(TableAccessError::OutOfBounds { index, length }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Table, TableAccessError};
+    use crate::vm::{FuncAddr, Value};
+    use crate::wasm::{Limits, RefType, TableType, ValueType};
+
+    fn funcref(address: usize) -> Value {
+        Value::FuncRef(Some(FuncAddr(address)))
+    }
+
+    #[test]
+    fn grow() {
+        let mut table = Table::new(TableType {
+            elem_type: RefType::FuncRef,
+            limits: Limits {
+                min: 1,
+                max: Some(3),
+            },
+        })
+        .unwrap();
+
+        assert_eq!(table.grow(2, funcref(7)), Some(1));
+        assert_eq!(
+            table.read(0, 3),
+            Ok(&[Value::FuncRef(None), funcref(7), funcref(7)][..])
+        );
+        assert_eq!(table.grow(1, funcref(7)), None);
+        assert_eq!(table.grow(0, funcref(7)), Some(3));
+        assert_eq!(
+            table.limits(),
+            Limits {
+                min: 3,
+                max: Some(3)
+            }
+        );
+    }
+
+    #[test]
+    fn access() {
+        let mut table = Table::new(TableType {
+            elem_type: RefType::ExternRef,
+            limits: Limits { min: 4, max: None },
+        })
+        .unwrap();
+
+        table.set(3, Value::ExternRef(Some(1))).unwrap();
+        table.fill(0, Value::ExternRef(Some(2)), 2).unwrap();
+        assert_eq!(
+            table.read(1, 3),
+            Ok(&[
+                Value::ExternRef(Some(2)),
+                Value::ExternRef(None),
+                Value::ExternRef(Some(1))
+            ][..])
+        );
+
+        assert_eq!(
+            table.get(4),
+            Err(TableAccessError::OutOfBounds {
+                index: 4,
+                length: 1
+            })
+        );
+        assert_eq!(
+            table.fill(3, Value::ExternRef(None), 2),
+            Err(TableAccessError::OutOfBounds {
+                index: 3,
+                length: 2
+            })
+        );
+        assert_eq!(table.get(3), Ok(Value::ExternRef(Some(1))));
+
+        // Empty accesses at the end are allowed
+        assert_eq!(table.read(4, 0), Ok(&[][..]));
+    }
+
+    #[test]
+    fn wrong_element_type() {
+        let mut table = Table::new(TableType {
+            elem_type: RefType::FuncRef,
+            limits: Limits { min: 1, max: None },
+        })
+        .unwrap();
+
+        let mismatch = TableAccessError::TypeMismatch {
+            expected: RefType::FuncRef,
+            actual: ValueType::RefType(RefType::ExternRef),
+        };
+        assert_eq!(table.set(0, Value::ExternRef(None)), Err(mismatch));
+        assert_eq!(table.fill(0, Value::ExternRef(None), 1), Err(mismatch));
+        assert_eq!(table.grow(1, Value::ExternRef(None)), None);
+        assert_eq!(table.size(), 1);
+    }
+
+    #[test]
+    fn allocation_failure() {
+        let table = Table::new(TableType {
+            elem_type: RefType::FuncRef,
+            limits: Limits {
+                min: u32::MAX,
+                max: None,
+            },
+        });
+
+        assert_eq!(table, None);
+    }
+}
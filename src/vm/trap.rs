@@ -0,0 +1,157 @@
+use std::fmt;
+
+use super::{FuncAddr, GlobalError, MemoryAccessError, TableAccessError};
+use crate::wasm::{FuncIdx, Name};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrapKind {
+    /// An `unreachable` instruction was executed
+    Unreachable,
+    MemoryOutOfBounds,
+    TableOutOfBounds,
+    IntegerDivideByZero,
+    /// The result of a signed division, or of a float to integer conversion, is not representable
+    IntegerOverflow,
+    /// A NaN was converted to an integer
+    InvalidConversionToInteger,
+    /// The signature of the function called by `call_indirect` differs from the expected one
+    IndirectCallTypeMismatch,
+    /// `call_indirect` referred to a null table element
+    UninitializedElement,
+    /// The maximum call depth was exceeded
+    StackOverflow,
+    /// An instruction found too few operands on the stack, which validation would have rejected
+    OperandStackUnderflow,
+    /// An instruction found an operand of the wrong type, which validation would have rejected
+    OperandTypeMismatch,
+    /// An instruction referred to a local, type, function, table, memory, global or
+    /// element segment that does not exist, which validation would have rejected
+    UnknownIndex,
+    UnsupportedInstruction(String),
+}
+
+impl fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrapKind::Unreachable => write!(f, "unreachable"),
+            TrapKind::MemoryOutOfBounds => write!(f, "out of bounds memory access"),
+            TrapKind::TableOutOfBounds => write!(f, "out of bounds table access"),
+            TrapKind::IntegerDivideByZero => write!(f, "integer divide by zero"),
+            TrapKind::IntegerOverflow => write!(f, "integer overflow"),
+            TrapKind::InvalidConversionToInteger => write!(f, "invalid conversion to integer"),
+            TrapKind::IndirectCallTypeMismatch => write!(f, "indirect call type mismatch"),
+            TrapKind::UninitializedElement => write!(f, "uninitialized element"),
+            TrapKind::StackOverflow => write!(f, "call stack exhausted"),
+            TrapKind::OperandStackUnderflow => write!(f, "operand stack underflow"),
+            TrapKind::OperandTypeMismatch => write!(f, "operand type mismatch"),
+            TrapKind::UnknownIndex => write!(f, "unknown index"),
+            TrapKind::UnsupportedInstruction(instruction) => {
+                write!(f, "unsupported instruction: {}", instruction)
+            }
+        }
+    }
+}
+
+/// A function that was active when a trap occurred
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// The address of the function in the store, which tells apart the functions of
+    /// different instances
+    pub address: FuncAddr,
+    /// The index of the function in the module that defines it
+    pub index: FuncIdx,
+    /// The name of the module that defines the function, from its name section
+    pub module_name: Option<Name>,
+    /// The name of the function, from the name section of its module
+    pub name: Option<Name>,
+}
+
+impl fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let FuncIdx(index) = self.index;
+        let FuncAddr(address) = self.address;
+
+        match &self.name {
+            Some(Name(name)) => write!(f, "{} (function {}, address {})", name, index, address)?,
+            None => write!(f, "function {} (address {})", index, address)?,
+        }
+
+        if let Some(Name(module)) = &self.module_name {
+            write!(f, " in module {}", module)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error that aborts the execution of WebAssembly code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trap {
+    pub kind: TrapKind,
+    /// The functions that were active when the trap occurred, innermost first
+    pub backtrace: Vec<FrameInfo>,
+}
+
+impl Trap {
+    pub fn new(kind: TrapKind) -> Self {
+        Trap {
+            kind,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Records that the trap unwound through the given function
+    pub(crate) fn unwind(mut self, function: FrameInfo) -> Self {
+        self.backtrace.push(function);
+        self
+    }
+}
+
+impl From<TrapKind> for Trap {
+    fn from(kind: TrapKind) -> Self {
+        Trap::new(kind)
+    }
+}
+
+impl From<MemoryAccessError> for Trap {
+    fn from(_: MemoryAccessError) -> Self {
+        Trap::new(TrapKind::MemoryOutOfBounds)
+    }
+}
+
+impl From<TableAccessError> for Trap {
+    fn from(error: TableAccessError) -> Self {
+        match error {
+            TableAccessError::OutOfBounds { .. } => Trap::new(TrapKind::TableOutOfBounds),
+            TableAccessError::TypeMismatch { .. } => Trap::new(TrapKind::OperandTypeMismatch),
+        }
+    }
+}
+
+/// Setting an immutable global, or setting a global to a value of another type,
+/// is an operand error that validation would have rejected
+impl From<GlobalError> for Trap {
+    fn from(_: GlobalError) -> Self {
+        Trap::new(TrapKind::OperandTypeMismatch)
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "wasm trap: {}", self.kind)?;
+
+        if !self.backtrace.is_empty() {
+            write!(f, "\nwasm backtrace:")?;
+
+            for (depth, function) in self.backtrace.iter().enumerate() {
+                write!(f, "\n  {}: {}", depth, function)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Trap {}
+
+pub type Result<T> = std::result::Result<T, Trap>;
@@ -1,10 +1,20 @@
 use std::fmt;
 
+#[derive(PartialEq)]
 pub struct Preamble {
     pub magic: [u8; 4],
     pub version: [u8; 4],
 }
 
+impl Default for Preamble {
+    fn default() -> Self {
+        Self {
+            magic: *b"\0asm",
+            version: [1, 0, 0, 0],
+        }
+    }
+}
+
 impl fmt::Debug for Preamble {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use std::str;
@@ -15,7 +25,7 @@ impl fmt::Debug for Preamble {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum NumType {
     I32,
     I64,
@@ -24,12 +34,13 @@ pub enum NumType {
 }
 
 // TODO: rename to ValType
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ValueType {
     NumType(NumType),
     RefType(RefType),
 }
 
+#[derive(Clone, PartialEq)]
 pub struct FuncType {
     pub parameter_types: Vec<ValueType>,
     pub result_types: Vec<ValueType>,
@@ -45,57 +56,59 @@ impl fmt::Debug for FuncType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct TypeIdx(pub usize);
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct FuncIdx(pub usize);
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct TableIdx(pub usize);
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct MemIdx(pub usize);
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct GlobalIdx(pub usize);
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ElemIdx(pub usize);
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct LocalIdx(pub usize);
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct LabelIdx(pub usize);
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum RefType {
     FuncRef,
     ExternRef,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Limits {
     pub min: u32,
     pub max: Option<u32>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct TableType {
     pub elem_type: RefType,
     pub limits: Limits,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct MemType {
     pub limits: Limits,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Mutability {
     Constant,
     Variable,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct GlobalType {
     pub value_type: ValueType,
     pub mutability: Mutability,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum ImportDescriptor {
     Func(TypeIdx),
     Table(TableType),
@@ -103,6 +116,7 @@ pub enum ImportDescriptor {
     Global(GlobalType),
 }
 
+#[derive(Clone, PartialEq, Eq)]
 pub struct Name(pub String);
 
 impl fmt::Debug for Name {
@@ -111,6 +125,7 @@ impl fmt::Debug for Name {
     }
 }
 
+#[derive(PartialEq)]
 pub struct Import {
     pub module: Name,
     pub name: Name,
@@ -127,64 +142,242 @@ impl fmt::Debug for Import {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum BlockType {
     Empty,
+    Value(ValueType),
+    TypeIdx(TypeIdx),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MemArg {
     pub align: usize,
     pub offset: usize,
 }
 
+/// The maximum number of blocks, loops and ifs that may be nested inside each other.
+///
+/// Nested instructions form a tree that is encoded, printed and dropped recursively,
+/// so the parsers and the validator reject deeper nesting.
+pub const MAX_NESTING_DEPTH: usize = 1024;
+
 // TODO: for a potential taxonomy: section 2.4.1 from spec
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     // Control instructions
     Unreachable,
+    Nop,
     Block(BlockType, Vec<Instruction>),
     Loop(BlockType, Vec<Instruction>),
     If(BlockType, Vec<Instruction>, Vec<Instruction>),
     Branch(LabelIdx),
     BranchIf(LabelIdx),
+    BranchTable(Vec<LabelIdx>, LabelIdx),
     Return,
     Call(FuncIdx),
+    CallIndirect(TypeIdx, TableIdx),
+
+    // Parametric instructions
+    Drop,
+    Select,
 
     // Variable instructions
     LocalGet(LocalIdx),
     LocalSet(LocalIdx),
+    LocalTee(LocalIdx),
     GlobalGet(GlobalIdx),
     GlobalSet(GlobalIdx),
 
+    // Table instructions
+    TableGet(TableIdx),
+    TableSet(TableIdx),
+    TableSize(TableIdx),
+    TableGrow(TableIdx),
+    TableFill(TableIdx),
+    /// Copies from the second table to the first one
+    TableCopy(TableIdx, TableIdx),
+    /// Copies from an element segment to a table
+    TableInit(TableIdx, ElemIdx),
+    ElemDrop(ElemIdx),
+
     // Memory instructions
     I32Load(MemArg),
+    I64Load(MemArg),
+    F32Load(MemArg),
+    F64Load(MemArg),
+    I32Load8Signed(MemArg),
+    I32Load8Unsigned(MemArg),
+    I32Load16Signed(MemArg),
+    I32Load16Unsigned(MemArg),
+    I64Load8Signed(MemArg),
+    I64Load8Unsigned(MemArg),
+    I64Load16Signed(MemArg),
+    I64Load16Unsigned(MemArg),
+    I64Load32Signed(MemArg),
+    I64Load32Unsigned(MemArg),
     I32Store(MemArg),
+    I64Store(MemArg),
+    F32Store(MemArg),
+    F64Store(MemArg),
+    I32Store8(MemArg),
+    I32Store16(MemArg),
+    I64Store8(MemArg),
+    I64Store16(MemArg),
+    I64Store32(MemArg),
+    MemorySize,
+    MemoryGrow,
 
     // Numeric instructions
     I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
     F64Const(f64),
+
+    I32Eqz,
     I32Eq,
+    I32Ne,
+    I32LtSigned,
+    I32LtUnsigned,
     I32GtSigned,
+    I32GtUnsigned,
+    I32LeSigned,
+    I32LeUnsigned,
+    I32GeSigned,
+    I32GeUnsigned,
+
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtSigned,
+    I64LtUnsigned,
+    I64GtSigned,
+    I64GtUnsigned,
+    I64LeSigned,
+    I64LeUnsigned,
+    I64GeSigned,
+    I64GeUnsigned,
+
+    F32Eq,
+    F32Ne,
+    F32Lt,
+    F32Gt,
+    F32Le,
+    F32Ge,
+
+    F64Eq,
+    F64Ne,
     F64Lt,
     F64Gt,
+    F64Le,
     F64Ge,
+
+    I32Clz,
+    I32Ctz,
+    I32Popcnt,
     I32Add,
     I32Sub,
     I32Mul,
+    I32DivSigned,
+    I32DivUnsigned,
+    I32RemSigned,
+    I32RemUnsigned,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrSigned,
+    I32ShrUnsigned,
+    I32Rotl,
+    I32Rotr,
+
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivSigned,
+    I64DivUnsigned,
+    I64RemSigned,
+    I64RemUnsigned,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrSigned,
+    I64ShrUnsigned,
+    I64Rotl,
+    I64Rotr,
+
+    F32Abs,
+    F32Neg,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    F32Nearest,
+    F32Sqrt,
+    F32Add,
+    F32Sub,
+    F32Mul,
+    F32Div,
+    F32Min,
+    F32Max,
+    F32Copysign,
+
+    F64Abs,
+    F64Neg,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+    F64Sqrt,
     F64Add,
     F64Sub,
     F64Mul,
     F64Div,
+    F64Min,
+    F64Max,
+    F64Copysign,
+
+    I32WrapI64,
+    I32TruncF32Signed,
+    I32TruncF32Unsigned,
+    I32TruncF64Signed,
+    I32TruncF64Unsigned,
+    I64ExtendI32Signed,
+    I64ExtendI32Unsigned,
+    I64TruncF32Signed,
+    I64TruncF32Unsigned,
+    I64TruncF64Signed,
+    I64TruncF64Unsigned,
+    F32ConvertI32Signed,
+    F32ConvertI32Unsigned,
+    F32ConvertI64Signed,
+    F32ConvertI64Unsigned,
+    F32DemoteF64,
+    F64ConvertI32Signed,
+    F64ConvertI32Unsigned,
+    F64ConvertI64Signed,
+    F64ConvertI64Unsigned,
+    F64PromoteF32,
+    I32ReinterpretF32,
+    I64ReinterpretF64,
+    F32ReinterpretI32,
+    F64ReinterpretI64,
+
+    // Reference instructions
+    RefNull(RefType),
+    RefIsNull,
+    RefFunc(FuncIdx),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Global {
     pub global_type: GlobalType,
     pub expression: Vec<Instruction>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum ExportDescriptor {
     Func(FuncIdx),
     Table(TableIdx),
@@ -192,22 +385,67 @@ pub enum ExportDescriptor {
     Global(GlobalIdx),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Export {
     pub name: Name,
     pub descriptor: ExportDescriptor,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+pub struct Start {
+    pub func: FuncIdx,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ElemMode {
+    Passive,
+    Active {
+        table: TableIdx,
+        offset: Vec<Instruction>,
+    },
+    Declarative,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Elem {
+    pub elem_type: RefType,
+    pub init: Vec<Vec<Instruction>>,
+    pub mode: ElemMode,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DataMode {
+    Passive,
+    Active {
+        memory: MemIdx,
+        offset: Vec<Instruction>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    pub init: Vec<u8>,
+    pub mode: DataMode,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Code {
     pub locals: Vec<ValueType>,
     pub body: Vec<Instruction>,
 }
 
-#[derive(Debug)]
-pub struct Func {
-    pub ftype: FuncType,
-    pub code: Code,
+/// Debug names for the module, its functions and their locals (section 7.4 from spec)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Names {
+    pub module: Option<Name>,
+    pub functions: Vec<(FuncIdx, Name)>,
+    pub locals: Vec<(FuncIdx, Vec<(LocalIdx, Name)>)>,
+}
+
+impl Names {
+    pub fn is_empty(&self) -> bool {
+        self.module.is_none() && self.functions.is_empty() && self.locals.is_empty()
+    }
 }
 
 pub struct Locals {
@@ -215,31 +453,54 @@ pub struct Locals {
     pub t: ValueType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SectionId {
+    Custom,
+    Type,
+    Import,
+    Function,
+    Table,
+    Memory,
+    Global,
+    Export,
+    Start,
+    Element,
+    Code,
+    Data,
+    DataCount,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum Section {
     Custom,
+    Name(Names),
     Type(Vec<FuncType>),
     Import(Vec<Import>),
     Function(Vec<TypeIdx>),
-    Table,
+    Table(Vec<TableType>),
     Memory(Vec<Limits>),
     Global(Vec<Global>),
     Export(Vec<Export>),
-    Start,
-    Element,
+    Start(Start),
+    Element(Vec<Elem>),
     Code(Vec<Code>),
-    Data,
+    Data(Vec<Data>),
+    DataCount(u32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, PartialEq)]
 pub struct Module {
     pub preamble: Preamble,
     pub types: Vec<FuncType>,
     pub imports: Vec<Import>,
     pub functions: Vec<TypeIdx>,
+    pub tables: Vec<TableType>,
     pub memories: Vec<Limits>,
     pub globals: Vec<Global>,
     pub exports: Vec<Export>,
+    pub start: Option<Start>,
+    pub elems: Vec<Elem>,
     pub codes: Vec<Code>,
-    // TODO: Add funcs component (see section 2.5.3 from spec)
+    pub datas: Vec<Data>,
+    pub names: Names,
 }
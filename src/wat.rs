@@ -0,0 +1,2222 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use super::wasm::*;
+
+/// Describes why a module in the text format could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (line {}, column {})",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    Keyword,
+    Id,
+    String,
+    Reserved,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    offset: usize,
+}
+
+fn error_at(text: &str, offset: usize, message: String) -> Error {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+
+    Error {
+        message,
+        line,
+        column,
+    }
+}
+
+fn is_idchar(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-./:<=>?@\\^_`|~".contains(&c)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token<'_>>> {
+    let bytes = text.as_bytes();
+    let mut tokens = vec![];
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let next = bytes.get(i + 1).copied();
+
+        let kind = match bytes[i] {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                i += 1;
+                continue;
+            }
+
+            // Line comment
+            b';' if next == Some(b';') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+
+            // (Nested) block comment
+            b'(' if next == Some(b';') => {
+                let mut depth = 0;
+                loop {
+                    if i >= bytes.len() {
+                        return Err(error_at(
+                            text,
+                            start,
+                            "Unterminated block comment".to_owned(),
+                        ));
+                    }
+
+                    if bytes[i..].starts_with(b"(;") {
+                        depth += 1;
+                        i += 2;
+                    } else if bytes[i..].starts_with(b";)") {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                continue;
+            }
+
+            b'(' => {
+                i += 1;
+                TokenKind::LParen
+            }
+
+            b')' => {
+                i += 1;
+                TokenKind::RParen
+            }
+
+            b'"' => {
+                i += 1;
+                loop {
+                    match bytes.get(i) {
+                        None => {
+                            return Err(error_at(text, start, "Unterminated string".to_owned()))
+                        }
+                        Some(b'"') => break,
+                        Some(b'\\') => i += 2,
+                        Some(_) => i += 1,
+                    }
+                }
+                i += 1;
+                TokenKind::String
+            }
+
+            c if is_idchar(c) => {
+                while i < bytes.len() && is_idchar(bytes[i]) {
+                    i += 1;
+                }
+
+                match c {
+                    b'$' if i - start > 1 => TokenKind::Id,
+                    b'a'..=b'z' => TokenKind::Keyword,
+                    _ => TokenKind::Reserved,
+                }
+            }
+
+            _ => {
+                let c = text[start..].chars().next().unwrap();
+                return Err(error_at(
+                    text,
+                    start,
+                    format!("Unexpected character: {:?}", c),
+                ));
+            }
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &text[start..i],
+            offset: start,
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Decodes the contents of a string token, which may contain arbitrary bytes through escapes
+fn string_bytes(text: &str) -> Option<Vec<u8>> {
+    let text = &text[1..text.len() - 1];
+    let mut result = vec![];
+
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            result.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        match chars.next()? {
+            't' => result.push(b'\t'),
+            'n' => result.push(b'\n'),
+            'r' => result.push(b'\r'),
+            '"' => result.push(b'"'),
+            '\'' => result.push(b'\''),
+            '\\' => result.push(b'\\'),
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+
+                let mut digits = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => digits.push(c),
+                    }
+                }
+
+                let c = char::from_u32(u32::try_from(parse_u64(&digits, 16)?).ok()?)?;
+                let mut buf = [0; 4];
+                result.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            high => {
+                let low = chars.next()?;
+                result.push((high.to_digit(16)? * 16 + low.to_digit(16)?) as u8);
+            }
+        }
+    }
+
+    Some(result)
+}
+
+/// Parses a sequence of digits in the given radix, which may be separated by single underscores
+fn parse_u64(digits: &str, radix: u32) -> Option<u64> {
+    if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+        return None;
+    }
+
+    if digits.contains("__") {
+        return None;
+    }
+
+    let mut result = 0u64;
+    for c in digits.chars().filter(|&c| c != '_') {
+        result = result
+            .checked_mul(radix as u64)?
+            .checked_add(c.to_digit(radix)? as u64)?;
+    }
+
+    Some(result)
+}
+
+fn split_sign(text: &str) -> (bool, &str) {
+    match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    }
+}
+
+/// Parses an integer literal into its sign and magnitude
+fn parse_integer(text: &str) -> Option<(bool, u64)> {
+    let (negative, text) = split_sign(text);
+
+    let magnitude = match text.strip_prefix("0x") {
+        Some(hex) => parse_u64(hex, 16)?,
+        None => parse_u64(text, 10)?,
+    };
+
+    Some((negative, magnitude))
+}
+
+fn parse_i32(text: &str) -> Option<i32> {
+    match parse_integer(text)? {
+        (false, magnitude) if magnitude <= u32::MAX as u64 => Some(magnitude as u32 as i32),
+        (true, magnitude) if magnitude <= 1 << 31 => Some((magnitude as i64).wrapping_neg() as i32),
+        _ => None,
+    }
+}
+
+fn parse_i64(text: &str) -> Option<i64> {
+    match parse_integer(text)? {
+        (false, magnitude) => Some(magnitude as i64),
+        (true, magnitude) if magnitude <= 1 << 63 => Some((magnitude as i64).wrapping_neg()),
+        _ => None,
+    }
+}
+
+fn parse_u32(text: &str) -> Option<u32> {
+    match parse_integer(text)? {
+        (false, magnitude) if !text.starts_with('+') => u32::try_from(magnitude).ok(),
+        _ => None,
+    }
+}
+
+/// Describes the layout of a binary floating point format
+struct FloatFormat {
+    mantissa_bits: u32,
+    exponent_bits: u32,
+}
+
+const F32_FORMAT: FloatFormat = FloatFormat {
+    mantissa_bits: 23,
+    exponent_bits: 8,
+};
+
+const F64_FORMAT: FloatFormat = FloatFormat {
+    mantissa_bits: 52,
+    exponent_bits: 11,
+};
+
+impl FloatFormat {
+    fn sign_bit(&self, negative: bool) -> u64 {
+        (negative as u64) << (self.mantissa_bits + self.exponent_bits)
+    }
+
+    fn infinity(&self) -> u64 {
+        ((1 << self.exponent_bits) - 1) << self.mantissa_bits
+    }
+
+    /// Rounds `significand * 2^exponent` to the nearest representable value (ties to even),
+    /// where `sticky` indicates that there are non-zero bits below the significand.
+    /// Returns None if the value is too large to be represented.
+    fn round(&self, negative: bool, significand: u64, sticky: bool, exponent: i64) -> Option<u64> {
+        if significand == 0 {
+            return Some(self.sign_bit(negative));
+        }
+
+        let bias = (1i64 << (self.exponent_bits - 1)) - 1;
+        let mantissa_bits = self.mantissa_bits as i64;
+
+        // Determine the exponent of the least significant bit of the result,
+        // which is limited from below by the subnormal numbers
+        let msb = 63 - significand.leading_zeros() as i64;
+        let lsb_exponent = (msb + exponent - mantissa_bits).max(1 - bias - mantissa_bits);
+
+        let shift = lsb_exponent - exponent;
+        let mut result = if shift <= 0 {
+            significand << -shift
+        } else if shift > 64 {
+            0
+        } else {
+            let kept = if shift == 64 { 0 } else { significand >> shift };
+            let remainder = if shift == 64 {
+                significand
+            } else {
+                significand & ((1 << shift) - 1)
+            };
+            let half = 1 << (shift - 1);
+
+            let round_up = remainder > half || (remainder == half && (sticky || kept & 1 == 1));
+            kept + round_up as u64
+        };
+
+        let mut lsb_exponent = lsb_exponent;
+        if result >> (self.mantissa_bits + 1) != 0 {
+            // Rounding overflowed into the next binade
+            result >>= 1;
+            lsb_exponent += 1;
+        }
+
+        let bits = if result >> self.mantissa_bits != 0 {
+            let biased_exponent = lsb_exponent + mantissa_bits + bias;
+            if biased_exponent >= (1 << self.exponent_bits) - 1 {
+                return None;
+            }
+
+            ((biased_exponent as u64) << self.mantissa_bits)
+                | (result & ((1 << self.mantissa_bits) - 1))
+        } else {
+            result
+        };
+
+        Some(self.sign_bit(negative) | bits)
+    }
+
+    /// Parses a float literal (section 6.3.2 from spec) into its bit pattern
+    fn parse(&self, text: &str) -> Option<u64> {
+        let (negative, text) = split_sign(text);
+
+        if text == "inf" {
+            return Some(self.sign_bit(negative) | self.infinity());
+        }
+
+        if text == "nan" {
+            let canonical = 1 << (self.mantissa_bits - 1);
+            return Some(self.sign_bit(negative) | self.infinity() | canonical);
+        }
+
+        if let Some(payload) = text.strip_prefix("nan:0x") {
+            let payload = parse_u64(payload, 16)?;
+            if payload == 0 || payload >> self.mantissa_bits != 0 {
+                return None;
+            }
+
+            return Some(self.sign_bit(negative) | self.infinity() | payload);
+        }
+
+        match text.strip_prefix("0x") {
+            Some(hex) => self.parse_hex(negative, hex),
+            None => self.parse_decimal(negative, text),
+        }
+    }
+
+    fn parse_hex(&self, negative: bool, text: &str) -> Option<u64> {
+        let (mantissa, exponent) = match text.find(['p', 'P']) {
+            Some(p) => (&text[..p], Some(&text[p + 1..])),
+            None => (text, None),
+        };
+
+        let (integer, fraction) = match mantissa.find('.') {
+            Some(dot) => (&mantissa[..dot], Some(&mantissa[dot + 1..])),
+            None => (mantissa, None),
+        };
+
+        // Validate the digit groups
+        parse_u64_digits_only(integer, 16)?;
+        if let Some(fraction) = fraction {
+            if !fraction.is_empty() {
+                parse_u64_digits_only(fraction, 16)?;
+            }
+        }
+
+        let mut significand = 0u64;
+        let mut sticky = false;
+        let mut exponent_adjust = 0i64;
+
+        let digits = integer.chars().filter(|&c| c != '_');
+        for digit in digits.map(|c| c.to_digit(16).unwrap() as u64) {
+            if significand >> 60 == 0 {
+                significand = significand * 16 + digit;
+            } else {
+                sticky |= digit != 0;
+                exponent_adjust += 4;
+            }
+        }
+
+        let digits = fraction.unwrap_or("").chars().filter(|&c| c != '_');
+        for digit in digits.map(|c| c.to_digit(16).unwrap() as u64) {
+            if significand >> 60 == 0 {
+                significand = significand * 16 + digit;
+                exponent_adjust -= 4;
+            } else {
+                sticky |= digit != 0;
+            }
+        }
+
+        let exponent = match exponent {
+            Some(exponent) => {
+                let (exponent_negative, digits) = split_sign(exponent);
+                let magnitude = parse_u64(digits, 10)?.min(1 << 32) as i64;
+                if exponent_negative {
+                    -magnitude
+                } else {
+                    magnitude
+                }
+            }
+            None => 0,
+        };
+
+        self.round(negative, significand, sticky, exponent + exponent_adjust)
+    }
+
+    fn parse_decimal(&self, negative: bool, text: &str) -> Option<u64> {
+        // Validate the digit groups, after which the standard library can do the rounding
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(e) => (&text[..e], Some(&text[e + 1..])),
+            None => (text, None),
+        };
+
+        let (integer, fraction) = match mantissa.find('.') {
+            Some(dot) => (&mantissa[..dot], Some(&mantissa[dot + 1..])),
+            None => (mantissa, None),
+        };
+
+        parse_u64_digits_only(integer, 10)?;
+        if let Some(fraction) = fraction {
+            if !fraction.is_empty() {
+                parse_u64_digits_only(fraction, 10)?;
+            }
+        }
+        if let Some(exponent) = exponent {
+            parse_u64_digits_only(split_sign(exponent).1, 10)?;
+        }
+
+        let text: String = text.chars().filter(|&c| c != '_').collect();
+        let bits = if self.mantissa_bits == F32_FORMAT.mantissa_bits {
+            let value = text.parse::<f32>().ok()?;
+            if value.is_infinite() {
+                return None;
+            }
+            value.to_bits() as u64
+        } else {
+            let value = text.parse::<f64>().ok()?;
+            if value.is_infinite() {
+                return None;
+            }
+            value.to_bits()
+        };
+
+        Some(self.sign_bit(negative) | bits)
+    }
+}
+
+/// Checks that the digits are valid (like `parse_u64`), without limiting their count
+fn parse_u64_digits_only(digits: &str, radix: u32) -> Option<()> {
+    if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+        return None;
+    }
+
+    if digits.contains("__") {
+        return None;
+    }
+
+    if digits.chars().all(|c| c == '_' || c.is_digit(radix)) {
+        Some(())
+    } else {
+        None
+    }
+}
+
+fn parse_f32(text: &str) -> Option<f32> {
+    Some(f32::from_bits(F32_FORMAT.parse(text)? as u32))
+}
+
+fn parse_f64(text: &str) -> Option<f64> {
+    Some(f64::from_bits(F64_FORMAT.parse(text)?))
+}
+
+fn value_type(keyword: &str) -> Option<ValueType> {
+    match keyword {
+        "i32" => Some(ValueType::NumType(NumType::I32)),
+        "i64" => Some(ValueType::NumType(NumType::I64)),
+        "f32" => Some(ValueType::NumType(NumType::F32)),
+        "f64" => Some(ValueType::NumType(NumType::F64)),
+        "funcref" => Some(ValueType::RefType(RefType::FuncRef)),
+        "externref" => Some(ValueType::RefType(RefType::ExternRef)),
+        _ => None,
+    }
+}
+
+fn ref_type(keyword: &str) -> Option<RefType> {
+    match keyword {
+        "funcref" => Some(RefType::FuncRef),
+        "externref" => Some(RefType::ExternRef),
+        _ => None,
+    }
+}
+
+/// Instructions without any immediates
+fn plain_instruction(keyword: &str) -> Option<Instruction> {
+    Some(match keyword {
+        "unreachable" => Instruction::Unreachable,
+        "nop" => Instruction::Nop,
+        "return" => Instruction::Return,
+        "drop" => Instruction::Drop,
+        "memory.size" => Instruction::MemorySize,
+        "memory.grow" => Instruction::MemoryGrow,
+        "ref.is_null" => Instruction::RefIsNull,
+
+        "i32.eqz" => Instruction::I32Eqz,
+        "i32.eq" => Instruction::I32Eq,
+        "i32.ne" => Instruction::I32Ne,
+        "i32.lt_s" => Instruction::I32LtSigned,
+        "i32.lt_u" => Instruction::I32LtUnsigned,
+        "i32.gt_s" => Instruction::I32GtSigned,
+        "i32.gt_u" => Instruction::I32GtUnsigned,
+        "i32.le_s" => Instruction::I32LeSigned,
+        "i32.le_u" => Instruction::I32LeUnsigned,
+        "i32.ge_s" => Instruction::I32GeSigned,
+        "i32.ge_u" => Instruction::I32GeUnsigned,
+
+        "i64.eqz" => Instruction::I64Eqz,
+        "i64.eq" => Instruction::I64Eq,
+        "i64.ne" => Instruction::I64Ne,
+        "i64.lt_s" => Instruction::I64LtSigned,
+        "i64.lt_u" => Instruction::I64LtUnsigned,
+        "i64.gt_s" => Instruction::I64GtSigned,
+        "i64.gt_u" => Instruction::I64GtUnsigned,
+        "i64.le_s" => Instruction::I64LeSigned,
+        "i64.le_u" => Instruction::I64LeUnsigned,
+        "i64.ge_s" => Instruction::I64GeSigned,
+        "i64.ge_u" => Instruction::I64GeUnsigned,
+
+        "f32.eq" => Instruction::F32Eq,
+        "f32.ne" => Instruction::F32Ne,
+        "f32.lt" => Instruction::F32Lt,
+        "f32.gt" => Instruction::F32Gt,
+        "f32.le" => Instruction::F32Le,
+        "f32.ge" => Instruction::F32Ge,
+
+        "f64.eq" => Instruction::F64Eq,
+        "f64.ne" => Instruction::F64Ne,
+        "f64.lt" => Instruction::F64Lt,
+        "f64.gt" => Instruction::F64Gt,
+        "f64.le" => Instruction::F64Le,
+        "f64.ge" => Instruction::F64Ge,
+
+        "i32.clz" => Instruction::I32Clz,
+        "i32.ctz" => Instruction::I32Ctz,
+        "i32.popcnt" => Instruction::I32Popcnt,
+        "i32.add" => Instruction::I32Add,
+        "i32.sub" => Instruction::I32Sub,
+        "i32.mul" => Instruction::I32Mul,
+        "i32.div_s" => Instruction::I32DivSigned,
+        "i32.div_u" => Instruction::I32DivUnsigned,
+        "i32.rem_s" => Instruction::I32RemSigned,
+        "i32.rem_u" => Instruction::I32RemUnsigned,
+        "i32.and" => Instruction::I32And,
+        "i32.or" => Instruction::I32Or,
+        "i32.xor" => Instruction::I32Xor,
+        "i32.shl" => Instruction::I32Shl,
+        "i32.shr_s" => Instruction::I32ShrSigned,
+        "i32.shr_u" => Instruction::I32ShrUnsigned,
+        "i32.rotl" => Instruction::I32Rotl,
+        "i32.rotr" => Instruction::I32Rotr,
+
+        "i64.clz" => Instruction::I64Clz,
+        "i64.ctz" => Instruction::I64Ctz,
+        "i64.popcnt" => Instruction::I64Popcnt,
+        "i64.add" => Instruction::I64Add,
+        "i64.sub" => Instruction::I64Sub,
+        "i64.mul" => Instruction::I64Mul,
+        "i64.div_s" => Instruction::I64DivSigned,
+        "i64.div_u" => Instruction::I64DivUnsigned,
+        "i64.rem_s" => Instruction::I64RemSigned,
+        "i64.rem_u" => Instruction::I64RemUnsigned,
+        "i64.and" => Instruction::I64And,
+        "i64.or" => Instruction::I64Or,
+        "i64.xor" => Instruction::I64Xor,
+        "i64.shl" => Instruction::I64Shl,
+        "i64.shr_s" => Instruction::I64ShrSigned,
+        "i64.shr_u" => Instruction::I64ShrUnsigned,
+        "i64.rotl" => Instruction::I64Rotl,
+        "i64.rotr" => Instruction::I64Rotr,
+
+        "f32.abs" => Instruction::F32Abs,
+        "f32.neg" => Instruction::F32Neg,
+        "f32.ceil" => Instruction::F32Ceil,
+        "f32.floor" => Instruction::F32Floor,
+        "f32.trunc" => Instruction::F32Trunc,
+        "f32.nearest" => Instruction::F32Nearest,
+        "f32.sqrt" => Instruction::F32Sqrt,
+        "f32.add" => Instruction::F32Add,
+        "f32.sub" => Instruction::F32Sub,
+        "f32.mul" => Instruction::F32Mul,
+        "f32.div" => Instruction::F32Div,
+        "f32.min" => Instruction::F32Min,
+        "f32.max" => Instruction::F32Max,
+        "f32.copysign" => Instruction::F32Copysign,
+
+        "f64.abs" => Instruction::F64Abs,
+        "f64.neg" => Instruction::F64Neg,
+        "f64.ceil" => Instruction::F64Ceil,
+        "f64.floor" => Instruction::F64Floor,
+        "f64.trunc" => Instruction::F64Trunc,
+        "f64.nearest" => Instruction::F64Nearest,
+        "f64.sqrt" => Instruction::F64Sqrt,
+        "f64.add" => Instruction::F64Add,
+        "f64.sub" => Instruction::F64Sub,
+        "f64.mul" => Instruction::F64Mul,
+        "f64.div" => Instruction::F64Div,
+        "f64.min" => Instruction::F64Min,
+        "f64.max" => Instruction::F64Max,
+        "f64.copysign" => Instruction::F64Copysign,
+
+        "i32.wrap_i64" => Instruction::I32WrapI64,
+        "i32.trunc_f32_s" => Instruction::I32TruncF32Signed,
+        "i32.trunc_f32_u" => Instruction::I32TruncF32Unsigned,
+        "i32.trunc_f64_s" => Instruction::I32TruncF64Signed,
+        "i32.trunc_f64_u" => Instruction::I32TruncF64Unsigned,
+        "i64.extend_i32_s" => Instruction::I64ExtendI32Signed,
+        "i64.extend_i32_u" => Instruction::I64ExtendI32Unsigned,
+        "i64.trunc_f32_s" => Instruction::I64TruncF32Signed,
+        "i64.trunc_f32_u" => Instruction::I64TruncF32Unsigned,
+        "i64.trunc_f64_s" => Instruction::I64TruncF64Signed,
+        "i64.trunc_f64_u" => Instruction::I64TruncF64Unsigned,
+        "f32.convert_i32_s" => Instruction::F32ConvertI32Signed,
+        "f32.convert_i32_u" => Instruction::F32ConvertI32Unsigned,
+        "f32.convert_i64_s" => Instruction::F32ConvertI64Signed,
+        "f32.convert_i64_u" => Instruction::F32ConvertI64Unsigned,
+        "f32.demote_f64" => Instruction::F32DemoteF64,
+        "f64.convert_i32_s" => Instruction::F64ConvertI32Signed,
+        "f64.convert_i32_u" => Instruction::F64ConvertI32Unsigned,
+        "f64.convert_i64_s" => Instruction::F64ConvertI64Signed,
+        "f64.convert_i64_u" => Instruction::F64ConvertI64Unsigned,
+        "f64.promote_f32" => Instruction::F64PromoteF32,
+        "i32.reinterpret_f32" => Instruction::I32ReinterpretF32,
+        "i64.reinterpret_f64" => Instruction::I64ReinterpretF64,
+        "f32.reinterpret_i32" => Instruction::F32ReinterpretI32,
+        "f64.reinterpret_i64" => Instruction::F64ReinterpretI64,
+
+        _ => return None,
+    })
+}
+
+type MemoryInstruction = fn(MemArg) -> Instruction;
+
+/// Memory instructions, together with their natural alignment (as exponent of 2)
+fn memory_instruction(keyword: &str) -> Option<(MemoryInstruction, usize)> {
+    Some(match keyword {
+        "i32.load" => (Instruction::I32Load, 2),
+        "i64.load" => (Instruction::I64Load, 3),
+        "f32.load" => (Instruction::F32Load, 2),
+        "f64.load" => (Instruction::F64Load, 3),
+        "i32.load8_s" => (Instruction::I32Load8Signed, 0),
+        "i32.load8_u" => (Instruction::I32Load8Unsigned, 0),
+        "i32.load16_s" => (Instruction::I32Load16Signed, 1),
+        "i32.load16_u" => (Instruction::I32Load16Unsigned, 1),
+        "i64.load8_s" => (Instruction::I64Load8Signed, 0),
+        "i64.load8_u" => (Instruction::I64Load8Unsigned, 0),
+        "i64.load16_s" => (Instruction::I64Load16Signed, 1),
+        "i64.load16_u" => (Instruction::I64Load16Unsigned, 1),
+        "i64.load32_s" => (Instruction::I64Load32Signed, 2),
+        "i64.load32_u" => (Instruction::I64Load32Unsigned, 2),
+        "i32.store" => (Instruction::I32Store, 2),
+        "i64.store" => (Instruction::I64Store, 3),
+        "f32.store" => (Instruction::F32Store, 2),
+        "f64.store" => (Instruction::F64Store, 3),
+        "i32.store8" => (Instruction::I32Store8, 0),
+        "i32.store16" => (Instruction::I32Store16, 1),
+        "i64.store8" => (Instruction::I64Store8, 0),
+        "i64.store16" => (Instruction::I64Store16, 1),
+        "i64.store32" => (Instruction::I64Store32, 2),
+        _ => return None,
+    })
+}
+
+/// Maps symbolic identifiers onto indices, one map per index space
+#[derive(Default)]
+struct Names<'a> {
+    types: HashMap<&'a str, usize>,
+    funcs: HashMap<&'a str, usize>,
+    tables: HashMap<&'a str, usize>,
+    memories: HashMap<&'a str, usize>,
+    globals: HashMap<&'a str, usize>,
+    elems: HashMap<&'a str, usize>,
+    datas: HashMap<&'a str, usize>,
+}
+
+/// Keeps track of the number of entries in each index space while walking the module fields
+#[derive(Default)]
+struct Counts {
+    funcs: usize,
+    tables: usize,
+    memories: usize,
+    globals: usize,
+    elems: usize,
+    datas: usize,
+}
+
+/// Symbolic names that are in scope within a function body
+#[derive(Default)]
+struct FuncContext<'a> {
+    locals: HashMap<&'a str, usize>,
+    labels: Vec<Option<&'a str>>,
+}
+
+/// A block or folded instruction of which the instructions are being parsed
+enum Open<'a> {
+    /// A block in the flat format. For an if, `then_code` holds the instructions
+    /// of the then branch once `else` has been found.
+    Block {
+        keyword: &'a str,
+        label: Option<&'a str>,
+        block_type: BlockType,
+        then_code: Option<Vec<Instruction>>,
+        outer: Vec<Instruction>,
+    },
+    /// A folded block or loop
+    FoldedBlock {
+        keyword: &'a str,
+        block_type: BlockType,
+        outer: Vec<Instruction>,
+    },
+    /// A folded if, of which the condition is parsed into the enclosing code until `outer`
+    /// takes it over at the start of the then branch
+    FoldedIf {
+        label: Option<&'a str>,
+        block_type: BlockType,
+        then_code: Option<Vec<Instruction>>,
+        outer: Option<Vec<Instruction>>,
+    },
+    /// A folded plain instruction, which follows its operands
+    Folded(Instruction),
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    names: Names<'a>,
+    counts: Counts,
+    module: Module,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: String) -> Result<T> {
+        let offset = match self.tokens.get(self.position) {
+            Some(token) => token.offset,
+            None => self.text.len(),
+        };
+
+        Err(error_at(self.text, offset, message))
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn peek_kind(&self, kind: TokenKind) -> bool {
+        matches!(self.peek(), Some(token) if token.kind == kind)
+    }
+
+    fn next(&mut self) -> Result<Token<'a>> {
+        match self.peek() {
+            Some(token) => {
+                self.position += 1;
+                Ok(token)
+            }
+            None => self.error("Unexpected end of input".to_owned()),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<Token<'a>> {
+        match self.peek() {
+            Some(token) if token.kind == kind => {
+                self.position += 1;
+                Ok(token)
+            }
+            Some(token) => self.error(format!("Expected {:?}, found: {}", kind, token.text)),
+            None => self.error(format!("Expected {:?}, found end of input", kind)),
+        }
+    }
+
+    fn expect_lparen(&mut self) -> Result<()> {
+        self.expect(TokenKind::LParen).map(|_| ())
+    }
+
+    fn expect_rparen(&mut self) -> Result<()> {
+        self.expect(TokenKind::RParen).map(|_| ())
+    }
+
+    fn peek_keyword(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Keyword => Some(token.text),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the next tokens are an opening parenthesis followed by the keyword
+    fn peek_field(&self, keyword: &str) -> bool {
+        match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(lparen), Some(token)) => {
+                lparen.kind == TokenKind::LParen
+                    && token.kind == TokenKind::Keyword
+                    && token.text == keyword
+            }
+            _ => false,
+        }
+    }
+
+    fn eat_field(&mut self, keyword: &str) -> bool {
+        if self.peek_field(keyword) {
+            self.position += 2;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_field(&mut self, keyword: &str) -> Result<()> {
+        if !self.eat_field(keyword) {
+            return self.error(format!("Expected ({}", keyword));
+        }
+
+        Ok(())
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword() == Some(keyword) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self) -> Result<&'a str> {
+        Ok(self.expect(TokenKind::Keyword)?.text)
+    }
+
+    fn eat_id(&mut self) -> Option<&'a str> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Id => {
+                self.position += 1;
+                Some(token.text)
+            }
+            _ => None,
+        }
+    }
+
+    /// Skips a parenthesized expression, including all nested expressions
+    fn skip_sexpr(&mut self) -> Result<()> {
+        self.expect_lparen()?;
+
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()?.kind {
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen => depth -= 1,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_string(&mut self) -> Result<Vec<u8>> {
+        let token = self.expect(TokenKind::String)?;
+
+        match string_bytes(token.text) {
+            Some(bytes) => Ok(bytes),
+            None => {
+                self.position -= 1;
+                self.error(format!("Invalid string: {}", token.text))
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<Name> {
+        let bytes = self.parse_string()?;
+
+        match String::from_utf8(bytes) {
+            Ok(name) => Ok(Name(name)),
+            Err(_) => {
+                self.position -= 1;
+                self.error("Invalid UTF8 name".to_owned())
+            }
+        }
+    }
+
+    fn parse_number<T>(&mut self, parse: fn(&str) -> Option<T>, what: &str) -> Result<T> {
+        let token = self.next()?;
+
+        let value = match token.kind {
+            TokenKind::Keyword | TokenKind::Reserved => parse(token.text),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => Ok(value),
+            None => {
+                self.position -= 1;
+                self.error(format!("Invalid {}: {}", what, token.text))
+            }
+        }
+    }
+
+    fn parse_u32(&mut self) -> Result<u32> {
+        self.parse_number(parse_u32, "unsigned integer")
+    }
+
+    /// Checks whether the next token is a (numeric or symbolic) index
+    fn peek_index(&self) -> bool {
+        self.peek_index_at(0)
+    }
+
+    /// Checks whether the token `ahead` positions after the next one is an index
+    fn peek_index_at(&self, ahead: usize) -> bool {
+        match self.tokens.get(self.position + ahead) {
+            Some(token) if token.kind == TokenKind::Id => true,
+            Some(token) if token.kind == TokenKind::Reserved => parse_u32(token.text).is_some(),
+            _ => false,
+        }
+    }
+
+    fn resolve_index(&mut self, names: &HashMap<&'a str, usize>, what: &str) -> Result<usize> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Id => match names.get(token.text) {
+                Some(&index) => {
+                    self.position += 1;
+                    Ok(index)
+                }
+                None => self.error(format!("Unknown {}: {}", what, token.text)),
+            },
+            _ => Ok(self.parse_u32()? as usize),
+        }
+    }
+
+    fn parse_type_idx(&mut self) -> Result<TypeIdx> {
+        let names = std::mem::take(&mut self.names.types);
+        let index = self.resolve_index(&names, "type");
+        self.names.types = names;
+        Ok(TypeIdx(index?))
+    }
+
+    fn parse_func_idx(&mut self) -> Result<FuncIdx> {
+        let names = std::mem::take(&mut self.names.funcs);
+        let index = self.resolve_index(&names, "function");
+        self.names.funcs = names;
+        Ok(FuncIdx(index?))
+    }
+
+    fn parse_table_idx(&mut self) -> Result<TableIdx> {
+        let names = std::mem::take(&mut self.names.tables);
+        let index = self.resolve_index(&names, "table");
+        self.names.tables = names;
+        Ok(TableIdx(index?))
+    }
+
+    fn parse_mem_idx(&mut self) -> Result<MemIdx> {
+        let names = std::mem::take(&mut self.names.memories);
+        let index = self.resolve_index(&names, "memory");
+        self.names.memories = names;
+        Ok(MemIdx(index?))
+    }
+
+    fn parse_global_idx(&mut self) -> Result<GlobalIdx> {
+        let names = std::mem::take(&mut self.names.globals);
+        let index = self.resolve_index(&names, "global");
+        self.names.globals = names;
+        Ok(GlobalIdx(index?))
+    }
+
+    fn parse_elem_idx(&mut self) -> Result<ElemIdx> {
+        let names = std::mem::take(&mut self.names.elems);
+        let index = self.resolve_index(&names, "element segment");
+        self.names.elems = names;
+        Ok(ElemIdx(index?))
+    }
+
+    /// Parses an optional table index, which defaults to table 0
+    fn parse_optional_table_idx(&mut self) -> Result<TableIdx> {
+        match self.peek_index() {
+            true => self.parse_table_idx(),
+            false => Ok(TableIdx(0)),
+        }
+    }
+
+    fn parse_local_idx(&mut self, context: &FuncContext<'a>) -> Result<LocalIdx> {
+        Ok(LocalIdx(self.resolve_index(&context.locals, "local")?))
+    }
+
+    fn parse_label_idx(&mut self, context: &FuncContext<'a>) -> Result<LabelIdx> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Id => {
+                let depth = context
+                    .labels
+                    .iter()
+                    .rev()
+                    .position(|&label| label == Some(token.text));
+
+                match depth {
+                    Some(depth) => {
+                        self.position += 1;
+                        Ok(LabelIdx(depth))
+                    }
+                    None => self.error(format!("Unknown label: {}", token.text)),
+                }
+            }
+            _ => Ok(LabelIdx(self.parse_u32()? as usize)),
+        }
+    }
+
+    fn parse_value_type(&mut self) -> Result<ValueType> {
+        let keyword = self.expect_keyword()?;
+
+        match value_type(keyword) {
+            Some(value_type) => Ok(value_type),
+            None => {
+                self.position -= 1;
+                self.error(format!("Invalid value type: {}", keyword))
+            }
+        }
+    }
+
+    fn parse_ref_type(&mut self) -> Result<RefType> {
+        let keyword = self.expect_keyword()?;
+
+        match ref_type(keyword) {
+            Some(ref_type) => Ok(ref_type),
+            None => {
+                self.position -= 1;
+                self.error(format!("Invalid reference type: {}", keyword))
+            }
+        }
+    }
+
+    /// Parses the heap type of `ref.null`
+    fn parse_heap_type(&mut self) -> Result<RefType> {
+        match self.expect_keyword()? {
+            "func" => Ok(RefType::FuncRef),
+            "extern" => Ok(RefType::ExternRef),
+            keyword => {
+                self.position -= 1;
+                self.error(format!("Invalid heap type: {}", keyword))
+            }
+        }
+    }
+
+    fn parse_limits(&mut self) -> Result<Limits> {
+        let min = self.parse_u32()?;
+
+        let max = if self.peek_index() {
+            Some(self.parse_u32()?)
+        } else {
+            None
+        };
+
+        Ok(Limits { min, max })
+    }
+
+    fn parse_table_type(&mut self) -> Result<TableType> {
+        let limits = self.parse_limits()?;
+        let elem_type = self.parse_ref_type()?;
+
+        Ok(TableType { elem_type, limits })
+    }
+
+    fn parse_global_type(&mut self) -> Result<GlobalType> {
+        if self.eat_field("mut") {
+            let value_type = self.parse_value_type()?;
+            self.expect_rparen()?;
+
+            return Ok(GlobalType {
+                value_type,
+                mutability: Mutability::Variable,
+            });
+        }
+
+        Ok(GlobalType {
+            value_type: self.parse_value_type()?,
+            mutability: Mutability::Constant,
+        })
+    }
+
+    /// Parses `(param ...)*` followed by `(result ...)*`, returning the function type and
+    /// the symbolic names of the parameters
+    fn parse_func_type(&mut self) -> Result<(FuncType, Vec<Option<&'a str>>)> {
+        let mut parameter_types = vec![];
+        let mut parameter_names = vec![];
+
+        while self.eat_field("param") {
+            if let Some(id) = self.eat_id() {
+                if parameter_names.contains(&Some(id)) {
+                    self.position -= 1;
+                    return self.error(format!("Duplicate identifier: {}", id));
+                }
+
+                parameter_types.push(self.parse_value_type()?);
+                parameter_names.push(Some(id));
+            } else {
+                while !self.peek_kind(TokenKind::RParen) {
+                    parameter_types.push(self.parse_value_type()?);
+                    parameter_names.push(None);
+                }
+            }
+
+            self.expect_rparen()?;
+        }
+
+        let mut result_types = vec![];
+        while self.eat_field("result") {
+            while !self.peek_kind(TokenKind::RParen) {
+                result_types.push(self.parse_value_type()?);
+            }
+
+            self.expect_rparen()?;
+        }
+
+        let ftype = FuncType {
+            parameter_types,
+            result_types,
+        };
+
+        Ok((ftype, parameter_names))
+    }
+
+    /// Finds the index of the function type, appending it to the types if it doesn't exist yet
+    fn type_index(&mut self, ftype: FuncType) -> TypeIdx {
+        match self.module.types.iter().position(|t| *t == ftype) {
+            Some(index) => TypeIdx(index),
+            None => {
+                self.module.types.push(ftype);
+                TypeIdx(self.module.types.len() - 1)
+            }
+        }
+    }
+
+    /// Parses a type use (section 6.6.3 from spec), returning the type index and
+    /// the symbolic names of the parameters
+    fn parse_type_use(&mut self) -> Result<(TypeIdx, Vec<Option<&'a str>>)> {
+        let explicit = if self.eat_field("type") {
+            let type_idx = self.parse_type_idx()?;
+            self.expect_rparen()?;
+            Some(type_idx)
+        } else {
+            None
+        };
+
+        let (ftype, mut parameter_names) = self.parse_func_type()?;
+
+        match explicit {
+            Some(type_idx) => {
+                let defined = match self.module.types.get(type_idx.0) {
+                    Some(defined) => defined,
+                    None => return self.error(format!("Unknown type: {}", type_idx.0)),
+                };
+
+                let inline = !ftype.parameter_types.is_empty() || !ftype.result_types.is_empty();
+                if inline && *defined != ftype {
+                    return self.error("Inline function type does not match type use".to_owned());
+                }
+
+                parameter_names.resize(defined.parameter_types.len(), None);
+
+                Ok((type_idx, parameter_names))
+            }
+            None => Ok((self.type_index(ftype), parameter_names)),
+        }
+    }
+
+    fn parse_block_type(&mut self) -> Result<BlockType> {
+        if self.peek_field("type") {
+            return Ok(BlockType::TypeIdx(self.parse_type_use()?.0));
+        }
+
+        let (ftype, _) = self.parse_func_type()?;
+
+        if ftype.parameter_types.is_empty() {
+            match ftype.result_types[..] {
+                [] => return Ok(BlockType::Empty),
+                [value_type] => return Ok(BlockType::Value(value_type)),
+                _ => {}
+            }
+        }
+
+        Ok(BlockType::TypeIdx(self.type_index(ftype)))
+    }
+
+    fn parse_memarg(&mut self, natural_alignment: usize) -> Result<MemArg> {
+        let mut memarg = MemArg {
+            align: natural_alignment,
+            offset: 0,
+        };
+
+        if let Some(offset) = self.peek_keyword().and_then(|k| k.strip_prefix("offset=")) {
+            memarg.offset = match parse_u32(offset) {
+                Some(offset) => offset as usize,
+                None => return self.error(format!("Invalid offset: {}", offset)),
+            };
+            self.position += 1;
+        }
+
+        if let Some(align) = self.peek_keyword().and_then(|k| k.strip_prefix("align=")) {
+            memarg.align = match parse_u32(align) {
+                Some(align) if align.is_power_of_two() => align.trailing_zeros() as usize,
+                _ => return self.error(format!("Invalid alignment: {}", align)),
+            };
+            self.position += 1;
+        }
+
+        Ok(memarg)
+    }
+
+    /// Parses the immediates of a non-block instruction, of which the keyword has been read
+    fn parse_plain_instr(
+        &mut self,
+        keyword: &'a str,
+        context: &mut FuncContext<'a>,
+    ) -> Result<Instruction> {
+        if let Some(instruction) = plain_instruction(keyword) {
+            if let Instruction::MemorySize | Instruction::MemoryGrow = instruction {
+                // Only the (implicit) memory 0 is supported
+                if self.peek_index() && self.parse_mem_idx()?.0 != 0 {
+                    self.position -= 1;
+                    return self.error("Only memory 0 is supported".to_owned());
+                }
+            }
+
+            return Ok(instruction);
+        }
+
+        if let Some((instruction, natural_alignment)) = memory_instruction(keyword) {
+            return Ok(instruction(self.parse_memarg(natural_alignment)?));
+        }
+
+        Ok(match keyword {
+            "br" => Instruction::Branch(self.parse_label_idx(context)?),
+            "br_if" => Instruction::BranchIf(self.parse_label_idx(context)?),
+            "br_table" => {
+                let mut labels = vec![self.parse_label_idx(context)?];
+                while self.peek_index() {
+                    labels.push(self.parse_label_idx(context)?);
+                }
+
+                let default = labels.pop().unwrap();
+                Instruction::BranchTable(labels, default)
+            }
+            "call" => Instruction::Call(self.parse_func_idx()?),
+            "call_indirect" => {
+                let table_idx = self.parse_optional_table_idx()?;
+
+                let (type_idx, _) = self.parse_type_use()?;
+                Instruction::CallIndirect(type_idx, table_idx)
+            }
+            "select" => {
+                // The optional result type is implied by the operands
+                if self.eat_field("result") {
+                    while !self.peek_kind(TokenKind::RParen) {
+                        self.parse_value_type()?;
+                    }
+                    self.expect_rparen()?;
+                }
+
+                Instruction::Select
+            }
+
+            "local.get" => Instruction::LocalGet(self.parse_local_idx(context)?),
+            "local.set" => Instruction::LocalSet(self.parse_local_idx(context)?),
+            "local.tee" => Instruction::LocalTee(self.parse_local_idx(context)?),
+            "global.get" => Instruction::GlobalGet(self.parse_global_idx()?),
+            "global.set" => Instruction::GlobalSet(self.parse_global_idx()?),
+
+            "table.get" => Instruction::TableGet(self.parse_optional_table_idx()?),
+            "table.set" => Instruction::TableSet(self.parse_optional_table_idx()?),
+            "table.size" => Instruction::TableSize(self.parse_optional_table_idx()?),
+            "table.grow" => Instruction::TableGrow(self.parse_optional_table_idx()?),
+            "table.fill" => Instruction::TableFill(self.parse_optional_table_idx()?),
+            "table.copy" => {
+                // Either both tables are given, or both default to table 0
+                let destination = self.parse_optional_table_idx()?;
+                let source = self.parse_optional_table_idx()?;
+                Instruction::TableCopy(destination, source)
+            }
+            "table.init" => {
+                // The table is optional, but comes before the element segment
+                let table_idx = match self.peek_index_at(1) {
+                    true => self.parse_table_idx()?,
+                    false => TableIdx(0),
+                };
+
+                Instruction::TableInit(table_idx, self.parse_elem_idx()?)
+            }
+            "elem.drop" => Instruction::ElemDrop(self.parse_elem_idx()?),
+
+            "i32.const" => Instruction::I32Const(self.parse_number(parse_i32, "i32")?),
+            "i64.const" => Instruction::I64Const(self.parse_number(parse_i64, "i64")?),
+            "f32.const" => Instruction::F32Const(self.parse_number(parse_f32, "f32")?),
+            "f64.const" => Instruction::F64Const(self.parse_number(parse_f64, "f64")?),
+
+            "ref.null" => Instruction::RefNull(self.parse_heap_type()?),
+            "ref.func" => Instruction::RefFunc(self.parse_func_idx()?),
+
+            _ => {
+                self.position -= 1;
+                return self.error(format!("Unknown instruction: {}", keyword));
+            }
+        })
+    }
+
+    /// Parses a sequence of (flat and folded) instructions, up to a closing parenthesis,
+    /// `else` or `end`, which is not consumed
+    fn parse_instrs(&mut self, context: &mut FuncContext<'a>) -> Result<Vec<Instruction>> {
+        self.parse_code(context, false)
+    }
+
+    /// Parses a single folded instruction, which has no locals or labels in scope
+    fn parse_folded_expr(&mut self) -> Result<Vec<Instruction>> {
+        if !self.peek_kind(TokenKind::LParen) {
+            self.expect_lparen()?;
+        }
+
+        self.parse_code(&mut FuncContext::default(), true)
+    }
+
+    /// Parses a sequence of instructions, or only the first one if `single` is set.
+    ///
+    /// Blocks and folded instructions are kept on a stack of their own rather than parsed
+    /// recursively, such that deeply nested input is rejected instead of exhausting the
+    /// call stack.
+    fn parse_code(
+        &mut self,
+        context: &mut FuncContext<'a>,
+        single: bool,
+    ) -> Result<Vec<Instruction>> {
+        let start = self.position;
+        let mut open: Vec<Open<'a>> = vec![];
+        let mut code = vec![];
+        let mut depth = 0;
+
+        loop {
+            if single && open.is_empty() && self.position != start {
+                break;
+            }
+
+            // The condition of a folded if consists of folded instructions only
+            if let Some(Open::FoldedIf {
+                label,
+                outer: outer @ None,
+                ..
+            }) = open.last_mut()
+            {
+                if !self.peek_kind(TokenKind::LParen) || self.peek_field("then") {
+                    self.expect_field("then")?;
+                    context.labels.push(*label);
+                    *outer = Some(std::mem::take(&mut code));
+                    continue;
+                }
+            }
+
+            let token = match self.peek() {
+                Some(token) => token,
+                None if open.is_empty() => break,
+                None => return self.unclosed(open.last()),
+            };
+
+            // The operands of a folded instruction are folded instructions themselves
+            if let Some(Open::Folded(_)) = open.last() {
+                if !matches!(token.kind, TokenKind::LParen | TokenKind::RParen) {
+                    return self.unclosed(None);
+                }
+            }
+
+            match token.kind {
+                TokenKind::RParen => match open.pop() {
+                    None => break,
+                    Some(Open::Folded(instruction)) => {
+                        self.position += 1;
+                        code.push(instruction);
+                    }
+                    Some(Open::FoldedBlock {
+                        keyword,
+                        block_type,
+                        outer,
+                    }) => {
+                        self.position += 1;
+                        context.labels.pop();
+                        depth -= 1;
+
+                        let block_code = std::mem::replace(&mut code, outer);
+                        code.push(match keyword {
+                            "block" => Instruction::Block(block_type, block_code),
+                            _ => Instruction::Loop(block_type, block_code),
+                        });
+                    }
+                    Some(Open::FoldedIf {
+                        label,
+                        block_type,
+                        then_code: None,
+                        outer: Some(outer),
+                    }) => {
+                        // The end of the then branch, which may be followed by an else branch
+                        self.position += 1;
+                        let then_code = std::mem::take(&mut code);
+
+                        if self.eat_field("else") {
+                            open.push(Open::FoldedIf {
+                                label,
+                                block_type,
+                                then_code: Some(then_code),
+                                outer: Some(outer),
+                            });
+                        } else {
+                            context.labels.pop();
+                            depth -= 1;
+
+                            code = outer;
+                            code.push(Instruction::If(block_type, then_code, vec![]));
+                            self.expect_rparen()?;
+                        }
+                    }
+                    Some(Open::FoldedIf {
+                        block_type,
+                        then_code: Some(then_code),
+                        outer: Some(outer),
+                        ..
+                    }) => {
+                        self.position += 1;
+                        context.labels.pop();
+                        depth -= 1;
+
+                        let else_code = std::mem::replace(&mut code, outer);
+                        code.push(Instruction::If(block_type, then_code, else_code));
+                        self.expect_rparen()?;
+                    }
+                    Some(block) => return self.unclosed(Some(&block)),
+                },
+                TokenKind::LParen => {
+                    self.position += 1;
+                    let keyword = self.expect_keyword()?;
+
+                    open.push(match keyword {
+                        "block" | "loop" | "if" => {
+                            depth = self.enter_block(depth)?;
+                            let label = self.eat_id();
+                            let block_type = self.parse_block_type()?;
+
+                            if keyword == "if" {
+                                Open::FoldedIf {
+                                    label,
+                                    block_type,
+                                    then_code: None,
+                                    outer: None,
+                                }
+                            } else {
+                                context.labels.push(label);
+                                Open::FoldedBlock {
+                                    keyword,
+                                    block_type,
+                                    outer: std::mem::take(&mut code),
+                                }
+                            }
+                        }
+                        // The operands follow, and precede the instruction in the flat format
+                        _ => Open::Folded(self.parse_plain_instr(keyword, context)?),
+                    });
+                }
+                TokenKind::Keyword => match (token.text, open.last_mut()) {
+                    ("end" | "else", None) => break,
+                    (
+                        "else",
+                        Some(Open::Block {
+                            keyword: "if",
+                            label,
+                            then_code: then_code @ None,
+                            ..
+                        }),
+                    ) => {
+                        self.position += 1;
+                        let label = *label;
+                        *then_code = Some(std::mem::take(&mut code));
+                        self.parse_end_label(label)?;
+                    }
+                    ("end", Some(Open::Block { .. })) => {
+                        self.position += 1;
+                        context.labels.pop();
+                        depth -= 1;
+
+                        let (keyword, label, block_type, then_code, outer) = match open.pop() {
+                            Some(Open::Block {
+                                keyword,
+                                label,
+                                block_type,
+                                then_code,
+                                outer,
+                            }) => (keyword, label, block_type, then_code, outer),
+                            _ => unreachable!("A block is open"),
+                        };
+                        self.parse_end_label(label)?;
+
+                        let block_code = std::mem::replace(&mut code, outer);
+                        code.push(match (keyword, then_code) {
+                            ("block", _) => Instruction::Block(block_type, block_code),
+                            ("loop", _) => Instruction::Loop(block_type, block_code),
+                            (_, Some(then_code)) => {
+                                Instruction::If(block_type, then_code, block_code)
+                            }
+                            (_, None) => Instruction::If(block_type, block_code, vec![]),
+                        });
+                    }
+                    ("end" | "else", block) => return self.unclosed(block.as_deref()),
+                    ("block" | "loop" | "if", _) => {
+                        self.position += 1;
+                        depth = self.enter_block(depth)?;
+                        let label = self.eat_id();
+                        let block_type = self.parse_block_type()?;
+
+                        context.labels.push(label);
+                        open.push(Open::Block {
+                            keyword: token.text,
+                            label,
+                            block_type,
+                            then_code: None,
+                            outer: std::mem::take(&mut code),
+                        });
+                    }
+                    (keyword, _) => {
+                        self.position += 1;
+                        let instruction = self.parse_plain_instr(keyword, context)?;
+                        code.push(instruction);
+                    }
+                },
+                _ => return self.error(format!("Expected instruction, found: {}", token.text)),
+            }
+        }
+
+        Ok(code)
+    }
+
+    /// Checks that a block, of which the keyword has just been read, may be entered
+    /// at the given nesting depth, and returns the depth inside of it
+    fn enter_block(&mut self, depth: usize) -> Result<usize> {
+        if depth == MAX_NESTING_DEPTH {
+            self.position -= 1;
+            return self.error("Blocks are nested too deeply".to_owned());
+        }
+
+        Ok(depth + 1)
+    }
+
+    /// Reports the token that was found where the innermost block or
+    /// folded instruction has to be closed
+    fn unclosed<T>(&mut self, block: Option<&Open<'a>>) -> Result<T> {
+        if let Some(Open::Block { keyword, .. }) = block {
+            return self.error(format!("Expected end of {}", keyword));
+        }
+
+        self.expect_rparen()?;
+        unreachable!("The next token does not close the folded instruction")
+    }
+
+    /// Parses the optional label that may follow `else` and `end`, which has to match the block
+    fn parse_end_label(&mut self, label: Option<&'a str>) -> Result<()> {
+        if let Some(id) = self.eat_id() {
+            if Some(id) != label {
+                self.position -= 1;
+                return self.error(format!("Mismatching label: {}", id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses a constant expression, which has no locals or labels in scope
+    fn parse_expr(&mut self) -> Result<Vec<Instruction>> {
+        self.parse_instrs(&mut FuncContext::default())
+    }
+
+    /// Parses an offset expression of an active element or data segment
+    fn parse_offset(&mut self) -> Result<Vec<Instruction>> {
+        if self.eat_field("offset") {
+            let offset = self.parse_expr()?;
+            self.expect_rparen()?;
+            return Ok(offset);
+        }
+
+        self.parse_folded_expr()
+    }
+
+    /// Parses the inline exports of a function, table, memory or global definition
+    fn parse_inline_exports(&mut self, descriptor: fn(usize) -> ExportDescriptor) -> Result<()> {
+        let index = match descriptor(0) {
+            ExportDescriptor::Func(_) => self.counts.funcs,
+            ExportDescriptor::Table(_) => self.counts.tables,
+            ExportDescriptor::Memory(_) => self.counts.memories,
+            ExportDescriptor::Global(_) => self.counts.globals,
+        };
+
+        while self.eat_field("export") {
+            let name = self.parse_name()?;
+            self.expect_rparen()?;
+
+            self.module.exports.push(Export {
+                name,
+                descriptor: descriptor(index),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Parses an inline import, returning its module and name
+    fn parse_inline_import(&mut self) -> Result<Option<(Name, Name)>> {
+        if !self.eat_field("import") {
+            return Ok(None);
+        }
+
+        let module = self.parse_name()?;
+        let name = self.parse_name()?;
+        self.expect_rparen()?;
+
+        Ok(Some((module, name)))
+    }
+
+    fn parse_type_field(&mut self) -> Result<()> {
+        let id = self.eat_id();
+
+        self.expect_field("func")?;
+        let (ftype, _) = self.parse_func_type()?;
+        self.expect_rparen()?;
+
+        if let Some(id) = id {
+            self.names.types.insert(id, self.module.types.len());
+        }
+        self.module.types.push(ftype);
+
+        Ok(())
+    }
+
+    fn parse_import_field(&mut self) -> Result<()> {
+        let module = self.parse_name()?;
+        let name = self.parse_name()?;
+
+        self.expect_lparen()?;
+        let descriptor = match self.expect_keyword()? {
+            "func" => {
+                self.eat_id();
+                self.counts.funcs += 1;
+                ImportDescriptor::Func(self.parse_type_use()?.0)
+            }
+            "table" => {
+                self.eat_id();
+                self.counts.tables += 1;
+                ImportDescriptor::Table(self.parse_table_type()?)
+            }
+            "memory" => {
+                self.eat_id();
+                self.counts.memories += 1;
+                ImportDescriptor::Memory(MemType {
+                    limits: self.parse_limits()?,
+                })
+            }
+            "global" => {
+                self.eat_id();
+                self.counts.globals += 1;
+                ImportDescriptor::Global(self.parse_global_type()?)
+            }
+            keyword => {
+                self.position -= 1;
+                return self.error(format!("Invalid import descriptor: {}", keyword));
+            }
+        };
+        self.expect_rparen()?;
+
+        self.module.imports.push(Import {
+            module,
+            name,
+            descriptor,
+        });
+
+        Ok(())
+    }
+
+    fn parse_func_field(&mut self) -> Result<()> {
+        self.eat_id();
+        self.parse_inline_exports(|index| ExportDescriptor::Func(FuncIdx(index)))?;
+
+        if let Some((module, name)) = self.parse_inline_import()? {
+            let (type_idx, _) = self.parse_type_use()?;
+
+            self.module.imports.push(Import {
+                module,
+                name,
+                descriptor: ImportDescriptor::Func(type_idx),
+            });
+            self.counts.funcs += 1;
+
+            return Ok(());
+        }
+
+        let (type_idx, parameter_names) = self.parse_type_use()?;
+
+        let mut context = FuncContext::default();
+        for (index, name) in parameter_names.iter().enumerate() {
+            if let Some(name) = *name {
+                context.locals.insert(name, index);
+            }
+        }
+
+        let mut locals = vec![];
+        while self.eat_field("local") {
+            if let Some(id) = self.eat_id() {
+                let index = parameter_names.len() + locals.len();
+                if context.locals.insert(id, index).is_some() {
+                    self.position -= 1;
+                    return self.error(format!("Duplicate identifier: {}", id));
+                }
+
+                locals.push(self.parse_value_type()?);
+            } else {
+                while !self.peek_kind(TokenKind::RParen) {
+                    locals.push(self.parse_value_type()?);
+                }
+            }
+
+            self.expect_rparen()?;
+        }
+
+        // The function body is the implicit outermost block
+        context.labels.push(None);
+        let body = self.parse_instrs(&mut context)?;
+
+        self.module.functions.push(type_idx);
+        self.module.codes.push(Code { locals, body });
+        self.counts.funcs += 1;
+
+        Ok(())
+    }
+
+    fn parse_table_field(&mut self) -> Result<()> {
+        self.eat_id();
+        self.parse_inline_exports(|index| ExportDescriptor::Table(TableIdx(index)))?;
+
+        if let Some((module, name)) = self.parse_inline_import()? {
+            let descriptor = ImportDescriptor::Table(self.parse_table_type()?);
+            self.module.imports.push(Import {
+                module,
+                name,
+                descriptor,
+            });
+            self.counts.tables += 1;
+
+            return Ok(());
+        }
+
+        if self.peek_keyword().and_then(ref_type).is_some() {
+            // Inline element segment
+            let elem_type = self.parse_ref_type()?;
+
+            self.expect_field("elem")?;
+            let init = self.parse_elem_list(elem_type)?;
+            self.expect_rparen()?;
+
+            let size = init.len() as u32;
+            self.module.tables.push(TableType {
+                elem_type,
+                limits: Limits {
+                    min: size,
+                    max: Some(size),
+                },
+            });
+
+            self.module.elems.push(Elem {
+                elem_type,
+                init,
+                mode: ElemMode::Active {
+                    table: TableIdx(self.counts.tables),
+                    offset: vec![Instruction::I32Const(0)],
+                },
+            });
+            self.counts.elems += 1;
+        } else {
+            let table_type = self.parse_table_type()?;
+            self.module.tables.push(table_type);
+        }
+
+        self.counts.tables += 1;
+
+        Ok(())
+    }
+
+    fn parse_memory_field(&mut self) -> Result<()> {
+        self.eat_id();
+        self.parse_inline_exports(|index| ExportDescriptor::Memory(MemIdx(index)))?;
+
+        if let Some((module, name)) = self.parse_inline_import()? {
+            let descriptor = ImportDescriptor::Memory(MemType {
+                limits: self.parse_limits()?,
+            });
+            self.module.imports.push(Import {
+                module,
+                name,
+                descriptor,
+            });
+            self.counts.memories += 1;
+
+            return Ok(());
+        }
+
+        if self.eat_field("data") {
+            // Inline data segment
+            let mut init = vec![];
+            while self.peek_kind(TokenKind::String) {
+                init.extend(self.parse_string()?);
+            }
+            self.expect_rparen()?;
+
+            let pages = init.len().div_ceil(0x10000) as u32;
+            self.module.memories.push(Limits {
+                min: pages,
+                max: Some(pages),
+            });
+
+            self.module.datas.push(Data {
+                init,
+                mode: DataMode::Active {
+                    memory: MemIdx(self.counts.memories),
+                    offset: vec![Instruction::I32Const(0)],
+                },
+            });
+            self.counts.datas += 1;
+        } else {
+            let limits = self.parse_limits()?;
+            self.module.memories.push(limits);
+        }
+
+        self.counts.memories += 1;
+
+        Ok(())
+    }
+
+    fn parse_global_field(&mut self) -> Result<()> {
+        self.eat_id();
+        self.parse_inline_exports(|index| ExportDescriptor::Global(GlobalIdx(index)))?;
+
+        if let Some((module, name)) = self.parse_inline_import()? {
+            let descriptor = ImportDescriptor::Global(self.parse_global_type()?);
+            self.module.imports.push(Import {
+                module,
+                name,
+                descriptor,
+            });
+            self.counts.globals += 1;
+
+            return Ok(());
+        }
+
+        let global_type = self.parse_global_type()?;
+        let expression = self.parse_expr()?;
+
+        self.module.globals.push(Global {
+            global_type,
+            expression,
+        });
+        self.counts.globals += 1;
+
+        Ok(())
+    }
+
+    fn parse_export_field(&mut self) -> Result<()> {
+        let name = self.parse_name()?;
+
+        self.expect_lparen()?;
+        let descriptor = match self.expect_keyword()? {
+            "func" => ExportDescriptor::Func(self.parse_func_idx()?),
+            "table" => ExportDescriptor::Table(self.parse_table_idx()?),
+            "memory" => ExportDescriptor::Memory(self.parse_mem_idx()?),
+            "global" => ExportDescriptor::Global(self.parse_global_idx()?),
+            keyword => {
+                self.position -= 1;
+                return self.error(format!("Invalid export descriptor: {}", keyword));
+            }
+        };
+        self.expect_rparen()?;
+
+        self.module.exports.push(Export { name, descriptor });
+
+        Ok(())
+    }
+
+    fn parse_start_field(&mut self) -> Result<()> {
+        if self.module.start.is_some() {
+            return self.error("Multiple start functions".to_owned());
+        }
+
+        let func = self.parse_func_idx()?;
+        self.module.start = Some(Start { func });
+
+        Ok(())
+    }
+
+    /// Parses the initializers of an element segment, given as expressions
+    /// (`(item ...)` or a single folded instruction) or function indices
+    fn parse_elem_list(&mut self, elem_type: RefType) -> Result<Vec<Vec<Instruction>>> {
+        let mut init = vec![];
+
+        if elem_type == RefType::FuncRef && self.peek_index() {
+            while self.peek_index() {
+                init.push(vec![Instruction::RefFunc(self.parse_func_idx()?)]);
+            }
+
+            return Ok(init);
+        }
+
+        while self.peek_kind(TokenKind::LParen) {
+            if self.eat_field("item") {
+                init.push(self.parse_expr()?);
+                self.expect_rparen()?;
+            } else {
+                init.push(self.parse_folded_expr()?);
+            }
+        }
+
+        Ok(init)
+    }
+
+    /// Parses the element type followed by the initializers of an element segment
+    fn parse_elem_type_and_list(&mut self) -> Result<(RefType, Vec<Vec<Instruction>>)> {
+        let elem_type = if self.eat_keyword("func") {
+            RefType::FuncRef
+        } else if self.peek_keyword().and_then(ref_type).is_some() {
+            self.parse_ref_type()?
+        } else {
+            // Abbreviation for a list of function indices
+            RefType::FuncRef
+        };
+
+        let init = self.parse_elem_list(elem_type)?;
+
+        Ok((elem_type, init))
+    }
+
+    fn parse_elem_field(&mut self) -> Result<()> {
+        self.eat_id();
+
+        let mode = if self.eat_keyword("declare") {
+            ElemMode::Declarative
+        } else if self.eat_field("table") {
+            let table = self.parse_table_idx()?;
+            self.expect_rparen()?;
+
+            ElemMode::Active {
+                table,
+                offset: self.parse_offset()?,
+            }
+        } else if self.peek_kind(TokenKind::LParen) {
+            ElemMode::Active {
+                table: TableIdx(0),
+                offset: self.parse_offset()?,
+            }
+        } else if self.peek_index() {
+            ElemMode::Active {
+                table: self.parse_table_idx()?,
+                offset: self.parse_offset()?,
+            }
+        } else {
+            ElemMode::Passive
+        };
+
+        let (elem_type, init) = self.parse_elem_type_and_list()?;
+
+        self.module.elems.push(Elem {
+            elem_type,
+            init,
+            mode,
+        });
+        self.counts.elems += 1;
+
+        Ok(())
+    }
+
+    fn parse_data_field(&mut self) -> Result<()> {
+        self.eat_id();
+
+        let mode = if self.eat_field("memory") {
+            let memory = self.parse_mem_idx()?;
+            self.expect_rparen()?;
+
+            DataMode::Active {
+                memory,
+                offset: self.parse_offset()?,
+            }
+        } else if self.peek_kind(TokenKind::LParen) {
+            DataMode::Active {
+                memory: MemIdx(0),
+                offset: self.parse_offset()?,
+            }
+        } else if self.peek_index() {
+            DataMode::Active {
+                memory: self.parse_mem_idx()?,
+                offset: self.parse_offset()?,
+            }
+        } else {
+            DataMode::Passive
+        };
+
+        let mut init = vec![];
+        while self.peek_kind(TokenKind::String) {
+            init.extend(self.parse_string()?);
+        }
+
+        self.module.datas.push(Data { init, mode });
+        self.counts.datas += 1;
+
+        Ok(())
+    }
+
+    /// First pass over the module fields: assigns indices to all symbolic identifiers,
+    /// such that they can be referred to before their definition, and collects the types,
+    /// such that the types defined through type uses end up after the explicit ones.
+    fn collect_definitions(&mut self) -> Result<()> {
+        let mut defined = false;
+
+        while self.peek_kind(TokenKind::LParen) {
+            let start = self.position;
+            self.expect_lparen()?;
+            let keyword = self.expect_keyword()?;
+
+            let index = match keyword {
+                "type" => {
+                    self.parse_type_field()?;
+                    self.expect_rparen()?;
+                    continue;
+                }
+                "import" => {
+                    self.parse_name()?;
+                    self.parse_name()?;
+                    self.expect_lparen()?;
+                    let keyword = self.expect_keyword()?;
+                    let id = self.eat_id();
+                    Some((keyword, id, true))
+                }
+                "func" | "table" | "memory" | "global" => {
+                    let id = self.eat_id();
+                    while self.peek_field("export") {
+                        self.skip_sexpr()?;
+                    }
+
+                    // Inline segments are part of the element and data index spaces
+                    if keyword == "table" && self.peek_keyword().and_then(ref_type).is_some() {
+                        self.counts.elems += 1;
+                    }
+                    if keyword == "memory" && self.peek_field("data") {
+                        self.counts.datas += 1;
+                    }
+
+                    Some((keyword, id, self.peek_field("import")))
+                }
+                "elem" | "data" => Some((keyword, self.eat_id(), false)),
+                "export" | "start" => None,
+                _ => {
+                    self.position -= 1;
+                    return self.error(format!("Unknown module field: {}", keyword));
+                }
+            };
+
+            if let Some((keyword, id, import)) = index {
+                // Imports occupy the first indices of each index space, hence have to precede
+                // the definitions
+                if import && defined {
+                    self.position = start;
+                    return self.error("Import after definition".to_owned());
+                }
+                defined |= !import && keyword != "elem" && keyword != "data";
+
+                let (names, count) = match keyword {
+                    "func" => (&mut self.names.funcs, &mut self.counts.funcs),
+                    "table" => (&mut self.names.tables, &mut self.counts.tables),
+                    "memory" => (&mut self.names.memories, &mut self.counts.memories),
+                    "global" => (&mut self.names.globals, &mut self.counts.globals),
+                    "elem" => (&mut self.names.elems, &mut self.counts.elems),
+                    "data" => (&mut self.names.datas, &mut self.counts.datas),
+                    _ => {
+                        self.position -= 1;
+                        return self.error(format!("Invalid import descriptor: {}", keyword));
+                    }
+                };
+
+                let duplicate = match id {
+                    Some(id) => names.insert(id, *count).is_some(),
+                    None => false,
+                };
+                *count += 1;
+
+                if duplicate {
+                    self.position = start;
+                    return self.error(format!("Duplicate identifier: {}", id.unwrap()));
+                }
+            }
+
+            self.position = start;
+            self.skip_sexpr()?;
+        }
+
+        self.counts = Counts::default();
+
+        Ok(())
+    }
+
+    /// Second pass over the module fields: builds the actual module
+    fn parse_fields(&mut self) -> Result<()> {
+        while self.peek_kind(TokenKind::LParen) {
+            self.expect_lparen()?;
+
+            match self.expect_keyword()? {
+                "type" => {
+                    // Already collected during the first pass
+                    self.position -= 2;
+                    self.skip_sexpr()?;
+                    continue;
+                }
+                "import" => self.parse_import_field()?,
+                "func" => self.parse_func_field()?,
+                "table" => self.parse_table_field()?,
+                "memory" => self.parse_memory_field()?,
+                "global" => self.parse_global_field()?,
+                "export" => self.parse_export_field()?,
+                "start" => self.parse_start_field()?,
+                "elem" => self.parse_elem_field()?,
+                "data" => self.parse_data_field()?,
+                keyword => {
+                    self.position -= 1;
+                    return self.error(format!("Unknown module field: {}", keyword));
+                }
+            }
+
+            self.expect_rparen()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a module in the WebAssembly text format.
+///
+/// Both the folded and the flat instruction formats are supported, as well as symbolic
+/// identifiers and the usual abbreviations (inline imports, exports, types and segments).
+/// The `(module ...)` wrapper may be omitted.
+pub fn parse(text: &str) -> Result<Module> {
+    let mut parser = Parser {
+        text,
+        tokens: tokenize(text)?,
+        position: 0,
+        names: Names::default(),
+        counts: Counts::default(),
+        module: Module::default(),
+    };
+
+    let wrapped = parser.eat_field("module");
+    if wrapped {
+        parser.eat_id();
+    }
+
+    let start = parser.position;
+    parser.collect_definitions()?;
+
+    parser.position = start;
+    parser.parse_fields()?;
+
+    if wrapped {
+        parser.expect_rparen()?;
+    }
+
+    if let Some(token) = parser.peek() {
+        return parser.error(format!("Unexpected token: {}", token.text));
+    }
+
+    Ok(parser.module)
+}
+
+impl Module {
+    pub fn from_wat(text: &str) -> Result<Module> {
+        parse(text)
+    }
+}
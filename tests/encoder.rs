@@ -0,0 +1,169 @@
+use wario::wasm::{
+    BlockType, Code, Data, DataMode, Elem, ElemMode, Export, ExportDescriptor, FuncIdx, FuncType,
+    Global, GlobalIdx, GlobalType, Import, ImportDescriptor, Instruction, LabelIdx, Limits,
+    LocalIdx, MemArg, MemIdx, Module, Mutability, Name, NumType, RefType, Start, TableIdx,
+    TableType, TypeIdx, ValueType,
+};
+
+const I32: ValueType = ValueType::NumType(NumType::I32);
+const I64: ValueType = ValueType::NumType(NumType::I64);
+const F64: ValueType = ValueType::NumType(NumType::F64);
+
+#[test]
+fn mandelbrot_round_trip() {
+    let bytes = include_bytes!("mandelbrot.wasm");
+    let module = Module::from_bytes(bytes).unwrap();
+
+    // The encoding isn't necessarily byte for byte identical (e.g. runs of locals get merged),
+    // but has to decode into the exact same module
+    let encoded = module.encode();
+    assert_eq!(Module::from_bytes(&encoded).unwrap(), module);
+    assert!(encoded.len() <= bytes.len());
+}
+
+#[test]
+fn write_to() {
+    let module = Module::default();
+
+    let mut bytes = vec![];
+    module.write_to(&mut bytes).unwrap();
+
+    assert_eq!(bytes, b"\0asm\x01\0\0\0");
+}
+
+#[test]
+fn constructed_module_round_trip() {
+    let module = Module {
+        types: vec![
+            FuncType {
+                parameter_types: vec![I32, F64],
+                result_types: vec![I64],
+            },
+            FuncType {
+                parameter_types: vec![],
+                result_types: vec![],
+            },
+        ],
+        imports: vec![Import {
+            module: Name("env".to_owned()),
+            name: Name("print".to_owned()),
+            descriptor: ImportDescriptor::Func(TypeIdx(1)),
+        }],
+        functions: vec![TypeIdx(0), TypeIdx(1)],
+        tables: vec![TableType {
+            elem_type: RefType::FuncRef,
+            limits: Limits {
+                min: 2,
+                max: Some(10),
+            },
+        }],
+        memories: vec![Limits { min: 1, max: None }],
+        globals: vec![Global {
+            global_type: GlobalType {
+                value_type: I64,
+                mutability: Mutability::Variable,
+            },
+            expression: vec![Instruction::I64Const(-1234567890123)],
+        }],
+        exports: vec![
+            Export {
+                name: Name("run".to_owned()),
+                descriptor: ExportDescriptor::Func(FuncIdx(1)),
+            },
+            Export {
+                name: Name("counter".to_owned()),
+                descriptor: ExportDescriptor::Global(GlobalIdx(0)),
+            },
+        ],
+        start: Some(Start { func: FuncIdx(2) }),
+        elems: vec![
+            Elem {
+                elem_type: RefType::FuncRef,
+                init: vec![
+                    vec![Instruction::RefFunc(FuncIdx(1))],
+                    vec![Instruction::RefFunc(FuncIdx(2))],
+                ],
+                mode: ElemMode::Active {
+                    table: TableIdx(0),
+                    offset: vec![Instruction::I32Const(0)],
+                },
+            },
+            Elem {
+                elem_type: RefType::FuncRef,
+                init: vec![vec![Instruction::RefNull(RefType::FuncRef)]],
+                mode: ElemMode::Passive,
+            },
+            Elem {
+                elem_type: RefType::ExternRef,
+                init: vec![vec![Instruction::RefNull(RefType::ExternRef)]],
+                mode: ElemMode::Active {
+                    table: TableIdx(1),
+                    offset: vec![Instruction::I32Const(1)],
+                },
+            },
+            Elem {
+                elem_type: RefType::FuncRef,
+                init: vec![vec![Instruction::RefFunc(FuncIdx(0))]],
+                mode: ElemMode::Declarative,
+            },
+        ],
+        codes: vec![
+            Code {
+                locals: vec![I32, I32, F64, I32],
+                body: vec![
+                    Instruction::LocalGet(LocalIdx(0)),
+                    Instruction::If(
+                        BlockType::Value(I64),
+                        vec![Instruction::I64Const(i64::MIN)],
+                        vec![Instruction::I64Const(i64::MAX)],
+                    ),
+                    Instruction::Block(
+                        BlockType::TypeIdx(TypeIdx(1)),
+                        vec![
+                            Instruction::LocalGet(LocalIdx(1)),
+                            Instruction::F64Const(-0.5),
+                            Instruction::F64Lt,
+                            Instruction::BranchIf(LabelIdx(0)),
+                            Instruction::BranchTable(vec![LabelIdx(0), LabelIdx(1)], LabelIdx(0)),
+                        ],
+                    ),
+                    Instruction::I32Const(0),
+                    Instruction::I64Load32Unsigned(MemArg {
+                        align: 2,
+                        offset: 1024,
+                    }),
+                    Instruction::I64Add,
+                ],
+            },
+            Code {
+                locals: vec![],
+                body: vec![
+                    Instruction::Call(FuncIdx(0)),
+                    Instruction::If(BlockType::Empty, vec![Instruction::Nop], vec![]),
+                    Instruction::MemorySize,
+                    Instruction::Drop,
+                ],
+            },
+        ],
+        datas: vec![
+            Data {
+                init: b"Hello, world!".to_vec(),
+                mode: DataMode::Active {
+                    memory: MemIdx(0),
+                    offset: vec![Instruction::I32Const(16)],
+                },
+            },
+            Data {
+                init: vec![0xDE, 0xAD, 0xBE, 0xEF],
+                mode: DataMode::Passive,
+            },
+        ],
+        ..Default::default()
+    };
+
+    let bytes = module.encode();
+    let parsed = Module::from_bytes(&bytes).unwrap();
+
+    assert_eq!(parsed, module);
+    assert_eq!(parsed.encode(), bytes);
+}
@@ -0,0 +1,316 @@
+use wario::vm::{
+    CallError, Caller, Extern, FrameInfo, Func, FuncAddr, GlobalError, Instance, Store, Trap,
+    TrapKind, Value,
+};
+use wario::wasm::{FuncIdx, FuncType, Module, NumType, ValueType};
+use wario::wat;
+
+fn parse(text: &str) -> Module {
+    wat::parse(text).unwrap()
+}
+
+const ARITHMETIC: &str = r#"
+    (func (export "add") (param i32 i32) (result i32)
+      (i32.add (local.get 0) (local.get 1)))
+    (func (export "scale") (param i64 f64) (result i64 f64)
+      (i64.mul (local.get 0) (i64.const 2))
+      (f64.mul (local.get 1) (f64.const 2)))
+    (func (export "nothing"))
+    (func (export "fail") (result f32) unreachable)
+    (memory (export "memory") 1)
+    "#;
+
+#[test]
+fn call_with_values() {
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &parse(ARITHMETIC), &[]).unwrap();
+
+    let add = instance.get_func("add").unwrap();
+    assert_eq!(
+        add.func_type(),
+        &FuncType {
+            parameter_types: vec![ValueType::NumType(NumType::I32); 2],
+            result_types: vec![ValueType::NumType(NumType::I32)],
+        }
+    );
+    assert_eq!(
+        add.call(&mut store, &[Value::I32(2), Value::I32(3)]),
+        Ok(vec![Value::I32(5)])
+    );
+
+    let scale = instance.get_func("scale").unwrap();
+    assert_eq!(
+        scale.call(&mut store, &[Value::I64(4), Value::F64(0.25)]),
+        Ok(vec![Value::I64(8), Value::F64(0.5)])
+    );
+
+    let nothing = instance.get_func("nothing").unwrap();
+    assert_eq!(nothing.call(&mut store, &[]), Ok(vec![]));
+}
+
+#[test]
+fn declared_locals() {
+    let module = parse(
+        r#"
+        (func (export "sum_to") (param $n i32) (result i32) (local $sum i32)
+          (block $done
+            (loop $next
+              (br_if $done (i32.eq (local.get $n) (i32.const 0)))
+              (local.set $sum (i32.add (local.get $sum) (local.get $n)))
+              (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+              (br $next)))
+          (local.get $sum))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &module, &[]).unwrap();
+    let sum_to = instance.get_typed_func::<i32, i32>("sum_to").unwrap();
+
+    assert_eq!(sum_to.call(&mut store, 10), Ok(55));
+    assert_eq!(sum_to.call(&mut store, 0), Ok(0));
+}
+
+#[test]
+fn get_func_only_finds_functions() {
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &parse(ARITHMETIC), &[]).unwrap();
+
+    assert!(instance.get_func("memory").is_none());
+    assert!(instance.get_func("subtract").is_none());
+}
+
+#[test]
+fn arguments_are_checked() {
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &parse(ARITHMETIC), &[]).unwrap();
+    let add = instance.get_func("add").unwrap();
+
+    // Too few arguments
+    assert!(matches!(
+        add.call(&mut store, &[Value::I32(2)]),
+        Err(CallError::SignatureMismatch { .. })
+    ));
+
+    // Wrong type
+    let error = add
+        .call(&mut store, &[Value::I32(2), Value::I64(3)])
+        .unwrap_err();
+    assert_eq!(
+        error,
+        CallError::SignatureMismatch {
+            expected: add.func_type().clone(),
+            actual: FuncType {
+                parameter_types: vec![
+                    ValueType::NumType(NumType::I32),
+                    ValueType::NumType(NumType::I64)
+                ],
+                result_types: vec![ValueType::NumType(NumType::I32)],
+            },
+        }
+    );
+}
+
+#[test]
+fn traps_are_returned() {
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &parse(ARITHMETIC), &[]).unwrap();
+    let fail = instance.get_func("fail").unwrap();
+
+    assert_eq!(
+        fail.call(&mut store, &[]),
+        Err(CallError::Trap(Trap {
+            kind: TrapKind::Unreachable,
+            backtrace: vec![FrameInfo {
+                address: FuncAddr(3),
+                index: FuncIdx(3),
+                module_name: None,
+                name: None,
+            }],
+        }))
+    );
+}
+
+#[test]
+fn typed_functions() {
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &parse(ARITHMETIC), &[]).unwrap();
+
+    let add = instance.get_typed_func::<(i32, i32), i32>("add").unwrap();
+    assert_eq!(add.call(&mut store, (2, 3)), Ok(5));
+    assert_eq!(add.call(&mut store, (i32::MAX, 1)), Ok(i32::MIN));
+
+    let scale = instance
+        .get_typed_func::<(i64, f64), (i64, f64)>("scale")
+        .unwrap();
+    assert_eq!(scale.call(&mut store, (4, 0.25)), Ok((8, 0.5)));
+
+    let nothing = instance.get_typed_func::<(), ()>("nothing").unwrap();
+    assert_eq!(nothing.call(&mut store, ()), Ok(()));
+
+    let fail = instance.get_typed_func::<(), f32>("fail").unwrap();
+    assert_eq!(
+        fail.call(&mut store, ()).map_err(|trap| trap.kind),
+        Err(TrapKind::Unreachable)
+    );
+}
+
+#[test]
+fn typed_function_signatures_are_checked() {
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &parse(ARITHMETIC), &[]).unwrap();
+
+    assert!(matches!(
+        instance.get_typed_func::<(i32, i32), i64>("add"),
+        Err(CallError::SignatureMismatch { .. })
+    ));
+    assert!(matches!(
+        instance.get_typed_func::<i32, i32>("add"),
+        Err(CallError::SignatureMismatch { .. })
+    ));
+
+    let error = instance.get_typed_func::<(), ()>("memory").unwrap_err();
+    assert_eq!(error, CallError::UnknownFunction("memory".to_owned()));
+    assert_eq!(error.to_string(), "Unknown function: memory");
+}
+
+#[test]
+fn exported_imports_keep_the_imported_type() {
+    let module = parse(
+        r#"
+        (import "env" "double" (func $double (param i32) (result i32)))
+        (export "double" (func $double))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let double = Func::wrap(&mut store, |value: i32| value * 2);
+    let instance = Instance::new(&mut store, &module, &[Extern::Func(double.address())]).unwrap();
+
+    let double = instance.get_typed_func::<i32, i32>("double").unwrap();
+    assert_eq!(double.call(&mut store, 21), Ok(42));
+}
+
+#[test]
+fn wrapped_host_functions() {
+    let mut store = Store::new();
+
+    let scale = Func::wrap(&mut store, |a: i32, b: f64| -> i64 {
+        (a as f64 * b) as i64
+    });
+    assert_eq!(
+        scale.func_type(),
+        &FuncType {
+            parameter_types: vec![
+                ValueType::NumType(NumType::I32),
+                ValueType::NumType(NumType::F64)
+            ],
+            result_types: vec![ValueType::NumType(NumType::I64)],
+        }
+    );
+    assert_eq!(
+        scale.call(&mut store, &[Value::I32(3), Value::F64(1.5)]),
+        Ok(vec![Value::I64(4)])
+    );
+
+    let divide = Func::wrap(&mut store, |a: i32, b: i32| (a / b, a % b));
+    assert_eq!(
+        divide
+            .typed::<(i32, i32), (i32, i32)>()
+            .unwrap()
+            .call(&mut store, (7, 2)),
+        Ok((3, 1))
+    );
+
+    let answer = Func::wrap(&mut store, || 42.0f32);
+    assert_eq!(answer.call(&mut store, &[]), Ok(vec![Value::F32(42.0)]));
+}
+
+#[test]
+fn host_functions_with_multiple_results() {
+    let module = parse(
+        r#"
+        (import "env" "divide" (func $divide (param i32 i32) (result i32 i32)))
+        (func (export "quotient_minus_remainder") (param i32 i32) (result i32)
+          (i32.sub (call $divide (local.get 0) (local.get 1))))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let divide = Func::wrap(&mut store, |a: i32, b: i32| (a / b, a % b));
+    let instance = Instance::new(&mut store, &module, &[Extern::Func(divide.address())]).unwrap();
+
+    let function = instance
+        .get_typed_func::<(i32, i32), i32>("quotient_minus_remainder")
+        .unwrap();
+    assert_eq!(function.call(&mut store, (17, 5)), Ok(1));
+}
+
+#[test]
+fn callers_expose_memory_and_globals() {
+    let module = parse(
+        r#"
+        (import "env" "print" (func $print (param i32 i32)))
+        (memory (export "memory") 1)
+        (global (export "calls") (mut i32) (i32.const 0))
+        (global (export "limit") i32 (i32.const 2))
+        (data (i32.const 16) "Hello, world!")
+        (func (export "hello") (call $print (i32.const 16) (i32.const 5)))
+        "#,
+    );
+
+    let print = |mut caller: Caller<Vec<String>>, address: i32, length: i32| {
+        let memory = caller.get_memory("memory").unwrap();
+        let mut bytes = vec![0; length as usize];
+        memory.read(address as usize, &mut bytes).unwrap();
+        caller.data_mut().push(String::from_utf8(bytes).unwrap());
+
+        let calls = match caller.get_global("calls") {
+            Some(Value::I32(calls)) => calls,
+            global => panic!("Unexpected global: {:?}", global),
+        };
+        caller.set_global("calls", Value::I32(calls + 1)).unwrap();
+
+        assert_eq!(caller.get_global("limit"), Some(Value::I32(2)));
+        assert_eq!(
+            caller.set_global("limit", Value::I32(3)),
+            Err(GlobalError::Immutable)
+        );
+        assert_eq!(
+            caller.set_global("calls", Value::I64(0)),
+            Err(GlobalError::TypeMismatch {
+                expected: ValueType::NumType(NumType::I32),
+                actual: ValueType::NumType(NumType::I64),
+            })
+        );
+        assert_eq!(
+            caller.set_global("memory", Value::I32(0)),
+            Err(GlobalError::UnknownGlobal("memory".to_owned()))
+        );
+        assert!(caller.get_memory("calls").is_none());
+    };
+
+    let mut store = Store::with_data(Vec::new());
+    let print = Func::wrap(&mut store, print);
+    let instance = Instance::new(&mut store, &module, &[Extern::Func(print.address())]).unwrap();
+
+    let hello = instance.get_typed_func::<(), ()>("hello").unwrap();
+    hello.call(&mut store, ()).unwrap();
+    hello.call(&mut store, ()).unwrap();
+
+    assert_eq!(store.data(), &vec!["Hello".to_owned(), "Hello".to_owned()]);
+    match instance.get_export("calls") {
+        Some(Extern::Global(calls)) => assert_eq!(store.global(calls), Value::I32(2)),
+        _ => panic!("Global is not exported"),
+    }
+}
+
+#[test]
+fn host_calls_have_no_calling_instance() {
+    let mut store = Store::new();
+    let exports = Func::wrap(&mut store, |caller: Caller<()>| {
+        caller.get_export("memory").is_none() as i32
+    });
+
+    assert_eq!(exports.call(&mut store, &[]), Ok(vec![Value::I32(1)]));
+}
@@ -0,0 +1,730 @@
+use wario::validate::{Location, ValidationError, ValidationErrorKind};
+use wario::vm::{
+    CallError, Caller, Extern, FrameInfo, Func, FuncAddr, GlobalError, Instance,
+    InstantiationError, Machine, Memory, Store, Table, Trap, TrapKind, Value,
+};
+use wario::wasm::{
+    FuncIdx, GlobalType, Instruction, Limits, MemIdx, Module, Mutability, Name, NumType, RefType,
+    TableIdx, TableType, ValueType,
+};
+use wario::wat;
+
+fn parse(text: &str) -> Module {
+    wat::parse(text).unwrap()
+}
+
+#[test]
+fn imported_functions_come_first() {
+    let module = parse(
+        r#"
+        (import "env" "answer" (func (result i32)))
+        (func (result i32) i32.const 1)
+        (func (export "call_import") (result i32) call 0)
+        (func (export "call_local") (result i32) call 1)
+        "#,
+    );
+
+    let mut store = Store::new();
+    let answer = Func::wrap(&mut store, || 42);
+    let instance = Instance::new(&mut store, &module, &[Extern::Func(answer.address())]).unwrap();
+
+    let mut machine = Machine::new();
+    machine
+        .invoke(
+            &mut store,
+            &instance,
+            &[Instruction::Call(FuncIdx(2)), Instruction::Call(FuncIdx(3))],
+            &mut vec![],
+        )
+        .unwrap();
+
+    assert_eq!(machine.stack, vec![Value::I32(42), Value::I32(1)]);
+}
+
+#[test]
+fn exports() {
+    let module = parse(
+        r#"
+        (import "env" "f" (func))
+        (memory (export "memory") 1)
+        (func (export "g"))
+        (export "f" (func 0))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let f = Func::wrap(&mut store, || {}).address();
+    let instance = Instance::new(&mut store, &module, &[Extern::Func(f)]).unwrap();
+
+    let names: Vec<_> = instance.exports().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["memory", "g", "f"]);
+
+    assert_eq!(instance.get_export("f"), Some(Extern::Func(f)));
+    assert!(matches!(
+        instance.get_export("memory"),
+        Some(Extern::Memory(_))
+    ));
+    assert!(matches!(instance.get_export("g"), Some(Extern::Func(address)) if address != f));
+    assert_eq!(instance.get_export("h"), None);
+}
+
+#[test]
+fn data_segments() {
+    let module = parse(
+        r#"
+        (import "env" "offset" (global i32))
+        (memory (export "memory") 1)
+        (data (i32.const 1) "abc")
+        (data (global.get 0) "\ff")
+        (data "passive")
+        "#,
+    );
+
+    let mut store = Store::new();
+    let global_type = GlobalType {
+        value_type: ValueType::NumType(NumType::I32),
+        mutability: Mutability::Constant,
+    };
+    let offset = store.add_global(global_type, Value::I32(2)).unwrap();
+    let instance = Instance::new(&mut store, &module, &[Extern::Global(offset)]).unwrap();
+
+    let memory = match instance.get_export("memory") {
+        Some(Extern::Memory(memory)) => memory,
+        _ => panic!("Memory is not exported"),
+    };
+
+    assert_eq!(store.memory(memory).data()[..5], *b"\0a\xffc\0");
+}
+
+#[test]
+fn globals() {
+    let exporter = parse(
+        r#"
+        (import "env" "base" (global $base i32))
+        (global $counter (export "counter") (mut i32) (global.get $base))
+        (global (export "ratio") f64 (f64.const 0.5))
+        (func (export "increment") (result i32)
+          (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+          (global.get $counter))
+        "#,
+    );
+    let importer = parse(
+        r#"
+        (import "env" "counter" (global $counter (mut i32)))
+        (func (export "reset") (global.set $counter (i32.const 0)))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let base = store
+        .add_global(
+            GlobalType {
+                value_type: ValueType::NumType(NumType::I32),
+                mutability: Mutability::Constant,
+            },
+            Value::I32(10),
+        )
+        .unwrap();
+    let exporter = Instance::new(&mut store, &exporter, &[Extern::Global(base)]).unwrap();
+    let counter = exporter.get_export("counter").unwrap();
+    let importer = Instance::new(&mut store, &importer, &[counter]).unwrap();
+
+    // Initialized from the imported global
+    assert_eq!(exporter.get_global(&store, "counter"), Some(Value::I32(10)));
+    assert_eq!(exporter.get_global(&store, "ratio"), Some(Value::F64(0.5)));
+    assert_eq!(exporter.get_global(&store, "increment"), None);
+
+    let increment = exporter.get_typed_func::<(), i32>("increment").unwrap();
+    assert_eq!(increment.call(&mut store, ()), Ok(11));
+
+    // Both instances share the global
+    let reset = importer.get_typed_func::<(), ()>("reset").unwrap();
+    reset.call(&mut store, ()).unwrap();
+    assert_eq!(exporter.get_global(&store, "counter"), Some(Value::I32(0)));
+
+    exporter
+        .set_global(&mut store, "counter", Value::I32(41))
+        .unwrap();
+    assert_eq!(increment.call(&mut store, ()), Ok(42));
+
+    assert_eq!(
+        exporter.set_global(&mut store, "ratio", Value::F64(1.0)),
+        Err(GlobalError::Immutable)
+    );
+    assert_eq!(
+        exporter.set_global(&mut store, "counter", Value::F32(1.0)),
+        Err(GlobalError::TypeMismatch {
+            expected: ValueType::NumType(NumType::I32),
+            actual: ValueType::NumType(NumType::F32),
+        })
+    );
+    assert_eq!(
+        exporter.set_global(&mut store, "missing", Value::I32(1)),
+        Err(GlobalError::UnknownGlobal("missing".to_owned()))
+    );
+    assert_eq!(store.global(base), Value::I32(10));
+
+    let global_type = GlobalType {
+        value_type: ValueType::NumType(NumType::I64),
+        mutability: Mutability::Variable,
+    };
+    assert_eq!(
+        store.add_global(global_type, Value::I32(0)),
+        Err(GlobalError::TypeMismatch {
+            expected: ValueType::NumType(NumType::I64),
+            actual: ValueType::NumType(NumType::I32),
+        })
+    );
+}
+
+#[test]
+fn data_segment_out_of_bounds() {
+    let module = parse(r#"(memory 1) (data (i32.const 65535) "ab")"#);
+
+    let mut store = Store::new();
+    let result = Instance::new(&mut store, &module, &[]);
+
+    assert_eq!(
+        result.err(),
+        Some(InstantiationError::Trap(Trap::new(
+            TrapKind::MemoryOutOfBounds
+        )))
+    );
+}
+
+#[test]
+fn element_segments_and_call_indirect() {
+    let module = parse(
+        r#"
+        (type $binary (func (param i32 i32) (result i32)))
+        (table $functions (export "functions") 5 funcref)
+        (func $add (type $binary) (i32.add (local.get 0) (local.get 1)))
+        (func $sub (type $binary) (i32.sub (local.get 0) (local.get 1)))
+        (func $answer (result i32) (i32.const 42))
+        (elem (i32.const 1) $add $sub $answer)
+        (func (export "apply") (param i32 i32 i32) (result i32)
+          (call_indirect (type $binary) (local.get 1) (local.get 2) (local.get 0)))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &module, &[]).unwrap();
+    let apply = instance
+        .get_typed_func::<(i32, i32, i32), i32>("apply")
+        .unwrap();
+
+    assert_eq!(apply.call(&mut store, (1, 5, 3)), Ok(8));
+    assert_eq!(apply.call(&mut store, (2, 5, 3)), Ok(2));
+
+    let traps = [
+        (0, TrapKind::UninitializedElement),
+        (3, TrapKind::IndirectCallTypeMismatch),
+        (5, TrapKind::TableOutOfBounds),
+        (-1, TrapKind::TableOutOfBounds),
+    ];
+    for (index, kind) in traps {
+        assert_eq!(
+            apply
+                .call(&mut store, (index, 5, 3))
+                .map_err(|trap| trap.kind),
+            Err(kind)
+        );
+    }
+
+    // The host can place its own functions in the table
+    let table = match instance.get_export("functions") {
+        Some(Extern::Table(table)) => table,
+        _ => panic!("Table is not exported"),
+    };
+    assert_eq!(store.table(table).get(0), Ok(Value::FuncRef(None)));
+    assert!(matches!(
+        store.table(table).get(1),
+        Ok(Value::FuncRef(Some(_)))
+    ));
+
+    let multiply = Func::wrap(&mut store, |a: i32, b: i32| a * b);
+    store
+        .table_mut(table)
+        .set(0, Value::FuncRef(Some(multiply.address())))
+        .unwrap();
+    assert_eq!(apply.call(&mut store, (0, 5, 3)), Ok(15));
+}
+
+#[test]
+fn table_instructions() {
+    let module = parse(
+        r#"
+        (type $constant (func (result i32)))
+        (table $functions 2 10 funcref)
+        (table $objects 2 externref)
+        (func $one (result i32) (i32.const 1))
+        (func $two (result i32) (i32.const 2))
+        (elem $numbers func $one $two)
+        (func (export "call") (param i32) (result i32)
+          (call_indirect (type $constant) (local.get 0)))
+        (func (export "grow") (param i32) (result i32)
+          (table.grow $functions (ref.null func) (local.get 0)))
+        (func (export "size") (result i32)
+          (table.size $functions))
+        (func (export "init") (param i32 i32 i32)
+          (table.init $functions $numbers (local.get 0) (local.get 1) (local.get 2)))
+        (func (export "drop")
+          (elem.drop $numbers))
+        (func (export "copy") (param i32 i32 i32)
+          (table.copy $functions $functions (local.get 0) (local.get 1) (local.get 2)))
+        (func (export "fill") (param i32 i32 i32)
+          (table.fill $functions
+            (local.get 0) (table.get $functions (local.get 1)) (local.get 2)))
+        (func (export "is_null") (param i32) (result i32)
+          (ref.is_null (table.get $functions (local.get 0))))
+        (func (export "set_one") (param i32)
+          (table.set $functions (local.get 0) (ref.func $one)))
+        (func (export "set_object") (param i32 externref)
+          (table.set $objects (local.get 0) (local.get 1)))
+        (func (export "get_object") (param i32) (result externref)
+          (table.get $objects (local.get 0)))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let instance = Instance::new(&mut store, &module, &[]).unwrap();
+    let call = instance.get_typed_func::<i32, i32>("call").unwrap();
+    let grow = instance.get_typed_func::<i32, i32>("grow").unwrap();
+    let size = instance.get_typed_func::<(), i32>("size").unwrap();
+    let init = instance
+        .get_typed_func::<(i32, i32, i32), ()>("init")
+        .unwrap();
+    let drop = instance.get_typed_func::<(), ()>("drop").unwrap();
+    let copy = instance
+        .get_typed_func::<(i32, i32, i32), ()>("copy")
+        .unwrap();
+    let fill = instance
+        .get_typed_func::<(i32, i32, i32), ()>("fill")
+        .unwrap();
+
+    assert_eq!(grow.call(&mut store, 3), Ok(2));
+    assert_eq!(grow.call(&mut store, 6), Ok(-1));
+    assert_eq!(size.call(&mut store, ()), Ok(5));
+
+    // Passive segments are kept until they are dropped
+    init.call(&mut store, (0, 0, 2)).unwrap();
+    init.call(&mut store, (4, 1, 1)).unwrap();
+    assert_eq!(call.call(&mut store, 0), Ok(1));
+    assert_eq!(call.call(&mut store, 1), Ok(2));
+    assert_eq!(call.call(&mut store, 4), Ok(2));
+    assert_eq!(
+        init.call(&mut store, (3, 1, 2)).map_err(|trap| trap.kind),
+        Err(TrapKind::TableOutOfBounds)
+    );
+
+    // Overlapping copies behave as if the source was copied first
+    copy.call(&mut store, (1, 0, 2)).unwrap();
+    assert_eq!(call.call(&mut store, 1), Ok(1));
+    assert_eq!(call.call(&mut store, 2), Ok(2));
+    assert_eq!(
+        copy.call(&mut store, (0, 4, 2)).map_err(|trap| trap.kind),
+        Err(TrapKind::TableOutOfBounds)
+    );
+
+    fill.call(&mut store, (0, 4, 2)).unwrap();
+    assert_eq!(call.call(&mut store, 0), Ok(2));
+    assert_eq!(call.call(&mut store, 1), Ok(2));
+    assert_eq!(
+        fill.call(&mut store, (4, 0, 2)).map_err(|trap| trap.kind),
+        Err(TrapKind::TableOutOfBounds)
+    );
+    // Out of bounds accesses do not change the table
+    assert_eq!(call.call(&mut store, 4), Ok(2));
+
+    // A dropped segment is empty
+    drop.call(&mut store, ()).unwrap();
+    init.call(&mut store, (0, 0, 0)).unwrap();
+    assert_eq!(
+        init.call(&mut store, (0, 0, 1)).map_err(|trap| trap.kind),
+        Err(TrapKind::TableOutOfBounds)
+    );
+
+    let is_null = instance.get_typed_func::<i32, i32>("is_null").unwrap();
+    let set_one = instance.get_typed_func::<i32, ()>("set_one").unwrap();
+    assert_eq!(is_null.call(&mut store, 3), Ok(1));
+    set_one.call(&mut store, 3).unwrap();
+    assert_eq!(is_null.call(&mut store, 3), Ok(0));
+    assert_eq!(call.call(&mut store, 3), Ok(1));
+
+    let set_object = instance.get_func("set_object").unwrap();
+    let get_object = instance.get_func("get_object").unwrap();
+    set_object
+        .call(&mut store, &[Value::I32(1), Value::ExternRef(Some(7))])
+        .unwrap();
+    assert_eq!(
+        get_object.call(&mut store, &[Value::I32(1)]),
+        Ok(vec![Value::ExternRef(Some(7))])
+    );
+    assert_eq!(
+        get_object.call(&mut store, &[Value::I32(0)]),
+        Ok(vec![Value::ExternRef(None)])
+    );
+    assert!(matches!(
+        get_object.call(&mut store, &[Value::I32(2)]),
+        Err(CallError::Trap(Trap {
+            kind: TrapKind::TableOutOfBounds,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn element_segment_out_of_bounds() {
+    let module = parse("(table 1 funcref) (func $f) (elem (i32.const 1) $f)");
+
+    let mut store = Store::new();
+    let result = Instance::new(&mut store, &module, &[]);
+
+    assert_eq!(
+        result.err(),
+        Some(InstantiationError::Trap(Trap::new(
+            TrapKind::TableOutOfBounds
+        )))
+    );
+
+    // Empty segments may start at the end of the table
+    let module = parse("(table 1 funcref) (elem (i32.const 1) func)");
+    assert!(Instance::new(&mut store, &module, &[]).is_ok());
+}
+
+#[test]
+fn start_function() {
+    let module = parse(
+        r#"
+        (import "env" "log" (func (param i32)))
+        (func $start (call 0 (i32.const 42)))
+        (start $start)
+        "#,
+    );
+
+    let mut store = Store::with_data(Vec::new());
+    let log = Func::wrap(&mut store, |mut caller: Caller<Vec<i32>>, value: i32| {
+        caller.data_mut().push(value)
+    });
+    Instance::new(&mut store, &module, &[Extern::Func(log.address())]).unwrap();
+
+    assert_eq!(store.data(), &vec![42]);
+}
+
+#[test]
+fn trapping_start_function() {
+    let module = parse("(func $start unreachable) (start $start)");
+
+    let mut store = Store::new();
+    let result = Instance::new(&mut store, &module, &[]);
+
+    assert_eq!(
+        result.err(),
+        Some(InstantiationError::Trap(Trap {
+            kind: TrapKind::Unreachable,
+            backtrace: vec![FrameInfo {
+                address: FuncAddr(0),
+                index: FuncIdx(0),
+                module_name: None,
+                name: None,
+            }],
+        }))
+    );
+}
+
+#[test]
+fn backtraces_across_instances() {
+    let mut callee = parse(r#"(func (export "fail") unreachable)"#);
+    callee.names.module = Some(Name("callee".to_owned()));
+    callee.names.functions = vec![(FuncIdx(0), Name("fail".to_owned()))];
+
+    let caller = parse(
+        r#"
+        (import "callee" "fail" (func $fail))
+        (func (export "run") call $fail)
+        "#,
+    );
+
+    let mut store = Store::new();
+    let callee = Instance::new(&mut store, &callee, &[]).unwrap();
+    let fail = callee.get_export("fail").unwrap();
+    let caller = Instance::new(&mut store, &caller, &[fail]).unwrap();
+
+    let run = caller.get_typed_func::<(), ()>("run").unwrap();
+    let trap = run.call(&mut store, ()).unwrap_err();
+
+    // Both functions have index 0 in their modules, but different addresses
+    assert_eq!(
+        trap.backtrace,
+        vec![
+            FrameInfo {
+                address: FuncAddr(0),
+                index: FuncIdx(0),
+                module_name: Some(Name("callee".to_owned())),
+                name: Some(Name("fail".to_owned())),
+            },
+            FrameInfo {
+                address: FuncAddr(1),
+                index: FuncIdx(1),
+                module_name: None,
+                name: None,
+            },
+        ]
+    );
+    assert_eq!(
+        trap.to_string(),
+        "wasm trap: unreachable\nwasm backtrace:\n  \
+         0: fail (function 0, address 0) in module callee\n  \
+         1: function 1 (address 1)"
+    );
+}
+
+#[test]
+fn invalid_module() {
+    let module = parse("(func (result i32))");
+
+    let mut store = Store::new();
+    let result = Instance::new(&mut store, &module, &[]);
+
+    assert!(matches!(
+        result,
+        Err(InstantiationError::Validation(ValidationError {
+            kind: ValidationErrorKind::OperandStackUnderflow,
+            location: Location::Func { .. },
+        }))
+    ));
+}
+
+#[test]
+fn table_too_large() {
+    let module = parse("(table 1 funcref) (table 0xFFFF_FFFF funcref)");
+
+    let mut store = Store::new();
+    let result = Instance::new(&mut store, &module, &[]);
+
+    assert_eq!(
+        result.err(),
+        Some(InstantiationError::TableTooLarge(TableIdx(1)))
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn memory_too_large() {
+    // A 4GiB memory may well fit on the host, so the test reruns itself in a
+    // process whose address space is limited to 1GB
+    if std::env::var_os("WARIO_LIMITED_ADDRESS_SPACE").is_none() {
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(r#"ulimit -v 1000000 && exec "$0" --exact memory_too_large"#)
+            .arg(std::env::current_exe().unwrap())
+            .env("WARIO_LIMITED_ADDRESS_SPACE", "1")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        return;
+    }
+
+    let module = parse("(memory 0x10000)");
+
+    let mut store = Store::new();
+    let result = Instance::new(&mut store, &module, &[]);
+
+    assert_eq!(
+        result.err(),
+        Some(InstantiationError::MemoryTooLarge(MemIdx(0)))
+    );
+}
+
+#[test]
+fn import_count_mismatch() {
+    let module = parse(r#"(import "env" "f" (func))"#);
+
+    let mut store = Store::new();
+    let result = Instance::new(&mut store, &module, &[]);
+
+    assert_eq!(
+        result.err(),
+        Some(InstantiationError::ImportCountMismatch {
+            expected: 1,
+            actual: 0,
+        })
+    );
+}
+
+#[test]
+fn incompatible_imports() {
+    let mut store = Store::new();
+    let function = Func::wrap(&mut store, |_: i32| {}).address();
+    let memory = store.add_memory(
+        Memory::new(Limits {
+            min: 1,
+            max: Some(2),
+        })
+        .unwrap(),
+    );
+    let table = store.add_table(
+        Table::new(TableType {
+            elem_type: RefType::FuncRef,
+            limits: Limits { min: 1, max: None },
+        })
+        .unwrap(),
+    );
+    let global = store
+        .add_global(
+            GlobalType {
+                value_type: ValueType::NumType(NumType::F64),
+                mutability: Mutability::Variable,
+            },
+            Value::F64(0.0),
+        )
+        .unwrap();
+
+    let incompatible = |store: &mut Store, text: &str, import: Extern| {
+        let module = parse(text);
+        match Instance::new(store, &module, &[import]) {
+            Err(InstantiationError::IncompatibleImport {
+                index: 0,
+                module,
+                name,
+            }) => module == Name("env".to_owned()) && name == Name("x".to_owned()),
+            _ => false,
+        }
+    };
+
+    // Wrong kind of entity
+    assert!(incompatible(
+        &mut store,
+        r#"(import "env" "x" (memory 1))"#,
+        Extern::Func(function)
+    ));
+
+    // Wrong parameter count
+    assert!(incompatible(
+        &mut store,
+        r#"(import "env" "x" (func (param i32 i32)))"#,
+        Extern::Func(function)
+    ));
+
+    // Wrong parameter type
+    assert!(incompatible(
+        &mut store,
+        r#"(import "env" "x" (func (param i64)))"#,
+        Extern::Func(function)
+    ));
+
+    // Wrong result count
+    assert!(incompatible(
+        &mut store,
+        r#"(import "env" "x" (func (param i32) (result i32)))"#,
+        Extern::Func(function)
+    ));
+
+    // The maximum of the memory exceeds the expected one
+    assert!(incompatible(
+        &mut store,
+        r#"(import "env" "x" (memory 1 1))"#,
+        Extern::Memory(memory)
+    ));
+
+    // The table is too small
+    assert!(incompatible(
+        &mut store,
+        r#"(import "env" "x" (table 2 funcref))"#,
+        Extern::Table(table)
+    ));
+
+    // Globals need the same type and mutability
+    assert!(incompatible(
+        &mut store,
+        r#"(import "env" "x" (global f64))"#,
+        Extern::Global(global)
+    ));
+
+    let module = parse(
+        r#"
+        (import "env" "f" (func (param i32)))
+        (import "env" "m" (memory 0 3))
+        (import "env" "t" (table 1 funcref))
+        (import "env" "g" (global (mut f64)))
+        "#,
+    );
+    let imports = [
+        Extern::Func(function),
+        Extern::Memory(memory),
+        Extern::Table(table),
+        Extern::Global(global),
+    ];
+    assert!(Instance::new(&mut store, &module, &imports).is_ok());
+}
+
+#[test]
+fn imports_from_another_store() {
+    let mut other = Store::new();
+    let function = Func::wrap(&mut other, || {}).address();
+    let memory = other.add_memory(Memory::new(Limits { min: 1, max: None }).unwrap());
+    let table = other.add_table(
+        Table::new(TableType {
+            elem_type: RefType::FuncRef,
+            limits: Limits { min: 1, max: None },
+        })
+        .unwrap(),
+    );
+    let global_type = GlobalType {
+        value_type: ValueType::NumType(NumType::I32),
+        mutability: Mutability::Constant,
+    };
+    let global = other.add_global(global_type, Value::I32(0)).unwrap();
+
+    // None of the entities exist in the store that the module is instantiated into
+    let imports = [
+        (r#"(import "env" "x" (func))"#, Extern::Func(function)),
+        (r#"(import "env" "x" (memory 1))"#, Extern::Memory(memory)),
+        (
+            r#"(import "env" "x" (table 1 funcref))"#,
+            Extern::Table(table),
+        ),
+        (r#"(import "env" "x" (global i32))"#, Extern::Global(global)),
+    ];
+    for (text, import) in imports {
+        let mut store = Store::new();
+        let result = Instance::new(&mut store, &parse(text), &[import]);
+
+        assert_eq!(
+            result.err(),
+            Some(InstantiationError::IncompatibleImport {
+                index: 0,
+                module: Name("env".to_owned()),
+                name: Name("x".to_owned()),
+            })
+        );
+    }
+}
+
+#[test]
+fn instances_share_imported_memory() {
+    let exporter = parse(r#"(memory (export "memory") 1) (data (i32.const 0) "\2a")"#);
+    let importer = parse(
+        r#"
+        (import "env" "memory" (memory 1))
+        (func (export "load") (result i32) (i32.load8_u (i32.const 0)))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let exporter = Instance::new(&mut store, &exporter, &[]).unwrap();
+    let memory = exporter.get_export("memory").unwrap();
+    let importer = Instance::new(&mut store, &importer, &[memory]).unwrap();
+
+    let mut machine = Machine::new();
+    machine
+        .invoke(
+            &mut store,
+            &importer,
+            &[Instruction::Call(FuncIdx(0))],
+            &mut vec![],
+        )
+        .unwrap();
+
+    assert_eq!(machine.stack, vec![Value::I32(42)]);
+}
@@ -0,0 +1,201 @@
+use wario::vm::{
+    Extern, Func, FuncAddr, Instance, InstantiationError, LinkError, Linker, Machine, Memory,
+    Store, Value,
+};
+use wario::wasm::{FuncIdx, Instruction, Limits, Module, Name};
+use wario::wat;
+
+fn parse(text: &str) -> Module {
+    wat::parse(text).unwrap()
+}
+
+fn constant(store: &mut Store, value: i32) -> FuncAddr {
+    Func::wrap(store, move || value).address()
+}
+
+fn call(store: &mut Store, instance: &Instance, function: usize) -> Vec<Value> {
+    let mut machine = Machine::new();
+    machine
+        .invoke(
+            store,
+            instance,
+            &[Instruction::Call(FuncIdx(function))],
+            &mut vec![],
+        )
+        .unwrap();
+    machine.stack
+}
+
+#[test]
+fn imports_are_resolved_by_name() {
+    let module = parse(
+        r#"
+        (import "env" "two" (func (result i32)))
+        (import "env" "one" (func (result i32)))
+        (func (result i32) (i32.sub (call 0) (call 1)))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let one = constant(&mut store, 1);
+    let two = constant(&mut store, 2);
+
+    // The order of the definitions does not matter
+    let mut linker = Linker::new();
+    linker
+        .define("env", "one", Extern::Func(one))
+        .unwrap()
+        .define("env", "two", Extern::Func(two))
+        .unwrap();
+
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+
+    assert_eq!(call(&mut store, &instance, 2), vec![Value::I32(1)]);
+}
+
+#[test]
+fn missing_import() {
+    let module = parse(
+        r#"
+        (import "env" "memory" (memory 1))
+        (import "env" "print" (func (param i32)))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let memory = store.add_memory(Memory::new(Limits { min: 1, max: None }).unwrap());
+
+    let mut linker = Linker::new();
+    linker
+        .define("env", "memory", Extern::Memory(memory))
+        .unwrap();
+    // Same name, different module
+    linker
+        .define("host", "print", Extern::Memory(memory))
+        .unwrap();
+
+    let error = linker.instantiate(&mut store, &module).unwrap_err();
+    assert_eq!(
+        error,
+        LinkError::MissingImport {
+            module: Name("env".to_owned()),
+            name: Name("print".to_owned()),
+        }
+    );
+    assert_eq!(error.to_string(), "Missing import env.print");
+}
+
+#[test]
+fn incompatible_import() {
+    let module = parse(
+        r#"
+        (import "env" "print" (func (param i32)))
+        (import "env" "memory" (memory 1))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let print = Func::wrap(&mut store, |_: i32| {}).address();
+    let memory = store.add_memory(Memory::new(Limits { min: 1, max: None }).unwrap());
+
+    // The names are swapped
+    let mut linker = Linker::new();
+    linker
+        .define("env", "print", Extern::Memory(memory))
+        .unwrap()
+        .define("env", "memory", Extern::Func(print))
+        .unwrap();
+
+    let error = linker.instantiate(&mut store, &module).unwrap_err();
+    assert_eq!(
+        error,
+        LinkError::Instantiation(InstantiationError::IncompatibleImport {
+            index: 0,
+            module: Name("env".to_owned()),
+            name: Name("print".to_owned()),
+        })
+    );
+    assert_eq!(
+        error.to_string(),
+        "Incompatible import env.print (import 0)"
+    );
+}
+
+#[test]
+fn host_function_signatures_are_checked() {
+    let module = parse(r#"(import "env" "log" (func (param i32 f64)))"#);
+
+    let mut store = Store::new();
+    let log_int = Func::wrap(&mut store, |_: i32, _: i32| {});
+    let log_float = Func::wrap(&mut store, |_: i32, _: f64| {});
+    let log_and_count = Func::wrap(&mut store, |_: i32, _: f64| 1);
+
+    for function in [log_int, log_and_count] {
+        let mut linker = Linker::new();
+        linker
+            .define("env", "log", Extern::Func(function.address()))
+            .unwrap();
+
+        assert!(matches!(
+            linker.instantiate(&mut store, &module),
+            Err(LinkError::Instantiation(
+                InstantiationError::IncompatibleImport { index: 0, .. }
+            ))
+        ));
+    }
+
+    let mut linker = Linker::new();
+    linker
+        .define("env", "log", Extern::Func(log_float.address()))
+        .unwrap();
+    assert!(linker.instantiate(&mut store, &module).is_ok());
+}
+
+#[test]
+fn duplicate_definition() {
+    let mut store = Store::new();
+    let one = constant(&mut store, 1);
+
+    let mut linker = Linker::new();
+    linker.define("env", "one", Extern::Func(one)).unwrap();
+
+    assert_eq!(
+        linker.define("env", "one", Extern::Func(one)).unwrap_err(),
+        LinkError::DuplicateDefinition {
+            module: "env".to_owned(),
+            name: "one".to_owned(),
+        }
+    );
+    assert_eq!(linker.get("env", "one"), Some(Extern::Func(one)));
+    assert_eq!(linker.get("env", "two"), None);
+}
+
+#[test]
+fn instances_as_modules() {
+    let library = parse(
+        r#"
+        (memory (export "memory") 1)
+        (data (i32.const 0) "\07")
+        (func (export "seven") (result i32) (i32.load8_u (i32.const 0)))
+        "#,
+    );
+    let application = parse(
+        r#"
+        (import "lib" "seven" (func $seven (result i32)))
+        (import "lib" "memory" (memory 1))
+        (func (result i32)
+          (i32.store8 (i32.const 0) (i32.const 8))
+          (call $seven))
+        "#,
+    );
+
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+
+    let library = linker.instantiate(&mut store, &library).unwrap();
+    linker.define_instance("lib", &library).unwrap();
+    let application = linker.instantiate(&mut store, &application).unwrap();
+
+    // The library reads what the application stored in the shared memory
+    assert_eq!(call(&mut store, &application, 1), vec![Value::I32(8)]);
+}
@@ -1,6 +1,7 @@
 use std::fs::File;
 
 use wario::parser::Result;
+use wario::vm::{Caller, Extern, Func, Linker, Store};
 use wario::wasm;
 
 fn open_file(filename: &str) -> File {
@@ -12,19 +13,80 @@ fn open_file(filename: &str) -> File {
     match File::open(path) {
         Ok(file) => file,
         Err(err) => panic!("Unable to open file: {}", err),
-    };
+    }
 }
 
 #[test]
 fn parse_wasm() -> Result<()> {
     let mut file = open_file("mandelbrot.wasm");
     let module = wasm::Module::parse(&mut file)?;
-    println!("{:#?}", module);
+    println!("{}", module);
+
+    Ok(())
+}
+
+#[test]
+fn parse_wasm_from_bytes() -> Result<()> {
+    let bytes = include_bytes!("mandelbrot.wasm");
+    let module = wasm::Module::from_bytes(bytes)?;
+
+    assert_eq!(module.types.len(), 7);
+    assert_eq!(module.imports.len(), 3);
+    assert_eq!(module.codes.len(), 4);
 
     Ok(())
 }
 
 #[test]
 fn run_wasm() -> Result<()> {
-    ()
+    let module = wasm::Module::from_bytes(include_bytes!("mandelbrot.wasm"))?;
+
+    // The output is collected in the store
+    let mut store = Store::with_data(String::new());
+    let printi = Func::wrap(&mut store, |mut caller: Caller<String>, value: i32| {
+        caller.data_mut().push_str(&value.to_string())
+    });
+    let printf = Func::wrap(&mut store, |mut caller: Caller<String>, value: f64| {
+        caller.data_mut().push_str(&value.to_string())
+    });
+    let printb = Func::wrap(&mut store, |mut caller: Caller<String>, byte: i32| {
+        caller.data_mut().push(char::from(byte as u8))
+    });
+
+    let mut linker = Linker::new();
+    linker
+        .define("runtime", "_printi", Extern::Func(printi.address()))
+        .unwrap()
+        .define("runtime", "_printf", Extern::Func(printf.address()))
+        .unwrap()
+        .define("runtime", "_printb", Extern::Func(printb.address()))
+        .unwrap();
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+
+    let in_mandelbrot = instance
+        .get_typed_func::<(f64, f64, i32), i32>("in_mandelbrot")
+        .unwrap();
+    assert_eq!(in_mandelbrot.call(&mut store, (0.0, 0.0, 100)), Ok(1));
+    assert_eq!(in_mandelbrot.call(&mut store, (-1.0, 0.0, 100)), Ok(1));
+    assert_eq!(in_mandelbrot.call(&mut store, (1.0, 1.0, 100)), Ok(0));
+
+    let main = instance.get_typed_func::<(), i32>("main").unwrap();
+    assert_eq!(main.call(&mut store, ()), Ok(0));
+
+    let lines: Vec<_> = store.data().lines().collect();
+    assert_eq!(lines.len(), 41);
+    assert!(lines.iter().all(|line| line.len() == 80));
+    assert!(lines
+        .iter()
+        .all(|line| line.chars().all(|c| c == '*' || c == '.')));
+
+    // The origin is in the set, the corners are not
+    assert_eq!(lines[20].as_bytes()[53], b'*');
+    assert_eq!(lines[0].as_bytes()[0], b'.');
+    assert_eq!(lines[40].as_bytes()[79], b'.');
+
+    // The set is symmetric about the real axis
+    assert!((0..20).all(|row| lines[row] == lines[40 - row]));
+
+    Ok(())
 }
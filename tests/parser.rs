@@ -0,0 +1,575 @@
+use wario::parser::ParseError;
+use wario::wasm::{Module, SectionId, MAX_NESTING_DEPTH};
+
+const PREAMBLE: [u8; 8] = [0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+
+fn module(sections: &[u8]) -> Vec<u8> {
+    let mut bytes = PREAMBLE.to_vec();
+    bytes.extend_from_slice(sections);
+    bytes
+}
+
+#[test]
+fn empty_module() {
+    let module = Module::from_bytes(&PREAMBLE).unwrap();
+    assert!(module.types.is_empty());
+}
+
+#[test]
+fn invalid_magic() {
+    let err = Module::from_bytes(b"\0wat\x01\0\0\0").unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::InvalidMagic {
+            magic: *b"\0wat",
+            offset: 0,
+            section: None,
+        }
+    );
+}
+
+#[test]
+fn truncated_preamble() {
+    let err = Module::from_bytes(b"\0as").unwrap_err();
+    assert!(matches!(err, ParseError::UnexpectedEof { .. }));
+}
+
+#[test]
+fn unknown_section() {
+    let err = Module::from_bytes(&module(&[0x2A, 0x00])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::UnknownSection {
+            id: 0x2A,
+            offset: 8,
+            section: None,
+        }
+    );
+}
+
+#[test]
+fn duplicate_section() {
+    // Two empty type sections
+    let err = Module::from_bytes(&module(&[0x01, 0x01, 0x00, 0x01, 0x01, 0x00])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::SectionOutOfOrder {
+            id: SectionId::Type,
+            offset: 11,
+            section: None,
+        }
+    );
+}
+
+#[test]
+fn section_out_of_order() {
+    // An empty function section followed by an empty type section
+    let err = Module::from_bytes(&module(&[0x03, 0x01, 0x00, 0x01, 0x01, 0x00])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::SectionOutOfOrder {
+            id: SectionId::Type,
+            offset: 11,
+            section: None,
+        }
+    );
+
+    // Custom sections may appear anywhere, and the data count section precedes the code section
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&module(&[
+        0x00, 0x02, 0x01, b'a',
+        0x01, 0x01, 0x00,
+        0x00, 0x02, 0x01, b'b',
+        0x0C, 0x01, 0x00,
+        0x0A, 0x01, 0x00,
+    ]));
+    assert!(module.is_ok());
+}
+
+#[test]
+fn truncated_section() {
+    // Type section announcing a single function type, which is missing
+    let err = Module::from_bytes(&module(&[0x01, 0x01, 0x01])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::UnexpectedEof {
+            offset: 11,
+            section: Some(SectionId::Type),
+        }
+    );
+}
+
+#[test]
+fn section_size_mismatch() {
+    // Type section containing a single [] -> [] function type, but claiming one byte too many
+    let err = Module::from_bytes(&module(&[0x01, 0x05, 0x01, 0x60, 0x00, 0x00, 0x00])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::SectionSizeMismatch {
+            expected: 5,
+            actual: 4,
+            offset: 10,
+            section: Some(SectionId::Type),
+        }
+    );
+}
+
+#[test]
+fn unknown_opcode() {
+    // Code section containing a single function body with opcode 0xFF
+    let err = Module::from_bytes(&module(&[0x0A, 0x05, 0x01, 0x03, 0x00, 0xFF, 0x0B])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::UnknownOpcode {
+            opcode: 0xFF,
+            offset: 13,
+            section: Some(SectionId::Code),
+        }
+    );
+}
+
+#[test]
+fn invalid_utf8() {
+    // Export section with a name consisting of an invalid UTF-8 sequence
+    let err = Module::from_bytes(&module(&[0x07, 0x05, 0x01, 0x02, 0xC3, 0x28, 0x00])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::InvalidUtf8 {
+            offset: 11,
+            section: Some(SectionId::Export),
+        }
+    );
+}
+
+#[test]
+fn leb_overflow() {
+    // Function section with a count that doesn't terminate within five bytes
+    let err =
+        Module::from_bytes(&module(&[0x03, 0x06, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::LebOverflow {
+            offset: 10,
+            section: Some(SectionId::Function),
+        }
+    );
+}
+
+fn code(body: &[u8]) -> Vec<u8> {
+    // Code section containing a single function body without locals
+    let mut section = vec![0x0A, body.len() as u8 + 3, 0x01, body.len() as u8 + 1, 0x00];
+    section.extend_from_slice(body);
+    module(&section)
+}
+
+#[test]
+fn if_without_else() {
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&code(&[
+        0x41, 0x01,       // i32.const 1
+        0x04, 0x40,       // if
+        0x01,             //   nop
+        0x0B,             // end
+        0x41, 0x02,       // i32.const 2
+        0x1A,             // drop
+        0x0B,             // end
+    ]))
+    .unwrap();
+
+    let body = format!("{:?}", module.codes[0].body);
+    assert_eq!(
+        body,
+        "[I32Const(1), If(Empty, [Nop], []), I32Const(2), Drop]"
+    );
+}
+
+#[test]
+fn mvp_instructions() {
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&code(&[
+        0x42, 0x7F,                   // i64.const -1
+        0x43, 0x00, 0x00, 0x80, 0x3F, // f32.const 1.0
+        0x0E, 0x02, 0x00, 0x01, 0x02, // br_table 0 1 2
+        0x11, 0x03, 0x00,             // call_indirect (type 3)
+        0x22, 0x04,                   // local.tee 4
+        0x31, 0x00, 0x08,             // i64.load8_u offset=8
+        0x3F, 0x00,                   // memory.size
+        0x40, 0x00,                   // memory.grow
+        0x78,                         // i32.rotr
+        0xBF,                         // f64.reinterpret_i64
+        0x0B,                         // end
+    ]))
+    .unwrap();
+
+    let body = format!("{:?}", module.codes[0].body);
+    assert_eq!(
+        body,
+        "[I64Const(-1), F32Const(1.0), \
+         BranchTable([LabelIdx(0), LabelIdx(1)], LabelIdx(2)), \
+         CallIndirect(TypeIdx(3), TableIdx(0)), LocalTee(LocalIdx(4)), \
+         I64Load8Unsigned(MemArg { align: 0, offset: 8 }), MemorySize, MemoryGrow, \
+         I32Rotr, F64ReinterpretI64]"
+    );
+}
+
+#[test]
+fn memory_size_requires_zero_byte() {
+    let err = Module::from_bytes(&code(&[0x3F, 0x01, 0x0B])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::ZeroByteExpected {
+            byte: 0x01,
+            offset: 14,
+            section: Some(SectionId::Code),
+        }
+    );
+}
+
+#[test]
+fn table_instructions() {
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&code(&[
+        0x25, 0x01,             // table.get 1
+        0x26, 0x00,             // table.set 0
+        0xFC, 0x0C, 0x02, 0x01, // table.init 1 2
+        0xFC, 0x0D, 0x03,       // elem.drop 3
+        0xFC, 0x0E, 0x00, 0x01, // table.copy 0 1
+        0xFC, 0x0F, 0x01,       // table.grow 1
+        0xFC, 0x10, 0x00,       // table.size 0
+        0xFC, 0x11, 0x02,       // table.fill 2
+        0x0B,                   // end
+    ]))
+    .unwrap();
+
+    let body = format!("{:?}", module.codes[0].body);
+    assert_eq!(
+        body,
+        "[TableGet(TableIdx(1)), TableSet(TableIdx(0)), \
+         TableInit(TableIdx(1), ElemIdx(2)), ElemDrop(ElemIdx(3)), \
+         TableCopy(TableIdx(0), TableIdx(1)), TableGrow(TableIdx(1)), \
+         TableSize(TableIdx(0)), TableFill(TableIdx(2))]"
+    );
+}
+
+#[test]
+fn unknown_prefixed_opcode() {
+    let err = Module::from_bytes(&code(&[0xFC, 0x80, 0x01, 0x0B])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::UnknownPrefixedOpcode {
+            prefix: 0xFC,
+            opcode: 128,
+            offset: 13,
+            section: Some(SectionId::Code),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "Unsupported opcode found: 0xFC 128 (stream pos = 13 (0x0D), section = Code)"
+    );
+}
+
+#[test]
+fn too_many_locals() {
+    #[rustfmt::skip]
+    let err = Module::from_bytes(&module(&[
+        // Code section with a single body declaring u32::MAX i32 locals
+        0x0A, 0x0A, 0x01, 0x08,
+        0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x7F,
+        0x0B,
+    ]))
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::TooManyLocals {
+            offset: 12,
+            section: Some(SectionId::Code),
+        }
+    );
+}
+
+#[test]
+fn table_start_element_and_data_sections() {
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&module(&[
+        // Type section: [] -> []
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+        // Function section
+        0x03, 0x02, 0x01, 0x00,
+        // Table section: funcref, min 1
+        0x04, 0x04, 0x01, 0x70, 0x00, 0x01,
+        // Memory section: min 1
+        0x05, 0x03, 0x01, 0x00, 0x01,
+        // Start section: function 0
+        0x08, 0x01, 0x00,
+        // Element section
+        0x09, 0x17, 0x04,
+        0x00, 0x41, 0x00, 0x0B, 0x01, 0x00, // active, offset 0, [func 0]
+        0x01, 0x00, 0x01, 0x00,             // passive, funcref, [func 0]
+        0x07, 0x70, 0x01, 0xD2, 0x00, 0x0B, // declarative, funcref, [ref.func 0]
+        0x05, 0x70, 0x01, 0xD0, 0x70, 0x0B, // passive, funcref, [ref.null func]
+        // Data count section
+        0x0C, 0x01, 0x02,
+        // Code section
+        0x0A, 0x04, 0x01, 0x02, 0x00, 0x0B,
+        // Data section
+        0x0B, 0x0D, 0x02,
+        0x00, 0x41, 0x08, 0x0B, 0x02, b'h', b'i', // active, offset 8
+        0x01, 0x03, b'a', b'b', b'c',             // passive
+    ]))
+    .unwrap();
+
+    assert_eq!(
+        format!("{:?}", module.tables),
+        "[TableType { elem_type: FuncRef, limits: Limits { min: 1, max: None } }]"
+    );
+    assert_eq!(
+        format!("{:?}", module.start),
+        "Some(Start { func: FuncIdx(0) })"
+    );
+
+    assert_eq!(module.elems.len(), 4);
+    assert_eq!(
+        format!("{:?}", module.elems[0]),
+        "Elem { elem_type: FuncRef, init: [[RefFunc(FuncIdx(0))]], \
+         mode: Active { table: TableIdx(0), offset: [I32Const(0)] } }"
+    );
+    assert_eq!(
+        format!("{:?}", module.elems[1]),
+        "Elem { elem_type: FuncRef, init: [[RefFunc(FuncIdx(0))]], mode: Passive }"
+    );
+    assert_eq!(
+        format!("{:?}", module.elems[2]),
+        "Elem { elem_type: FuncRef, init: [[RefFunc(FuncIdx(0))]], mode: Declarative }"
+    );
+    assert_eq!(
+        format!("{:?}", module.elems[3]),
+        "Elem { elem_type: FuncRef, init: [[RefNull(FuncRef)]], mode: Passive }"
+    );
+
+    assert_eq!(module.datas.len(), 2);
+    assert_eq!(module.datas[0].init, b"hi");
+    assert_eq!(
+        format!("{:?}", module.datas[0].mode),
+        "Active { memory: MemIdx(0), offset: [I32Const(8)] }"
+    );
+    assert_eq!(module.datas[1].init, b"abc");
+    assert_eq!(format!("{:?}", module.datas[1].mode), "Passive");
+}
+
+#[test]
+fn invalid_data_flags() {
+    let err = Module::from_bytes(&module(&[0x0B, 0x02, 0x01, 0x03])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::InvalidDataFlags {
+            flags: 3,
+            offset: 11,
+            section: Some(SectionId::Data),
+        }
+    );
+}
+
+#[test]
+fn invalid_limits_flag() {
+    // Memory section with a single memory of at least 1 and at most 2 pages
+    let parsed = Module::from_bytes(&module(&[0x05, 0x04, 0x01, 0x01, 0x01, 0x02])).unwrap();
+    assert_eq!(parsed.memories[0].max, Some(2));
+
+    // Only the flags 0x00 and 0x01 are defined
+    let err = Module::from_bytes(&module(&[0x05, 0x03, 0x01, 0x02, 0x01])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::InvalidLimitsFlag {
+            flag: 2,
+            offset: 11,
+            section: Some(SectionId::Memory),
+        }
+    );
+}
+
+#[test]
+fn block_types() {
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&code(&[
+        0x02, 0x40, 0x0B,             // block
+        0x02, 0x7C, 0x0B,             // block (result f64)
+        0x03, 0x02, 0x0B,             // loop (type 2)
+        0x02, 0x80, 0x01, 0x0B,       // block (type 128)
+        0x0B,                         // end
+    ]))
+    .unwrap();
+
+    let body = format!("{:?}", module.codes[0].body);
+    assert_eq!(
+        body,
+        "[Block(Empty, []), Block(Value(NumType(F64)), []), \
+         Loop(TypeIdx(TypeIdx(2)), []), Block(TypeIdx(TypeIdx(128)), [])]"
+    );
+}
+
+#[test]
+fn invalid_block_type() {
+    // 0x7F 0x7F would be a negative s33, which is not a valid type index
+    let err = Module::from_bytes(&code(&[0x02, 0xFF, 0x7F, 0x0B, 0x0B])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::InvalidBlockType {
+            block_type: 0xFF,
+            offset: 14,
+            section: Some(SectionId::Code),
+        }
+    );
+}
+
+fn leb128(mut value: usize) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// A module with a single function body of `depth` nested blocks
+fn nested_blocks(depth: usize) -> Vec<u8> {
+    let mut body = vec![0x00];
+    body.extend([0x02, 0x40].repeat(depth));
+    body.extend(vec![0x0B; depth + 1]);
+
+    let mut code = vec![0x01];
+    code.extend(leb128(body.len()));
+    code.extend(body);
+
+    let mut section = vec![0x0A];
+    section.extend(leb128(code.len()));
+    section.extend(code);
+    module(&section)
+}
+
+#[test]
+fn nesting_depth() {
+    let module = Module::from_bytes(&nested_blocks(MAX_NESTING_DEPTH)).unwrap();
+    assert_eq!(module.codes.len(), 1);
+
+    let bytes = nested_blocks(MAX_NESTING_DEPTH + 1);
+    let err = Module::from_bytes(&bytes).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::NestingTooDeep {
+            // The opcode of the innermost block, followed by its block type and all ends
+            offset: (bytes.len() - 1 - 1 - (MAX_NESTING_DEPTH + 2)) as u64,
+            section: Some(SectionId::Code),
+        }
+    );
+}
+
+#[test]
+fn leb128_constants() {
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&code(&[
+        0x41, 0x40,                               // i32.const -64
+        0x41, 0x80, 0x00,                         // i32.const 0 (padded)
+        0x41, 0xFF, 0xFF, 0xFF, 0xFF, 0x07,       // i32.const 2147483647
+        0x41, 0x80, 0x80, 0x80, 0x80, 0x78,       // i32.const -2147483648
+        0x42, 0x80, 0x80, 0x80, 0x80, 0x80,
+              0x80, 0x80, 0x80, 0x80, 0x7F,       // i64.const -9223372036854775808
+        0x42, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+              0xFF, 0xFF, 0xFF, 0xFF, 0x00,       // i64.const 9223372036854775807
+        0x0B,                                     // end
+    ]))
+    .unwrap();
+
+    let body = format!("{:?}", module.codes[0].body);
+    assert_eq!(
+        body,
+        "[I32Const(-64), I32Const(0), I32Const(2147483647), I32Const(-2147483648), \
+         I64Const(-9223372036854775808), I64Const(9223372036854775807)]"
+    );
+}
+
+#[test]
+fn leb128_too_long() {
+    let err =
+        Module::from_bytes(&code(&[0x41, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00, 0x0B])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::LebOverflow {
+            offset: 14,
+            section: Some(SectionId::Code),
+        }
+    );
+}
+
+#[test]
+fn leb128_signed_out_of_range() {
+    // The unused bits of the last byte have to match the sign bit
+    let err = Module::from_bytes(&code(&[0x41, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x0B])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::LebOutOfRange {
+            offset: 14,
+            section: Some(SectionId::Code),
+        }
+    );
+
+    let err = Module::from_bytes(&code(&[
+        0x42, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01, 0x0B,
+    ]))
+    .unwrap_err();
+    assert!(matches!(err, ParseError::LebOutOfRange { offset: 14, .. }));
+}
+
+#[test]
+fn leb128_unsigned_out_of_range() {
+    // Function section with a count that doesn't fit in 32 bits
+    let err = Module::from_bytes(&module(&[0x03, 0x05, 0xFF, 0xFF, 0xFF, 0xFF, 0x1F])).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::LebOutOfRange {
+            offset: 10,
+            section: Some(SectionId::Function),
+        }
+    );
+}
+
+#[test]
+fn name_section() {
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&module(&[
+        0x00, 0x19, 0x04, b'n', b'a', b'm', b'e',
+        0x00, 0x02, 0x01, b'm',                               // module name
+        0x01, 0x06, 0x01, 0x00, 0x03, b'a', b'd', b'd',       // function names
+        0x02, 0x06, 0x01, 0x00, 0x01, 0x00, 0x01, b'x',       // local names
+    ]))
+    .unwrap();
+
+    assert_eq!(
+        format!("{:?}", module.names),
+        "Names { module: Some(m), \
+         functions: [(FuncIdx(0), add)], \
+         locals: [(FuncIdx(0), [(LocalIdx(0), x)])] }"
+    );
+}
+
+#[test]
+fn malformed_name_section_is_ignored() {
+    #[rustfmt::skip]
+    let module = Module::from_bytes(&module(&[
+        0x00, 0x0C, 0x04, b'n', b'a', b'm', b'e',
+        0x01, 0x05, 0x01, 0x00, 0x09, b'a', b'b',             // name exceeds subsection
+    ]))
+    .unwrap();
+
+    assert!(module.names.is_empty());
+}
+
+#[test]
+fn other_custom_sections_are_skipped() {
+    let module = Module::from_bytes(&module(&[0x00, 0x04, 0x02, b'h', b'i', 0xFF])).unwrap();
+    assert!(module.names.is_empty());
+}
@@ -0,0 +1,163 @@
+use wario::wasm::{FuncIdx, LocalIdx, Module, Name, Names};
+use wario::wat;
+
+#[test]
+fn module() {
+    let module = wat::parse(
+        r#"
+        (module
+          (import "env" "print" (func (param i32)))
+          (memory (export "memory") 1 2)
+          (global (mut f64) (f64.const -0.5))
+          (func (export "count") (param i32) (result i32)
+            (local i32 i64)
+            (block
+              (loop
+                (br_if 1 (i32.eqz (local.get 0)))
+                (if (i32.load8_u offset=4 (local.get 0))
+                  (then (call 0 (local.get 1)))
+                  (else (nop)))
+                (br 0)))
+            (local.get 1))
+          (data (i32.const 16) "a\"b\n"))
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        module.to_string(),
+        r#"(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (import "env" "print" (func (;0;) (type 0)))
+  (func (;1;) (type 1) (param i32) (result i32)
+    (local i32 i64)
+    block
+      loop
+        local.get 0
+        i32.eqz
+        br_if 1
+        local.get 0
+        i32.load8_u offset=4
+        if
+          local.get 1
+          call 0
+        else
+          nop
+        end
+        br 0
+      end
+    end
+    local.get 1)
+  (memory (;0;) 1 2)
+  (global (;0;) (mut f64) (f64.const -0.5))
+  (export "memory" (memory 0))
+  (export "count" (func 1))
+  (data (;0;) (i32.const 16) "a\"b\0a"))"#
+    );
+}
+
+#[test]
+fn mandelbrot_round_trip() {
+    let module = Module::from_bytes(include_bytes!("mandelbrot.wasm")).unwrap();
+
+    assert_eq!(wat::parse(&module.to_string()).unwrap(), module);
+}
+
+#[test]
+fn segments_round_trip() {
+    let module = wat::parse(
+        r#"
+        (module
+          (table 2 funcref)
+          (table 1 externref)
+          (memory 1)
+          (func $f)
+          (elem (i32.const 0) $f $f)
+          (elem (table 1) (offset (i32.const 0)) externref (ref.null extern))
+          (elem funcref (ref.null func) (item (ref.func $f)))
+          (elem declare func $f)
+          (elem $e func $f)
+          (func
+            (table.set 1 (i32.const 0) (table.get 1 (i32.const 0)))
+            (drop (table.grow 0 (ref.null func) (table.size 0)))
+            (table.fill 0 (i32.const 0) (ref.null func) (i32.const 2))
+            (table.copy 0 0 (i32.const 0) (i32.const 1) (i32.const 1))
+            (table.init 0 $e (i32.const 0) (i32.const 0) (i32.const 1))
+            (elem.drop $e))
+          (data (i32.const 1) "\ff")
+          (data "passive"))
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(wat::parse(&module.to_string()).unwrap(), module);
+}
+
+#[test]
+fn special_floats() {
+    let module = wat::parse(
+        r#"
+        (func
+          f32.const -nan
+          f32.const nan:0x200
+          f32.const inf
+          f64.const -0
+          f64.const 0x1p-1074
+          f64.const 1e300)
+        "#,
+    )
+    .unwrap();
+
+    let text = module.to_string();
+    assert!(text.contains("f32.const -nan\n"));
+    assert!(text.contains("f32.const nan:0x200\n"));
+    assert!(text.contains("f32.const inf\n"));
+    assert!(text.contains("f64.const -0.0\n"));
+    assert!(text.contains("f64.const 5e-324\n"));
+    assert!(text.contains("f64.const 1e300)"));
+
+    // Compare the bits, because NaN is not equal to itself
+    let reparsed = wat::parse(&text).unwrap();
+    assert_eq!(format!("{:?}", reparsed), format!("{:?}", module));
+}
+
+#[test]
+fn names() {
+    let mut module = wat::parse(
+        r#"
+        (func (param i32) (local f64)
+          (call 1 (local.get 0) (local.get 1)))
+        (func (param i32 f64))
+        (export "first" (func 0))
+        "#,
+    )
+    .unwrap();
+
+    module.names = Names {
+        module: Some(Name("demo".to_owned())),
+        functions: vec![
+            (FuncIdx(0), Name("first".to_owned())),
+            // Not a valid identifier, so it is left out
+            (FuncIdx(1), Name("second one".to_owned())),
+        ],
+        locals: vec![(FuncIdx(0), vec![(LocalIdx(1), Name("x".to_owned()))])],
+    };
+
+    // The names survive encoding
+    let module = Module::from_bytes(&module.encode()).unwrap();
+
+    assert_eq!(
+        module.to_string(),
+        r#"(module $demo
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param i32 f64)))
+  (func $first (;0;) (type 0) (param i32)
+    (local $x f64)
+    local.get 0
+    local.get $x
+    call 1)
+  (func (;1;) (type 1) (param i32 f64))
+  (export "first" (func $first)))"#
+    );
+}
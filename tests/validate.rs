@@ -0,0 +1,378 @@
+use wario::validate::{Location, ValidationError, ValidationErrorKind};
+use wario::wasm::{
+    BlockType, ElemIdx, FuncIdx, GlobalIdx, Instruction, LabelIdx, LocalIdx, Module, Name, NumType,
+    RefType, TypeIdx, ValueType, MAX_NESTING_DEPTH,
+};
+use wario::wat;
+
+const I32: ValueType = ValueType::NumType(NumType::I32);
+const I64: ValueType = ValueType::NumType(NumType::I64);
+
+fn validate(text: &str) -> Result<(), ValidationError> {
+    wat::parse(text).unwrap().validate()
+}
+
+fn error(kind: ValidationErrorKind, location: Location) -> Result<(), ValidationError> {
+    Err(ValidationError { kind, location })
+}
+
+fn at(func: usize, instruction: usize) -> Location {
+    Location::Func {
+        func: FuncIdx(func),
+        instruction,
+    }
+}
+
+#[test]
+fn mandelbrot() {
+    let module = Module::from_bytes(include_bytes!("mandelbrot.wasm")).unwrap();
+    assert_eq!(module.validate(), Ok(()));
+}
+
+#[test]
+fn valid_module() {
+    let result = validate(
+        r#"
+        (import "env" "limit" (global $limit i32))
+        (memory 1)
+        (table 1 funcref)
+        (global $counter (mut i32) (global.get $limit))
+        (func $select (param i32) (result i32)
+          (block $done (result i32)
+            (drop
+              (block $zero (result i32)
+                (br_table $zero $done (i32.const 7) (local.get 0))))
+            (i32.const 0)))
+        (func (export "run") (result i64)
+          (local i64)
+          (global.set $counter (call $select (i32.load (i32.const 0))))
+          (loop $again
+            (br_if $again (i32.eqz (global.get $counter))))
+          (if (result i64) (i32.const 1)
+            (then (i64.const 1))
+            (else (unreachable) (i32.add) (drop) (local.get 0)))
+          (drop (call_indirect (param i32) (result i32) (i32.const 0) (i32.const 0)))
+          (ref.is_null (ref.func $select))
+          (i64.extend_i32_u)
+          (i64.add)
+          (return))
+        (elem declare func $select)
+        (data (i32.const 0) "\01\00\00\00")
+        "#,
+    );
+
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn type_mismatch() {
+    let result = validate(
+        r#"
+        (func)
+        (func (param i64) (result i32)
+          block (result i32)
+            i32.const 1
+          end
+          local.get 0
+          i32.add)
+        "#,
+    );
+
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::TypeMismatch {
+                expected: Some(I32),
+                actual: Some(I64),
+            },
+            at(1, 3)
+        )
+    );
+}
+
+#[test]
+fn operand_stack_underflow() {
+    let result = validate("(func (result i32) (block (i32.const 1) (drop) (drop)) (i32.const 0))");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::OperandStackUnderflow, at(0, 3))
+    );
+}
+
+#[test]
+fn values_left_on_stack() {
+    let result = validate("(func (i32.const 1))");
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::OperandStackHeightMismatch {
+                expected: 0,
+                actual: 1,
+            },
+            at(0, 0)
+        )
+    );
+}
+
+#[test]
+fn if_without_else_needs_matching_types() {
+    let result =
+        validate("(func (result i32) (if (result i32) (i32.const 1) (then (i32.const 2))))");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::OperandStackUnderflow, at(0, 2))
+    );
+}
+
+#[test]
+fn nesting_depth() {
+    let text = format!(
+        "(func {})",
+        "block ".repeat(MAX_NESTING_DEPTH) + &"end ".repeat(MAX_NESTING_DEPTH)
+    );
+    let mut module = wat::parse(&text).unwrap();
+    assert_eq!(module.validate(), Ok(()));
+
+    // The parsers do not produce deeper nesting, but a module can be built by hand
+    let body = std::mem::take(&mut module.codes[0].body);
+    module.codes[0].body = vec![Instruction::Block(BlockType::Empty, body)];
+    assert_eq!(
+        module.validate(),
+        error(
+            ValidationErrorKind::NestingTooDeep,
+            at(0, MAX_NESTING_DEPTH)
+        )
+    );
+}
+
+#[test]
+fn unknown_indices() {
+    let result = validate("(func (local.get 1))");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::UnknownLocal(LocalIdx(1)), at(0, 0))
+    );
+
+    let result = validate("(func (call 1))");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::UnknownFunc(FuncIdx(1)), at(0, 0))
+    );
+
+    let result = validate("(func (block (br 2)))");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::UnknownLabel(LabelIdx(2)), at(0, 1))
+    );
+
+    let result = validate("(func (drop (i32.load (i32.const 0))))");
+    assert!(matches!(
+        result,
+        Err(ValidationError {
+            kind: ValidationErrorKind::UnknownMemory(_),
+            ..
+        })
+    ));
+
+    let result = validate("(type (func)) (func (call_indirect (type 0) (i32.const 0)))");
+    assert!(matches!(
+        result,
+        Err(ValidationError {
+            kind: ValidationErrorKind::UnknownTable(_),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn table_instructions() {
+    let result = validate(
+        r#"
+        (table $functions 1 funcref)
+        (table $objects 1 externref)
+        (elem $e func $f)
+        (func $f (param externref) (result i32)
+          (table.set $objects (i32.const 0) (local.get 0))
+          (table.fill $functions (i32.const 0) (table.get $functions (i32.const 0)) (i32.const 1))
+          (table.init $functions $e (i32.const 0) (i32.const 0) (i32.const 1))
+          (elem.drop $e)
+          (table.grow $objects (ref.null extern) (table.size $objects)))
+        "#,
+    );
+    assert_eq!(result, Ok(()));
+
+    let result = validate("(func (elem.drop 0))");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::UnknownElem(ElemIdx(0)), at(0, 0))
+    );
+
+    let result = validate(
+        r#"
+        (table $functions 1 funcref)
+        (table $objects 1 externref)
+        (func (table.copy $objects $functions (i32.const 0) (i32.const 0) (i32.const 1)))
+        "#,
+    );
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::TableTypeMismatch {
+                expected: RefType::ExternRef,
+                actual: RefType::FuncRef,
+            },
+            at(0, 3)
+        )
+    );
+
+    let result = validate(
+        r#"
+        (table 1 externref)
+        (elem func 0)
+        (func (table.init 0 (i32.const 0) (i32.const 0) (i32.const 1)))
+        "#,
+    );
+    assert!(matches!(
+        result,
+        Err(ValidationError {
+            kind: ValidationErrorKind::TableTypeMismatch { .. },
+            ..
+        })
+    ));
+}
+
+#[test]
+fn branch_table_arity() {
+    let result = validate(
+        "(func (block (result i32) (block (br_table 0 1 (i32.const 0) (i32.const 0)))) (drop))",
+    );
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::BranchTableArityMismatch {
+                expected: 1,
+                actual: 0,
+            },
+            at(0, 4)
+        )
+    );
+}
+
+#[test]
+fn invalid_alignment() {
+    let result = validate("(memory 1) (func (drop (i32.load align=8 (i32.const 0))))");
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::InvalidAlignment {
+                align: 3,
+                natural: 2,
+            },
+            at(0, 1)
+        )
+    );
+}
+
+#[test]
+fn immutable_global() {
+    let result = validate("(global i32 (i32.const 0)) (func (global.set 0 (i32.const 1)))");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::ImmutableGlobal(GlobalIdx(0)), at(0, 1))
+    );
+}
+
+#[test]
+fn constant_expressions() {
+    let result = validate("(global i32 (i32.add (i32.const 1) (i32.const 2)))");
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::ConstantExpressionRequired,
+            Location::Global(GlobalIdx(0))
+        )
+    );
+
+    // Only imported globals can be referred to
+    let result = validate("(global i32 (i32.const 1)) (global i32 (global.get 0))");
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::ConstantExpressionRequired,
+            Location::Global(GlobalIdx(1))
+        )
+    );
+
+    let result = validate("(memory 1) (data (i64.const 0) \"\")");
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::TypeMismatch {
+                expected: Some(I32),
+                actual: Some(I64),
+            },
+            Location::Data(0)
+        )
+    );
+}
+
+#[test]
+fn undeclared_function_reference() {
+    let result = validate("(func (drop (ref.func 0)))");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::UndeclaredFuncRef(FuncIdx(0)), at(0, 0))
+    );
+}
+
+#[test]
+fn module_level_checks() {
+    let result = validate("(memory 2 1)");
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::InvalidLimits {
+                min: 2,
+                max: Some(1),
+            },
+            Location::Memory(wario::wasm::MemIdx(0))
+        )
+    );
+
+    let result = validate(r#"(func (export "f")) (func (export "f"))"#);
+    assert_eq!(
+        result,
+        error(
+            ValidationErrorKind::DuplicateExport(Name("f".to_owned())),
+            Location::Export(1)
+        )
+    );
+
+    let result = validate("(func (param i32)) (start 0)");
+    assert_eq!(
+        result,
+        error(ValidationErrorKind::InvalidStartFunction, Location::Start)
+    );
+
+    let mut module = wat::parse("(func)").unwrap();
+    module.functions.push(TypeIdx(0));
+    assert_eq!(
+        module.validate(),
+        error(
+            ValidationErrorKind::FunctionCodeMismatch {
+                functions: 2,
+                codes: 1,
+            },
+            Location::Module
+        )
+    );
+}
+
+#[test]
+fn error_message() {
+    let error = validate("(func (result i32) (f32.const 0))").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Type mismatch: expected: NumType(I32) actual: NumType(F32) (function 0, instruction 0)"
+    );
+}
@@ -0,0 +1,473 @@
+use wario::wasm::{
+    BlockType, Code, Data, DataMode, Elem, ElemIdx, ElemMode, Export, ExportDescriptor, FuncIdx,
+    FuncType, Global, GlobalIdx, GlobalType, Import, ImportDescriptor, Instruction, LabelIdx,
+    Limits, LocalIdx, MemArg, MemIdx, Module, Mutability, Name, NumType, RefType, TableIdx,
+    TableType, TypeIdx, ValueType, MAX_NESTING_DEPTH,
+};
+use wario::wat;
+
+const I32: ValueType = ValueType::NumType(NumType::I32);
+const F64: ValueType = ValueType::NumType(NumType::F64);
+
+fn body(text: &str) -> Vec<Instruction> {
+    let module = wat::parse(text).unwrap();
+    module.codes.into_iter().next().unwrap().body
+}
+
+#[test]
+fn empty_module() {
+    assert_eq!(wat::parse("(module)").unwrap(), Module::default());
+    assert_eq!(wat::parse("").unwrap(), Module::default());
+    assert_eq!(
+        wat::parse("(module $m ;; comment\n (; (nested) ;) )").unwrap(),
+        Module::default()
+    );
+}
+
+#[test]
+fn module() {
+    let module = wat::parse(
+        r#"
+        (module
+          (type $binary (func (param f64 f64) (result i32)))
+          (import "env" "print" (func $print (param i32)))
+          (memory (export "memory") 1 2)
+          (table $table 2 funcref)
+          (global $counter (mut i32) (i32.const 42))
+          (func $add (export "add") (type $binary) (param $x f64) (param $y f64) (result i32)
+            (local $sum f64)
+            (local.set $sum (f64.add (local.get $x) (local.get $y)))
+            (call $print (global.get $counter))
+            (f64.gt (local.get $sum) (f64.const 0)))
+          (elem (i32.const 0) $add $print)
+          (data (i32.const 8) "hi\00\n")
+          (start $print))
+        "#,
+    );
+
+    let expected = Module {
+        types: vec![
+            FuncType {
+                parameter_types: vec![F64, F64],
+                result_types: vec![I32],
+            },
+            FuncType {
+                parameter_types: vec![I32],
+                result_types: vec![],
+            },
+        ],
+        imports: vec![Import {
+            module: Name("env".to_owned()),
+            name: Name("print".to_owned()),
+            descriptor: ImportDescriptor::Func(TypeIdx(1)),
+        }],
+        functions: vec![TypeIdx(0)],
+        tables: vec![TableType {
+            elem_type: RefType::FuncRef,
+            limits: Limits { min: 2, max: None },
+        }],
+        memories: vec![Limits {
+            min: 1,
+            max: Some(2),
+        }],
+        globals: vec![Global {
+            global_type: GlobalType {
+                value_type: I32,
+                mutability: Mutability::Variable,
+            },
+            expression: vec![Instruction::I32Const(42)],
+        }],
+        exports: vec![
+            Export {
+                name: Name("memory".to_owned()),
+                descriptor: ExportDescriptor::Memory(MemIdx(0)),
+            },
+            Export {
+                name: Name("add".to_owned()),
+                descriptor: ExportDescriptor::Func(FuncIdx(1)),
+            },
+        ],
+        start: Some(wario::wasm::Start { func: FuncIdx(0) }),
+        elems: vec![Elem {
+            elem_type: RefType::FuncRef,
+            init: vec![
+                vec![Instruction::RefFunc(FuncIdx(1))],
+                vec![Instruction::RefFunc(FuncIdx(0))],
+            ],
+            mode: ElemMode::Active {
+                table: TableIdx(0),
+                offset: vec![Instruction::I32Const(0)],
+            },
+        }],
+        codes: vec![Code {
+            locals: vec![F64],
+            body: vec![
+                Instruction::LocalGet(LocalIdx(0)),
+                Instruction::LocalGet(LocalIdx(1)),
+                Instruction::F64Add,
+                Instruction::LocalSet(LocalIdx(2)),
+                Instruction::GlobalGet(GlobalIdx(0)),
+                Instruction::Call(FuncIdx(0)),
+                Instruction::LocalGet(LocalIdx(2)),
+                Instruction::F64Const(0.0),
+                Instruction::F64Gt,
+            ],
+        }],
+        datas: vec![Data {
+            init: b"hi\0\n".to_vec(),
+            mode: DataMode::Active {
+                memory: MemIdx(0),
+                offset: vec![Instruction::I32Const(8)],
+            },
+        }],
+        ..Default::default()
+    };
+
+    assert_eq!(module, Ok(expected));
+}
+
+#[test]
+fn flat_and_folded_instructions() {
+    let flat = body(
+        r#"
+        (func $f (param $n i32) (result i32)
+          block $outer (result i32)
+            loop $inner
+              local.get $n
+              br_if $outer
+              local.get 0
+              if
+                br $inner
+              else
+                nop
+              end
+            end
+            i32.const 1
+          end)
+        "#,
+    );
+
+    let folded = body(
+        r#"
+        (func $f (param $n i32) (result i32)
+          (block $outer (result i32)
+            (loop $inner
+              (br_if $outer (local.get $n))
+              (if (local.get 0)
+                (then (br $inner))
+                (else (nop))))
+            (i32.const 1)))
+        "#,
+    );
+
+    let expected = vec![Instruction::Block(
+        BlockType::Value(I32),
+        vec![
+            Instruction::Loop(
+                BlockType::Empty,
+                vec![
+                    Instruction::LocalGet(LocalIdx(0)),
+                    Instruction::BranchIf(LabelIdx(1)),
+                    Instruction::LocalGet(LocalIdx(0)),
+                    Instruction::If(
+                        BlockType::Empty,
+                        vec![Instruction::Branch(LabelIdx(1))],
+                        vec![Instruction::Nop],
+                    ),
+                ],
+            ),
+            Instruction::I32Const(1),
+        ],
+    )];
+
+    assert_eq!(flat, expected);
+    assert_eq!(folded, expected);
+}
+
+#[test]
+fn forward_references() {
+    let module = wat::parse(
+        r#"
+        (func (call $second))
+        (func $second (call 0))
+        (export "second" (func $second))
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(module.codes[0].body, vec![Instruction::Call(FuncIdx(1))]);
+    assert_eq!(
+        module.exports[0].descriptor,
+        ExportDescriptor::Func(FuncIdx(1))
+    );
+}
+
+#[test]
+fn block_types() {
+    let module = wat::parse(
+        r#"
+        (func
+          (block (param i32) (result i32 i32) (unreachable))
+          (call_indirect (param f64) (result i32) (i32.const 0)))
+        "#,
+    )
+    .unwrap();
+
+    // The implicit types get appended to the type section
+    assert_eq!(module.types.len(), 3);
+    assert_eq!(
+        module.codes[0].body,
+        vec![
+            Instruction::Block(
+                BlockType::TypeIdx(TypeIdx(1)),
+                vec![Instruction::Unreachable]
+            ),
+            Instruction::I32Const(0),
+            Instruction::CallIndirect(TypeIdx(2), TableIdx(0)),
+        ]
+    );
+}
+
+#[test]
+fn memory_instructions() {
+    let code = body(
+        r#"
+        (func
+          (i64.store offset=16 align=4 (i32.const 0) (i64.load8_u offset=0x20 (i32.const 4)))
+          (drop (memory.grow (memory.size))))
+        "#,
+    );
+
+    assert_eq!(
+        code,
+        vec![
+            Instruction::I32Const(0),
+            Instruction::I32Const(4),
+            Instruction::I64Load8Unsigned(MemArg {
+                align: 0,
+                offset: 32
+            }),
+            Instruction::I64Store(MemArg {
+                align: 2,
+                offset: 16
+            }),
+            Instruction::MemorySize,
+            Instruction::MemoryGrow,
+            Instruction::Drop,
+        ]
+    );
+}
+
+#[test]
+fn table_instructions() {
+    let code = body(
+        r#"
+        (table $a 1 funcref)
+        (table $b 1 funcref)
+        (elem $e func $f)
+        (func $f
+          (table.set (i32.const 0) (table.get $b (i32.const 0)))
+          (drop (table.grow $b (ref.null func) (table.size)))
+          (table.fill $a (i32.const 0) (ref.null func) (i32.const 1))
+          (table.copy $a $b (i32.const 0) (i32.const 0) (i32.const 1))
+          (table.copy (i32.const 0) (i32.const 0) (i32.const 0))
+          (table.init $b $e (i32.const 0) (i32.const 0) (i32.const 1))
+          (table.init $e (i32.const 0) (i32.const 0) (i32.const 1))
+          (elem.drop $e))
+        "#,
+    );
+
+    assert_eq!(
+        code,
+        vec![
+            Instruction::I32Const(0),
+            Instruction::I32Const(0),
+            Instruction::TableGet(TableIdx(1)),
+            Instruction::TableSet(TableIdx(0)),
+            Instruction::RefNull(RefType::FuncRef),
+            Instruction::TableSize(TableIdx(0)),
+            Instruction::TableGrow(TableIdx(1)),
+            Instruction::Drop,
+            Instruction::I32Const(0),
+            Instruction::RefNull(RefType::FuncRef),
+            Instruction::I32Const(1),
+            Instruction::TableFill(TableIdx(0)),
+            Instruction::I32Const(0),
+            Instruction::I32Const(0),
+            Instruction::I32Const(1),
+            Instruction::TableCopy(TableIdx(0), TableIdx(1)),
+            Instruction::I32Const(0),
+            Instruction::I32Const(0),
+            Instruction::I32Const(0),
+            Instruction::TableCopy(TableIdx(0), TableIdx(0)),
+            Instruction::I32Const(0),
+            Instruction::I32Const(0),
+            Instruction::I32Const(1),
+            Instruction::TableInit(TableIdx(1), ElemIdx(0)),
+            Instruction::I32Const(0),
+            Instruction::I32Const(0),
+            Instruction::I32Const(1),
+            Instruction::TableInit(TableIdx(0), ElemIdx(0)),
+            Instruction::ElemDrop(ElemIdx(0)),
+        ]
+    );
+}
+
+#[test]
+fn numeric_literals() {
+    let code = body(
+        r#"
+        (func
+          i32.const 0xFFFF_FFFF
+          i32.const -2147483648
+          i64.const -0x8000000000000000
+          f32.const 0x1p-149
+          f32.const -inf
+          f64.const 0x1.8p1
+          f64.const 1_000.5e-1
+          f64.const nan:0x4
+          f64.const 0x1.fffffffffffff8p0)
+        "#,
+    );
+
+    let bits: Vec<_> = code
+        .iter()
+        .map(|instruction| match *instruction {
+            Instruction::I32Const(value) => value as u32 as u64,
+            Instruction::I64Const(value) => value as u64,
+            Instruction::F32Const(value) => value.to_bits() as u64,
+            Instruction::F64Const(value) => value.to_bits(),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    assert_eq!(
+        bits,
+        vec![
+            0xFFFF_FFFF,
+            0x8000_0000,
+            0x8000_0000_0000_0000,
+            0x0000_0001,
+            0xFF80_0000,
+            3.0f64.to_bits(),
+            100.05f64.to_bits(),
+            0x7FF0_0000_0000_0004,
+            // Rounds half to even
+            2.0f64.to_bits(),
+        ]
+    );
+}
+
+#[test]
+fn integer_out_of_range() {
+    let error = wat::parse("(func i32.const 0x1_0000_0000)").unwrap_err();
+    assert_eq!(error.message, "Invalid i32: 0x1_0000_0000");
+    assert_eq!((error.line, error.column), (1, 17));
+}
+
+#[test]
+fn unicode_escapes() {
+    let module = wat::parse(r#"(memory 1) (data (i32.const 0) "\u{41}\u{10FFFF}")"#).unwrap();
+    assert_eq!(module.datas[0].init, "A\u{10FFFF}".as_bytes());
+
+    let error = wat::parse(r#"(memory 1) (data (i32.const 0) "\u{100000041}")"#).unwrap_err();
+    assert_eq!(error.message, r#"Invalid string: "\u{100000041}""#);
+
+    let error = wat::parse(r#"(memory 1) (data (i32.const 0) "\u{D800}")"#).unwrap_err();
+    assert_eq!(error.message, r#"Invalid string: "\u{D800}""#);
+}
+
+#[test]
+fn unknown_label() {
+    let error = wat::parse("(module\n  (func\n    (block $a (br $b))))").unwrap_err();
+    assert_eq!(error.to_string(), "Unknown label: $b (line 3, column 19)");
+}
+
+#[test]
+fn unknown_instruction() {
+    let error = wat::parse("(func i32.foo)").unwrap_err();
+    assert_eq!(error.message, "Unknown instruction: i32.foo");
+}
+
+#[test]
+fn nesting_depth() {
+    let flat = |depth| format!("(func {} {})", "block ".repeat(depth), "end ".repeat(depth));
+    assert!(wat::parse(&flat(MAX_NESTING_DEPTH)).is_ok());
+
+    let error = wat::parse(&flat(MAX_NESTING_DEPTH + 1)).unwrap_err();
+    assert_eq!(error.message, "Blocks are nested too deeply");
+    assert_eq!(error.column, 7 + 6 * MAX_NESTING_DEPTH);
+
+    let folded = |depth| format!("(func {}{})", "(loop ".repeat(depth), ")".repeat(depth));
+    assert!(wat::parse(&folded(MAX_NESTING_DEPTH)).is_ok());
+
+    let error = wat::parse(&folded(MAX_NESTING_DEPTH + 1)).unwrap_err();
+    assert_eq!(error.message, "Blocks are nested too deeply");
+}
+
+#[test]
+fn duplicate_local_identifiers() {
+    let error = wat::parse("(func (param $a i32) (local $a i32))").unwrap_err();
+    assert_eq!(error.message, "Duplicate identifier: $a");
+    assert_eq!((error.line, error.column), (1, 29));
+
+    let error = wat::parse("(func (local $a i32) (local $a i64))").unwrap_err();
+    assert_eq!(error.message, "Duplicate identifier: $a");
+
+    let error = wat::parse("(func (param $a i32) (param $a i32))").unwrap_err();
+    assert_eq!(error.message, "Duplicate identifier: $a");
+    assert_eq!((error.line, error.column), (1, 29));
+
+    assert!(wat::parse("(func (param $a i32) (local $b i32)) (func (local $a i32))").is_ok());
+}
+
+#[test]
+fn import_after_definition() {
+    let error = wat::parse(r#"(func) (import "a" "b" (func))"#).unwrap_err();
+    assert_eq!(error.message, "Import after definition");
+}
+
+#[test]
+fn inline_segments() {
+    let module = wat::parse(
+        r#"
+        (func $f)
+        (table funcref (elem $f $f))
+        (memory (data "abc"))
+        "#,
+    )
+    .unwrap();
+
+    let limits = |size| Limits {
+        min: size,
+        max: Some(size),
+    };
+
+    assert_eq!(module.tables[0].limits, limits(2));
+    assert_eq!(module.elems[0].init.len(), 2);
+    assert_eq!(module.memories, vec![limits(1)]);
+    assert_eq!(module.datas[0].init, b"abc");
+}
+
+#[test]
+fn encode_round_trip() {
+    let module = wat::parse(
+        r#"
+        (module
+          (import "env" "memory" (memory 1))
+          (func $fac (export "fac") (param i64) (result i64)
+            (if (result i64) (i64.eqz (local.get 0))
+              (then (i64.const 1))
+              (else (i64.mul (local.get 0) (call $fac (i64.sub (local.get 0) (i64.const 1)))))))
+          (table $t 2 funcref)
+          (elem $e func $fac)
+          (func
+            (table.init $t $e (i32.const 1) (i32.const 0) (i32.const 1))
+            (table.copy $t $t (i32.const 0) (i32.const 1) (i32.const 1))
+            (elem.drop $e)))
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(Module::from_bytes(&module.encode()).unwrap(), module);
+}